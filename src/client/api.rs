@@ -0,0 +1,961 @@
+use crate::client::tunnel::SshTunnel;
+use crate::daemon::api::*;
+use crate::{config, path};
+use anyhow::{anyhow, bail, Context, Result};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use reqwest::header::{
+    HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_ENCODING, CONTENT_TYPE,
+};
+use reqwest::{Certificate, StatusCode};
+use std::io::Write;
+use std::time::Duration;
+use std::{fs, thread};
+
+use serde::{Deserialize, Serialize};
+
+/// How much longer than its own timeout to wait for a task, for the daemon to
+/// kill it and report back.
+const TASK_TIMEOUT_GRACE: Duration = Duration::from_secs(5);
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+pub enum DeploymentResponse {
+    Ok(ApiDeploymentResponse),
+    Err(ErrorResponse),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+pub enum ForwardResponse {
+    Ok(ApiForwardResponse),
+    Err(ErrorResponse),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+pub enum TaskRunResponse {
+    Ok(ApiTaskRunResponse),
+    Err(ErrorResponse),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+pub enum TaskRunStatusResponse {
+    Ok(ApiTaskRunStatusResponse),
+    Err(ErrorResponse),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+pub enum OperationResponse {
+    Ok(ApiOperationResponse),
+    Err(ErrorResponse),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+pub enum RollbackResponse {
+    Ok(ApiRollbackResponse),
+    Err(ErrorResponse),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+pub enum ModuleEnvResponse {
+    Ok(ApiModuleEnvResponse),
+    Err(ErrorResponse),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+pub enum EnvRefreshResponse {
+    Ok(ApiEnvRefreshResponse),
+    Err(ErrorResponse),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+pub enum LogInfoResponse {
+    Ok(ApiLogResponse),
+    Err(ErrorResponse),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+pub enum LogReadResponse {
+    Ok(ApiLogReadResponse),
+    Err(ErrorResponse),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+pub enum UpgradeResponse {
+    Ok(ApiUpgradeResponse),
+    Err(ErrorResponse),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+pub enum LockResponse {
+    Ok(ApiLockResponse),
+    Err(ErrorResponse),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+pub enum LockRenewResponse {
+    Ok(ApiLockHolder),
+    Err(ErrorResponse),
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+pub enum LogSearchResponse {
+    Ok(ApiLogSearchResponse),
+    Err(ErrorResponse),
+}
+
+/// Fails with a readable error if the daemon rejected the auth token, or
+/// failed internally while handling the request.
+trait Authorized: Sized {
+    fn authorized(self) -> Result<Self>;
+}
+
+impl Authorized for Response {
+    fn authorized(self) -> Result<Self> {
+        if self.status() == StatusCode::UNAUTHORIZED {
+            bail!(
+                "The daemon rejected the request. Check the auth token of \
+                the current context"
+            );
+        }
+        if self.status() == StatusCode::INTERNAL_SERVER_ERROR {
+            // Errors which are not raised by a handler (eg. a panic) get a
+            // response from the default catcher, which is not JSON.
+            let status = self.status();
+            let body = self.text()?;
+            match serde_json::from_str::<ErrorResponse>(&body) {
+                Ok(error) => bail!(error.message),
+                Err(_) if body.trim().is_empty() => {
+                    bail!(
+                        "The daemon failed to handle the request ({})",
+                        status
+                    )
+                }
+                Err(_) => bail!(
+                    "The daemon failed to handle the request ({}): {}",
+                    status,
+                    body.trim()
+                ),
+            }
+        }
+        Ok(self)
+    }
+}
+
+/// Sets the body of a request to JSON, gzip compressed if it is large.
+trait CompressedJson: Sized {
+    fn compressed_json<T: Serialize + ?Sized>(self, json: &T) -> Result<Self>;
+}
+
+impl CompressedJson for RequestBuilder {
+    fn compressed_json<T: Serialize + ?Sized>(self, json: &T) -> Result<Self> {
+        let body = serde_json::to_vec(json)?;
+        let request = self.header(CONTENT_TYPE, "application/json");
+        if body.len() < COMPRESSION_THRESHOLD {
+            return Ok(request.body(body));
+        }
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&body)?;
+        Ok(request
+            .header(CONTENT_ENCODING, "gzip")
+            .body(encoder.finish()?))
+    }
+}
+
+/// How requests to the daemon are retried when the connection fails, eg.
+/// while the daemon is restarting.
+pub struct RetryPolicy {
+    /// The number of retries after the first attempt.
+    pub attempts: u32,
+    /// The delay before the first retry, doubled for each following one.
+    pub backoff: Duration,
+    /// Whether requests which are not safe to repeat are retried too.
+    pub non_idempotent: bool,
+}
+
+impl RetryPolicy {
+    pub fn from(cfg: &config::ClientConfig) -> RetryPolicy {
+        let default = RetryPolicy::default();
+        RetryPolicy {
+            attempts: cfg.retry_attempts.unwrap_or(default.attempts),
+            backoff: cfg
+                .retry_backoff_ms
+                .map(Duration::from_millis)
+                .unwrap_or(default.backoff),
+            non_idempotent: cfg
+                .retry_non_idempotent
+                .unwrap_or(default.non_idempotent),
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy {
+            attempts: 3,
+            backoff: Duration::from_millis(200),
+            non_idempotent: false,
+        }
+    }
+}
+
+/// Sends requests, retrying them according to a [RetryPolicy].
+///
+/// Only failures to connect or to complete the request are retried, and
+/// never timeouts (as the daemon may still be processing the request).
+trait Retrying {
+    /// Sends a request which is safe to repeat.
+    fn send_idempotent(self, policy: &RetryPolicy)
+        -> reqwest::Result<Response>;
+    /// Sends a request which is only retried if the policy allows it.
+    fn send_non_idempotent(
+        self,
+        policy: &RetryPolicy,
+    ) -> reqwest::Result<Response>;
+}
+
+impl Retrying for RequestBuilder {
+    fn send_idempotent(
+        self,
+        policy: &RetryPolicy,
+    ) -> reqwest::Result<Response> {
+        send_with_retries(self, policy.attempts, policy.backoff)
+    }
+
+    fn send_non_idempotent(
+        self,
+        policy: &RetryPolicy,
+    ) -> reqwest::Result<Response> {
+        let attempts = if policy.non_idempotent {
+            policy.attempts
+        } else {
+            0
+        };
+        send_with_retries(self, attempts, policy.backoff)
+    }
+}
+
+fn send_with_retries(
+    request: RequestBuilder,
+    attempts: u32,
+    backoff: Duration,
+) -> reqwest::Result<Response> {
+    let mut delay = backoff;
+    for _ in 0..attempts {
+        // Requests with streamed bodies cannot be cloned, nor retried.
+        let attempt = match request.try_clone() {
+            Some(attempt) => attempt,
+            None => break,
+        };
+        match attempt.send() {
+            Err(e) if !e.is_timeout() && (e.is_connect() || e.is_request()) => {
+                thread::sleep(delay);
+                delay *= 2;
+            }
+            result => return result,
+        }
+    }
+    request.send()
+}
+
+/// How long requests to the daemon may take.
+pub struct Timeouts {
+    /// The timeout for connecting, or none to wait indefinitely.
+    pub connect: Option<Duration>,
+    /// The timeout for a response to any request but deploying a task.
+    pub request: Duration,
+    /// The timeout for deploying a task, unless the task has its own.
+    pub task: Duration,
+}
+
+impl Timeouts {
+    /// Reads the timeouts from the config, where `request` (the `--timeout`
+    /// flag) takes precedence over the configured request timeout.
+    pub fn from(cfg: &config::ClientConfig, request: Option<u64>) -> Timeouts {
+        let default = Timeouts::default();
+        Timeouts {
+            connect: cfg.connect_timeout_secs.map(Duration::from_secs),
+            request: request
+                .or(cfg.request_timeout_secs)
+                .map(Duration::from_secs)
+                .unwrap_or(default.request),
+            task: cfg
+                .task_timeout_secs
+                .map(Duration::from_secs)
+                .unwrap_or(default.task),
+        }
+    }
+}
+
+impl Default for Timeouts {
+    fn default() -> Timeouts {
+        Timeouts {
+            connect: None,
+            request: Duration::from_secs(30),
+            task: Duration::from_secs(180),
+        }
+    }
+}
+
+/// Where and how to reach a daemon.
+#[derive(Default)]
+pub struct Endpoint {
+    /// The base URL of the daemon API.
+    pub url: String,
+    /// The token to authenticate with.
+    pub auth_token: Option<String>,
+    /// A PEM file with an additional certificate to trust.
+    pub ca_cert: Option<String>,
+    /// The tunnel the URL points to, kept open for the lifetime of the
+    /// client.
+    pub tunnel: Option<SshTunnel>,
+}
+
+/// A client of the daemon API, returning typed responses.
+///
+/// A single HTTP client is shared by all requests, so that connections to
+/// the daemon are kept alive and reused.
+///
+/// ```no_run
+/// use cartel::client::api::CartelApiClient;
+///
+/// let client = CartelApiClient::with_url("http://localhost:13754/api/v1")?;
+/// for module in client.status()?.status {
+///     println!("{} {:?}", module.name, module.status);
+/// }
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub struct CartelApiClient {
+    /// The base URL of the daemon API (eg. `http://localhost:13754/api/v1`).
+    pub url: String,
+    client: Client,
+    retry: RetryPolicy,
+    task_timeout: Duration,
+    _tunnel: Option<SshTunnel>,
+}
+
+impl CartelApiClient {
+    /// Creates a client of the daemon at the given endpoint.
+    pub fn new(
+        endpoint: Endpoint,
+        retry: RetryPolicy,
+        timeouts: Timeouts,
+    ) -> Result<CartelApiClient> {
+        let mut headers = HeaderMap::new();
+        if let Some(token) = &endpoint.auth_token {
+            let value = HeaderValue::from_str(&format!("Bearer {}", token))
+                .map_err(|_| anyhow!("Invalid characters in auth token"))?;
+            headers.insert(AUTHORIZATION, value);
+        }
+        let mut builder = Client::builder()
+            .default_headers(headers)
+            .timeout(timeouts.request);
+        if let Some(timeout) = timeouts.connect {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(ca_cert) = &endpoint.ca_cert {
+            let pem = path::from_user_str(ca_cert)
+                .ok_or_else(|| anyhow!("Failed to locate users home dir"))
+                .and_then(|path| Ok(fs::read(path)?))
+                .with_context(|| format!("Failed to read {}", ca_cert))?;
+            let cert = Certificate::from_pem(&pem)
+                .with_context(|| format!("Invalid certificate {}", ca_cert))?;
+            builder = builder.add_root_certificate(cert);
+        }
+        let client = builder
+            .build()
+            .with_context(|| "Failed to create the HTTP client")?;
+        Ok(CartelApiClient {
+            url: endpoint.url,
+            client,
+            retry,
+            task_timeout: timeouts.task,
+            _tunnel: endpoint.tunnel,
+        })
+    }
+
+    /// Creates a client of the daemon at the given URL (eg.
+    /// `http://localhost:13754/api/v1`) with the default retries and
+    /// timeouts.
+    pub fn with_url(url: &str) -> Result<CartelApiClient> {
+        let endpoint = Endpoint {
+            url: url.to_string(),
+            ..Default::default()
+        };
+        CartelApiClient::new(
+            endpoint,
+            RetryPolicy::default(),
+            Timeouts::default(),
+        )
+    }
+
+    /// Deploys a service, returning a handle to monitor its health with.
+    ///
+    /// The request carries an idempotency key, so it is safe to retry.
+    pub fn deploy(
+        &self,
+        command: &ApiDeploymentCommand,
+    ) -> Result<ApiDeploymentResponse> {
+        let deployment_result: DeploymentResponse = self
+            .client
+            .post(&(self.url.to_owned() + "/deploy"))
+            .header(IDEMPOTENCY_KEY_HEADER, idempotency_key())
+            .compressed_json(command)?
+            .send_idempotent(&self.retry)?
+            .authorized()?
+            .json()?;
+
+        match deployment_result {
+            DeploymentResponse::Ok(r) => Ok(r),
+            DeploymentResponse::Err(e) => bail!(e.message),
+        }
+    }
+
+    /// Starts running a task in the background, returning the id of the run
+    /// to poll with [CartelApiClient::task_run].
+    pub fn start_task(
+        &self,
+        command: &ApiTaskDeploymentCommand,
+    ) -> Result<ApiTaskRunResponse> {
+        let run_result: TaskRunResponse = self
+            .client
+            .post(&(self.url.to_owned() + "/tasks/run"))
+            .header(IDEMPOTENCY_KEY_HEADER, idempotency_key())
+            .compressed_json(command)?
+            .send_idempotent(&self.retry)?
+            .authorized()?
+            .json()?;
+
+        match run_result {
+            TaskRunResponse::Ok(r) => Ok(r),
+            TaskRunResponse::Err(e) => bail!(e.message),
+        }
+    }
+
+    /// Returns the status of a task run started in the background.
+    pub fn task_run(&self, id: &str) -> Result<ApiTaskRunStatusResponse> {
+        let status_result: TaskRunStatusResponse = self
+            .client
+            .get(&format!("{}/tasks/{}", self.url, id))
+            .send_idempotent(&self.retry)?
+            .authorized()?
+            .json()?;
+
+        match status_result {
+            TaskRunStatusResponse::Ok(r) => Ok(r),
+            TaskRunStatusResponse::Err(e) => bail!(e.message),
+        }
+    }
+
+    /// Returns the recorded executions of tasks, newest first, optionally
+    /// only those of a task (of a project).
+    pub fn task_history(
+        &self,
+        task_name: Option<&str>,
+        project: Option<&str>,
+    ) -> Result<ApiTaskHistoryResponse> {
+        let query: Vec<_> = [("task", task_name), ("project", project)]
+            .iter()
+            .filter_map(|(key, value)| value.map(|value| (*key, value)))
+            .collect();
+        let history = self
+            .client
+            .get(&(self.url.to_owned() + "/tasks/history"))
+            .query(&query)
+            .send_idempotent(&self.retry)?
+            .authorized()?
+            .json()?;
+
+        Ok(history)
+    }
+
+    /// Records a manual intervention on a service in the event log.
+    pub fn record_event(
+        &self,
+        command: &ApiEventCommand,
+    ) -> Result<ApiOperationResponse> {
+        let response: OperationResponse = self
+            .client
+            .post(&(self.url.to_owned() + "/events"))
+            .json(command)
+            .send_non_idempotent(&self.retry)?
+            .authorized()?
+            .json()?;
+
+        match response {
+            OperationResponse::Ok(r) => Ok(r),
+            OperationResponse::Err(e) => bail!(e.message),
+        }
+    }
+
+    /// Returns the events recorded by the daemon, newest first.
+    pub fn events(
+        &self,
+        module_name: Option<&str>,
+    ) -> Result<ApiEventsResponse> {
+        let query: Vec<_> =
+            module_name.map(|m| ("module", m)).into_iter().collect();
+        let events = self
+            .client
+            .get(&(self.url.to_owned() + "/events"))
+            .query(&query)
+            .send_idempotent(&self.retry)?
+            .authorized()?
+            .json()?;
+
+        Ok(events)
+    }
+
+    /// Returns how long to wait for a task to finish, given its own timeout
+    /// (in seconds) if it has one.
+    pub fn task_timeout(
+        &self,
+        timeout: Option<u64>,
+        command: &ApiTaskDeploymentCommand,
+    ) -> Duration {
+        // The timeout applies to each attempt of the task. The daemon kills
+        // tasks with a timeout of their own, so wait a little longer for it to
+        // report back.
+        let attempt_timeout = match timeout {
+            Some(timeout) => Duration::from_secs(timeout) + TASK_TIMEOUT_GRACE,
+            None => self.task_timeout,
+        };
+        attempt_timeout * (command.retries + 1)
+            + Duration::from_secs(command.retry_delay) * command.retries
+    }
+
+    /// Stops a module.
+    pub fn stop(&self, module_name: &str) -> Result<ApiOperationResponse> {
+        let command = ApiOperationCommand {
+            operation: ApiModuleOperation::STOP,
+            module_name: module_name.to_string(),
+        };
+        self.operation(&command, true)
+    }
+
+    /// Restarts a module.
+    pub fn restart(&self, module_name: &str) -> Result<ApiOperationResponse> {
+        let command = ApiOperationCommand {
+            operation: ApiModuleOperation::RESTART,
+            module_name: module_name.to_string(),
+        };
+        self.operation(&command, false)
+    }
+
+    /// Returns the environment a running module was spawned with.
+    pub fn module_env(
+        &self,
+        module_name: &str,
+    ) -> Result<ApiModuleEnvResponse> {
+        let response: ModuleEnvResponse = self
+            .client
+            .get(&format!("{}/modules/{}/env", self.url, module_name))
+            .send_idempotent(&self.retry)?
+            .authorized()?
+            .json()?;
+
+        match response {
+            ModuleEnvResponse::Ok(r) => Ok(r),
+            ModuleEnvResponse::Err(e) => bail!(e.message),
+        }
+    }
+
+    /// Writes to the input of a running interactive service.
+    pub fn write_stdin(
+        &self,
+        module_name: &str,
+        input: &str,
+    ) -> Result<ApiOperationResponse> {
+        let command = ApiStdinCommand {
+            input: input.to_string(),
+        };
+        let response: OperationResponse = self
+            .client
+            .post(&format!("{}/modules/{}/stdin", self.url, module_name))
+            .json(&command)
+            .send_non_idempotent(&self.retry)?
+            .authorized()?
+            .json()?;
+
+        match response {
+            OperationResponse::Ok(r) => Ok(r),
+            OperationResponse::Err(e) => bail!(e.message),
+        }
+    }
+
+    /// Forwards a port for a running service, until the service stops.
+    pub fn forward(
+        &self,
+        module_name: &str,
+        command: &ApiForwardCommand,
+    ) -> Result<ApiForwardResponse> {
+        let response: ForwardResponse = self
+            .client
+            .post(&format!("{}/modules/{}/forwards", self.url, module_name))
+            .json(command)
+            .send_non_idempotent(&self.retry)?
+            .authorized()?
+            .json()?;
+
+        match response {
+            ForwardResponse::Ok(r) => Ok(r),
+            ForwardResponse::Err(e) => bail!(e.message),
+        }
+    }
+
+    /// Makes the daemon grab the environment of the login shell now,
+    /// returning what changed.
+    pub fn refresh_env(&self) -> Result<ApiEnvRefreshResponse> {
+        let response: EnvRefreshResponse = self
+            .client
+            .post(&format!("{}/env/refresh", self.url))
+            .send_idempotent(&self.retry)?
+            .authorized()?
+            .json()?;
+
+        match response {
+            EnvRefreshResponse::Ok(r) => Ok(r),
+            EnvRefreshResponse::Err(e) => bail!(e.message),
+        }
+    }
+
+    /// Returns when the daemon last grabbed the environment of the login
+    /// shell.
+    pub fn env_status(&self) -> Result<ApiEnvStatusResponse> {
+        let status = self
+            .client
+            .get(&format!("{}/env", self.url))
+            .send_idempotent(&self.retry)?
+            .authorized()?
+            .json()?;
+
+        Ok(status)
+    }
+
+    /// Acquires the deployment lock, or returns who holds it.
+    ///
+    /// Returns None if the daemon does not support locking (as it is older
+    /// than the client).
+    pub fn acquire_lock(
+        &self,
+        command: &ApiLockCommand,
+    ) -> Result<Option<ApiLockResponse>> {
+        let response = self
+            .client
+            .post(&(self.url.to_owned() + "/lock"))
+            .json(command)
+            .send_non_idempotent(&self.retry)?
+            .authorized()?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        match response.json()? {
+            LockResponse::Ok(r) => Ok(Some(r)),
+            LockResponse::Err(e) => bail!(e.message),
+        }
+    }
+
+    pub fn renew_lock(&self, id: &str, ttl: u64) -> Result<ApiLockHolder> {
+        let response: LockRenewResponse = self
+            .client
+            .post(&format!("{}/lock/{}/renew", self.url, id))
+            .json(&ApiLockRenewCommand { ttl })
+            .send_idempotent(&self.retry)?
+            .authorized()?
+            .json()?;
+
+        match response {
+            LockRenewResponse::Ok(r) => Ok(r),
+            LockRenewResponse::Err(e) => bail!(e.message),
+        }
+    }
+
+    pub fn release_lock(&self, id: &str) -> Result<ApiOperationResponse> {
+        let response: OperationResponse = self
+            .client
+            .delete(&format!("{}/lock/{}", self.url, id))
+            .send_idempotent(&self.retry)?
+            .authorized()?
+            .json()?;
+
+        match response {
+            OperationResponse::Ok(r) => Ok(r),
+            OperationResponse::Err(e) => bail!(e.message),
+        }
+    }
+
+    pub fn lock_status(&self) -> Result<ApiLockStatusResponse> {
+        let status = self
+            .client
+            .get(&format!("{}/lock", self.url))
+            .send_idempotent(&self.retry)?
+            .authorized()?
+            .json()?;
+
+        Ok(status)
+    }
+
+    pub fn daemon_health(&self) -> Result<ApiDaemonHealthResponse> {
+        let health = self
+            .client
+            .get(&format!("{}/daemon/health", self.url))
+            .send_idempotent(&self.retry)?
+            .authorized()?
+            .json()?;
+
+        Ok(health)
+    }
+
+    /// Replaces the daemon with the given executable, keeping its services
+    /// running.
+    pub fn upgrade_daemon(
+        &self,
+        executable: &str,
+    ) -> Result<ApiUpgradeResponse> {
+        let response = self
+            .client
+            .post(&format!("{}/daemon/upgrade", self.url))
+            .json(&ApiUpgradeCommand {
+                executable: executable.to_string(),
+            })
+            .send_non_idempotent(&self.retry)?
+            .authorized()?;
+        if response.status() == StatusCode::NOT_FOUND {
+            bail!(
+                "The daemon is too old to be upgraded in place, restart it \
+                with `cartel daemon restart` instead"
+            );
+        }
+
+        match response.json()? {
+            UpgradeResponse::Ok(r) => Ok(r),
+            UpgradeResponse::Err(e) => bail!(e.message),
+        }
+    }
+
+    fn operation(
+        &self,
+        command: &ApiOperationCommand,
+        idempotent: bool,
+    ) -> Result<ApiOperationResponse> {
+        let request = self
+            .client
+            .post(&(self.url.to_owned() + "/operation"))
+            .json(command);
+        let response = if idempotent {
+            request.send_idempotent(&self.retry)?
+        } else {
+            request.send_non_idempotent(&self.retry)?
+        };
+        let operation_result: OperationResponse =
+            response.authorized()?.json()?;
+
+        match operation_result {
+            OperationResponse::Ok(r) => Ok(r),
+            OperationResponse::Err(e) => bail!(e.message),
+        }
+    }
+
+    /// Stops the given modules, in order.
+    pub fn stop_many(
+        &self,
+        module_names: &[String],
+    ) -> Result<ApiOperationResponse> {
+        let command = ApiStopManyCommand {
+            modules: module_names.to_vec(),
+        };
+
+        let operation_result: OperationResponse = self
+            .client
+            .post(&(self.url.to_owned() + "/stop_many"))
+            .json(&command)
+            .send_idempotent(&self.retry)?
+            .authorized()?
+            .json()?;
+
+        match operation_result {
+            OperationResponse::Ok(r) => Ok(r),
+            OperationResponse::Err(e) => bail!(e.message),
+        }
+    }
+
+    /// Rolls back the given modules to their previous definitions, or the
+    /// services changed by the last deploy if none are given.
+    ///
+    /// This is not retried, as rolling back twice undoes the rollback.
+    pub fn rollback(
+        &self,
+        module_names: &[String],
+    ) -> Result<ApiRollbackResponse> {
+        let command = ApiRollbackCommand {
+            modules: module_names.to_vec(),
+        };
+
+        let rollback_result: RollbackResponse = self
+            .client
+            .post(&(self.url.to_owned() + "/rollback"))
+            .json(&command)
+            .send_non_idempotent(&self.retry)?
+            .authorized()?
+            .json()?;
+
+        match rollback_result {
+            RollbackResponse::Ok(r) => Ok(r),
+            RollbackResponse::Err(e) => bail!(e.message),
+        }
+    }
+
+    /// Stops all running modules.
+    pub fn stop_all(&self) -> Result<ApiOperationResponse> {
+        let operation_result: OperationResponse = self
+            .client
+            .post(&(self.url.to_owned() + "/stop_all"))
+            .send_idempotent(&self.retry)?
+            .authorized()?
+            .json()?;
+
+        match operation_result {
+            OperationResponse::Ok(r) => Ok(r),
+            OperationResponse::Err(e) => bail!(e.message),
+        }
+    }
+
+    /// Checks whether the daemon responds, without retrying.
+    pub fn ping(&self) -> Result<()> {
+        self.client
+            .get(&(self.url.to_owned() + "/status"))
+            .send()?
+            .authorized()?;
+        Ok(())
+    }
+
+    /// Returns the status of all modules known to the daemon.
+    pub fn status(&self) -> Result<ApiModuleStatusResponse> {
+        let status = self
+            .client
+            .get(&(self.url.to_owned() + "/status"))
+            .send_idempotent(&self.retry)?
+            .authorized()?
+            .json()?;
+
+        Ok(status)
+    }
+
+    /// Returns the status of all modules known to the daemon, including the
+    /// ports each running module listens on.
+    pub fn status_with_ports(&self) -> Result<ApiModuleStatusResponse> {
+        let status = self
+            .client
+            .get(&(self.url.to_owned() + "/status?ports=true"))
+            .send_idempotent(&self.retry)?
+            .authorized()?
+            .json()?;
+
+        Ok(status)
+    }
+
+    /// Returns the path of the log file of a module.
+    pub fn log_file(
+        &self,
+        request: &ApiLogFileRequest,
+    ) -> Result<ApiLogResponse> {
+        let status: LogInfoResponse = self
+            .client
+            .post(&(self.url.to_owned() + "/log_file"))
+            .json(request)
+            .send_idempotent(&self.retry)?
+            .authorized()?
+            .json()?;
+
+        match status {
+            LogInfoResponse::Ok(r) => Ok(r),
+            LogInfoResponse::Err(e) => bail!(e.message),
+        }
+    }
+
+    /// Searches the logs of a module.
+    pub fn search_logs(
+        &self,
+        module_name: &str,
+        request: &ApiLogSearchRequest,
+    ) -> Result<ApiLogSearchResponse> {
+        let response: LogSearchResponse = self
+            .client
+            .post(&format!("{}/logs/{}/search", self.url, module_name))
+            .json(request)
+            .send_idempotent(&self.retry)?
+            .authorized()?
+            .json()?;
+
+        match response {
+            LogSearchResponse::Ok(r) => Ok(r),
+            LogSearchResponse::Err(e) => bail!(e.message),
+        }
+    }
+
+    /// Reads the logs of a module, starting at an offset.
+    pub fn read_logs(
+        &self,
+        module_name: &str,
+        request: &ApiLogReadRequest,
+    ) -> Result<ApiLogReadResponse> {
+        let response: LogReadResponse = self
+            .client
+            .post(&format!("{}/logs/{}/read", self.url, module_name))
+            .json(request)
+            .send_idempotent(&self.retry)?
+            .authorized()?
+            .json()?;
+
+        match response {
+            LogReadResponse::Ok(r) => Ok(r),
+            LogReadResponse::Err(e) => bail!(e.message),
+        }
+    }
+
+    /// Returns the health of a service being deployed.
+    pub fn health(&self, monitor_handle: &str) -> Result<ApiHealthResponse> {
+        let health = self
+            .client
+            .get(&(self.url.to_owned() + "/health/" + monitor_handle))
+            .send_idempotent(&self.retry)?
+            .authorized()?
+            .json()?;
+
+        Ok(health)
+    }
+
+    /// Returns which of the given services need to be (re)deployed.
+    pub fn plan(
+        &self,
+        request: &ApiGetPlanRequest,
+    ) -> Result<ApiGetPlanResponse> {
+        let get_plan_result = self
+            .client
+            .post(&(self.url.to_owned() + "/get_plan"))
+            .compressed_json(request)?
+            .send_idempotent(&self.retry)?
+            .authorized()?
+            .json()?;
+        Ok(get_plan_result)
+    }
+}
+
+/// Generates a key which identifies a request across its retries.
+fn idempotency_key() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
@@ -1,7 +1,13 @@
+use crate::client::api::{CartelApiClient, Endpoint, RetryPolicy, Timeouts};
 use crate::client::commands::*;
-use crate::config;
-use anyhow::{anyhow, bail, Error, Result};
-use clap::{crate_version, App, AppSettings, Arg, ArgMatches, SubCommand};
+use crate::client::definitions::read_project_config;
+use crate::client::tunnel::SshTunnel;
+use crate::{config, discovery};
+use anyhow::{anyhow, bail, Context, Error, Result};
+use clap::{
+    crate_version, App, AppSettings, Arg, ArgMatches, Shell, SubCommand,
+};
+use reqwest::Url;
 use std::env;
 
 pub struct ClientConfig {
@@ -11,12 +17,58 @@ pub struct ClientConfig {
     pub default_pager_cmd: Vec<String>,
     pub full_pager_cmd: Vec<String>,
     pub follow_pager_cmd: Vec<String>,
-    pub daemon_url: String,
     pub default_dir: Option<String>,
+    /// The daemon, unless running with `--offline`.
+    daemon: Option<CartelApiClient>,
+}
+
+impl ClientConfig {
+    /// Returns the daemon to send requests to.
+    pub fn daemon(&self) -> Result<&CartelApiClient> {
+        self.daemon.as_ref().ok_or_else(|| {
+            anyhow!("This command needs the daemon, which --offline disables")
+        })
+    }
 }
 
 pub fn cli_app() -> Result<()> {
-    let matches = App::new("cartel")
+    let matches = app().get_matches();
+
+    // Configuration commands do not talk to the daemon, and should work even
+    // if the current context is invalid.
+    if let ("config", Some(config_cli_opts)) = matches.subcommand() {
+        return invoke_config_subcommand(config_cli_opts);
+    }
+    if let ("completions", Some(completions_cli_opts)) = matches.subcommand() {
+        let shell = completions_cli_opts
+            .value_of("shell")
+            .and_then(|shell| shell.parse::<Shell>().ok())
+            .ok_or_else(|| anyhow!("Expected a shell"))?;
+        return completions_cmd(shell, &mut app());
+    }
+
+    let cfg = cfg(&matches)?;
+    let result = match invoke_subcommand(&matches, &cfg) {
+        // Only the local daemon can be started.
+        Err(e)
+            if is_connection_error(&e)
+                && selected_context(&matches).is_none()
+                && !matches.is_present("ssh")
+                && !matches.is_present("offline")
+                && should_start_daemon() =>
+        {
+            start_daemon(cfg.daemon()?)?;
+            invoke_subcommand(&matches, &cfg)
+        }
+        result => result,
+    };
+    result.map_err(|e| handle_daemon_offline(e, cfg.verbose > 0))?;
+    Ok(())
+}
+
+/// Builds the command line interface of the client.
+fn app() -> App<'static, 'static> {
+    App::new("cartel")
         .version(crate_version!())
         .about("Development workflow service orchestrator")
         .setting(AppSettings::SubcommandRequiredElseHelp)
@@ -52,6 +104,43 @@ pub fn cli_app() -> Result<()> {
                 .multiple(true)
                 .help("Sets the level of verbosity"),
         )
+        .arg(
+            Arg::with_name("context")
+                .long("context")
+                .value_name("NAME")
+                .help(
+                    "The daemon context to use (overrides the \
+                    client.current_context option)",
+                )
+                .takes_value(true)
+                .multiple(false),
+        )
+        .arg(
+            Arg::with_name("ssh")
+                .long("ssh")
+                .value_name("DESTINATION")
+                .help(
+                    "Reach the daemon through an ssh tunnel to the given \
+                    host (eg. user@devbox)",
+                )
+                .takes_value(true)
+                .multiple(false),
+        )
+        .arg(Arg::with_name("offline").long("offline").help(
+            "Run without connecting to the daemon, for commands \
+                    which do not need it (eg. export, run, shell)",
+        ))
+        .arg(
+            Arg::with_name("timeout")
+                .long("timeout")
+                .value_name("SECS")
+                .help(
+                    "Seconds to wait for the daemon to respond (overrides \
+                    the client.request_timeout_secs option)",
+                )
+                .takes_value(true)
+                .multiple(false),
+        )
         .subcommand(
             SubCommand::with_name("deploy")
                 .about("Deploys a module (and it's dependencies)")
@@ -62,6 +151,16 @@ pub fn cli_app() -> Result<()> {
                         .short("f")
                         .long("force"),
                 )
+                .arg(
+                    Arg::with_name("force_selected")
+                        .long("force-selected")
+                        .conflicts_with("force")
+                        .help(
+                            "Force deploy only the given modules (and the \
+                            services of given groups), while the rest are \
+                            only deployed if needed",
+                        ),
+                )
                 .arg(
                     Arg::with_name("env")
                         .short("e")
@@ -80,16 +179,34 @@ pub fn cli_app() -> Result<()> {
                 )
                 .arg(
                     Arg::with_name("modules")
-                        .help("Modules to deploy")
+                        .help(
+                            "Modules to deploy (groups may be given as \
+                            @group)",
+                        )
                         .multiple(true)
                         .takes_value(true),
                 )
+                .arg(
+                    Arg::with_name("list_groups")
+                        .long("list-groups")
+                        .conflicts_with("modules")
+                        .help("Prints the names of the groups, one per line"),
+                )
                 .arg(
                     Arg::with_name("skip_checks")
                         .short("z")
                         .long("no-checks")
                         .help("Disables running checks"),
                 )
+                .arg(
+                    Arg::with_name("checks")
+                        .long("checks")
+                        .conflicts_with("skip_checks")
+                        .help(
+                            "Runs checks (overrides the client.skip_checks \
+                            option)",
+                        ),
+                )
                 .arg(
                     Arg::with_name("only_selected")
                         .short("o")
@@ -103,6 +220,15 @@ pub fn cli_app() -> Result<()> {
                         .conflicts_with("skip_readiness_checks")
                         .help("Waits for all readiness checks to complete"),
                 )
+                .arg(
+                    Arg::with_name("no_wait")
+                        .long("no-wait")
+                        .conflicts_with("wait")
+                        .help(
+                            "Does not wait for all readiness checks \
+                            (overrides the client.default_wait option)",
+                        ),
+                )
                 .arg(
                     Arg::with_name("serial")
                         .short("k")
@@ -125,18 +251,93 @@ pub fn cli_app() -> Result<()> {
                         .short("s")
                         .long("no-readiness")
                         .help("Disables running readiness checks"),
+                )
+                .arg(
+                    Arg::with_name("changed")
+                        .long("changed")
+                        .conflicts_with_all(&["force", "only_selected"])
+                        .help(
+                            "Only deploys the services which changed (or \
+                            are not running or healthy), and the tasks \
+                            depending on them",
+                        ),
+                )
+                .arg(Arg::with_name("open").long("open").help(
+                    "Opens the URLs of the selected services in the browser \
+                    once they are ready",
+                ))
+                .arg(
+                    Arg::with_name("wait_for_lock").long("wait-for-lock").help(
+                        "Waits for another client deploying to the daemon to \
+                    finish, rather than failing",
+                    ),
+                )
+                .arg(Arg::with_name("timings").long("timings").help(
+                    "Prints how long each module took to deploy, and the \
+                            critical path through the dependencies",
+                ))
+                .arg(
+                    Arg::with_name("resume")
+                        .long("resume")
+                        .conflicts_with("only_selected")
+                        .help(
+                            "Resumes the last deploy of the same modules \
+                            which did not complete, skipping the checks and \
+                            the modules it deployed",
+                        ),
+                )
+                .arg(Arg::with_name("keep_going").long("keep-going").help(
+                    "Keeps deploying the modules which do not depend \
+                            on a failed one, and prints a summary at the end",
+                ))
+                .arg(Arg::with_name("dry_run").long("dry-run").help(
+                    "Prints the order modules would be deployed in \
+                            and the planned action for each, without \
+                            deploying anything",
+                ))
+                .arg(
+                    Arg::with_name("confirm")
+                        .long("confirm")
+                        .conflicts_with("dry_run")
+                        .help(
+                            "Prints the planned action for each module and \
+                            asks before deploying",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("no_confirm")
+                        .long("no-confirm")
+                        .conflicts_with("confirm")
+                        .help(
+                            "Deploys without asking (overrides the \
+                            client.confirm_deploy option)",
+                        ),
                 ),
         )
         .subcommand(
             SubCommand::with_name("run")
                 .visible_alias("r")
-                .about("Runs a task (but NOT it's dependencies)")
+                .about("Runs tasks (but NOT their dependencies)")
                 .arg(
                     Arg::with_name("task")
-                        .help("The task ro run")
-                        .multiple(false)
+                        .help("The tasks to run")
+                        .multiple(true)
+                        .required(true)
                         .takes_value(true),
-                ),
+                )
+                .arg(
+                    Arg::with_name("parallel")
+                        .short("p")
+                        .long("parallel")
+                        .help(
+                            "Runs the tasks concurrently rather than one \
+                            after the other (stopping at the first failure)",
+                        ),
+                )
+                .arg(Arg::with_name("with_deps").long("with-deps").help(
+                    "Deploys the dependencies of the tasks (as deploy \
+                    does) before running them",
+                )),
         )
         .subcommand(
             SubCommand::with_name("shell")
@@ -149,10 +350,58 @@ pub fn cli_app() -> Result<()> {
                         .help("The shell type to open")
                         .takes_value(true),
                 )
+                .arg(
+                    Arg::with_name("list")
+                        .short("l")
+                        .long("list")
+                        .help(
+                            "List the shells of all services (or of the \
+                            given service) instead of opening one",
+                        )
+                        .conflicts_with("type")
+                        .takes_value(false),
+                )
+                .arg(
+                    Arg::with_name("service_env")
+                        .long("service-env")
+                        .help(
+                            "Run the shell with the environment the running \
+                            service was spawned with",
+                        )
+                        .takes_value(false),
+                )
                 .arg(
                     Arg::with_name("service")
                         .help("The service to open a shell for")
                         .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("args")
+                        .help(
+                            "Arguments substituted into the command of the \
+                            shell (eg. -- -c 'select 1')",
+                        )
+                        .multiple(true)
+                        .last(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("tmux")
+                .about(
+                    "Open a tmux session with a pane following the logs of \
+                    each service",
+                )
+                .arg(
+                    Arg::with_name("shells")
+                        .short("s")
+                        .long("shells")
+                        .help("Open the shell of services which define one")
+                        .takes_value(false),
+                )
+                .arg(
+                    Arg::with_name("group")
+                        .help("The group whose services to open panes for")
+                        .takes_value(true),
                 ),
         )
         .subcommand(
@@ -164,6 +413,144 @@ pub fn cli_app() -> Result<()> {
                         .long("no-color")
                         .help("Disable coloured output")
                         .takes_value(false),
+                )
+                .arg(
+                    Arg::with_name("quiet")
+                        .short("q")
+                        .long("quiet")
+                        .help("Only print the names of the services"),
+                )
+                .arg(
+                    Arg::with_name("status")
+                        .long("status")
+                        .help("Only list services with the given status")
+                        .takes_value(true)
+                        .possible_values(&[
+                            "running", "stopped", "exited", "waiting",
+                        ]),
+                )
+                .arg(
+                    Arg::with_name("filter")
+                        .long("filter")
+                        .value_name("FILTER")
+                        .help(
+                            "Only list services matching the filter (eg. \
+                            name=api*)",
+                        )
+                        .takes_value(true)
+                        .number_of_values(1)
+                        .multiple(true),
+                )
+                .arg(
+                    Arg::with_name("sort")
+                        .long("sort")
+                        .help("The order to list services in")
+                        .takes_value(true)
+                        .possible_values(&["uptime", "cpu", "mem", "name"]),
+                )
+                .arg(
+                    Arg::with_name("port")
+                        .long("port")
+                        .value_name("PORT")
+                        .help("Only list the service listening on the port")
+                        .takes_value(true),
+                )
+                .arg(Arg::with_name("all").short("a").long("all").help(
+                    "Also list services which are defined but were never \
+                    deployed",
+                ))
+                .arg(Arg::with_name("by-group").long("by-group").help(
+                    "List services under the groups they belong to, \
+                            with a summary of each group",
+                ))
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .help(
+                            "The output format, where wide also shows \
+                            restarts and the last exit code",
+                        )
+                        .takes_value(true)
+                        .possible_values(&["wide", "json"]),
+                )
+                .arg(Arg::with_name("timestamps").long("timestamps").help(
+                    "Show when services were started (or exited) as local \
+                    timestamps",
+                ))
+                .arg(
+                    Arg::with_name("columns")
+                        .long("columns")
+                        .value_name("COLUMNS")
+                        .help(
+                            "The columns to show, separated by commas (eg. \
+                            pid,name,cpu,mem,ports,uptime)",
+                        )
+                        .takes_value(true)
+                        .conflicts_with("output"),
+                )
+                .arg(
+                    Arg::with_name("watch")
+                        .short("w")
+                        .long("watch")
+                        .value_name("SECS")
+                        .help(
+                            "Redraw the list every SECS seconds (2 by \
+                            default), until interrupted",
+                        )
+                        .takes_value(true)
+                        .min_values(0)
+                        .max_values(1),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("attach")
+                .about("Print the output of a running service as it is logged")
+                .arg(
+                    Arg::with_name("stdin")
+                        .long("stdin")
+                        .help(
+                            "Also send the lines typed to the input of the \
+                            service, which has to be interactive",
+                        )
+                        .takes_value(false),
+                )
+                .arg(
+                    Arg::with_name("service")
+                        .help("The service to attach to")
+                        .required(true)
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("forward")
+                .about("Forward a port to a running service until it stops")
+                .after_help(
+                    "The port is forwarded by the daemon, using `socat` or \
+                    `ssh -L` when --via is given. The remote side is either \
+                    a port or a host:port pair.\n\n\
+                    Example: cartel forward postgres 5433:5432",
+                )
+                .arg(
+                    Arg::with_name("via")
+                        .long("via")
+                        .help(
+                            "Forward through an ssh destination \
+                            ([user@]host) instead",
+                        )
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("service")
+                        .help("The service to forward a port to")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("ports")
+                        .help("The ports to forward (local:remote)")
+                        .required(true)
+                        .takes_value(true),
                 ),
         )
         .subcommand(
@@ -193,6 +580,61 @@ pub fn cli_app() -> Result<()> {
                         .help("Print the full logs")
                         .takes_value(false),
                 )
+                .arg(
+                    Arg::with_name("pretty_json")
+                        .long("pretty-json")
+                        .short("j")
+                        .help(
+                            "Render JSON log lines in a readable form (does \
+                            not use a pager)",
+                        )
+                        .takes_value(false),
+                )
+                .arg(
+                    Arg::with_name("grep")
+                        .long("grep")
+                        .short("g")
+                        .conflicts_with_all(&["follow", "all"])
+                        .help("Print only the lines matching a regex")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("run")
+                        .long("run")
+                        .conflicts_with_all(&["grep", "since", "until"])
+                        .help(
+                            "Print the logs of a previous run of a task (1 \
+                            being the last run)",
+                        )
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("last")
+                        .long("last")
+                        .conflicts_with_all(&["run", "grep", "since", "until"])
+                        .help("Print the logs of the last run of a task")
+                        .takes_value(false),
+                )
+                .arg(
+                    Arg::with_name("since")
+                        .long("since")
+                        .conflicts_with_all(&["follow", "all"])
+                        .help(
+                            "Print only the lines logged since a time (eg. \
+                            10m, 2h or 2021-08-28 10:00:00)",
+                        )
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("until")
+                        .long("until")
+                        .conflicts_with_all(&["follow", "all"])
+                        .help(
+                            "Print only the lines logged until a time (eg. \
+                            10m, 2h or 2021-08-28 10:00:00)",
+                        )
+                        .takes_value(true),
+                )
                 .arg(
                     Arg::with_name("service")
                         .help("The service to print the logs of")
@@ -211,7 +653,23 @@ pub fn cli_app() -> Result<()> {
                 ),
         )
         .subcommand(
-            SubCommand::with_name("down").about("Stop all running services"),
+            SubCommand::with_name("down")
+                .about("Stop all running services")
+                .arg(
+                    Arg::with_name("wait_for_lock").long("wait-for-lock").help(
+                        "Waits for another client deploying to the daemon to \
+                    finish, rather than failing",
+                    ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("lock")
+                .about("Inspect the deployment lock of the daemon")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("status")
+                        .about("Show who holds the deployment lock"),
+                ),
         )
         .subcommand(
             SubCommand::with_name("restart")
@@ -223,12 +681,164 @@ pub fn cli_app() -> Result<()> {
                         .takes_value(true),
                 ),
         )
+        .subcommand(
+            SubCommand::with_name("rollback")
+                .about(
+                    "Redeploy services with the definitions they were \
+                    deployed with before",
+                )
+                .arg(
+                    Arg::with_name("services")
+                        .help("Services to roll back")
+                        .multiple(true)
+                        .takes_value(true)
+                        .required_unless("last_deploy"),
+                )
+                .arg(
+                    Arg::with_name("last_deploy")
+                        .long("last-deploy")
+                        .help(
+                            "Roll back the services changed by the last deploy",
+                        )
+                        .conflicts_with("services"),
+                )
+                .arg(
+                    Arg::with_name("wait_for_lock").long("wait-for-lock").help(
+                        "Waits for another client deploying to the daemon to \
+                    finish, rather than failing",
+                    ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("task")
+                .about("Inspect the runs of tasks")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("history")
+                        .about(
+                            "Lists the recent runs of a task, when they ran \
+                            and how they exited",
+                        )
+                        .arg(
+                            Arg::with_name("task")
+                                .help("The task to list the runs of")
+                                .required(true)
+                                .takes_value(true),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("events")
+                .about(
+                    "Lists the shells opened and commands run for services, \
+                    newest first",
+                )
+                .arg(
+                    Arg::with_name("service")
+                        .help("Only list the events of this service")
+                        .takes_value(true),
+                ),
+        )
         .subcommand(
             SubCommand::with_name("daemon")
                 .about("Control the daemon")
                 .subcommand(
                     SubCommand::with_name("restart")
                         .about("Restart the daemon"),
+                )
+                .subcommand(SubCommand::with_name("refresh-env").about(
+                    "Grab the environment of the login shell now and \
+                        show what changed",
+                ))
+                .subcommand(SubCommand::with_name("env-status").about(
+                    "Show when the environment of the login shell was \
+                        last grabbed",
+                ))
+                .subcommand(
+                    SubCommand::with_name("upgrade")
+                        .about(
+                            "Replace the daemon with a new executable, \
+                            keeping its services running",
+                        )
+                        .arg(
+                            Arg::with_name("executable")
+                                .long("executable")
+                                .value_name("PATH")
+                                .help(
+                                    "The daemon executable to upgrade to \
+                                    (defaults to the one next to the client, \
+                                    or on the PATH)",
+                                )
+                                .takes_value(true),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("logs")
+                        .about("View the logs of the daemon itself")
+                        .arg(
+                            Arg::with_name("follow")
+                                .short("f")
+                                .long("follow")
+                                .help("Follow the logs"),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("doctor")
+                .about("Check the health of the internals of the daemon"),
+        )
+        .subcommand(
+            SubCommand::with_name("open")
+                .about("Open the URL of a service in the browser once ready")
+                .arg(
+                    Arg::with_name("service")
+                        .help("The service to open")
+                        .required(true)
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("env")
+                .about("Print the environment variables of a module")
+                .arg(
+                    Arg::with_name("module")
+                        .help("The service, task or shell to print the env of")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(Arg::with_name("diff").long("diff").help(
+                    "Compare the environment of the running service \
+                            with its current definition",
+                ))
+                .arg(
+                    Arg::with_name("export")
+                        .long("export")
+                        .conflicts_with("diff")
+                        .help(
+                            "Print the resolved environment as statements a \
+                            shell can evaluate",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .requires("export")
+                        .takes_value(true)
+                        .possible_values(&["sh", "fish", "dotenv"])
+                        .help(
+                            "The format to export the environment in \
+                            (default: sh)",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("env")
+                        .short("e")
+                        .long("env")
+                        .help("Environment set to activate")
+                        .takes_value(true)
+                        .require_delimiter(true)
+                        .value_delimiter("\0")
+                        .multiple(true),
                 ),
         )
         .subcommand(
@@ -249,6 +859,142 @@ pub fn cli_app() -> Result<()> {
                         .multiple(true),
                 ),
         )
+        .subcommand(
+            SubCommand::with_name("import")
+                .about("Imports module definitions from other tools")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("compose")
+                        .about(
+                            "Converts a docker-compose file into module \
+                            definitions",
+                        )
+                        .arg(
+                            Arg::with_name("file")
+                                .help(
+                                    "The compose file to import (defaults \
+                                    to docker-compose.yml)",
+                                )
+                                .takes_value(true)
+                                .multiple(false),
+                        )
+                        .arg(
+                            Arg::with_name("output")
+                                .long("output")
+                                .value_name("FILE")
+                                .help(
+                                    "The module definitions file to write \
+                                    (or merge) into",
+                                )
+                                .default_value("cartel.yml")
+                                .takes_value(true),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("procfile")
+                        .about("Converts a Procfile into module definitions")
+                        .arg(
+                            Arg::with_name("file")
+                                .help(
+                                    "The Procfile to import (defaults to \
+                                    Procfile)",
+                                )
+                                .takes_value(true)
+                                .multiple(false),
+                        )
+                        .arg(
+                            Arg::with_name("output")
+                                .long("output")
+                                .value_name("FILE")
+                                .help(
+                                    "The module definitions file to write \
+                                    (or merge) into",
+                                )
+                                .default_value("cartel.yml")
+                                .takes_value(true),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("export")
+                .about("Exports module definitions for use by other tools")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("procfile")
+                        .about("Exports the services as a Procfile")
+                        .arg(
+                            Arg::with_name("output")
+                                .long("output")
+                                .value_name("FILE")
+                                .help(
+                                    "The file to write to (printed if not \
+                                    given)",
+                                )
+                                .takes_value(true),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("systemd")
+                        .about("Exports the services as systemd units")
+                        .arg(Arg::with_name("user").long("user").help(
+                            "Exports user units (written to the \
+                                    user unit directory by default)",
+                        ))
+                        .arg(
+                            Arg::with_name("output_dir")
+                                .long("output-dir")
+                                .value_name("DIR")
+                                .help("The directory to write the units to")
+                                .takes_value(true),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("k8s")
+                        .about(
+                            "Exports the services as Kubernetes Deployments \
+                            and Services",
+                        )
+                        .arg(
+                            Arg::with_name("image")
+                                .long("image")
+                                .value_name("IMAGE")
+                                .help(
+                                    "The container image, where {name} is \
+                                    replaced by the service name (default: \
+                                    {name}:latest)",
+                                )
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name("output")
+                                .long("output")
+                                .value_name("FILE")
+                                .help(
+                                    "The file to write to (printed if not \
+                                    given)",
+                                )
+                                .takes_value(true),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("vscode")
+                        .about(
+                            "Exports VS Code tasks and launch configurations \
+                            for the modules",
+                        )
+                        .arg(
+                            Arg::with_name("output_dir")
+                                .long("output-dir")
+                                .value_name("DIR")
+                                .help(
+                                    "The directory to write tasks.json and \
+                                    launch.json to",
+                                )
+                                .default_value(".vscode")
+                                .takes_value(true),
+                        ),
+                ),
+        )
         .subcommand(
             SubCommand::with_name("config")
                 .about("Update configuration")
@@ -310,14 +1056,49 @@ pub fn cli_app() -> Result<()> {
                 )
                 .subcommand(SubCommand::with_name("view").about(
                     "View all currently set/unset configuration options",
-                )),
+                ))
+                .subcommand(SubCommand::with_name("list").about(
+                    "List all configuration options with their values, \
+                    defaults and descriptions",
+                ))
+                .subcommand(
+                    SubCommand::with_name("use-context")
+                        .about("Sets the daemon context used by default")
+                        .arg(
+                            Arg::with_name("name")
+                                .help(
+                                    "The name of the context (or 'default' \
+                                    for the local daemon)",
+                                )
+                                .required(true)
+                                .takes_value(true)
+                                .multiple(false),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("contexts")
+                        .about("Lists the available daemon contexts"),
+                )
+                .subcommand(SubCommand::with_name("edit").about(
+                    "Opens the config file in $EDITOR, validating it once \
+                    saved",
+                ))
+                .subcommand(
+                    SubCommand::with_name("path")
+                        .about("Prints the location of the config file"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("completions")
+                .about("Prints the shell completion script")
+                .arg(
+                    Arg::with_name("shell")
+                        .help("The shell to complete in")
+                        .possible_values(&["bash", "zsh", "fish"])
+                        .required(true)
+                        .takes_value(true),
+                ),
         )
-        .get_matches();
-
-    let cfg = cfg(&matches)?;
-    invoke_subcommand(&matches, &cfg)
-        .map_err(|e| handle_daemon_offline(e, cfg.verbose > 0))?;
-    Ok(())
 }
 
 fn cfg(matches: &ArgMatches) -> Result<ClientConfig> {
@@ -327,12 +1108,6 @@ fn cfg(matches: &ArgMatches) -> Result<ClientConfig> {
     let follow_pager_cmd =
         parse_cmd_from_env("CARTEL_FOLLOW_LOG_PAGER", "less +F")?;
 
-    let daemon_url = config::PERSISTED_CONFIG
-        .daemon
-        .port
-        .as_ref()
-        .map(|port| format!("http://localhost:{}/api/v1", port));
-
     let default_dir = config::PERSISTED_CONFIG.client.default_dir.clone();
 
     Ok(ClientConfig {
@@ -343,11 +1118,85 @@ fn cfg(matches: &ArgMatches) -> Result<ClientConfig> {
         full_pager_cmd,
         follow_pager_cmd,
         default_dir,
-        daemon_url: daemon_url
-            .unwrap_or_else(|| String::from("http://localhost:13754/api/v1")),
+        daemon: if matches.is_present("offline") {
+            None
+        } else {
+            Some(daemon(matches)?)
+        },
     })
 }
 
+/// Resolves the daemon to talk to from the selected context.
+///
+/// Unless a context is selected the local daemon is used. With `--ssh` (or
+/// the `ssh` option of the context) the daemon is reached through an ssh
+/// tunnel, with the URL of the daemon resolved on the remote host.
+fn daemon(matches: &ArgMatches) -> Result<CartelApiClient> {
+    let persisted = &config::PERSISTED_CONFIG;
+    let (url, auth_token, ca_cert, ssh) = match selected_context(matches) {
+        Some(name) => {
+            let context = persisted.contexts.get(name).ok_or_else(|| {
+                anyhow!(
+                    "Unknown context '{}'. Run `cartel config contexts` to \
+                    list the available contexts",
+                    name
+                )
+            })?;
+            (
+                context.url.trim_end_matches('/').to_string(),
+                context.auth_token.clone(),
+                context.ca_cert.clone(),
+                matches.value_of("ssh").or(context.ssh.as_deref()),
+            )
+        }
+        None => {
+            // Unless a port is configured, look for a running daemon, as it
+            // may have been started with `--port`.
+            let port = match matches.value_of("ssh") {
+                Some(_) => persisted.daemon.port,
+                None => persisted.daemon.port.or_else(discovery::discover_port),
+            };
+            (
+                format!(
+                    "http://localhost:{}",
+                    port.unwrap_or(config::DEFAULT_DAEMON_PORT)
+                ),
+                persisted.daemon.auth_token.clone(),
+                None,
+                matches.value_of("ssh"),
+            )
+        }
+    };
+
+    let (url, tunnel) = match ssh {
+        Some(destination) => {
+            let remote = Url::parse(&url)
+                .with_context(|| format!("Invalid daemon URL '{}'", url))?;
+            let tunnel = SshTunnel::open(
+                destination,
+                remote.host_str().unwrap_or("localhost"),
+                remote
+                    .port_or_known_default()
+                    .unwrap_or(config::DEFAULT_DAEMON_PORT),
+            )?;
+            (format!("http://127.0.0.1:{}", tunnel.port), Some(tunnel))
+        }
+        None => (url, None),
+    };
+
+    let endpoint = Endpoint {
+        url: format!("{}/api/v1", url),
+        auth_token,
+        ca_cert,
+        tunnel,
+    };
+    CartelApiClient::new(
+        endpoint,
+        RetryPolicy::from(&persisted.client),
+        timeouts(matches)?,
+    )
+}
+
 fn parse_cmd_from_env(env: &str, default: &str) -> Result<Vec<String>> {
     #[allow(clippy::or_fun_call)]
     let cmd_str = env::var(env).unwrap_or(default.to_string());
@@ -366,22 +1215,44 @@ fn parse_cmd_from_env(env: &str, default: &str) -> Result<Vec<String>> {
 
 fn invoke_subcommand(matches: &ArgMatches, cfg: &ClientConfig) -> Result<()> {
     match matches.subcommand() {
+        ("deploy", Some(deploy_cli_opts))
+            if deploy_cli_opts.is_present("list_groups") =>
+        {
+            list_groups_cmd(cfg)?;
+        }
         ("deploy", Some(deploy_cli_opts)) => {
             let modules_to_deploy = deploy_cli_opts
                 .values_of("modules")
                 .ok_or_else(|| anyhow!("Expected at least one module"))?
                 .collect();
-            let options = DeployOptions::from(deploy_cli_opts);
-            deploy_cmd(modules_to_deploy, cfg, &options)?;
+            let project = read_project_config(cfg)?;
+            let mut options = DeployOptions::from(
+                deploy_cli_opts,
+                &config::PERSISTED_CONFIG.client,
+                project.as_ref(),
+            );
+            deploy_cmd(modules_to_deploy, cfg, &mut options)?;
         }
         ("run", Some(run_cli_opts)) => {
-            let task_name = run_cli_opts
-                .value_of("task")
-                .ok_or_else(|| anyhow!("Expected task name"))?;
-            run_task_cmd(task_name, cfg)?;
+            let task_names: Vec<_> = run_cli_opts
+                .values_of("task")
+                .ok_or_else(|| anyhow!("Expected task name"))?
+                .collect();
+            let parallel = run_cli_opts.is_present("parallel");
+            let mut deploy_options = if run_cli_opts.is_present("with_deps") {
+                let project = read_project_config(cfg)?;
+                Some(DeployOptions::from(
+                    run_cli_opts,
+                    &config::PERSISTED_CONFIG.client,
+                    project.as_ref(),
+                ))
+            } else {
+                None
+            };
+            run_tasks_cmd(&task_names, parallel, deploy_options.as_mut(), cfg)?;
         }
         ("ps", Some(ps_opts)) => {
-            let opts = PsOpts::from(ps_opts);
+            let opts = PsOpts::from(ps_opts, &config::PERSISTED_CONFIG.client)?;
             list_modules_cmd(&opts, cfg)?;
         }
         ("stop", Some(stop_cli_opts)) => {
@@ -391,8 +1262,13 @@ fn invoke_subcommand(matches: &ArgMatches, cfg: &ClientConfig) -> Result<()> {
                 .collect();
             stop_service_cmd(modules_to_stop, cfg)?;
         }
-        ("down", Some(_down_cli_opts)) => {
-            down_cmd(cfg)?;
+        ("down", Some(down_cli_opts)) => {
+            down_cmd(cfg, down_cli_opts.is_present("wait_for_lock"))?;
+        }
+        ("lock", Some(lock_cli_opts)) => {
+            if let ("status", _) = lock_cli_opts.subcommand() {
+                lock_status_cmd(cfg)?;
+            }
         }
         ("restart", Some(restart_cli_opts)) => {
             let module_to_restart = restart_cli_opts
@@ -400,12 +1276,60 @@ fn invoke_subcommand(matches: &ArgMatches, cfg: &ClientConfig) -> Result<()> {
                 .ok_or_else(|| anyhow!("Expected service name"))?;
             restart_module_cmd(module_to_restart, cfg)?;
         }
+        ("rollback", Some(rollback_cli_opts)) => {
+            let services: Vec<String> = rollback_cli_opts
+                .values_of("services")
+                .map(|it| it.map(String::from).collect())
+                .unwrap_or_default();
+            rollback_cmd(
+                &services,
+                cfg,
+                rollback_cli_opts.is_present("wait_for_lock"),
+            )?;
+        }
+        ("shell", Some(shell_cli_opts))
+            if shell_cli_opts.is_present("list") =>
+        {
+            list_shells_cmd(shell_cli_opts.value_of("service"), cfg)?;
+        }
         ("shell", Some(shell_cli_opts)) => {
             let service_name = shell_cli_opts
                 .value_of("service")
                 .ok_or_else(|| anyhow!("Expected service name"))?;
             let shell_type = shell_cli_opts.value_of("type");
-            open_shell(service_name, shell_type, cfg)?;
+            let args: Vec<_> = shell_cli_opts
+                .values_of("args")
+                .map(|args| args.collect())
+                .unwrap_or_default();
+            let service_env = shell_cli_opts.is_present("service_env");
+            open_shell(service_name, shell_type, &args, service_env, cfg)?;
+        }
+        ("tmux", Some(tmux_cli_opts)) => {
+            tmux_cmd(
+                tmux_cli_opts.value_of("group"),
+                tmux_cli_opts.is_present("shells"),
+                cfg,
+            )?;
+        }
+        ("attach", Some(attach_cli_opts)) => {
+            let service_name = attach_cli_opts
+                .value_of("service")
+                .ok_or_else(|| anyhow!("Expected service name"))?;
+            attach_cmd(service_name, attach_cli_opts.is_present("stdin"), cfg)?;
+        }
+        ("forward", Some(forward_cli_opts)) => {
+            let service_name = forward_cli_opts
+                .value_of("service")
+                .ok_or_else(|| anyhow!("Expected service name"))?;
+            let ports = forward_cli_opts
+                .value_of("ports")
+                .ok_or_else(|| anyhow!("Expected ports"))?;
+            forward_cmd(
+                service_name,
+                ports,
+                forward_cli_opts.value_of("via"),
+                cfg,
+            )?;
         }
         ("logs", Some(logs_cli_opts)) => {
             let module_name = logs_cli_opts
@@ -414,18 +1338,99 @@ fn invoke_subcommand(matches: &ArgMatches, cfg: &ClientConfig) -> Result<()> {
             let follow = logs_cli_opts.is_present("follow");
             let all = logs_cli_opts.is_present("all");
 
-            let mode = if follow {
-                LogMode::FOLLOW
-            } else if all {
-                LogMode::FULL
-            } else {
-                LogMode::DEFAULT
+            let pretty_json = logs_cli_opts.is_present("pretty_json");
+            let filter = LogFilter {
+                pattern: logs_cli_opts.value_of("grep"),
+                since: logs_cli_opts
+                    .value_of("since")
+                    .map(parse_log_time)
+                    .transpose()?,
+                until: logs_cli_opts
+                    .value_of("until")
+                    .map(parse_log_time)
+                    .transpose()?,
             };
 
-            print_logs(module_name, mode, cfg)?;
+            if filter.pattern.is_some()
+                || filter.since.is_some()
+                || filter.until.is_some()
+            {
+                search_logs(module_name, &filter, pretty_json, cfg)?;
+            } else {
+                let mode = if follow {
+                    LogMode::FOLLOW
+                } else if all {
+                    LogMode::FULL
+                } else {
+                    LogMode::DEFAULT
+                };
+
+                let run = if logs_cli_opts.is_present("last") {
+                    Some(1)
+                } else {
+                    logs_cli_opts
+                        .value_of("run")
+                        .map(|run| {
+                            run.parse::<usize>().map_err(|_| {
+                                anyhow!("Expected a run number, got '{}'", run)
+                            })
+                        })
+                        .transpose()?
+                };
+
+                if pretty_json {
+                    print_pretty_logs(module_name, mode, run, cfg)?;
+                } else {
+                    print_logs(module_name, mode, run, cfg)?;
+                }
+            }
         }
-        ("daemon", _) => {
-            restart_daemon()?;
+        ("task", Some(task_cli_opts)) => {
+            if let ("history", Some(opts)) = task_cli_opts.subcommand() {
+                task_history_cmd(opts.value_of("task").unwrap(), cfg)?;
+            }
+        }
+        ("events", Some(events_cli_opts)) => {
+            events_cmd(events_cli_opts.value_of("service"), cfg)?;
+        }
+        ("daemon", Some(daemon_cli_opts)) => match daemon_cli_opts.subcommand()
+        {
+            ("refresh-env", _) => refresh_env_cmd(cfg)?,
+            ("env-status", _) => env_status_cmd(cfg)?,
+            ("upgrade", Some(upgrade_cli_opts)) => upgrade_daemon_cmd(
+                upgrade_cli_opts.value_of("executable"),
+                cfg,
+            )?,
+            ("logs", Some(logs_cli_opts)) => {
+                daemon_logs_cmd(logs_cli_opts.is_present("follow"), cfg)?
+            }
+            _ => restart_daemon()?,
+        },
+        ("doctor", Some(_)) => doctor_cmd(cfg)?,
+        ("open", Some(open_cli_opts)) => {
+            let service = open_cli_opts
+                .value_of("service")
+                .ok_or_else(|| anyhow!("Expected service name"))?;
+            open_cmd(service, cfg)?;
+        }
+        ("env", Some(env_cli_opts)) => {
+            let module = env_cli_opts
+                .value_of("module")
+                .ok_or_else(|| anyhow!("Expected module name"))?;
+            let project = read_project_config(cfg)?;
+            let options = DeployOptions::from(
+                env_cli_opts,
+                &config::PERSISTED_CONFIG.client,
+                project.as_ref(),
+            );
+            if env_cli_opts.is_present("diff") {
+                env_diff_cmd(module, &options, cfg)?;
+            } else if env_cli_opts.is_present("export") {
+                let format = env_cli_opts.value_of("format").unwrap_or("sh");
+                env_export_cmd(module, format, &options, cfg)?;
+            } else {
+                env_cmd(module, &options, cfg)?;
+            }
         }
         ("exec", Some(exec_cli_opts)) => {
             let service = exec_cli_opts.value_of("service").unwrap();
@@ -436,27 +1441,47 @@ fn invoke_subcommand(matches: &ArgMatches, cfg: &ClientConfig) -> Result<()> {
 
             exec_cmd(service, &command, cfg)?;
         }
-        ("config", Some(config_cli_opts)) => {
-            match config_cli_opts.subcommand() {
-                ("set", Some(opts)) => {
-                    let key = opts.value_of("key").unwrap();
-                    let value = opts.value_of("value").unwrap();
-                    set_option(&key, &value)?;
+        ("import", Some(import_cli_opts)) => {
+            match import_cli_opts.subcommand() {
+                ("compose", Some(opts)) => {
+                    import_compose_cmd(
+                        opts.value_of("file"),
+                        opts.value_of("output").unwrap(),
+                    )?;
                 }
-                ("toggle", Some(opts)) => {
-                    let key = opts.value_of("key").unwrap();
-                    toggle_option(key)?;
+                ("procfile", Some(opts)) => {
+                    import_procfile_cmd(
+                        opts.value_of("file"),
+                        opts.value_of("output").unwrap(),
+                    )?;
+                }
+                _ => {}
+            }
+        }
+        ("export", Some(export_cli_opts)) => {
+            match export_cli_opts.subcommand() {
+                ("procfile", Some(opts)) => {
+                    export_procfile_cmd(cfg, opts.value_of("output"))?;
                 }
-                ("get", Some(opts)) => {
-                    let key = opts.value_of("key").unwrap();
-                    get_option(key)?;
+                ("systemd", Some(opts)) => {
+                    export_systemd_cmd(
+                        cfg,
+                        opts.is_present("user"),
+                        opts.value_of("output_dir"),
+                    )?;
                 }
-                ("remove", Some(opts)) => {
-                    let key = opts.value_of("key").unwrap();
-                    remove_option(key)?;
+                ("vscode", Some(opts)) => {
+                    export_vscode_cmd(
+                        cfg,
+                        opts.value_of("output_dir").unwrap(),
+                    )?;
                 }
-                ("view", _) => {
-                    view_all_options()?;
+                ("k8s", Some(opts)) => {
+                    export_k8s_cmd(
+                        cfg,
+                        opts.value_of("image"),
+                        opts.value_of("output"),
+                    )?;
                 }
                 _ => {}
             }
@@ -466,14 +1491,79 @@ fn invoke_subcommand(matches: &ArgMatches, cfg: &ClientConfig) -> Result<()> {
     Ok(())
 }
 
+fn invoke_config_subcommand(config_cli_opts: &ArgMatches) -> Result<()> {
+    match config_cli_opts.subcommand() {
+        ("set", Some(opts)) => {
+            let key = opts.value_of("key").unwrap();
+            let value = opts.value_of("value").unwrap();
+            set_option(key, value)?;
+        }
+        ("toggle", Some(opts)) => {
+            let key = opts.value_of("key").unwrap();
+            toggle_option(key)?;
+        }
+        ("get", Some(opts)) => {
+            let key = opts.value_of("key").unwrap();
+            get_option(key)?;
+        }
+        ("remove", Some(opts)) => {
+            let key = opts.value_of("key").unwrap();
+            remove_option(key)?;
+        }
+        ("view", _) => {
+            view_all_options()?;
+        }
+        ("list", _) => {
+            list_options()?;
+        }
+        ("use-context", Some(opts)) => {
+            let name = opts.value_of("name").unwrap();
+            use_context(name)?;
+        }
+        ("contexts", _) => {
+            list_contexts()?;
+        }
+        ("edit", _) => {
+            edit_config()?;
+        }
+        ("path", _) => {
+            print_config_path()?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn timeouts(matches: &ArgMatches) -> Result<Timeouts> {
+    let timeout = matches
+        .value_of("timeout")
+        .map(|timeout| {
+            timeout.parse::<u64>().map_err(|_| {
+                anyhow!("Invalid timeout '{}', expected seconds", timeout)
+            })
+        })
+        .transpose()?;
+    Ok(Timeouts::from(&config::PERSISTED_CONFIG.client, timeout))
+}
+
+/// Returns the name of the selected context, unless it is the local daemon.
+fn selected_context<'a>(matches: &'a ArgMatches) -> Option<&'a str> {
+    matches
+        .value_of("context")
+        .or_else(|| config::PERSISTED_CONFIG.client.current_context.as_deref())
+        .filter(|name| *name != config::DEFAULT_CONTEXT)
+}
+
+fn is_connection_error(err: &Error) -> bool {
+    if let Some(req_err) = err.downcast_ref::<reqwest::Error>() {
+        req_err.is_connect()
+    } else {
+        false
+    }
+}
+
 fn handle_daemon_offline(err: Error, verbose: bool) -> Error {
-    let is_conn_err =
-        if let Some(req_err) = err.downcast_ref::<reqwest::Error>() {
-            req_err.is_connect()
-        } else {
-            false
-        };
-    if is_conn_err {
+    if is_connection_error(&err) {
         let msg = anyhow!(
             "Could not connect to daemon. \
                 Is the daemon running? (try `cartel daemon restart`)",
@@ -38,3 +38,18 @@ pub fn shell_to_cmd_interactive(shell_cmd: &str) -> Vec<String> {
     interactive_shell_cmd.push(String::from(shell_cmd));
     interactive_shell_cmd
 }
+
+/// Quotes a value so that a shell reads it as a single word.
+///
+/// Values consisting only of safe characters are returned as is.
+pub fn shell_quote(value: &str) -> String {
+    let is_safe = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:=@%+,".contains(c));
+    if is_safe {
+        value.to_string()
+    } else {
+        format!("'{}'", value.replace('\'', "'\\''"))
+    }
+}
@@ -0,0 +1,75 @@
+use crate::client::cli::ClientConfig;
+use crate::client::commands::logs::get_log_file;
+use anyhow::{bail, Result};
+use std::fs::File;
+use std::io::{self, BufRead, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+/// How often the log file of the service is checked for new output.
+const OUTPUT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Attaches the terminal to a running service, printing its output as it is
+/// logged.
+///
+/// With `stdin` the lines typed are also written to the input of the
+/// service, which has to be `interactive`. Detaching (with Ctrl-D, or Ctrl-C
+/// when only following output) leaves the service running.
+pub fn attach_cmd(
+    service_name: &str,
+    stdin: bool,
+    cfg: &ClientConfig,
+) -> Result<()> {
+    let daemon = cfg.daemon()?;
+    if stdin {
+        // Fails early if the service is not running or not interactive.
+        daemon.write_stdin(service_name, "")?;
+    }
+    let log_file = get_log_file(service_name, None, cfg)?;
+    if !Path::new(&log_file).exists() {
+        bail!("Log file not found for module {}", service_name);
+    }
+    let mut output = File::open(&log_file)?;
+    output.seek(SeekFrom::End(0))?;
+
+    if !stdin {
+        tprint!(
+            "Attached to {}, press Ctrl-C to detach",
+            cbold!(service_name)
+        );
+        return follow_output(output);
+    }
+
+    tprint!(
+        "Attached to {}, press Ctrl-D to detach",
+        cbold!(service_name)
+    );
+    thread::spawn(move || follow_output(output));
+    for line in io::stdin().lock().lines() {
+        daemon.write_stdin(service_name, &format!("{}\n", line?))?;
+    }
+    Ok(())
+}
+
+/// Prints the output of the service as it is written to its log file.
+///
+/// Output is printed as is, so that prompts not ending in a newline are
+/// shown too.
+fn follow_output(mut output: File) -> Result<()> {
+    let mut buf = [0; 8192];
+    loop {
+        if output.metadata()?.len() < output.stream_position()? {
+            // The log file was truncated (eg. the service was redeployed).
+            output.seek(SeekFrom::Start(0))?;
+        }
+        match output.read(&mut buf)? {
+            0 => thread::sleep(OUTPUT_POLL_INTERVAL),
+            n => {
+                let mut stdout = io::stdout();
+                stdout.write_all(&buf[..n])?;
+                stdout.flush()?;
+            }
+        }
+    }
+}
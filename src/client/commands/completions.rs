@@ -0,0 +1,45 @@
+use anyhow::Result;
+use clap::{App, Shell};
+use std::io::{self, Write};
+
+/// Completes the `@group` arguments of `deploy` with the names of the groups,
+/// on top of the completions generated by clap.
+const BASH_GROUP_COMPLETION: &str = r#"
+_cartel_with_groups() {
+    local line="${COMP_LINE:0:COMP_POINT}"
+    local word="${line##* }"
+    if [[ " ${COMP_WORDS[*]} " == *" deploy "* && "$word" == @* ]]; then
+        local groups
+        groups="$(cartel deploy --list-groups 2>/dev/null)"
+        # When '@' breaks words only the part after it gets replaced.
+        if [[ "$COMP_WORDBREAKS" == *@* ]]; then
+            COMPREPLY=($(compgen -W "$groups" -- "${word#@}"))
+        else
+            COMPREPLY=($(compgen -P @ -W "$groups" -- "${word#@}"))
+        fi
+        return 0
+    fi
+    _cartel "$@"
+}
+
+complete -F _cartel_with_groups -o bashdefault -o default cartel
+"#;
+
+const FISH_GROUP_COMPLETION: &str = r#"
+complete -c cartel -n "__fish_seen_subcommand_from deploy" -a "(cartel deploy --list-groups 2>/dev/null | string replace -r '^' '@')" -d 'Group'
+"#;
+
+/// Prints the completion script of the given shell.
+///
+/// For bash and fish the script also completes group names (prefixed with
+/// `@`) when deploying, by listing the groups of the current project.
+pub fn completions_cmd(shell: Shell, app: &mut App) -> Result<()> {
+    let mut stdout = io::stdout();
+    app.gen_completions_to("cartel", shell, &mut stdout);
+    match shell {
+        Shell::Bash => stdout.write_all(BASH_GROUP_COMPLETION.as_bytes())?,
+        Shell::Fish => stdout.write_all(FISH_GROUP_COMPLETION.as_bytes())?,
+        _ => {}
+    }
+    Ok(())
+}
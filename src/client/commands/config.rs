@@ -1,5 +1,10 @@
 use crate::config;
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
+use std::io::{self, Write};
+use std::process::Command;
+use std::{env, fs};
+use tabwriter::TabWriter;
+use text_io::read;
 
 pub fn set_option(key: &str, value: &str) -> Result<()> {
     let mut editable_cfg = config::read_persisted_config_as_editable()?;
@@ -25,27 +30,155 @@ pub fn remove_option(key: &str) -> Result<()> {
     Ok(())
 }
 
+/// Formats a value for display, noting when it is overridden by an
+/// environment variable.
+fn display_value(key: &str, value: Option<String>) -> String {
+    match config::env_override(key) {
+        Some(v) => format!(
+            "{} {}",
+            cbold!(v),
+            cdim!(format!("(from {})", config::env_var_name(key)))
+        ),
+        None => {
+            cbold!(value.unwrap_or_else(|| String::from("None"))).to_string()
+        }
+    }
+}
+
 pub fn get_option(key: &str) -> Result<()> {
     let editable_cfg = config::read_persisted_config_as_editable()?;
-    let value = editable_cfg.get_option(key)?;
-    match value {
-        Some(v) => tprint!("{} => {}", cbold!(key), cbold!(v)),
-        None => tprint!("{} => {}", cbold!(key), cbold!("None")),
-    }
+    let value = match key {
+        // Surface the resolved directory, as the option is typically unset.
+        "daemon.log_dir" => Some(
+            config::PERSISTED_CONFIG
+                .daemon
+                .log_dir()
+                .display()
+                .to_string(),
+        ),
+        _ => editable_cfg.get_option(key)?,
+    };
+    tprint!("{} => {}", cbold!(key), display_value(key, value));
     Ok(())
 }
 
 pub fn view_all_options() -> Result<()> {
     let editable_cfg = config::read_persisted_config_as_editable()?;
     for (key, val) in editable_cfg.get_all_options()? {
-        tprint!(
-            "{} => {}",
-            cbold!(key),
-            match val {
-                Some(v) => cbold!(v),
-                None => cbold!(String::from("None")),
-            }
-        )
+        let value = display_value(&key, val);
+        tprint!("{} => {}", cbold!(key), value)
     }
     Ok(())
 }
+
+pub fn use_context(name: &str) -> Result<()> {
+    let mut editable_cfg = config::read_persisted_config_as_editable()?;
+    editable_cfg.use_context(name)?;
+    editable_cfg.save()?;
+    tprint!("Using context {}", cbold!(name));
+    Ok(())
+}
+
+/// Prints the available daemon contexts, marking the one in use.
+pub fn list_contexts() -> Result<()> {
+    let editable_cfg = config::read_persisted_config_as_editable()?;
+    let current = config::env_override("client.current_context")
+        .or(editable_cfg.get_option("client.current_context")?)
+        .unwrap_or_else(|| String::from(config::DEFAULT_CONTEXT));
+
+    let mut names = vec![String::from(config::DEFAULT_CONTEXT)];
+    names.extend(editable_cfg.context_names());
+    for name in names {
+        if name == current {
+            tprint!("* {}", cbold!(name));
+        } else {
+            tprint!("  {}", name);
+        }
+    }
+    Ok(())
+}
+
+/// Prints all known configuration options, their values, defaults and
+/// descriptions.
+pub fn list_options() -> Result<()> {
+    let editable_cfg = config::read_persisted_config_as_editable()?;
+    let mut tw = TabWriter::new(io::stdout()).minwidth(8);
+
+    writeln!(
+        &mut tw,
+        "{}",
+        console::Style::new()
+            .bold()
+            .apply_to("key\tvalue\tdefault\tdescription")
+    )?;
+    for (key, option, value) in editable_cfg.list_options()? {
+        let value = config::env_override(key)
+            .map(|v| format!("{} ({})", v, config::env_var_name(key)))
+            .or(value);
+        writeln!(
+            &mut tw,
+            "{}\t{}\t{}\t{}",
+            key,
+            value.as_deref().unwrap_or("-"),
+            option.default_value().as_deref().unwrap_or("-"),
+            cdim!(option.description)
+        )?;
+    }
+    tw.flush()?;
+    Ok(())
+}
+
+/// Prints the location of the config file.
+pub fn print_config_path() -> Result<()> {
+    tprint!("{}", config::config_file_path().display());
+    Ok(())
+}
+
+/// Opens the config file in the users editor and validates it once saved.
+///
+/// If the edited file is invalid the user may re-open the editor, otherwise
+/// the previous contents are restored.
+pub fn edit_config() -> Result<()> {
+    config::create_config_if_not_exists()?;
+    let path = config::config_file_path();
+    let previous = fs::read_to_string(&path)
+        .with_context(|| "Failed to read config file")?;
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| String::from("vi"));
+    let editor_cmd: Vec<&str> = editor.split_whitespace().collect();
+    if editor_cmd.is_empty() {
+        bail!("Invalid editor specified. Are you overriding $EDITOR?");
+    }
+
+    loop {
+        let status = Command::new(editor_cmd[0])
+            .args(&editor_cmd[1..])
+            .arg(&path)
+            .status()
+            .with_context(|| format!("Failed to start editor '{}'", editor))?;
+        if !status.success() {
+            bail!("Editor '{}' exited with {}", editor, status);
+        }
+
+        match config::read_persisted_config_from_path(&path) {
+            Ok(_) => {
+                tprint!("{} {}", cbold!("Saved"), path.display());
+                return Ok(());
+            }
+            Err(e) => {
+                teprinterr!(e);
+                tprint!(
+                    "{} (y/n)",
+                    cbold!("Would you like to re-open the editor?")
+                );
+                let line: String = read!("{}\n");
+                if line.trim().to_lowercase() != "y" {
+                    fs::write(&path, &previous)
+                        .with_context(|| "Failed to restore config file")?;
+                    bail!("The config file was not changed");
+                }
+            }
+        }
+    }
+}
@@ -1,8 +1,25 @@
+use crate::client::api::CartelApiClient;
+use crate::client::cli::ClientConfig;
+use crate::client::commands::logs::{page_log_file, LogMode};
+use crate::client::commands::task::format_timestamp;
 use crate::client::emoji::{HOUR_GLASS, SUCCESS};
+use crate::config;
+use crate::daemon::daemon_log::daemon_log_path;
 use crate::shell::active_shell_path;
-use anyhow::{bail, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use console::style;
-use std::process::Command;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+use std::{env, thread};
+use text_io::read;
+
+/// The name of the daemon executable.
+const DAEMON_EXE: &str = "cartel-daemon";
+
+/// How long to wait for a started daemon to respond.
+const DAEMON_START_TIMEOUT: Duration = Duration::from_secs(10);
 
 pub fn restart_daemon() -> Result<()> {
     let active_shell = if let Some(path) = active_shell_path() {
@@ -29,3 +46,188 @@ pub fn restart_daemon() -> Result<()> {
     tprintstep!(style("Daemon restarted").bold().green(), 2, 2, SUCCESS);
     Ok(())
 }
+
+/// Makes the daemon grab the environment of the login shell now, printing
+/// the names of the variables which changed.
+pub fn refresh_env_cmd(cfg: &ClientConfig) -> Result<()> {
+    let result = cfg.daemon()?.refresh_env()?;
+    if result.added.is_empty()
+        && result.removed.is_empty()
+        && result.changed.is_empty()
+    {
+        println!("The login environment is unchanged");
+        return Ok(());
+    }
+    println!("The login environment changed:");
+    for key in &result.added {
+        println!("  {} {}", csuccess!("+"), key);
+    }
+    for key in &result.removed {
+        println!("  {} {}", cfail!("-"), key);
+    }
+    for key in &result.changed {
+        println!("  {} {}", cbold!("~"), key);
+    }
+    println!(
+        "{}",
+        cdim!(
+            "Modules using the login environment get the new one when they \
+            are next started."
+        )
+    );
+    Ok(())
+}
+
+/// Prints whether the daemon refreshes the environment of the login shell,
+/// and when it last did.
+pub fn env_status_cmd(cfg: &ClientConfig) -> Result<()> {
+    let status = cfg.daemon()?.env_status()?;
+    let grabber = if status.grabber_enabled {
+        "enabled"
+    } else {
+        "disabled"
+    };
+    let refreshed = status
+        .refreshed_at
+        .map(format_timestamp)
+        .unwrap_or_else(|| String::from("never"));
+    println!("{:<16}{}", cbold!("Env-grabber:"), grabber);
+    println!("{:<16}{}", cbold!("Last refreshed:"), refreshed);
+    println!("{:<16}{}", cbold!("Variables:"), status.variables);
+    Ok(())
+}
+
+/// Pages the log file of the daemon itself.
+pub fn daemon_logs_cmd(follow: bool, cfg: &ClientConfig) -> Result<()> {
+    let log_file = daemon_log_path();
+    if !log_file.exists() {
+        bail!("Log file of the daemon not found at {}", log_file.display());
+    }
+    let log_mode = if follow {
+        LogMode::FOLLOW
+    } else {
+        LogMode::DEFAULT
+    };
+    page_log_file(log_file.as_os_str(), log_mode, cfg)
+}
+
+/// Returns whether the daemon should be started, asking the user unless the
+/// `client.auto_start_daemon` option is set.
+///
+/// The user is only asked if the client is attended, as otherwise there is
+/// no one to answer.
+pub fn should_start_daemon() -> bool {
+    match config::PERSISTED_CONFIG.client.auto_start_daemon {
+        Some(auto_start) => auto_start,
+        None if console::user_attended() => {
+            tprint!(
+                "{} (y/n)",
+                cbold!(
+                    "The daemon is not running. Would you like to start it?"
+                )
+            );
+            let line: String = read!("{}\n");
+            line.trim().to_lowercase() == "y"
+        }
+        None => false,
+    }
+}
+
+/// Returns the daemon executable next to the client, or the one on the
+/// `PATH` if there is none.
+fn daemon_executable() -> PathBuf {
+    env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.join(DAEMON_EXE)))
+        .filter(|exe| exe.exists())
+        .unwrap_or_else(|| PathBuf::from(DAEMON_EXE))
+}
+
+/// Replaces the daemon with a new executable (by default that of
+/// [daemon_executable]) without stopping its services, and waits for the new
+/// daemon to respond.
+pub fn upgrade_daemon_cmd(
+    executable: Option<&str>,
+    cfg: &ClientConfig,
+) -> Result<()> {
+    let executable = match executable {
+        Some(path) => env::current_dir()?.join(path),
+        None => find_executable(&daemon_executable())?,
+    };
+    let daemon = cfg.daemon()?;
+    let previous = daemon.daemon_health()?.instance_id;
+
+    tprintstep!("Upgrading daemon...", 1, 2, HOUR_GLASS);
+    let response = daemon.upgrade_daemon(&executable.to_string_lossy())?;
+    let started = Instant::now();
+    loop {
+        thread::sleep(Duration::from_millis(100));
+        match daemon.daemon_health() {
+            Ok(health) if health.instance_id != previous => break,
+            _ if started.elapsed() > DAEMON_START_TIMEOUT => bail!(
+                "The daemon was not upgraded, see `cartel daemon logs` \
+                for why"
+            ),
+            _ => {}
+        }
+    }
+    tprintstep!(
+        style(format!(
+            "Daemon upgraded to {}, {} running service(s) kept running",
+            response.version, response.running_services
+        ))
+        .bold()
+        .green(),
+        2,
+        2,
+        SUCCESS
+    );
+    Ok(())
+}
+
+/// Returns the absolute path of an executable, looking it up on the `PATH`
+/// if only its name is given.
+fn find_executable(exe: &Path) -> Result<PathBuf> {
+    if exe.components().count() > 1 {
+        return Ok(exe.to_path_buf());
+    }
+    env::var_os("PATH")
+        .iter()
+        .flat_map(env::split_paths)
+        .map(|dir| dir.join(exe))
+        .find(|path| path.is_file())
+        .ok_or_else(|| anyhow!("{} not found on the PATH", exe.display()))
+}
+
+/// Starts the daemon in the background and waits for it to respond.
+///
+/// The daemon executable next to the client is preferred over the one on
+/// the `PATH`.
+pub fn start_daemon(daemon: &CartelApiClient) -> Result<()> {
+    tprintstep!("Starting daemon...", 1, 2, HOUR_GLASS);
+    let exe = daemon_executable();
+
+    // The daemon only forks into the background (returning once detached)
+    // if it leads its process group.
+    let status = Command::new(&exe)
+        .arg("--detach")
+        .process_group(0)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .with_context(|| format!("Failed to start {}", exe.display()))?;
+    if !status.success() {
+        bail!("Failed to start the daemon ({})", status);
+    }
+
+    let started = Instant::now();
+    while daemon.ping().is_err() {
+        if started.elapsed() > DAEMON_START_TIMEOUT {
+            bail!("The daemon did not respond after starting it");
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    tprintstep!(style("Daemon started").bold().green(), 2, 2, SUCCESS);
+    Ok(())
+}
@@ -1,89 +1,221 @@
 use crate::client::cli::ClientConfig;
 use crate::client::commands::deployer::{
-    Deployer, ModuleDeploymentPlan, ModuleToDeploy,
+    Deployer, ModuleDeploymentPlan, ModuleTiming, ModuleToDeploy,
+};
+use crate::client::commands::lock::with_deploy_lock;
+use crate::client::commands::open::open_service_url;
+use crate::client::definitions::{
+    read_module_definitions, read_project_definition, ProjectConfig,
+    PROJECT_CONFIG_FILE,
 };
-use crate::client::definitions::read_module_definitions;
 use crate::client::emoji::{
     LINK, LOOKING_GLASS, SPIRAL_NOTEBOOK, SUCCESS, TEXTBOOK, VAN,
 };
 use crate::client::module::{
-    module_names_set, remove_checks, ModuleDefinition, ModuleMarker,
+    group_services, module_by_name, module_names_set, remove_checks, Hook,
+    InnerDefinition, ModuleDefinition, ModuleKind, ModuleMarker,
+};
+use crate::client::process::run_hook;
+use crate::client::request::get_plan;
+use crate::client::resume::{plan_key, DeployProgress};
+use crate::client::validation::{
+    validate_modules_selected, validate_required_env,
 };
-use crate::client::validation::validate_modules_selected;
-use crate::dependency::{DependencyGraph, DependencyNode};
-use anyhow::Result;
+use crate::config;
+use crate::daemon::api::ApiPlannedAction;
+use crate::dependency::{
+    DependencyGraph, DependencyNode, EdgeDirection, WithDependencies,
+};
+use anyhow::{bail, Result};
 use clap::ArgMatches;
 use crossbeam_queue::ArrayQueue;
 use crossbeam_utils::thread;
 use indicatif::MultiProgress;
-use std::collections::HashSet;
+use parking_lot::Mutex;
+use std::collections::{HashMap, HashSet};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+use text_io::read;
 
 pub struct DeployOptions {
     pub force_deploy: bool,
+    /// Force deploy only the selected modules (and the services of selected
+    /// groups), while the rest follow the plan.
+    pub force_selected: bool,
+    /// The modules forced with `force_selected`, known once the module
+    /// definitions are read.
+    pub forced: HashSet<String>,
     pub skip_checks: bool,
     pub only_selected: bool,
     pub skip_readiness_checks: bool,
     pub active_envs: Vec<String>,
+    /// Where the environment sets were activated from, unless given with
+    /// `--env`.
+    pub active_envs_source: Option<&'static str>,
     pub threads: u8,
     pub wait: bool,
+    /// Print what would be deployed, without deploying anything.
+    pub dry_run: bool,
+    /// Print what would be deployed, and ask before deploying it.
+    pub confirm: bool,
+    /// Only deploy the services which would be redeployed (and the tasks
+    /// depending on them), skipping the rest without asking the daemon.
+    pub changed: bool,
+    /// Keep deploying the modules which do not depend on one which failed,
+    /// rather than stopping at the first failure.
+    pub keep_going: bool,
+    /// Print how long each module took to deploy once done.
+    pub timings: bool,
+    /// Continue the last deploy of the same plan which did not complete,
+    /// skipping the checks and the modules it completed.
+    pub resume: bool,
+    /// Open the URLs of the selected services in the browser once they are
+    /// ready.
+    pub open: bool,
+    /// Identifies this deploy to the daemon, so that the services it changes
+    /// can be rolled back together.
+    pub deployment_id: String,
+    /// Wait for the deployment lock if another client holds it, rather than
+    /// failing.
+    pub wait_for_lock: bool,
 }
 
 impl DeployOptions {
-    pub fn from(opts: &ArgMatches) -> DeployOptions {
+    /// Builds the deploy options from the CLI flags, falling back to the
+    /// defaults from the project and client configuration.
+    pub fn from(
+        opts: &ArgMatches,
+        defaults: &config::ClientConfig,
+        project: Option<&ProjectConfig>,
+    ) -> DeployOptions {
         let force_deploy = opts.is_present("force");
         let skip_readiness_checks = opts.is_present("skip_readiness_checks");
-        let skip_checks = opts.is_present("skip_checks");
-        let wait = opts.is_present("wait");
+        let skip_checks = !opts.is_present("checks")
+            && (opts.is_present("skip_checks")
+                || defaults.skip_checks.unwrap_or(false));
+        let wait = !opts.is_present("no_wait")
+            && !skip_readiness_checks
+            && (opts.is_present("wait")
+                || defaults.default_wait.unwrap_or(false));
         let serial = opts.is_present("serial");
+        let dry_run = opts.is_present("dry_run");
+        let confirm = !dry_run
+            && !opts.is_present("no_confirm")
+            && (opts.is_present("confirm")
+                || defaults.confirm_deploy.unwrap_or(false));
 
-        let active_envs = if let Some(it) = opts.values_of("env") {
-            it.map(String::from).collect()
-        } else {
-            vec![]
-        };
+        let project_envs = project
+            .map(|p| p.active_envs.clone())
+            .filter(|envs| !envs.is_empty());
+        let (active_envs, active_envs_source) =
+            if let Some(it) = opts.values_of("env") {
+                (it.map(String::from).collect(), None)
+            } else if let Some(envs) = project_envs {
+                (envs, Some(PROJECT_CONFIG_FILE))
+            } else {
+                (defaults.active_envs(), Some("client.active_envs"))
+            };
 
+        let default_threads = defaults.default_threads.unwrap_or(4);
         let threads = if serial {
             1
         } else {
             opts.value_of("threads")
-                .unwrap_or("4")
-                .parse::<u8>()
-                .unwrap_or(4)
+                .map(|t| t.parse::<u8>().unwrap_or(default_threads))
+                .unwrap_or(default_threads)
         };
 
         let only_selected = opts.is_present("only_selected");
         Self {
             force_deploy,
+            force_selected: opts.is_present("force_selected"),
+            forced: HashSet::new(),
             skip_checks,
             only_selected,
             skip_readiness_checks,
             active_envs,
+            active_envs_source,
             threads,
             wait,
+            dry_run,
+            confirm,
+            changed: opts.is_present("changed"),
+            keep_going: opts.is_present("keep_going"),
+            timings: opts.is_present("timings"),
+            resume: opts.is_present("resume"),
+            open: opts.is_present("open"),
+            deployment_id: uuid::Uuid::new_v4().to_string(),
+            wait_for_lock: opts.is_present("wait_for_lock"),
         }
     }
+
+    /// Returns whether a module is forced to deploy, either as all modules
+    /// are or as it was selected to be.
+    pub fn is_forced(&self, module_name: &str) -> bool {
+        self.force_deploy || self.forced.contains(module_name)
+    }
 }
 
 pub fn deploy_cmd(
     modules_to_deploy: Vec<&str>,
     cfg: &ClientConfig,
-    deploy_opts: &DeployOptions,
+    deploy_opts: &mut DeployOptions,
+) -> Result<()> {
+    // A dry run changes nothing, so it does not need the lock.
+    if deploy_opts.dry_run {
+        return deploy_modules(modules_to_deploy, cfg, deploy_opts);
+    }
+    let description = format!("deploy {}", modules_to_deploy.join(", "));
+    let wait = deploy_opts.wait_for_lock;
+    with_deploy_lock(cfg.daemon()?, &description, wait, || {
+        deploy_modules(modules_to_deploy, cfg, deploy_opts)
+    })
+}
+
+fn deploy_modules(
+    modules_to_deploy: Vec<&str>,
+    cfg: &ClientConfig,
+    deploy_opts: &mut DeployOptions,
 ) -> Result<()> {
     tprintstep!("Looking for module definitions...", 1, 6, LOOKING_GLASS);
     let mut module_defs = read_module_definitions(cfg)?;
+    let project = read_project_definition(cfg)?;
+    if let Some(source) = deploy_opts.active_envs_source {
+        if !deploy_opts.active_envs.is_empty() {
+            tprint!(
+                "      {} {} {}",
+                cdim!("Activated environment sets:"),
+                cbold!(deploy_opts.active_envs.join(", ")),
+                cdim!(format!("(from {})", source))
+            );
+        }
+    }
     let checks_map = remove_checks(&mut module_defs);
     let module_names = module_names_set(&module_defs);
+    let modules_to_deploy =
+        resolve_group_refs(modules_to_deploy, &module_defs)?;
 
     validate_modules_selected(&module_names, &modules_to_deploy)?;
+    if deploy_opts.force_selected {
+        deploy_opts.forced = forced_modules(&modules_to_deploy, &module_defs)?;
+    }
+    let deploy_opts = &*deploy_opts;
 
-    let deployed: Vec<_> = if !deploy_opts.only_selected {
+    let started = Instant::now();
+    let (deployed, outcome, prerequisites) = if !deploy_opts.only_selected {
         tprintstep!("Resolving dependencies...", 2, 6, LINK);
         let graph = DependencyGraph::from(&module_defs, &modules_to_deploy);
         let dependencies = resolve_dependencies(&graph)?;
+        validate_required_env(&dependencies.all, deploy_opts)?;
+        let progress = deploy_progress(
+            &dependencies.groupped,
+            project.working_dir.as_deref(),
+            deploy_opts,
+        )?;
 
-        if deploy_opts.skip_checks {
+        // Checks passed before the deploy being resumed got interrupted.
+        if deploy_opts.skip_checks || !progress.completed.is_empty() {
             tprintskipped!("Running checks...", 3, 6, TEXTBOOK);
         } else {
             tprintstep!("Running checks...", 3, 6, TEXTBOOK);
@@ -93,14 +225,46 @@ pub fn deploy_cmd(
         tprintstep!("Obtaining plan...", 4, 6, SPIRAL_NOTEBOOK);
         let deployment_plan =
             Deployer::obtain_plan(&dependencies.all, cfg, deploy_opts)?;
+        if deploy_opts.dry_run {
+            tprintskipped!("Deploying...", 5, 6, VAN);
+            print_plan(&dependencies.groupped, &deployment_plan, deploy_opts);
+            tprintstep!(
+                csuccess!("Dry run, nothing was deployed"),
+                6,
+                6,
+                SUCCESS
+            );
+            return Ok(());
+        }
+        if deploy_opts.confirm {
+            confirm_plan(
+                &dependencies.groupped,
+                &deployment_plan,
+                deploy_opts,
+            )?;
+        }
+        // Modules skipped as unchanged are left out of those deployed.
+        let deployed: Vec<String> = dependencies
+            .all
+            .iter()
+            .map(|d| d.key.clone())
+            .filter(|key| {
+                !deploy_opts.changed
+                    || *deployment_plan.should_deploy.get(key).unwrap_or(&true)
+            })
+            .collect();
         tprintstep!("Deploying...", 5, 6, VAN);
-        deploy_with_dependencies(
+        run_hook(Hook::PreDeploy, &project)?;
+        let prerequisites = prerequisites(&dependencies.groupped);
+        let outcome = deploy_with_dependencies(
             &dependencies.groupped,
+            &prerequisites,
             deployment_plan,
+            progress,
             cfg,
             deploy_opts,
         )?;
-        dependencies.all.iter().map(|d| d.key.clone()).collect()
+        (deployed, outcome, prerequisites)
     } else {
         tprintskipped!("Resolving dependencies...", 2, 6, LINK);
         let modules_to_deploy_set: HashSet<_> =
@@ -113,6 +277,7 @@ pub fn deploy_cmd(
 
         let modules_to_deploy: Vec<ModuleToDeploy> =
             selected.iter().map(|m| ModuleToDeploy::from(*m)).collect();
+        validate_required_env(&selected, deploy_opts)?;
 
         if deploy_opts.skip_checks {
             tprintskipped!("Running checks...", 3, 6, TEXTBOOK);
@@ -120,18 +285,363 @@ pub fn deploy_cmd(
             tprintstep!("Running checks...", 3, 6, TEXTBOOK);
             Deployer::run_checks(checks_map, &selected)?;
         }
-        tprintskipped!("Obtaining plan...", 4, 6, SPIRAL_NOTEBOOK);
+        if deploy_opts.dry_run || deploy_opts.confirm {
+            // Without dependencies tasks always run, so only the plan of the
+            // services is needed.
+            tprintstep!("Obtaining plan...", 4, 6, SPIRAL_NOTEBOOK);
+            let plan = get_plan(&selected, deploy_opts, cfg.daemon()?)?;
+            let deployment_plan = ModuleDeploymentPlan {
+                should_deploy: HashMap::new(),
+                planned: plan.plan,
+                changed: HashSet::new(),
+            };
+            let groups = std::slice::from_ref(&modules_to_deploy);
+            if deploy_opts.dry_run {
+                tprintskipped!("Deploying...", 5, 6, VAN);
+                print_plan(groups, &deployment_plan, deploy_opts);
+                tprintstep!(
+                    csuccess!("Dry run, nothing was deployed"),
+                    6,
+                    6,
+                    SUCCESS
+                );
+                return Ok(());
+            }
+            confirm_plan(groups, &deployment_plan, deploy_opts)?;
+        } else {
+            tprintskipped!("Obtaining plan...", 4, 6, SPIRAL_NOTEBOOK);
+        }
         tprintstep!("Deploying...", 5, 6, VAN);
-        deploy_without_dependencies(&modules_to_deploy, cfg, deploy_opts)?;
-        selected.iter().map(|m| m.name.clone()).collect()
+        run_hook(Hook::PreDeploy, &project)?;
+        let outcome =
+            deploy_without_dependencies(&modules_to_deploy, cfg, deploy_opts)?;
+        let deployed: Vec<String> =
+            selected.iter().map(|m| m.name.clone()).collect();
+        (deployed, outcome, HashMap::new())
     };
 
+    if deploy_opts.keep_going {
+        print_outcome(&deployed, &outcome);
+    }
+    if deploy_opts.timings {
+        print_timings(&outcome.timings, &prerequisites, started.elapsed());
+    }
+    if !outcome.failed.is_empty() {
+        bail!("{} module(s) failed to deploy", outcome.failed.len());
+    }
+    let deployed: Vec<_> = deployed
+        .into_iter()
+        .filter(|name| {
+            !outcome.skipped.iter().any(|(skipped, _)| skipped == name)
+        })
+        .collect();
+    run_hook(Hook::PostDeploy, &project)?;
+
     let deploy_txt =
         format!("{}: {:?}", csuccess!("Deployed modules"), deployed);
     tprintstep!(deploy_txt, 6, 6, SUCCESS);
+    if deploy_opts.open {
+        open_selected(&modules_to_deploy, &module_defs, cfg)?;
+    }
+    Ok(())
+}
+
+/// Opens the URLs of the selected services (and the services of selected
+/// groups) which define one.
+fn open_selected(
+    selected: &[&str],
+    module_defs: &[ModuleDefinition],
+    cfg: &ClientConfig,
+) -> Result<()> {
+    let mut services: Vec<_> =
+        forced_modules(selected, module_defs)?.into_iter().collect();
+    services.sort();
+    for name in services {
+        let url = match module_by_name(&name, module_defs).map(|m| &m.inner) {
+            Some(InnerDefinition::Service(svc)) => svc.url.as_deref(),
+            _ => None,
+        };
+        if let Some(url) = url {
+            open_service_url(&name, url, cfg)?;
+        }
+    }
+    Ok(())
+}
+
+/// Prints the names of the groups, one per line (eg. for completing them).
+pub fn list_groups_cmd(cfg: &ClientConfig) -> Result<()> {
+    let module_defs = read_module_definitions(cfg)?;
+    for module in module_defs.iter().filter(|m| m.kind == ModuleKind::Group) {
+        println!("{}", module.name);
+    }
+    Ok(())
+}
+
+/// Returns the selected modules, along with the services of selected groups.
+fn forced_modules(
+    selected: &[&str],
+    module_defs: &[ModuleDefinition],
+) -> Result<HashSet<String>> {
+    let mut forced = HashSet::new();
+    for name in selected {
+        match module_by_name(name, module_defs) {
+            Some(m) if m.kind == ModuleKind::Group => forced.extend(
+                group_services(name, module_defs)?
+                    .into_iter()
+                    .map(String::from),
+            ),
+            _ => {
+                forced.insert(name.to_string());
+            }
+        }
+    }
+    Ok(forced)
+}
+
+/// Strips the `@` from the modules referring to a group (eg. `@frontend`),
+/// failing if they don't name a group.
+fn resolve_group_refs<'a>(
+    modules: Vec<&'a str>,
+    module_defs: &[ModuleDefinition],
+) -> Result<Vec<&'a str>> {
+    modules
+        .into_iter()
+        .map(|name| {
+            let group = match name.strip_prefix('@') {
+                Some(group) => group,
+                None => return Ok(name),
+            };
+            match module_defs.iter().find(|m| m.name == group) {
+                Some(m) if m.kind == ModuleKind::Group => Ok(group),
+                Some(_) => bail!("'{}' is not a group", group),
+                None => bail!("The group '{}' does not exist", group),
+            }
+        })
+        .collect()
+}
+
+/// Prints the plan and asks whether to go ahead with the deploy, failing if
+/// the user declines.
+fn confirm_plan(
+    groups: &[Vec<ModuleToDeploy>],
+    deployment_plan: &ModuleDeploymentPlan,
+    deploy_opts: &DeployOptions,
+) -> Result<()> {
+    // Without a terminal there is no one to ask.
+    if !console::user_attended() {
+        bail!("Cannot ask to confirm the deploy without a terminal (use --no-confirm)");
+    }
+    print_plan(groups, deployment_plan, deploy_opts);
+    tprint!("{} (y/n)", cbold!("Would you like to deploy?"));
+    let line: String = read!("{}\n");
+    if line.trim().to_lowercase() != "y" {
+        bail!("The deploy was cancelled, nothing was deployed");
+    }
     Ok(())
 }
 
+/// Prints the groups modules would be deployed in (in order), with the
+/// action planned for each of them.
+fn print_plan(
+    groups: &[Vec<ModuleToDeploy>],
+    deployment_plan: &ModuleDeploymentPlan,
+    deploy_opts: &DeployOptions,
+) {
+    // Align the modules of all groups alike.
+    let width = groups
+        .iter()
+        .flatten()
+        .map(|m| m.definition.name.len())
+        .max()
+        .unwrap_or(0);
+    for (idx, group) in groups.iter().enumerate() {
+        tprint!("      {}", cbold!(format!("Group {}", idx + 1)));
+        for module in group {
+            let (kind, action) = match &module.definition.inner {
+                InnerDefinition::Service(svc) => {
+                    let force = deploy_opts.is_forced(&svc.name);
+                    let action =
+                        match (deployment_plan.planned.get(&svc.name), force) {
+                            (
+                                Some(ApiPlannedAction::AlreadyDeployed),
+                                false,
+                            ) => "already deployed",
+                            (Some(ApiPlannedAction::AlreadyDeployed), true) => {
+                                "will redeploy (forced)"
+                            }
+                            (Some(ApiPlannedAction::WillRedeploy), _)
+                                if deployment_plan
+                                    .changed
+                                    .contains(&svc.name) =>
+                            {
+                                "will redeploy (changed)"
+                            }
+                            (Some(ApiPlannedAction::WillRedeploy), _) => {
+                                "will redeploy"
+                            }
+                            (Some(ApiPlannedAction::WillDeploy), _)
+                            | (None, _) => "will deploy",
+                        };
+                    ("service", action)
+                }
+                InnerDefinition::Task(tsk) => {
+                    let runs = deploy_opts.is_forced(&tsk.name)
+                        || *deployment_plan
+                            .should_deploy
+                            .get(&tsk.name)
+                            .unwrap_or(&true);
+                    ("task", if runs { "will run" } else { "will skip" })
+                }
+                // Groups only bring in other modules.
+                _ => continue,
+            };
+            tprint!(
+                "        {:width$}  {}  {}",
+                module.definition.name,
+                cdim!(format!("{:7}", kind)),
+                action,
+                width = width
+            );
+        }
+    }
+}
+
+/// The modules which failed to deploy (when keeping going after failures),
+/// and those skipped because they depend on one of them.
+#[derive(Default)]
+struct DeployOutcome {
+    failed: Vec<(String, anyhow::Error)>,
+    /// The skipped modules, each with the module it depends on which failed
+    /// (or was skipped).
+    skipped: Vec<(String, String)>,
+    /// How long each of the services and tasks deployed took.
+    timings: Vec<ModuleTiming>,
+}
+
+impl DeployOutcome {
+    fn failed_or_skipped(&self, name: &str) -> bool {
+        self.failed.iter().any(|(failed, _)| failed == name)
+            || self.skipped.iter().any(|(skipped, _)| skipped == name)
+    }
+}
+
+/// Prints which of the modules succeeded, failed (and why) or were skipped.
+fn print_outcome(modules: &[String], outcome: &DeployOutcome) {
+    let succeeded: Vec<_> = modules
+        .iter()
+        .filter(|name| !outcome.failed_or_skipped(name))
+        .map(String::as_str)
+        .collect();
+    if !succeeded.is_empty() {
+        tprint!("      {} {}", csuccess!("Succeeded:"), succeeded.join(", "));
+    }
+    for (name, e) in &outcome.failed {
+        // Only the first line, as the error was shown in full already.
+        let reason = e.to_string();
+        let reason = reason.lines().next().unwrap_or_default();
+        tprint!(
+            "      {} {} {}",
+            cfail!("Failed:"),
+            name,
+            cdim!(format!("({})", reason))
+        );
+    }
+    for (name, dependency) in &outcome.skipped {
+        tprint!(
+            "      {} {} {}",
+            cdim!("Skipped:"),
+            name,
+            cdim!(format!("(depends on {})", dependency))
+        );
+    }
+}
+
+/// Prints how long each module took to deploy, the total time, and the
+/// critical path (the chain of modules each waited on, ending with the one
+/// which finished last).
+fn print_timings(
+    timings: &[ModuleTiming],
+    prerequisites: &HashMap<String, Vec<String>>,
+    total: Duration,
+) {
+    let mut timings: Vec<_> = timings.iter().collect();
+    timings.sort_by_key(|t| t.started);
+    let width = timings.iter().map(|t| t.name.len()).max().unwrap_or(0);
+
+    tprint!("      {}", cbold!("Timings"));
+    tprint!(
+        "        {}",
+        cdim!(format!(
+            "{:width$}  {:7}  {:>7}  {:>7}  {:>7}",
+            "name",
+            "kind",
+            "deploy",
+            "healthy",
+            "total",
+            width = width
+        ))
+    );
+    for timing in &timings {
+        let healthy = timing.healthy.unwrap_or_default();
+        tprint!(
+            "        {:width$}  {:7}  {:>7}  {:>7}  {:>7}",
+            timing.name,
+            timing.kind.to_string().to_lowercase(),
+            format_elapsed(timing.total - healthy),
+            timing.healthy.map_or("-".to_string(), format_elapsed),
+            format_elapsed(timing.total),
+            width = width
+        );
+    }
+    tprint!("      {} {}", cbold!("Total:"), format_elapsed(total));
+
+    // Walk back from the module which finished last, through the
+    // prerequisite of each which finished last. Modules without one waited
+    // on whichever module finished last before they started (eg. the last
+    // of the previous group).
+    let finished = |t: &ModuleTiming| t.started + t.total;
+    let by_name: HashMap<_, _> =
+        timings.iter().map(|t| (t.name.as_str(), *t)).collect();
+    let mut critical_path = vec![];
+    let mut current = timings.iter().copied().max_by_key(|t| finished(t));
+    while let Some(timing) = current {
+        critical_path.push(timing);
+        let prerequisite = prerequisites
+            .get(&timing.name)
+            .into_iter()
+            .flatten()
+            .filter_map(|name| by_name.get(name.as_str()).copied())
+            .max_by_key(|t| finished(t));
+        current = prerequisite.or_else(|| {
+            timings
+                .iter()
+                .copied()
+                .filter(|t| finished(t) <= timing.started)
+                .max_by_key(|t| finished(t))
+        });
+    }
+    if !critical_path.is_empty() {
+        let path = critical_path
+            .iter()
+            .rev()
+            .map(|t| format!("{} ({})", t.name, format_elapsed(t.total)))
+            .collect::<Vec<_>>()
+            .join(" -> ");
+        tprint!("      {} {}", cbold!("Critical path:"), path);
+    }
+}
+
+/// Formats a duration as seconds (eg. `3.2s`), or minutes and seconds for
+/// longer ones (eg. `2m 05s`).
+fn format_elapsed(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs < 60 {
+        format!("{:.1}s", duration.as_secs_f64())
+    } else {
+        format!("{}m {:02}s", secs / 60, secs % 60)
+    }
+}
+
 struct DeploymentGraph<'a> {
     groupped: Vec<Vec<ModuleToDeploy<'a>>>,
     all: Vec<&'a DependencyNode<&'a ModuleDefinition, ModuleMarker>>,
@@ -157,8 +667,10 @@ fn deploy(
     deployment_plan: Option<Arc<ModuleDeploymentPlan>>,
     cfg: &ClientConfig,
     deploy_opts: &DeployOptions,
-) -> Result<()> {
+) -> Result<DeployOutcome> {
     let multiprogress = Arc::new(MultiProgress::new());
+    let failures = Mutex::new(Vec::new());
+    let timings = Mutex::new(Vec::new());
     let sync_point = Arc::new(AtomicBool::new(false));
 
     // Maintain a queue of modules that need to be deployed. The queue
@@ -179,6 +691,8 @@ fn deploy(
         let deployment_plan = &deployment_plan;
         let cfg = &cfg;
         let deploy_opts = &deploy_opts;
+        let failures = &failures;
+        let timings = &timings;
         let mut worker_threads = vec![];
 
         for _ in 0..deploy_opts.threads {
@@ -188,7 +702,13 @@ fn deploy(
                     queue.clone(),
                     deployment_plan.clone(),
                 );
-                deployer.do_work(modules, cfg, deploy_opts)?;
+                deployer.do_work(
+                    modules,
+                    cfg,
+                    deploy_opts,
+                    failures,
+                    timings,
+                )?;
                 Ok(())
             }));
         }
@@ -223,27 +743,147 @@ fn deploy(
         return Err(*e);
     }
 
-    Ok(())
+    Ok(DeployOutcome {
+        failed: failures.into_inner(),
+        skipped: vec![],
+        timings: timings.into_inner(),
+    })
+}
+
+/// Returns the modules each module requires to be deployed first, by both
+/// the edges pointing from it (its dependencies) and to it (eg. it being a
+/// post task of a group). Weak edges only order modules.
+fn prerequisites(
+    groups: &[Vec<ModuleToDeploy>],
+) -> HashMap<String, Vec<String>> {
+    let mut prerequisites: HashMap<String, Vec<String>> = HashMap::new();
+    for module in groups.iter().flatten() {
+        for edge in module.definition.dependencies() {
+            if edge.is_weak {
+                continue;
+            }
+            let (module, prerequisite) = match edge.direction {
+                EdgeDirection::To => (edge.edge_src, edge.edge_dst),
+                EdgeDirection::From => (edge.edge_dst, edge.edge_src),
+            };
+            prerequisites.entry(module).or_default().push(prerequisite);
+        }
+    }
+    prerequisites
+}
+
+/// Returns the progress of the deploy, read from the last deploy of the
+/// same plan which did not complete when resuming.
+fn deploy_progress(
+    groups: &[Vec<ModuleToDeploy>],
+    project_dir: Option<&str>,
+    deploy_opts: &DeployOptions,
+) -> Result<DeployProgress> {
+    let names: Vec<Vec<&str>> = groups
+        .iter()
+        .map(|group| group.iter().map(|m| m.definition.name.as_str()).collect())
+        .collect();
+    let key = plan_key(project_dir, &deploy_opts.active_envs, &names);
+    if !deploy_opts.resume {
+        return Ok(DeployProgress::new(key));
+    }
+    match DeployProgress::read(&key)? {
+        Some(progress) => {
+            let mut completed: Vec<_> =
+                progress.completed.iter().map(String::as_str).collect();
+            completed.sort_unstable();
+            tprint!(
+                "      {} {}",
+                cdim!("Resuming, skipping the completed modules:"),
+                completed.join(", ")
+            );
+            Ok(progress)
+        }
+        None => {
+            tprint!(
+                "      {}",
+                cdim!("No incomplete deploy to resume, deploying all modules")
+            );
+            Ok(DeployProgress::new(key))
+        }
+    }
 }
 
+/// Deploys the groups in order, recording the modules of each group which
+/// completed so that a deploy which did not complete can be resumed.
 fn deploy_with_dependencies(
     groups: &[Vec<ModuleToDeploy>],
+    prerequisites: &HashMap<String, Vec<String>>,
     deployment_plan: ModuleDeploymentPlan,
+    mut progress: DeployProgress,
     cfg: &ClientConfig,
     deploy_opts: &DeployOptions,
-) -> Result<()> {
+) -> Result<DeployOutcome> {
     let deployment_plan = Arc::new(deployment_plan);
+    let mut outcome = DeployOutcome::default();
     for group in groups {
-        deploy(group, Some(Arc::clone(&deployment_plan)), cfg, deploy_opts)?;
+        // Skip the modules depending on one which failed (or was skipped),
+        // and those completed before resuming.
+        let mut to_deploy = vec![];
+        for module in group {
+            let name = &module.definition.name;
+            if progress.completed.contains(name) {
+                continue;
+            }
+            let blocked_by =
+                prerequisites.get(name).into_iter().flatten().find(
+                    |prerequisite| outcome.failed_or_skipped(prerequisite),
+                );
+            match blocked_by {
+                Some(dependency) => {
+                    outcome.skipped.push((name.clone(), dependency.clone()))
+                }
+                None => to_deploy.push(ModuleToDeploy {
+                    definition: module.definition,
+                    marker: module.marker,
+                }),
+            }
+        }
+        let group_outcome = match deploy(
+            &to_deploy,
+            Some(Arc::clone(&deployment_plan)),
+            cfg,
+            deploy_opts,
+        ) {
+            Ok(group_outcome) => group_outcome,
+            Err(e) => {
+                progress.write()?;
+                return Err(e);
+            }
+        };
+        progress.completed.extend(
+            to_deploy
+                .iter()
+                .map(|m| &m.definition.name)
+                .filter(|name| {
+                    !group_outcome
+                        .failed
+                        .iter()
+                        .any(|(failed, _)| failed == *name)
+                })
+                .cloned(),
+        );
+        // Record the progress after each group, so that a deploy which is
+        // interrupted can be resumed as well.
+        progress.write()?;
+        outcome.failed.extend(group_outcome.failed);
+        outcome.timings.extend(group_outcome.timings);
     }
-    Ok(())
+    if outcome.failed.is_empty() {
+        progress.remove()?;
+    }
+    Ok(outcome)
 }
 
 fn deploy_without_dependencies(
     sorted: &[ModuleToDeploy],
     cfg: &ClientConfig,
     deploy_opts: &DeployOptions,
-) -> Result<()> {
-    deploy(sorted, None, cfg, deploy_opts)?;
-    Ok(())
+) -> Result<DeployOutcome> {
+    deploy(sorted, None, cfg, deploy_opts)
 }
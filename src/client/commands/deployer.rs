@@ -1,9 +1,10 @@
 use crate::client::cli::ClientConfig;
 use crate::client::commands::DeployOptions;
 use crate::client::emoji::{HOUR_GLASS, SUCCESS, YELLOW_NOTEBOOK};
+use crate::client::mask::redact;
 use crate::client::module::{
     CheckDefinition, GroupDefinition, InnerDefinition, ModuleDefinition,
-    ModuleMarker, ServiceOrTaskDefinition, SuggestedFixDefinition,
+    ModuleKind, ModuleMarker, ServiceOrTaskDefinition, SuggestedFixDefinition,
 };
 use crate::client::process::{apply_suggested_fix, run_check};
 use crate::client::progress::{
@@ -12,15 +13,18 @@ use crate::client::progress::{
 use crate::client::request;
 use crate::client::request::get_plan;
 use crate::daemon::api::{
-    ApiGetPlanResponse, ApiPlannedAction, ApiProbeStatus,
+    ApiGetPlanResponse, ApiPlannedAction, ApiProbeStatus, ApiTaskRunStatus,
 };
 use crate::dependency::DependencyNode;
 use anyhow::{anyhow, bail, Result};
 use crossbeam_queue::ArrayQueue;
-use indicatif::{MultiProgress, ProgressBar};
+use crossbeam_utils::thread;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use parking_lot::Mutex;
 use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use text_io::read;
 
 pub struct Deployer {
@@ -31,6 +35,23 @@ pub struct Deployer {
 
 pub struct ModuleDeploymentPlan {
     pub should_deploy: HashMap<String, bool>,
+    /// The action the daemon plans to take for each service.
+    pub planned: HashMap<String, ApiPlannedAction>,
+    /// The services deployed with a different definition than the current.
+    pub changed: HashSet<String>,
+}
+
+/// How long deploying a service (or running a task) took.
+pub struct ModuleTiming {
+    pub name: String,
+    pub kind: ModuleKind,
+    pub started: Instant,
+    /// The time from starting to deploy the module until it was done,
+    /// including waiting for it to be healthy.
+    pub total: Duration,
+    /// The time spent waiting for the service to be healthy, if it was
+    /// waited on.
+    pub healthy: Option<Duration>,
 }
 
 pub struct ModuleToDeploy<'a> {
@@ -51,57 +72,100 @@ impl Deployer {
         }
     }
 
+    /// Deploys modules off the shared queue until it is empty.
+    ///
+    /// A module failing to deploy stops the work, unless `keep_going` is
+    /// set in which case the failure is added to `failures`. The time taken
+    /// by each service or task deployed is added to `timings`.
     pub fn do_work(
         &self,
         modules: &[ModuleToDeploy],
         cfg: &ClientConfig,
         deploy_opts: &DeployOptions,
+        failures: &Mutex<Vec<(String, anyhow::Error)>>,
+        timings: &Mutex<Vec<ModuleTiming>>,
     ) -> Result<()> {
         // Consume modules from the shared queue and deploy them.
         while !self.queue.is_empty() {
             if let Some(idx) = self.queue.pop() {
                 let module_to_deploy = &modules[idx];
-                self.deploy_module(module_to_deploy, cfg, deploy_opts)?;
+                let definition = module_to_deploy.definition;
+                let started = Instant::now();
+                match self.deploy_module(module_to_deploy, cfg, deploy_opts) {
+                    Ok(healthy) => {
+                        if let ModuleKind::Service | ModuleKind::Task =
+                            definition.kind
+                        {
+                            timings.lock().push(ModuleTiming {
+                                name: definition.name.clone(),
+                                kind: definition.kind.clone(),
+                                started,
+                                total: started.elapsed(),
+                                healthy,
+                            });
+                        }
+                    }
+                    Err(e) if deploy_opts.keep_going => {
+                        failures.lock().push((definition.name.clone(), e));
+                    }
+                    Err(e) => return Err(e),
+                }
             }
         }
         Ok(())
     }
 
+    /// Deploys a service, waiting for it to be healthy if needed.
+    ///
+    /// Returns how long it took for the service to be healthy, if it was
+    /// waited on.
     fn deploy_and_maybe_wait_service(
         &self,
         service: &ServiceOrTaskDefinition,
         marker: Option<ModuleMarker>,
         cfg: &ClientConfig,
         deploy_opts: &DeployOptions,
-    ) -> Result<()> {
+    ) -> Result<Option<Duration>> {
+        // Services which would not be redeployed are skipped without asking
+        // the daemon.
+        if deploy_opts.changed && !self.should_deploy(&service.name, false) {
+            self.skip_unchanged(service);
+            return Ok(None);
+        }
         let monitor_handle = self.deploy_service(service, cfg, deploy_opts)?;
         let node_marked = marker == Some(ModuleMarker::WaitProbe);
+        // Dependents explicitly opted out of waiting for this service.
+        let no_wait = marker == Some(ModuleMarker::NoWait);
 
         if let Some(handle) = monitor_handle {
             if (node_marked
-                || service.always_await_readiness_probe
+                || (service.always_await_readiness_probe && !no_wait)
                 || deploy_opts.wait)
                 && !deploy_opts.skip_readiness_checks
             {
+                let waiting = Instant::now();
                 self.wait_until_healthy(
                     service.name.as_str(),
                     handle.as_str(),
                     cfg,
                 )?;
+                return Ok(Some(waiting.elapsed()));
             }
         }
-        Ok(())
+        Ok(None)
     }
 
+    /// Deploys a module, returning how long it took for it to be healthy if
+    /// it was waited on.
     fn deploy_module(
         &self,
         module: &ModuleToDeploy,
         cfg: &ClientConfig,
         deploy_opts: &DeployOptions,
-    ) -> Result<()> {
+    ) -> Result<Option<Duration>> {
         match module.definition.inner {
             InnerDefinition::Task(ref task) => {
-                self.deploy_task(task, deploy_opts, cfg)
+                self.deploy_task(task, deploy_opts, cfg).map(|_| None)
             }
             InnerDefinition::Service(ref service) => self
                 .deploy_and_maybe_wait_service(
@@ -112,12 +176,11 @@ impl Deployer {
                 ),
             InnerDefinition::Group(ref group) => {
                 self.deploy_group(group);
-                Ok(())
+                Ok(None)
             }
-            InnerDefinition::Check(_) => Ok(()),
-            InnerDefinition::Shell(_) => Ok(()),
-        }?;
-        Ok(())
+            InnerDefinition::Check(_) => Ok(None),
+            InnerDefinition::Shell(_) => Ok(None),
+        }
     }
 
     fn deploy_service(
@@ -131,9 +194,20 @@ impl Deployer {
 
         let pb = self.multiprogress.add(ProgressBar::new(std::u64::MAX));
         let wu = WaitUntil::new_multi(&spin_opt, pb);
+        let mut log_tail = vec![];
         let deploy_result = wu.spin_until_status(|| {
             let result =
-                request::deploy_module(module, deploy_opts, &cfg.daemon_url)?;
+                request::deploy_module(module, deploy_opts, cfg.daemon()?)?;
+
+            if let Some(code) = result.exit_code {
+                log_tail = result.log_tail;
+                bail!(
+                    "The service {} exited with code {} right after being \
+                    deployed.",
+                    module.name,
+                    code
+                );
+            }
 
             let deploy_status = if result.deployed {
                 csuccess!("(Deployed)")
@@ -141,9 +215,10 @@ impl Deployer {
                 cdim!("(Already deployed)")
             };
             Ok(WaitResult::from(result, deploy_status.to_string()))
-        })?;
+        });
+        self.show_log_tail(&log_tail);
 
-        let monitor_handle = deploy_result.monitor;
+        let monitor_handle = deploy_result?.monitor;
         Ok(monitor_handle)
     }
 
@@ -157,36 +232,50 @@ impl Deployer {
         let spin_opt = SpinnerOptions::new(message);
         let pb = self.multiprogress.add(ProgressBar::new(std::u64::MAX));
         let wu = WaitUntil::new_multi(&spin_opt, pb);
+        let mut log_tail = vec![];
 
-        wu.spin_until_status(|| loop {
+        let result = wu.spin_until_status(|| loop {
             let status = csuccess!("(Done)").to_string();
-            match request::poll_health(monitor_handle, &cfg.daemon_url)?
-                .probe_status
-            {
+            let health = cfg.daemon()?.health(monitor_handle)?;
+            match health.probe_status {
                 Some(ApiProbeStatus::Successful) => {
                     break Ok(WaitResult::from((), status))
                 }
                 Some(ApiProbeStatus::RetriesExceeded) => {
+                    log_tail = health.log_tail;
                     bail!(
-                        "The service did not complete its readiness probe checks in time.\n\
-                        Check the logs for more details."
+                        "The service did not complete its readiness probe checks in time{}.\n\
+                        Check the logs for more details.",
+                        probe_reason(&health.reason)
                     )
                 }
                 Some(ApiProbeStatus::Error) => {
                     bail!(
                         "An error occured while waiting for the service \
-                        readiness probe to complete.\nThis is usually a mistake in \
+                        readiness probe to complete{}.\nThis is usually a mistake in \
                         the probe configuration, ensure the command or \
-                        condition is correct."
+                        condition is correct.",
+                        probe_reason(&health.reason)
                     )
                 }
                 _ => {
                     std::thread::sleep(Duration::from_secs(2));
                 }
         }
-    })?;
+    });
+        self.show_log_tail(&log_tail);
 
-        Ok(())
+        result
+    }
+
+    /// Shows the last lines of a module's log under its spinner.
+    fn show_log_tail(&self, log_tail: &[String]) {
+        let style = ProgressStyle::default_spinner().template("        {msg}");
+        for line in log_tail {
+            let pb = self.multiprogress.add(ProgressBar::new(u64::MAX));
+            pb.set_style(style.clone());
+            pb.finish_with_message(cdim!(redact(line)).to_string());
+        }
     }
 
     fn deploy_task(
@@ -199,26 +288,126 @@ impl Deployer {
         let spin_opt = SpinnerOptions::new(message);
 
         let pb = self.multiprogress.add(ProgressBar::new(std::u64::MAX));
+        let tail_pb = pb.clone();
         let wu = WaitUntil::new_multi(&spin_opt, pb);
-        let force = deploy_opts.force_deploy;
+        let force = deploy_opts.is_forced(&module.name);
 
         // If none of this tasks services will be deployed then skip deploying
         // this task also.
         let skipped_by_plan = !self.should_deploy(module.name.as_str(), force);
-        wu.spin_until_status(|| {
+        let mut output = vec![];
+        let result = wu.spin_until_status(|| {
             if skipped_by_plan {
                 return Ok(WaitResult::from(
                     false,
                     cdim!("(Skipping)").to_string(),
                 ));
             }
-            let result =
-                request::deploy_task(module, deploy_opts, &cfg.daemon_url)?;
-            let status = csuccess!("(Done)").to_string();
-            Ok(WaitResult::from(result.success, status))
-        })?;
+            let done = AtomicBool::new(false);
+            let started = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .ok();
+            // A custom log file is appended to by every run of the task, so
+            // its output from previous runs is skipped.
+            let offset = match module.log_file_path {
+                Some(_) => request::task_log_end(module, cfg.daemon()?)?,
+                None => 0,
+            };
+            let result = thread::scope(|s| {
+                let tail = s.spawn(|_| {
+                    Self::tail_task_log(
+                        module,
+                        &tail_pb,
+                        &spin_opt.message,
+                        offset,
+                        started,
+                        &done,
+                        cfg,
+                    )
+                });
+                let result =
+                    request::deploy_task(module, deploy_opts, cfg.daemon()?);
+                done.store(true, Ordering::Relaxed);
+                // The tail only shows progress, the outcome of the task
+                // stands even if it panicked.
+                let _ = tail.join();
+                result
+            })
+            .map_err(|_| anyhow!("Failed to deploy task {}", module.name))??;
+            output = result.output;
+            if let ApiTaskRunStatus::Failed = result.status {
+                let task_name = result.task_name;
+                bail!(result
+                    .error
+                    .unwrap_or_else(|| format!("Task {} failed", task_name)))
+            }
+            let status = match result.skip_reason {
+                Some(reason) => {
+                    cdim!(format!("(Skipped, {})", reason)).to_string()
+                }
+                None => csuccess!("(Done)").to_string(),
+            };
+            Ok(WaitResult::from(true, status))
+        });
+        if result.is_err() || module.show_output {
+            self.show_log_tail(&output);
+        }
 
-        Ok(())
+        result.map(|_| ())
+    }
+
+    /// Shows the latest line of output of a running task next to its spinner.
+    ///
+    /// Polls the daemon for new log output from `offset` until `done` is set,
+    /// so that long running tasks don't appear to hang. Output written before
+    /// `started` (epoch milliseconds) belongs to a previous run and is
+    /// ignored.
+    fn tail_task_log(
+        task: &ServiceOrTaskDefinition,
+        pb: &ProgressBar,
+        message: &str,
+        mut offset: u64,
+        started: Option<u64>,
+        done: &AtomicBool,
+        cfg: &ClientConfig,
+    ) {
+        let daemon = match cfg.daemon() {
+            Ok(daemon) => daemon,
+            Err(_) => return,
+        };
+        let mut pending = String::new();
+
+        while !done.load(Ordering::Relaxed) {
+            std::thread::sleep(Duration::from_millis(250));
+            let chunk =
+                match request::read_task_logs(task, offset, started, daemon) {
+                    Ok(chunk) => chunk,
+                    Err(_) => continue,
+                };
+            if chunk.offset < offset {
+                pending.clear();
+            }
+            offset = chunk.offset;
+            pending.push_str(&chunk.data);
+
+            let last_line = pending
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .last()
+                .map(|line| redact(line).chars().take(80).collect::<String>());
+            if let Some(line) = last_line {
+                if !done.load(Ordering::Relaxed) {
+                    pb.set_message(format!("{} {}", message, cdim!(line)));
+                }
+            }
+
+            // Only the last line is ever shown, drop everything before it.
+            if let Some(idx) = pending.trim_end().rfind('\n') {
+                pending = pending.split_off(idx + 1);
+            }
+        }
     }
 
     fn deploy_group(&self, module: &GroupDefinition) {
@@ -230,6 +419,15 @@ impl Deployer {
         ws.stop_with_status(csuccess!("(Done)").to_string());
     }
 
+    fn skip_unchanged(&self, module: &ServiceOrTaskDefinition) {
+        let message = format!("Deploying {}", cbold!(&module.name));
+        let spin_opt = SpinnerOptions::new(message);
+
+        let pb = self.multiprogress.add(ProgressBar::new(u64::MAX));
+        let mut ws = WaitSpin::from(&spin_opt, pb);
+        ws.stop_with_status(cdim!("(Unchanged)").to_string());
+    }
+
     fn should_deploy(&self, module_name: &str, force: bool) -> bool {
         if force {
             true
@@ -365,7 +563,7 @@ impl Deployer {
         deploy_opts: &DeployOptions,
     ) -> Result<ModuleDeploymentPlan> {
         let module_defs: Vec<_> = modules.iter().map(|m| m.value).collect();
-        let plan = get_plan(&module_defs, deploy_opts, &cfg.daemon_url)?;
+        let plan = get_plan(&module_defs, deploy_opts, cfg.daemon()?)?;
 
         let should_deploy = modules
             .iter()
@@ -376,7 +574,8 @@ impl Deployer {
                     // already deployed). Maybe considering skipping anyway to
                     // avoid the net request.
                     let should_deploy =
-                        Self::is_going_to_deploy(&plan, &svc.name);
+                        Self::is_going_to_deploy(&plan, &svc.name)
+                            || deploy_opts.is_forced(&svc.name);
                     Some((svc.name.clone(), should_deploy))
                 }
                 InnerDefinition::Task(tsk) => {
@@ -385,6 +584,7 @@ impl Deployer {
                     let any_origin_deploys =
                         module.origin_nodes.iter().any(|origin_node| {
                             Self::is_going_to_deploy(&plan, origin_node)
+                                || deploy_opts.is_forced(origin_node)
                         });
                     Some((tsk.name.clone(), any_origin_deploys))
                 }
@@ -392,6 +592,30 @@ impl Deployer {
             })
             .collect();
 
-        Ok(ModuleDeploymentPlan { should_deploy })
+        let changed = plan
+            .hashes
+            .into_iter()
+            .filter(|(_, hashes)| {
+                hashes
+                    .deployed
+                    .as_ref()
+                    .map_or(false, |deployed| *deployed != hashes.requested)
+            })
+            .map(|(name, _)| name)
+            .collect();
+
+        Ok(ModuleDeploymentPlan {
+            should_deploy,
+            planned: plan.plan,
+            changed,
+        })
+    }
+}
+
+/// Formats why a probe failed, to follow a message about its failure.
+fn probe_reason(reason: &Option<String>) -> String {
+    match reason {
+        Some(reason) => format!(" ({})", reason),
+        None => String::new(),
     }
 }
@@ -0,0 +1,110 @@
+use crate::client::cli::ClientConfig;
+use crate::client::commands::task::format_timestamp;
+use anyhow::{bail, Result};
+use indicatif::HumanBytes;
+use std::time::Duration;
+
+/// The number of queued commands above which the monitor runtime is
+/// considered to be falling behind (its queue holds 32).
+const MAX_MONITOR_QUEUE_DEPTH: usize = 16;
+
+/// How long ago the env-grabber may have last refreshed the login
+/// environment before it is considered stale (it refreshes every 5s).
+const MAX_ENV_AGE: Duration = Duration::from_secs(60);
+
+/// The space in the log directory below which it is considered to be
+/// running out.
+const MIN_LOG_DIR_SPACE: u64 = 1024 * 1024 * 1024;
+
+/// Checks the health of the internals of the daemon, printing the result of
+/// each check. Fails if any of them found a problem.
+pub fn doctor_cmd(cfg: &ClientConfig) -> Result<()> {
+    let health = cfg.daemon()?.daemon_health()?;
+    let mut problems = 0;
+    let mut check = |ok: bool, name: &str, detail: String| {
+        let mark = if ok {
+            csuccess!("✔")
+        } else {
+            problems += 1;
+            cfail!("✘")
+        };
+        println!("{} {:<18}{}", mark, cbold!(name), detail);
+    };
+
+    let uptime = timeago::Formatter::new()
+        .ago("")
+        .convert(Duration::from_secs(health.uptime));
+    check(
+        true,
+        "Daemon",
+        format!(
+            "up for {}, since {}",
+            uptime,
+            format_timestamp(health.started_at)
+        ),
+    );
+    match health.tracked_modules {
+        Some(count) => check(true, "Modules", format!("{} tracked", count)),
+        None => check(
+            false,
+            "Modules",
+            String::from("the daemon is too busy to count them"),
+        ),
+    }
+    check(
+        health.monitor_runtime_alive,
+        "Monitor runtime",
+        if health.monitor_runtime_alive {
+            String::from("responsive")
+        } else {
+            String::from("not responding, probes are not being checked")
+        },
+    );
+    check(
+        health.monitor_queue_depth <= MAX_MONITOR_QUEUE_DEPTH,
+        "Probes",
+        format!(
+            "{} polled, {} queued commands",
+            health.monitors, health.monitor_queue_depth
+        ),
+    );
+    if health.env_grabber_enabled {
+        let age = health.env_refreshed_at.map(|refreshed_at| {
+            let now = chrono::Local::now().timestamp().max(0) as u64;
+            Duration::from_secs(now.saturating_sub(refreshed_at))
+        });
+        check(
+            age.map_or(false, |age| age <= MAX_ENV_AGE),
+            "Env-grabber",
+            match health.env_refreshed_at {
+                Some(refreshed_at) => {
+                    format!("last refreshed {}", format_timestamp(refreshed_at))
+                }
+                None => String::from("never refreshed"),
+            },
+        );
+    } else {
+        check(true, "Env-grabber", String::from("disabled"));
+    }
+    match health.log_dir_available {
+        Some(available) => check(
+            available >= MIN_LOG_DIR_SPACE,
+            "Log directory",
+            format!(
+                "{} available in {}",
+                HumanBytes(available),
+                health.log_dir
+            ),
+        ),
+        None => check(
+            false,
+            "Log directory",
+            format!("could not read the free space of {}", health.log_dir),
+        ),
+    }
+
+    if problems > 0 {
+        bail!("Found {} problem(s) with the daemon", problems);
+    }
+    Ok(())
+}
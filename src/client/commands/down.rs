@@ -1,12 +1,32 @@
 use crate::client::cli::ClientConfig;
+use crate::client::commands::lock::with_deploy_lock;
+use crate::client::commands::stop::teardown_order;
+use crate::client::definitions::read_project_definition;
 use crate::client::emoji::{HOUR_GLASS, SUCCESS};
-use crate::client::request;
+use crate::client::module::Hook;
+use crate::client::process::run_hook;
+use crate::daemon::api::ApiModuleRunStatus;
 use anyhow::Result;
 use console::style;
 
-pub fn down_cmd(cfg: &ClientConfig) -> Result<()> {
+pub fn down_cmd(cfg: &ClientConfig, wait_for_lock: bool) -> Result<()> {
+    with_deploy_lock(cfg.daemon()?, "down", wait_for_lock, || down(cfg))
+}
+
+fn down(cfg: &ClientConfig) -> Result<()> {
     tprintstep!("Stopping all service(s)...", 1, 2, HOUR_GLASS);
-    request::stop_all(&cfg.daemon_url)?;
+    let status = cfg.daemon()?.status()?.status;
+    let running: Vec<&str> = status
+        .iter()
+        .filter(|m| m.status == ApiModuleRunStatus::RUNNING)
+        .map(|m| m.name.as_str())
+        .collect();
+
+    // Stop services in reverse dependency order first, then clean up anything
+    // that may have been left behind.
+    cfg.daemon()?.stop_many(&teardown_order(&running, cfg))?;
+    cfg.daemon()?.stop_all()?;
+    run_hook(Hook::PostDown, &read_project_definition(cfg)?)?;
     tprintstep!(style("Service(s) stopped").bold().green(), 2, 2, SUCCESS);
     Ok(())
 }
@@ -0,0 +1,187 @@
+use crate::client::cli::ClientConfig;
+use crate::client::cmd::shell_quote;
+use crate::client::commands::shell::service_tmpdir_env;
+use crate::client::commands::DeployOptions;
+use crate::client::definitions::get_module_by_name;
+use crate::client::dotenv::dotenv_quote;
+use crate::client::mask::redact;
+use crate::client::module::{
+    is_masked, resolve_env, EnvValue, InnerDefinition,
+};
+use crate::client::request::build_env;
+use crate::client::substitution::substituted_command;
+use crate::constants::MASKED_VALUE;
+use anyhow::{bail, Result};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// Prints the environment variables of a service, task or shell as defined,
+/// with the active environment sets applied.
+///
+/// Secrets are never read and the values of masked variables are printed as
+/// `********`, while substituted commands are printed as written rather than
+/// run.
+pub fn env_cmd(
+    module_name: &str,
+    opts: &DeployOptions,
+    cfg: &ClientConfig,
+) -> Result<()> {
+    let module = match get_module_by_name(module_name, cfg)? {
+        Some(module) => module,
+        None => bail!("Module with name {} not found", module_name),
+    };
+    let environment = match &module.inner {
+        InnerDefinition::Service(svc) | InnerDefinition::Task(svc) => {
+            build_env(svc, opts)?
+        }
+        InnerDefinition::Shell(shell) => shell.environment.clone(),
+        _ => bail!("{} has no environment", module_name),
+    };
+
+    let environment: BTreeMap<_, _> = environment.iter().collect();
+    for (key, value) in environment {
+        let value = if is_masked(key, value) {
+            MASKED_VALUE
+        } else {
+            value.plain().unwrap_or(MASKED_VALUE)
+        };
+        println!("{}={}", key, value);
+    }
+
+    Ok(())
+}
+
+/// Prints the fully resolved environment of a service, task or shell (with
+/// secrets read and substituted commands run), in a format which can be
+/// evaluated by a shell (`sh` or `fish`) or read as a `dotenv` file.
+///
+/// Services with a temp directory also get the variable it is exported as,
+/// if they are running.
+pub fn env_export_cmd(
+    module_name: &str,
+    format: &str,
+    opts: &DeployOptions,
+    cfg: &ClientConfig,
+) -> Result<()> {
+    let module = match get_module_by_name(module_name, cfg)? {
+        Some(module) => module,
+        None => bail!("Module with name {} not found", module_name),
+    };
+    let mut environment = match &module.inner {
+        InnerDefinition::Service(svc) | InnerDefinition::Task(svc) => {
+            resolve_env(&build_env(svc, opts)?)?
+        }
+        InnerDefinition::Shell(shell) => resolve_env(&shell.environment)?,
+        _ => bail!("{} has no environment", module_name),
+    };
+    if let InnerDefinition::Service(svc) = &module.inner {
+        if svc.tmpdir {
+            environment.extend(service_tmpdir_env(module_name, cfg));
+        }
+    }
+
+    let environment: BTreeMap<_, _> = environment.iter().collect();
+    for (key, value) in environment {
+        match format {
+            "fish" => println!("set -gx {} {}", key, fish_quote(value)),
+            "dotenv" => println!("{}={}", key, dotenv_quote(value)),
+            _ => println!("export {}={}", key, shell_quote(value)),
+        }
+    }
+
+    Ok(())
+}
+
+/// Quotes a value so that fish reads it as a single word.
+fn fish_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// Compares the environment a running service was launched with against the
+/// one its current definition (with the active environment sets applied)
+/// would produce, printing the variables which would be added (`+`), removed
+/// (`-`) or changed (`~`) by deploying it again.
+///
+/// Unlike [env_cmd] secrets are read and substituted commands are run, so
+/// that their values can be compared. Masked values are never printed.
+pub fn env_diff_cmd(
+    service_name: &str,
+    opts: &DeployOptions,
+    cfg: &ClientConfig,
+) -> Result<()> {
+    let service = match get_module_by_name(service_name, cfg)? {
+        Some(module) => match module.inner {
+            InnerDefinition::Service(svc) => svc,
+            _ => bail!("{} is not a service", service_name),
+        },
+        None => bail!("Service with name {} not found", service_name),
+    };
+    let running = cfg.daemon()?.module_env(service_name)?;
+    let environment = build_env(&service, opts)?;
+    let current = resolve_env(&environment)?;
+
+    let masked = |key: &str| {
+        running.masked_env.iter().any(|k| k == key)
+            || environment.get(key).map_or(false, |v| is_masked(key, v))
+    };
+    // Variables inherited by the running service only matter if the
+    // definition now sets them.
+    let keys: BTreeSet<_> =
+        current.keys().chain(running.defined.keys()).collect();
+    let mut differences = 0;
+    for key in keys {
+        let show = |value: &str| {
+            if masked(key) {
+                MASKED_VALUE.to_string()
+            } else {
+                redact(value).into_owned()
+            }
+        };
+        match (running.environment.get(key), current.get(key)) {
+            (None, Some(new)) => {
+                println!("{} {}={}", csuccess!("+"), key, show(new));
+            }
+            (Some(old), None) => {
+                println!("{} {}={}", cfail!("-"), key, show(old));
+            }
+            (Some(old), Some(new)) if old != new => {
+                println!(
+                    "{} {}={} {} {}",
+                    cbold!("~"),
+                    key,
+                    show(old),
+                    cdim!("->"),
+                    show(new)
+                );
+            }
+            _ => continue,
+        }
+        differences += 1;
+    }
+
+    if differences == 0 {
+        println!(
+            "The environment of {} matches its definition",
+            cbold!(service_name)
+        );
+    } else if has_substitutions(&environment) {
+        println!(
+            "{}",
+            cdim!(
+                "Substituted commands were run again, so their values may \
+                differ even if the definition did not change."
+            )
+        );
+    }
+
+    Ok(())
+}
+
+/// Whether any of the values are substituted commands.
+fn has_substitutions(environment: &HashMap<String, EnvValue>) -> bool {
+    environment.values().any(|value| match value {
+        EnvValue::Value(value) | EnvValue::Annotated { value, .. } => {
+            substituted_command(value).is_some()
+        }
+        EnvValue::Secret { .. } => false,
+    })
+}
@@ -0,0 +1,78 @@
+use crate::client::cli::ClientConfig;
+use crate::daemon::api::ApiEventCommand;
+use anyhow::Result;
+use chrono::{Local, TimeZone};
+use nix::unistd::{gethostname, getuid, User};
+use std::io::{self, Write};
+use tabwriter::TabWriter;
+
+/// Records a manual intervention on a service (eg. opening a shell) in the
+/// event log of the daemon.
+///
+/// This is best effort, as shells and commands are run by the client and do
+/// not need the daemon.
+pub(crate) fn record_event(
+    kind: &str,
+    module_name: &str,
+    command: &[String],
+    cfg: &ClientConfig,
+) {
+    let event = ApiEventCommand {
+        kind: kind.to_string(),
+        module_name: module_name.to_string(),
+        command: command.to_vec(),
+        user: current_user(),
+        host: current_host(),
+    };
+    if let Ok(daemon) = cfg.daemon() {
+        let _ = daemon.record_event(&event);
+    }
+}
+
+pub(crate) fn current_user() -> String {
+    User::from_uid(getuid())
+        .ok()
+        .flatten()
+        .map(|user| user.name)
+        .unwrap_or_else(|| getuid().to_string())
+}
+
+pub(crate) fn current_host() -> String {
+    let mut buf = [0u8; 256];
+    gethostname(&mut buf)
+        .map(|host| host.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Lists the events recorded by the daemon, newest first, optionally only
+/// those of a service.
+pub fn events_cmd(module_name: Option<&str>, cfg: &ClientConfig) -> Result<()> {
+    let events = cfg.daemon()?.events(module_name)?.events;
+    if events.is_empty() {
+        println!("No events were recorded");
+        return Ok(());
+    }
+
+    let mut tw = TabWriter::new(io::stdout()).minwidth(8);
+    writeln!(&mut tw, "TIME\tUSER\tADDRESS\tKIND\tSERVICE\tCOMMAND")?;
+    for event in &events {
+        let time = Local
+            .timestamp_opt(event.time as i64, 0)
+            .single()
+            .map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_else(|| String::from("-"));
+        writeln!(
+            &mut tw,
+            "{}\t{}@{}\t{}\t{}\t{}\t{}",
+            time,
+            event.user,
+            event.host,
+            event.address.as_deref().unwrap_or("-"),
+            event.kind,
+            event.module_name,
+            event.command.join(" ")
+        )?;
+    }
+    tw.flush()?;
+    Ok(())
+}
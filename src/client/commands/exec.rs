@@ -1,5 +1,7 @@
 use crate::client::cli::ClientConfig;
 use crate::client::cmd::cmd_in_shell;
+use crate::client::commands::events::record_event;
+use crate::client::commands::shell::service_tmpdir_env;
 use crate::client::definitions::get_module_by_name;
 use crate::client::module::InnerDefinition;
 use crate::command_builder::CommandBuilder;
@@ -14,9 +16,9 @@ pub fn exec_cmd(
 ) -> Result<()> {
     let module = get_module_by_name(service, cfg)?;
 
-    let working_dir = if let Some(ref m) = module {
+    let (working_dir, has_tmpdir) = if let Some(ref m) = module {
         if let InnerDefinition::Service(svc) = &m.inner {
-            &svc.working_dir
+            (&svc.working_dir, svc.tmpdir)
         } else {
             bail!(
                 "Expected service with name {} but found {:?}",
@@ -29,8 +31,12 @@ pub fn exec_cmd(
     };
 
     let cmd_line = cmd_in_shell(command);
+    record_event("exec", service, &cmd_line, cfg);
 
     let mut cmd = CommandBuilder::new(&cmd_line);
+    if has_tmpdir {
+        cmd.env(&service_tmpdir_env(service, cfg));
+    }
     cmd.work_dir(working_dir.as_ref().map(Path::new));
 
     #[cfg(unix)]
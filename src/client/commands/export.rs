@@ -0,0 +1,715 @@
+use crate::client::cli::ClientConfig;
+use crate::client::cmd::shell_quote;
+use crate::client::definitions::{
+    locate_module_definitions_file, read_module_definitions,
+};
+use crate::client::emoji::SUCCESS;
+use crate::client::module::{
+    InnerDefinition, Probe, ServiceOrTaskDefinition, TermSignal,
+};
+use crate::client::substitution::substituted_command;
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The prefix of the labels of generated VS Code tasks and launch
+/// configurations, which identifies them when exporting again.
+const VSCODE_LABEL_PREFIX: &str = "cartel: ";
+
+/// Returns the services in the module definitions, in definition order.
+fn read_services(cfg: &ClientConfig) -> Result<Vec<ServiceOrTaskDefinition>> {
+    let services: Vec<_> = read_module_definitions(cfg)?
+        .into_iter()
+        .filter_map(|module| match module.inner {
+            InnerDefinition::Service(def) => Some(def),
+            _ => None,
+        })
+        .collect();
+    if services.is_empty() {
+        bail!("No services found in the module definitions");
+    }
+    Ok(services)
+}
+
+/// Returns the command of a service as a shell statement.
+fn shell_statement(service: &ServiceOrTaskDefinition) -> String {
+    match &service.shell {
+        Some(shell) if service.command.is_empty() => shell.clone(),
+        _ => service
+            .command
+            .iter()
+            .map(|arg| shell_quote(arg))
+            .collect::<Vec<_>>()
+            .join(" "),
+    }
+}
+
+/// Returns the environment of a service to export, leaving out secrets (which
+/// are only read from the keychain when deployed) and values substituted from
+/// the output of a command (which are only run when deployed).
+fn exported_environment(
+    service: &ServiceOrTaskDefinition,
+) -> BTreeMap<&str, &str> {
+    let mut environment = BTreeMap::new();
+    for (key, value) in &service.environment {
+        match value.plain() {
+            Some(value) if substituted_command(value).is_some() => eprintln!(
+                "{} of {} is substituted from a command and was left out of \
+                the export",
+                key, service.name
+            ),
+            Some(value) => {
+                environment.insert(key.as_str(), value);
+            }
+            None => eprintln!(
+                "{} is a secret of {} and was left out of the export",
+                key, service.name
+            ),
+        }
+    }
+    environment
+}
+
+/// Writes the exported content to the given file, or prints it if none is
+/// given.
+fn write_export(content: &str, output: Option<&str>) -> Result<()> {
+    match output {
+        Some(path) => {
+            fs::write(path, content)
+                .with_context(|| format!("Failed to write {}", path))?;
+            tprint!("{} Exported to {}", SUCCESS, cbold!(path));
+        }
+        None => print!("{}", content),
+    }
+    Ok(())
+}
+
+/// Exports the services in the module definitions as a Procfile.
+///
+/// Procfiles only hold a command per process, so the environment and working
+/// directory of each service are folded into it.
+pub fn export_procfile_cmd(
+    cfg: &ClientConfig,
+    output: Option<&str>,
+) -> Result<()> {
+    let services = read_services(cfg)?;
+    let definitions_dir = definitions_dir(cfg)?;
+
+    let mut procfile = String::new();
+    for service in services.iter() {
+        let mut statement = String::new();
+        if let Some(dir) = &service.working_dir {
+            if Path::new(dir) != definitions_dir {
+                let dir = Path::new(dir)
+                    .strip_prefix(&definitions_dir)
+                    .unwrap_or_else(|_| Path::new(dir));
+                statement.push_str(&format!(
+                    "cd {} && ",
+                    shell_quote(&dir.display().to_string())
+                ));
+            }
+        }
+        let environment = exported_environment(service);
+        for (key, value) in environment {
+            statement.push_str(&format!("{}={} ", key, shell_quote(value)));
+        }
+        statement.push_str(&shell_statement(service));
+        procfile.push_str(&format!("{}: {}\n", service.name, statement));
+    }
+    write_export(&procfile, output)
+}
+
+/// Returns the directory of the module definitions file.
+fn definitions_dir(cfg: &ClientConfig) -> Result<PathBuf> {
+    let file =
+        locate_module_definitions_file(&cfg.module_file, &cfg.default_dir)
+            .ok_or_else(|| {
+                anyhow!("Failed to locate module definitions file (cartel.yml)")
+            })?;
+    Ok(file.canonicalize()?.parent().unwrap().to_path_buf())
+}
+
+/// Exports each service in the module definitions as a systemd unit.
+///
+/// The units are named `cartel-<service>.service` and written to `output_dir`,
+/// which defaults to the user unit directory for `--user` units and to the
+/// current directory otherwise. Dependencies on other services are mapped to
+/// `Requires=` and `After=`, while tasks cannot be expressed and are skipped.
+pub fn export_systemd_cmd(
+    cfg: &ClientConfig,
+    user: bool,
+    output_dir: Option<&str>,
+) -> Result<()> {
+    let services = read_services(cfg)?;
+    let service_names: Vec<&str> =
+        services.iter().map(|s| s.name.as_str()).collect();
+    let output_dir = match output_dir {
+        Some(dir) => PathBuf::from(dir),
+        None if user => dirs::config_dir()
+            .ok_or_else(|| anyhow!("Failed to locate users config dir"))?
+            .join("systemd/user"),
+        None => PathBuf::from("."),
+    };
+    fs::create_dir_all(&output_dir).with_context(|| {
+        format!("Failed to create {}", output_dir.display())
+    })?;
+
+    for service in services.iter() {
+        let dependencies: Vec<String> = service
+            .dependencies
+            .iter()
+            .map(|dependency| dependency.name.as_str())
+            .filter(|name| service_names.contains(name))
+            .map(unit_name)
+            .collect();
+        let unit = systemd_unit(service, &dependencies, user);
+        let path = output_dir.join(unit_name(&service.name));
+        fs::write(&path, unit)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        tprint!("{} Exported {}", SUCCESS, cbold!(path.display()));
+    }
+
+    let reload = if user {
+        "systemctl --user daemon-reload"
+    } else {
+        "systemctl daemon-reload"
+    };
+    tprint!("Run `{}` to load the units", reload);
+    Ok(())
+}
+
+/// Returns the unit name of a service, replacing characters systemd does not
+/// allow in unit names.
+fn unit_name(service: &str) -> String {
+    let name: String = service
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || ":-_.".contains(c) {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    format!("cartel-{}.service", name)
+}
+
+fn systemd_unit(
+    service: &ServiceOrTaskDefinition,
+    dependencies: &[String],
+    user: bool,
+) -> String {
+    let mut unit = String::from("[Unit]\n");
+    unit.push_str(&format!("Description={} (cartel)\n", service.name));
+    if !dependencies.is_empty() {
+        unit.push_str(&format!("Requires={}\n", dependencies.join(" ")));
+        unit.push_str(&format!("After={}\n", dependencies.join(" ")));
+    }
+
+    unit.push_str("\n[Service]\n");
+    unit.push_str(&format!("ExecStart={}\n", systemd_exec_start(service)));
+    if let Some(dir) = &service.working_dir {
+        unit.push_str(&format!(
+            "WorkingDirectory={}\n",
+            dir.replace('%', "%%")
+        ));
+    }
+    let environment = exported_environment(service);
+    for (key, value) in environment {
+        unit.push_str(&format!(
+            "Environment={}\n",
+            systemd_quote(&format!("{}={}", key, value), false)
+        ));
+    }
+    let signal = match service.termination_signal {
+        TermSignal::KILL => "SIGKILL",
+        TermSignal::TERM => "SIGTERM",
+        TermSignal::INT => "SIGINT",
+    };
+    unit.push_str(&format!("KillSignal={}\n", signal));
+    unit.push_str("Restart=on-failure\n");
+
+    unit.push_str("\n[Install]\n");
+    let target = if user {
+        "default.target"
+    } else {
+        "multi-user.target"
+    };
+    unit.push_str(&format!("WantedBy={}\n", target));
+    unit
+}
+
+/// Returns the `ExecStart=` command line of a service.
+///
+/// Shell statements are run through bash (as cartel does by default), while
+/// commands not given as an absolute path are looked up through `env`.
+fn systemd_exec_start(service: &ServiceOrTaskDefinition) -> String {
+    let args: Vec<String> = match &service.shell {
+        Some(shell) if service.command.is_empty() => {
+            vec![String::from("/bin/bash"), String::from("-c"), shell.clone()]
+        }
+        _ if !service.command[0].starts_with('/') => {
+            let mut args = vec![String::from("/usr/bin/env")];
+            args.extend(service.command.iter().cloned());
+            args
+        }
+        _ => service.command.clone(),
+    };
+    args.iter()
+        .map(|arg| systemd_quote(arg, true))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Quotes a value for use in a unit file, escaping `%` specifiers and (for
+/// command lines only) `$` variables.
+fn systemd_quote(value: &str, in_command_line: bool) -> String {
+    let mut escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('%', "%%");
+    if in_command_line {
+        escaped = escaped.replace('$', "$$");
+    }
+    let is_safe = !escaped.is_empty()
+        && escaped
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:=@+,".contains(c));
+    if is_safe {
+        escaped
+    } else {
+        format!("\"{}\"", escaped)
+    }
+}
+
+/// The header of exported Kubernetes manifests.
+const K8S_HEADER: &str = "# Generated by `cartel export k8s`. Replace the \
+    image of each container with one providing its command.\n";
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct K8sManifest<S: Serialize> {
+    api_version: &'static str,
+    kind: &'static str,
+    metadata: K8sMetadata,
+    spec: S,
+}
+
+#[derive(Serialize)]
+struct K8sMetadata {
+    name: String,
+    labels: BTreeMap<&'static str, String>,
+}
+
+#[derive(Serialize)]
+struct K8sDeploymentSpec {
+    replicas: u32,
+    selector: K8sSelector,
+    template: K8sPodTemplate,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct K8sSelector {
+    match_labels: BTreeMap<&'static str, String>,
+}
+
+#[derive(Serialize)]
+struct K8sTemplateMetadata {
+    labels: BTreeMap<&'static str, String>,
+}
+
+#[derive(Serialize)]
+struct K8sPodTemplate {
+    metadata: K8sTemplateMetadata,
+    spec: K8sPodSpec,
+}
+
+#[derive(Serialize)]
+struct K8sPodSpec {
+    containers: Vec<K8sContainer>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct K8sContainer {
+    name: String,
+    image: String,
+    command: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    env: Vec<K8sEnvVar>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    ports: Vec<K8sContainerPort>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    readiness_probe: Option<K8sProbe>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    liveness_probe: Option<K8sProbe>,
+}
+
+#[derive(Serialize)]
+struct K8sEnvVar {
+    name: String,
+    value: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct K8sContainerPort {
+    container_port: u16,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct K8sProbe {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    exec: Option<K8sExecAction>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tcp_socket: Option<K8sTcpSocketAction>,
+    failure_threshold: u32,
+}
+
+#[derive(Serialize)]
+struct K8sExecAction {
+    command: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct K8sTcpSocketAction {
+    port: u16,
+}
+
+#[derive(Serialize)]
+struct K8sServiceSpec {
+    selector: BTreeMap<&'static str, String>,
+    ports: Vec<K8sServicePort>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct K8sServicePort {
+    name: String,
+    port: u16,
+    target_port: u16,
+}
+
+/// Exports the services in the module definitions as Kubernetes manifests.
+///
+/// Each service becomes a Deployment running its command, with its
+/// environment and with exec / network probes mapped to readiness and
+/// liveness probes (log line probes have no equivalent and are left out).
+/// Services listening on a port, as known from their network probes or a
+/// `PORT` variable, are also exposed by a Service.
+pub fn export_k8s_cmd(
+    cfg: &ClientConfig,
+    image: Option<&str>,
+    output: Option<&str>,
+) -> Result<()> {
+    let services = read_services(cfg)?;
+
+    let mut manifests = String::from(K8S_HEADER);
+    for service in services.iter() {
+        let name = k8s_name(&service.name);
+        let ports = service.declared_ports();
+        let image = match image {
+            Some(image) => image.replace("{name}", &name),
+            None => format!("{}:latest", name),
+        };
+        let deployment = k8s_deployment(service, &name, &image, &ports);
+        manifests.push_str(&serde_yaml::to_string(&deployment)?);
+        if !ports.is_empty() {
+            let k8s_service = k8s_service(&name, &ports);
+            manifests.push_str(&serde_yaml::to_string(&k8s_service)?);
+        }
+    }
+    write_export(&manifests, output)
+}
+
+/// Returns the name of a service as a valid Kubernetes object name (a
+/// lowercase DNS label).
+fn k8s_name(service: &str) -> String {
+    let name: String = service
+        .to_ascii_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    let name = name.trim_matches('-');
+    name.chars()
+        .take(63)
+        .collect::<String>()
+        .trim_end_matches('-')
+        .into()
+}
+
+fn k8s_labels(name: &str) -> BTreeMap<&'static str, String> {
+    let mut labels = BTreeMap::new();
+    labels.insert("app", name.to_string());
+    labels
+}
+
+fn k8s_deployment(
+    service: &ServiceOrTaskDefinition,
+    name: &str,
+    image: &str,
+    ports: &BTreeSet<u16>,
+) -> K8sManifest<K8sDeploymentSpec> {
+    let command = match &service.shell {
+        Some(shell) if service.command.is_empty() => {
+            vec![String::from("sh"), String::from("-c"), shell.clone()]
+        }
+        _ => service.command.clone(),
+    };
+    let environment = exported_environment(service);
+    let container = K8sContainer {
+        name: name.to_string(),
+        image: image.to_string(),
+        command,
+        env: environment
+            .into_iter()
+            .map(|(key, value)| K8sEnvVar {
+                name: key.to_string(),
+                value: value.to_string(),
+            })
+            .collect(),
+        ports: ports
+            .iter()
+            .map(|port| K8sContainerPort {
+                container_port: *port,
+            })
+            .collect(),
+        readiness_probe: service.readiness_probe.as_ref().and_then(k8s_probe),
+        liveness_probe: service.liveness_probe.as_ref().and_then(k8s_probe),
+    };
+
+    K8sManifest {
+        api_version: "apps/v1",
+        kind: "Deployment",
+        metadata: K8sMetadata {
+            name: name.to_string(),
+            labels: k8s_labels(name),
+        },
+        spec: K8sDeploymentSpec {
+            replicas: 1,
+            selector: K8sSelector {
+                match_labels: k8s_labels(name),
+            },
+            template: K8sPodTemplate {
+                metadata: K8sTemplateMetadata {
+                    labels: k8s_labels(name),
+                },
+                spec: K8sPodSpec {
+                    containers: vec![container],
+                },
+            },
+        },
+    }
+}
+
+fn k8s_probe(probe: &Probe) -> Option<K8sProbe> {
+    match probe {
+        Probe::Exec(probe) => {
+            let command = match &probe.shell {
+                Some(shell) if probe.command.is_empty() => {
+                    vec![String::from("sh"), String::from("-c"), shell.clone()]
+                }
+                _ => probe.command.clone(),
+            };
+            Some(K8sProbe {
+                exec: Some(K8sExecAction { command }),
+                tcp_socket: None,
+                failure_threshold: probe.retries.max(1),
+            })
+        }
+        Probe::Net(probe) => Some(K8sProbe {
+            exec: None,
+            tcp_socket: Some(K8sTcpSocketAction { port: probe.port }),
+            failure_threshold: probe.retries.max(1),
+        }),
+        Probe::LogLine(_) => None,
+    }
+}
+
+fn k8s_service(
+    name: &str,
+    ports: &BTreeSet<u16>,
+) -> K8sManifest<K8sServiceSpec> {
+    K8sManifest {
+        api_version: "v1",
+        kind: "Service",
+        metadata: K8sMetadata {
+            name: name.to_string(),
+            labels: k8s_labels(name),
+        },
+        spec: K8sServiceSpec {
+            selector: k8s_labels(name),
+            ports: ports
+                .iter()
+                .map(|port| K8sServicePort {
+                    name: format!("port-{}", port),
+                    port: *port,
+                    target_port: *port,
+                })
+                .collect(),
+        },
+    }
+}
+
+/// Exports VS Code tasks and launch configurations for the modules in the
+/// module definitions.
+///
+/// Tasks are generated to deploy, stop and follow the logs of each service
+/// (tasks are run instead of deployed), and to deploy and stop each group.
+/// Each service gets a launch configuration deploying it and following its
+/// logs, and each group a compound launching the configurations of its
+/// services once the group has been deployed.
+///
+/// Generated entries are labelled `cartel: ...` and replace those of a
+/// previous export, while any other entries in the files are kept.
+pub fn export_vscode_cmd(cfg: &ClientConfig, output_dir: &str) -> Result<()> {
+    let modules = read_module_definitions(cfg)?;
+    let service_names: Vec<&str> = modules
+        .iter()
+        .filter(|module| matches!(module.inner, InnerDefinition::Service(_)))
+        .map(|module| module.name.as_str())
+        .collect();
+
+    let mut tasks = vec![];
+    let mut configurations = vec![];
+    let mut compounds = vec![];
+    for module in modules.iter() {
+        let name = module.name.as_str();
+        match &module.inner {
+            InnerDefinition::Service(_) => {
+                tasks.push(vscode_task("deploy", name, &["deploy", name]));
+                tasks.push(vscode_task("stop", name, &["stop", name]));
+                tasks.push(vscode_task("logs", name, &["logs", "-f", name]));
+                configurations.push(json!({
+                    "name": vscode_label(name),
+                    "type": "node-terminal",
+                    "request": "launch",
+                    "command": format!(
+                        "cartel deploy {} && cartel logs -f {}",
+                        shell_quote(name),
+                        shell_quote(name)
+                    ),
+                    "cwd": "${workspaceFolder}",
+                }));
+            }
+            InnerDefinition::Task(_) => {
+                tasks.push(vscode_task("run", name, &["run", name]));
+                tasks.push(vscode_task("logs", name, &["logs", name]));
+            }
+            InnerDefinition::Group(group) => {
+                let services: Vec<&str> = group
+                    .dependencies
+                    .iter()
+                    .map(String::as_str)
+                    .filter(|dependency| service_names.contains(dependency))
+                    .collect();
+                tasks.push(vscode_task("deploy", name, &["deploy", name]));
+                if services.is_empty() {
+                    continue;
+                }
+                let mut stop_args = vec!["stop"];
+                stop_args.extend(services.iter());
+                tasks.push(vscode_task("stop", name, &stop_args));
+                compounds.push(json!({
+                    "name": vscode_label(name),
+                    "configurations": services
+                        .iter()
+                        .map(|service| vscode_label(service))
+                        .collect::<Vec<_>>(),
+                    "preLaunchTask": vscode_label(&format!("deploy {}", name)),
+                    "stopAll": true,
+                }));
+            }
+            _ => {}
+        }
+    }
+
+    let output_dir = Path::new(output_dir);
+    fs::create_dir_all(output_dir).with_context(|| {
+        format!("Failed to create {}", output_dir.display())
+    })?;
+    write_vscode_file(
+        &output_dir.join("tasks.json"),
+        json!({"version": "2.0.0"}),
+        &[("tasks", "label", tasks)],
+    )?;
+    write_vscode_file(
+        &output_dir.join("launch.json"),
+        json!({"version": "0.2.0"}),
+        &[
+            ("configurations", "name", configurations),
+            ("compounds", "name", compounds),
+        ],
+    )
+}
+
+fn vscode_label(name: &str) -> String {
+    format!("{}{}", VSCODE_LABEL_PREFIX, name)
+}
+
+fn vscode_task(action: &str, module: &str, args: &[&str]) -> Value {
+    json!({
+        "label": vscode_label(&format!("{} {}", action, module)),
+        "type": "process",
+        "command": "cartel",
+        "args": args,
+        "problemMatcher": [],
+    })
+}
+
+/// Writes the generated entries into a VS Code configuration file, merging
+/// them with its existing contents (if any).
+///
+/// Each of `lists` names an array of the file, the key identifying its
+/// entries and the generated entries, which replace previously generated
+/// ones.
+fn write_vscode_file(
+    path: &Path,
+    default: Value,
+    lists: &[(&str, &str, Vec<Value>)],
+) -> Result<()> {
+    let mut file = if path.exists() {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        serde_json::from_str(&content).with_context(|| {
+            format!(
+                "Failed to parse {} (comments are not supported, remove \
+                them or export to another directory with --output-dir)",
+                path.display()
+            )
+        })?
+    } else {
+        default
+    };
+    let object = file
+        .as_object_mut()
+        .ok_or_else(|| anyhow!("Expected an object in {}", path.display()))?;
+
+    for (list, key, generated) in lists {
+        let mut entries: Vec<Value> = match object.remove(*list) {
+            Some(Value::Array(entries)) => entries,
+            Some(_) => bail!("Expected '{}' to be a list", list),
+            None => vec![],
+        };
+        entries.retain(|entry| {
+            !entry
+                .get(*key)
+                .and_then(Value::as_str)
+                .map_or(false, |label| label.starts_with(VSCODE_LABEL_PREFIX))
+        });
+        entries.extend(generated.iter().cloned());
+        if !entries.is_empty() {
+            object.insert(list.to_string(), Value::Array(entries));
+        }
+    }
+
+    fs::write(path, serde_json::to_string_pretty(&file)? + "\n")
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    tprint!("{} Exported {}", SUCCESS, cbold!(path.display()));
+    Ok(())
+}
@@ -0,0 +1,67 @@
+use crate::client::cli::ClientConfig;
+use crate::daemon::api::ApiForwardCommand;
+use anyhow::{anyhow, bail, Result};
+
+/// The host connections are forwarded to when only a port is given.
+const DEFAULT_REMOTE_HOST: &str = "127.0.0.1";
+
+/// Forwards a port to a running service, until the service stops.
+///
+/// The forwarding process is run by the daemon, so `local` is a port on the
+/// daemon's host. `ports` has the form `local:remote`, where `remote` is a
+/// port or a `host:port` pair.
+pub fn forward_cmd(
+    service_name: &str,
+    ports: &str,
+    via: Option<&str>,
+    cfg: &ClientConfig,
+) -> Result<()> {
+    let command = parse_forward(ports, via)?;
+    let local_port = command.local_port;
+    let remote = format!("{}:{}", command.remote_host, command.remote_port);
+    let result = cfg.daemon()?.forward(service_name, &command)?;
+
+    if let Some(code) = result.exit_code {
+        for line in &result.log_tail {
+            tiprint!(4, "{}", cdim!(line));
+        }
+        bail!(
+            "The forward of port {} exited with code {} right after being \
+            started.",
+            local_port,
+            code
+        );
+    }
+    tprint!(
+        "Forwarding port {} to {}{} for {} {}",
+        cbold!(local_port),
+        remote,
+        via.map(|v| format!(" via {}", v)).unwrap_or_default(),
+        cbold!(service_name),
+        cdim!(format!("({})", result.name))
+    );
+    Ok(())
+}
+
+fn parse_forward(ports: &str, via: Option<&str>) -> Result<ApiForwardCommand> {
+    let invalid = || {
+        anyhow!(
+            "Invalid ports '{}', expected local:remote (eg. 8080:80 or \
+            8080:host:80)",
+            ports
+        )
+    };
+    let (local, remote) = ports.split_once(':').ok_or_else(invalid)?;
+    let (remote_host, remote_port) = match remote.rsplit_once(':') {
+        Some((host, port)) if !host.is_empty() => (host, port),
+        Some(_) => return Err(invalid()),
+        None => (DEFAULT_REMOTE_HOST, remote),
+    };
+
+    Ok(ApiForwardCommand {
+        local_port: local.parse().map_err(|_| invalid())?,
+        remote_host: remote_host.to_string(),
+        remote_port: remote_port.parse().map_err(|_| invalid())?,
+        via: via.map(str::to_string),
+    })
+}
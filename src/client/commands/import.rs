@@ -0,0 +1,405 @@
+use crate::client::cmd::shell_quote;
+use crate::client::emoji::SUCCESS;
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+use std::collections::{BTreeMap, HashSet};
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// The file names looked up when no compose file is given.
+const COMPOSE_FILES: [&str; 4] = [
+    "docker-compose.yml",
+    "docker-compose.yaml",
+    "compose.yml",
+    "compose.yaml",
+];
+
+/// The name of the Procfile looked up when none is given.
+pub const PROCFILE: &str = "Procfile";
+
+/// The port assigned to the first process of a Procfile.
+const PROCFILE_BASE_PORT: usize = 5000;
+
+/// The number of retries of probes imported from a healthcheck without any.
+const DEFAULT_HEALTHCHECK_RETRIES: u32 = 5;
+
+#[derive(Deserialize)]
+struct ComposeFile {
+    #[serde(default)]
+    services: BTreeMap<String, ComposeService>,
+}
+
+#[derive(Deserialize)]
+struct ComposeService {
+    #[serde(default)]
+    depends_on: ComposeDependsOn,
+    #[serde(default)]
+    environment: ComposeEnvironment,
+    healthcheck: Option<ComposeHealthcheck>,
+}
+
+/// Dependencies are either a list of names, or a map of names to conditions.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ComposeDependsOn {
+    List(Vec<String>),
+    Map(BTreeMap<String, Value>),
+}
+
+impl Default for ComposeDependsOn {
+    fn default() -> Self {
+        ComposeDependsOn::List(vec![])
+    }
+}
+
+/// The environment is either a list of `KEY=VALUE`, or a map.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ComposeEnvironment {
+    List(Vec<String>),
+    Map(BTreeMap<String, Option<Value>>),
+}
+
+impl Default for ComposeEnvironment {
+    fn default() -> Self {
+        ComposeEnvironment::List(vec![])
+    }
+}
+
+#[derive(Deserialize)]
+struct ComposeHealthcheck {
+    test: Option<ComposeTest>,
+    retries: Option<u32>,
+    #[serde(default)]
+    disable: bool,
+}
+
+/// The healthcheck test is either a shell statement, or a list starting with
+/// `CMD`, `CMD-SHELL` or `NONE`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ComposeTest {
+    Shell(String),
+    List(Vec<String>),
+}
+
+#[derive(Serialize)]
+struct ImportedService {
+    kind: &'static str,
+    name: String,
+    shell: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    working_dir: Option<String>,
+    termination_signal: &'static str,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    environment: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    dependencies: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    readiness_probe: Option<ImportedProbe>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    liveness_probe: Option<ImportedProbe>,
+}
+
+#[derive(Serialize, Clone)]
+struct ImportedProbe {
+    #[serde(rename = "type")]
+    probe_type: &'static str,
+    retries: u32,
+    shell: String,
+}
+
+/// Converts a docker-compose file into module definitions.
+///
+/// Each compose service becomes a service running `docker-compose run` for
+/// it, passing on its environment so that environment sets apply to the
+/// container. Healthchecks are mapped to readiness and liveness probes.
+///
+/// The definitions are written to `output`, or appended to it if it already
+/// exists (skipping any modules it already defines).
+pub fn import_compose_cmd(file: Option<&str>, output: &str) -> Result<()> {
+    let compose_path = match file {
+        Some(file) => PathBuf::from(file),
+        None => locate_compose_file()?,
+    };
+    let content = fs::read_to_string(&compose_path).with_context(|| {
+        format!("Failed to read {}", compose_path.display())
+    })?;
+    let compose: ComposeFile = serde_yaml::from_str(&content)
+        .with_context(|| "Failed to parse compose file")?;
+
+    let working_dir = source_working_dir(&compose_path, Path::new(output))?;
+    let compose_file_name = compose_path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("docker-compose.yml");
+
+    let imported = compose
+        .services
+        .iter()
+        .map(|(name, service)| {
+            import_service(name, service, compose_file_name, &working_dir)
+        })
+        .collect();
+    write_definitions(imported, &compose_path, output)
+}
+
+/// Converts a Procfile into module definitions.
+///
+/// Each process type becomes a service running its command. As with foreman,
+/// a `PORT` is assigned to each process (starting at 5000, in steps of 100)
+/// if its command refers to it.
+pub fn import_procfile_cmd(file: Option<&str>, output: &str) -> Result<()> {
+    let procfile_path = PathBuf::from(file.unwrap_or(PROCFILE));
+    let content = fs::read_to_string(&procfile_path).with_context(|| {
+        format!("Failed to read {}", procfile_path.display())
+    })?;
+    let working_dir = source_working_dir(&procfile_path, Path::new(output))?;
+
+    let mut imported = vec![];
+    for (idx, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (name, command) = match line.split_once(':') {
+            Some((name, command)) if is_process_name(name) => {
+                (name.to_string(), command.trim().to_string())
+            }
+            _ => bail!("Invalid Procfile entry on line {}: {}", idx + 1, line),
+        };
+
+        let mut environment = BTreeMap::new();
+        if command.contains("PORT") {
+            let port = PROCFILE_BASE_PORT + 100 * imported.len();
+            environment.insert(String::from("PORT"), port.to_string());
+        }
+        imported.push(ImportedService {
+            kind: "Service",
+            name,
+            shell: command,
+            working_dir: working_dir.clone(),
+            termination_signal: "TERM",
+            environment,
+            dependencies: vec![],
+            readiness_probe: None,
+            liveness_probe: None,
+        });
+    }
+    write_definitions(imported, &procfile_path, output)
+}
+
+fn is_process_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Writes the imported modules to the output file, or appends them if it
+/// already exists (skipping any modules it already defines).
+fn write_definitions(
+    imported: Vec<ImportedService>,
+    source: &Path,
+    output: &str,
+) -> Result<()> {
+    let output_path = Path::new(output);
+    let existing = existing_module_names(output_path)?;
+
+    let mut documents = String::new();
+    let mut skipped = vec![];
+    for module in imported {
+        if existing.contains(&module.name) {
+            skipped.push(module.name);
+            continue;
+        }
+        documents.push_str(&serde_yaml::to_string(&module)?);
+    }
+
+    if documents.is_empty() {
+        bail!("No new services to import from {}", source.display());
+    }
+
+    let mut output_file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(output_path)
+        .with_context(|| format!("Failed to open {}", output))?;
+    if !existing.is_empty() {
+        // Separate the first imported document from the existing ones.
+        output_file.write_all(b"\n")?;
+    } else {
+        documents = documents.trim_start_matches("---\n").to_string();
+    }
+    output_file.write_all(documents.as_bytes())?;
+
+    for name in skipped {
+        tprint!("{} {} (already defined)", cdim!("Skipped"), cbold!(name));
+    }
+    tprint!(
+        "{} Imported {} into {}",
+        SUCCESS,
+        source.display(),
+        cbold!(output)
+    );
+    Ok(())
+}
+
+fn locate_compose_file() -> Result<PathBuf> {
+    let cwd = env::current_dir()?;
+    COMPOSE_FILES
+        .iter()
+        .map(|name| cwd.join(name))
+        .find(|path| path.exists())
+        .ok_or_else(|| {
+            anyhow!("Failed to locate a compose file (docker-compose.yml)")
+        })
+}
+
+/// Returns the names of the modules already defined in the given file.
+fn existing_module_names(path: &Path) -> Result<HashSet<String>> {
+    if !path.exists() {
+        return Ok(HashSet::new());
+    }
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let mut names = HashSet::new();
+    for document in serde_yaml::Deserializer::from_str(&content) {
+        let value = Value::deserialize(document)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+        if let Some(Value::String(name)) = value.get("name") {
+            names.insert(name.clone());
+        }
+    }
+    Ok(names)
+}
+
+/// Returns the working directory of imported services, if the source file is
+/// not in the same directory as the module definitions file.
+fn source_working_dir(
+    source_path: &Path,
+    output_path: &Path,
+) -> Result<Option<String>> {
+    let source_dir = source_path
+        .canonicalize()?
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap();
+    let output_dir = match output_path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.canonicalize()?,
+        _ => env::current_dir()?,
+    };
+    if source_dir == output_dir {
+        Ok(None)
+    } else {
+        Ok(Some(source_dir.display().to_string()))
+    }
+}
+
+fn import_service(
+    name: &str,
+    service: &ComposeService,
+    compose_file_name: &str,
+    working_dir: &Option<String>,
+) -> ImportedService {
+    let environment = compose_environment(&service.environment);
+    let dependencies = match &service.depends_on {
+        ComposeDependsOn::List(names) => names.clone(),
+        ComposeDependsOn::Map(names) => names.keys().cloned().collect(),
+    };
+
+    // The container is named so that probes can execute commands in it.
+    let container_name = format!("cartel-{}", name);
+    let mut shell = format!(
+        "docker-compose -f {} run --rm --no-deps --service-ports --name {}",
+        compose_file_name, container_name
+    );
+    for key in environment.keys() {
+        shell.push_str(&format!(" -e {}", key));
+    }
+    shell.push_str(&format!(" {}", name));
+
+    let probe = service.healthcheck.as_ref().and_then(|healthcheck| {
+        import_healthcheck(healthcheck, &container_name)
+    });
+
+    ImportedService {
+        kind: "Service",
+        name: name.to_string(),
+        shell,
+        working_dir: working_dir.clone(),
+        termination_signal: "INT",
+        environment: environment
+            .into_iter()
+            .filter_map(|(key, value)| value.map(|v| (key, v)))
+            .collect(),
+        dependencies,
+        readiness_probe: probe.clone(),
+        liveness_probe: probe,
+    }
+}
+
+/// Returns the environment of a compose service. Variables without a value
+/// are passed on from the environment of the service.
+fn compose_environment(
+    environment: &ComposeEnvironment,
+) -> BTreeMap<String, Option<String>> {
+    match environment {
+        ComposeEnvironment::List(entries) => entries
+            .iter()
+            .map(|entry| match entry.split_once('=') {
+                Some((key, value)) => {
+                    (key.to_string(), Some(value.to_string()))
+                }
+                None => (entry.to_string(), None),
+            })
+            .collect(),
+        ComposeEnvironment::Map(entries) => entries
+            .iter()
+            .map(|(key, value)| {
+                let value = match value {
+                    Some(Value::String(s)) => Some(s.clone()),
+                    Some(Value::Number(n)) => Some(n.to_string()),
+                    Some(Value::Bool(b)) => Some(b.to_string()),
+                    _ => None,
+                };
+                (key.clone(), value)
+            })
+            .collect(),
+    }
+}
+
+fn import_healthcheck(
+    healthcheck: &ComposeHealthcheck,
+    container_name: &str,
+) -> Option<ImportedProbe> {
+    if healthcheck.disable {
+        return None;
+    }
+    let command = match healthcheck.test.as_ref()? {
+        ComposeTest::Shell(statement) => shell_command(statement),
+        ComposeTest::List(parts) => match parts.split_first() {
+            Some((kind, args)) if kind == "CMD" && !args.is_empty() => args
+                .iter()
+                .map(|arg| shell_quote(arg))
+                .collect::<Vec<_>>()
+                .join(" "),
+            Some((kind, args)) if kind == "CMD-SHELL" && !args.is_empty() => {
+                shell_command(&args.join(" "))
+            }
+            _ => return None,
+        },
+    };
+    Some(ImportedProbe {
+        probe_type: "exec",
+        retries: healthcheck.retries.unwrap_or(DEFAULT_HEALTHCHECK_RETRIES),
+        shell: format!("docker exec {} {}", container_name, command),
+    })
+}
+
+fn shell_command(statement: &str) -> String {
+    format!("sh -c {}", shell_quote(statement))
+}
@@ -0,0 +1,178 @@
+use crate::client::api::CartelApiClient;
+use crate::client::cli::ClientConfig;
+use crate::client::commands::events::{current_host, current_user};
+use crate::client::commands::task::format_timestamp;
+use crate::daemon::api::{ApiLockCommand, ApiLockHolder};
+use anyhow::{bail, Result};
+use crossbeam_utils::thread;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+/// How long the deployment lock is held for unless renewed, which bounds how
+/// long a client which crashed keeps others waiting.
+const LOCK_TTL: Duration = Duration::from_secs(30);
+
+/// How often the deployment lock is renewed while held.
+const LOCK_RENEW_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How often a client waiting for the deployment lock tries to acquire it.
+const LOCK_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Runs `operation` while holding the deployment lock of the daemon, so that
+/// other clients cannot deploy or tear down modules at the same time.
+///
+/// If another client holds the lock this fails, or waits for it to be
+/// released if `wait` is set. The lock is renewed in the background while
+/// `operation` runs, and released once it finishes.
+///
+/// Renewals which fail are retried. If the lock could not be renewed before
+/// it expired, another client may have taken it while `operation` ran, so
+/// this fails even if `operation` succeeded.
+pub fn with_deploy_lock<T>(
+    daemon: &CartelApiClient,
+    description: &str,
+    wait: bool,
+    operation: impl FnOnce() -> Result<T>,
+) -> Result<T> {
+    let id = match acquire(daemon, description, wait)? {
+        Some(id) => id,
+        // The daemon does not support locking.
+        None => return operation(),
+    };
+
+    let (done_tx, done_rx) = mpsc::channel::<()>();
+    let lock_id = id.as_str();
+    let (result, lost) = thread::scope(|s| {
+        let renewal =
+            s.spawn(move |_| renew_until_done(daemon, lock_id, done_rx));
+        let result = operation();
+        drop(done_tx);
+        (
+            result,
+            renewal.join().expect("Lock renewal thread panicked"),
+        )
+    })
+    .expect("Lock renewal thread panicked");
+
+    if let Err(e) = daemon.release_lock(&id) {
+        teprint!(format!("Failed to release the deployment lock: {:#}", e));
+    }
+    match lost {
+        Some(e) if result.is_ok() => Err(e.context(
+            "The deployment lock expired as it could not be renewed, so \
+            another client may have deployed at the same time",
+        )),
+        Some(e) => {
+            teprint!(format!(
+                "The deployment lock expired as it could not be renewed: {:#}",
+                e
+            ));
+            result
+        }
+        None => result,
+    }
+}
+
+/// Renews the deployment lock until `done` is signalled, retrying renewals
+/// which fail.
+///
+/// Returns the error of the last renewal if the lock expired as it could not
+/// be renewed in time.
+fn renew_until_done(
+    daemon: &CartelApiClient,
+    id: &str,
+    done: mpsc::Receiver<()>,
+) -> Option<anyhow::Error> {
+    let mut renewed_at = Instant::now();
+    while let Err(RecvTimeoutError::Timeout) =
+        done.recv_timeout(LOCK_RENEW_INTERVAL)
+    {
+        match daemon.renew_lock(id, LOCK_TTL.as_secs()) {
+            Ok(_) => renewed_at = Instant::now(),
+            Err(e) if renewed_at.elapsed() >= LOCK_TTL => return Some(e),
+            Err(e) => {
+                teprint!(format!(
+                    "Failed to renew the deployment lock, retrying: {:#}",
+                    e
+                ));
+            }
+        }
+    }
+    None
+}
+
+/// Acquires the deployment lock, returning its id or None if the daemon does
+/// not support locking.
+fn acquire(
+    daemon: &CartelApiClient,
+    description: &str,
+    wait: bool,
+) -> Result<Option<String>> {
+    let command = ApiLockCommand {
+        user: current_user(),
+        host: current_host(),
+        pid: std::process::id(),
+        operation: description.to_string(),
+        ttl: LOCK_TTL.as_secs(),
+    };
+    let mut waiting = false;
+    loop {
+        let response = match daemon.acquire_lock(&command)? {
+            Some(response) => response,
+            None => return Ok(None),
+        };
+        if let Some(id) = response.id {
+            return Ok(Some(id));
+        }
+        if !wait {
+            bail!(
+                "Deployments are locked by {}. Try again once it finishes, \
+                or wait for it with --wait-for-lock",
+                describe_holder(&response.holder)
+            );
+        }
+        if !waiting {
+            tprint!(
+                "{} {}",
+                cdim!("Waiting for the deployment lock held by"),
+                describe_holder(&response.holder)
+            );
+            waiting = true;
+        }
+        std::thread::sleep(LOCK_POLL_INTERVAL);
+    }
+}
+
+fn describe_holder(holder: &ApiLockHolder) -> String {
+    format!(
+        "{}@{} (pid {}, {}, since {})",
+        holder.user,
+        holder.host,
+        holder.pid,
+        holder.operation,
+        format_timestamp(holder.acquired_at)
+    )
+}
+
+/// Prints who holds the deployment lock, if anyone.
+pub fn lock_status_cmd(cfg: &ClientConfig) -> Result<()> {
+    match cfg.daemon()?.lock_status()?.holder {
+        Some(holder) => {
+            println!(
+                "{:<14}{}@{}",
+                cbold!("Held by:"),
+                holder.user,
+                holder.host
+            );
+            println!("{:<14}{}", cbold!("Pid:"), holder.pid);
+            println!("{:<14}{}", cbold!("Operation:"), holder.operation);
+            println!(
+                "{:<14}{}",
+                cbold!("Since:"),
+                format_timestamp(holder.acquired_at)
+            );
+        }
+        None => println!("Deployments are not locked"),
+    }
+    Ok(())
+}
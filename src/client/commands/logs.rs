@@ -1,13 +1,29 @@
 use crate::client::cli::ClientConfig;
 use crate::client::definitions::get_module_by_name;
+use crate::client::json_log::pretty_print_line;
 use crate::client::module::{InnerDefinition, ModuleKind};
 use crate::client::request;
-use anyhow::{bail, Result};
-use std::ffi::OsString;
+use crate::daemon::api::ApiLogSearchRequest;
+use crate::daemon::logs::{rotated_segments, COMPRESSED_EXTENSION};
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use flate2::read::GzDecoder;
+use std::collections::VecDeque;
+use std::ffi::{OsStr, OsString};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
 #[cfg(unix)]
 use std::os::unix::process::CommandExt;
-use std::path::Path;
-use std::process::Command;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+/// The number of lines printed before following a log file.
+const TAIL_LINES: usize = 30;
+
+/// The maximum number of lines printed for a time window of the logs.
+const MAX_WINDOW_LINES: usize = 10_000;
 
 pub enum LogMode {
     FULL,
@@ -15,43 +31,315 @@ pub enum LogMode {
     DEFAULT,
 }
 
-fn get_log_file(module_name: &str, cfg: &ClientConfig) -> Result<OsString> {
+/// Information needed to locate the log file of a module.
+struct LogTarget {
+    kind: ModuleKind,
+    project: Option<String>,
+    /// A custom log file path (only used for tasks).
+    custom_path: Option<String>,
+}
+
+fn get_log_target(module_name: &str, cfg: &ClientConfig) -> Result<LogTarget> {
     let module = get_module_by_name(module_name, cfg)?;
+    match module {
+        Some(m) => {
+            let (project, custom_path) = match &m.inner {
+                InnerDefinition::Task(tsk) => {
+                    (tsk.project.clone(), tsk.log_file_path.clone())
+                }
+                InnerDefinition::Service(svc) => (svc.project.clone(), None),
+                _ => (None, None),
+            };
+            Ok(LogTarget {
+                kind: m.kind,
+                project,
+                custom_path,
+            })
+        }
+        // A service may have been removed from the module definitions file. In
+        // such case we want to still try to obtain logs for it.
+        None => Ok(LogTarget {
+            kind: ModuleKind::Service,
+            project: None,
+            custom_path: None,
+        }),
+    }
+}
+
+/// Returns the log file of a module.
+///
+/// For tasks a previous `run` can be selected, `1` being the last run.
+pub(crate) fn get_log_file(
+    module_name: &str,
+    run: Option<usize>,
+    cfg: &ClientConfig,
+) -> Result<OsString> {
+    let target = get_log_target(module_name, cfg)?;
+    if run.is_some() && target.kind != ModuleKind::Task {
+        bail!("Previous runs can only be selected for tasks");
+    }
+
     // If it is a task with a custom path then use that. Since tasks are
     // stateless there is no reason to contact the daemon.
-    if let Some(ref m) = module {
-        if let InnerDefinition::Task(tsk) = &m.inner {
-            if let Some(path) = &tsk.log_file_path {
-                return Ok(OsString::from(path));
+    if let Some(path) = target.custom_path {
+        if run.map_or(false, |run| run > 1) {
+            bail!(
+                "Task {} logs to a custom path, only its last run is kept",
+                module_name
+            );
+        }
+        return Ok(OsString::from(path));
+    }
+
+    let path = request::log_file_path(
+        module_name,
+        &target.kind,
+        target.project.as_deref(),
+        run,
+        cfg.daemon()?,
+    )?
+    .log_file_path;
+    Ok(path)
+}
+
+/// Filters applied to the lines of a log file.
+pub struct LogFilter<'a> {
+    /// A regex that lines must match.
+    pub pattern: Option<&'a str>,
+    /// Only include lines logged at or after this time (in epoch seconds).
+    pub since: Option<i64>,
+    /// Only include lines logged at or before this time (in epoch seconds).
+    pub until: Option<i64>,
+}
+
+/// Parses a `--since`/`--until` value into epoch seconds.
+///
+/// Accepts a duration relative to now (eg. `30s`, `10m`, `2h`, `1d`) or a
+/// timestamp (eg. `2021-08-28T10:00:00Z` or `2021-08-28 10:00:00`).
+pub fn parse_log_time(value: &str) -> Result<i64> {
+    let value = value.trim();
+    let unit_seconds = match value.chars().last() {
+        Some('s') => Some(1),
+        Some('m') => Some(60),
+        Some('h') => Some(60 * 60),
+        Some('d') => Some(24 * 60 * 60),
+        _ => None,
+    };
+    if let Some(unit_seconds) = unit_seconds {
+        if let Ok(amount) = value[..value.len() - 1].parse::<i64>() {
+            return Ok(Local::now().timestamp() - amount * unit_seconds);
+        }
+    }
+
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(value) {
+        return Ok(datetime.timestamp());
+    }
+    NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S")
+        .ok()
+        .and_then(|naive| Local.from_local_datetime(&naive).single())
+        .map(|datetime| datetime.timestamp())
+        .ok_or_else(|| {
+            anyhow!(
+                "Invalid time '{}', expected a duration (eg. 10m, 2h) or a \
+                timestamp (eg. 2021-08-28 10:00:00)",
+                value
+            )
+        })
+}
+
+/// Prints the lines of the modules logs that pass the given filter.
+///
+/// The search is performed by the daemon so that large log files don't have
+/// to be paged through. Lines matching a pattern are prefixed with their line
+/// number.
+pub fn search_logs(
+    module_name: &str,
+    filter: &LogFilter,
+    pretty_json: bool,
+    cfg: &ClientConfig,
+) -> Result<()> {
+    let target = get_log_target(module_name, cfg)?;
+    let request = ApiLogSearchRequest {
+        module_kind: (&target.kind).into(),
+        project: target.project,
+        log_file_path: target.custom_path,
+        pattern: filter.pattern.unwrap_or("").to_string(),
+        since: filter.since,
+        until: filter.until,
+        max_results: match filter.pattern {
+            Some(_) => None,
+            None => Some(MAX_WINDOW_LINES),
+        },
+    };
+    let response = cfg.daemon()?.search_logs(module_name, &request)?;
+
+    for log_line in &response.matches {
+        let line = if pretty_json {
+            pretty_print_line(&log_line.line)
+        } else {
+            log_line.line.clone()
+        };
+        match filter.pattern {
+            Some(_) => {
+                tprint!("{}: {}", cdim!(log_line.line_number), line);
+            }
+            None => {
+                tprint!("{}", line);
             }
         }
     }
 
-    let module_kind = if let Some(m) = module {
-        m.kind
+    if response.truncated {
+        eprintln!(
+            "{}",
+            cdim!(format!(
+                "Only showing the last {} lines",
+                response.matches.len()
+            ))
+        );
+    }
+    Ok(())
+}
+
+/// Opens a log file or rotated segment, decompressing it if needed.
+fn open_log_segment(path: &Path) -> Result<Box<dyn Read>> {
+    let file = File::open(path)?;
+    if path
+        .extension()
+        .map_or(false, |ext| ext == COMPRESSED_EXTENSION)
+    {
+        Ok(Box::new(GzDecoder::new(file)))
     } else {
-        // A service may have been removed from the module definitions file. In
-        // such case we want to still try to obtain logs for it.
-        ModuleKind::Service
-    };
+        Ok(Box::new(file))
+    }
+}
 
-    let path =
-        request::log_file_path(module_name, &module_kind, &cfg.daemon_url)?
-            .log_file_path;
-    Ok(path)
+/// Pages the rotated segments of a log file followed by the log file itself.
+///
+/// The segments are decompressed and piped into the pager one after another.
+fn page_log_segments(
+    segments: &[PathBuf],
+    log_file: &Path,
+    pager_cmd: &[String],
+) -> Result<()> {
+    let mut pager = Command::new(&pager_cmd[0])
+        .args(&pager_cmd[1..])
+        .stdin(Stdio::piped())
+        .spawn()?;
+    let mut stdin = pager.stdin.take().expect("Pager stdin is piped");
+
+    for path in segments.iter().map(PathBuf::as_path).chain([log_file]) {
+        match io::copy(&mut open_log_segment(path)?, &mut stdin) {
+            // The pager was closed before reading all of the logs.
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => break,
+            result => {
+                result?;
+            }
+        }
+    }
+
+    drop(stdin);
+    pager.wait()?;
+    Ok(())
+}
+
+/// Prints the logs of a module, rendering JSON lines in a readable form.
+///
+/// Unlike [print_logs] no pager is used. In the full mode the whole log file
+/// is printed, otherwise the last few lines are printed, and in the follow
+/// mode the file is then followed for new lines.
+pub fn print_pretty_logs(
+    module_name: &str,
+    log_mode: LogMode,
+    run: Option<usize>,
+    cfg: &ClientConfig,
+) -> Result<()> {
+    let log_file = get_log_file(module_name, run, cfg)?;
+
+    if !Path::new(&log_file).exists() {
+        bail!("Log file not found for module {}", module_name);
+    }
+
+    if let LogMode::FULL = log_mode {
+        for segment in rotated_segments(Path::new(&log_file))? {
+            for line in BufReader::new(open_log_segment(&segment)?).lines() {
+                tprint!("{}", pretty_print_line(line?.trim_end()));
+            }
+        }
+    }
+
+    let mut reader = BufReader::new(File::open(&log_file)?);
+    let mut tail = VecDeque::new();
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 0 {
+        match log_mode {
+            LogMode::FULL => {
+                tprint!("{}", pretty_print_line(line.trim_end()));
+            }
+            _ => {
+                if tail.len() == TAIL_LINES {
+                    tail.pop_front();
+                }
+                tail.push_back(line.clone());
+            }
+        }
+        line.clear();
+    }
+
+    if let LogMode::FULL = log_mode {
+        return Ok(());
+    }
+
+    for tail_line in tail {
+        tprint!("{}", pretty_print_line(tail_line.trim_end()));
+    }
+
+    if let LogMode::DEFAULT = log_mode {
+        return Ok(());
+    }
+
+    loop {
+        let position = reader.stream_position()?;
+        if reader.get_ref().metadata()?.len() < position {
+            // The log file was truncated (eg. the service was redeployed).
+            reader.seek(SeekFrom::Start(0))?;
+        }
+
+        if reader.read_line(&mut line)? == 0 {
+            thread::sleep(Duration::from_millis(250));
+            continue;
+        }
+        if line.ends_with('\n') {
+            tprint!("{}", pretty_print_line(line.trim_end()));
+            line.clear();
+        }
+    }
 }
 
+/// Pages the logs of a module.
+///
+/// For tasks a previous `run` can be selected, `1` being the last run.
 pub fn print_logs(
     module_name: &str,
     log_mode: LogMode,
+    run: Option<usize>,
     cfg: &ClientConfig,
 ) -> Result<()> {
-    let log_file = get_log_file(module_name, cfg)?;
+    let log_file = get_log_file(module_name, run, cfg)?;
 
     if !Path::new(&log_file).exists() {
         bail!("Log file not found for module {}", module_name);
     }
 
+    page_log_file(&log_file, log_mode, cfg)
+}
+
+/// Pages a log file using the pager configured for the given mode.
+pub(crate) fn page_log_file(
+    log_file: &OsStr,
+    log_mode: LogMode,
+    cfg: &ClientConfig,
+) -> Result<()> {
     // This might fail on systems like Windows since paths may not be UTF-8
     // encoded there. Since we are using 'less' to page the logs and we don't
     // support Windows this is not currently an issue, but worth revisiting
@@ -66,6 +354,18 @@ pub fn print_logs(
         LogMode::FULL => &cfg.full_pager_cmd,
     };
 
+    // Logs which have been rotated are paged across all of their segments.
+    if let LogMode::FULL = log_mode {
+        let segments = rotated_segments(Path::new(&log_file))?;
+        if !segments.is_empty() {
+            return page_log_segments(
+                &segments,
+                Path::new(&log_file),
+                pager_cmd,
+            );
+        }
+    }
+
     #[cfg(unix)]
     {
         Command::new(&pager_cmd[0])
@@ -1,25 +1,51 @@
+mod attach;
+mod completions;
 mod config;
 mod convert;
 mod daemon;
 mod deploy;
 mod deployer;
+mod doctor;
 mod down;
+mod env;
+mod events;
 mod exec;
+mod export;
+mod forward;
+mod import;
+mod lock;
 mod logs;
+mod open;
 mod ps;
 mod restart;
+mod rollback;
 mod run;
 mod shell;
 mod stop;
+mod task;
+mod tmux;
 
+pub use self::attach::*;
+pub use self::completions::*;
 pub use self::config::*;
 pub use self::daemon::*;
 pub use self::deploy::*;
+pub use self::doctor::*;
 pub use self::down::*;
+pub use self::env::*;
+pub use self::events::*;
 pub use self::exec::*;
+pub use self::export::*;
+pub use self::forward::*;
+pub use self::import::*;
+pub use self::lock::*;
 pub use self::logs::*;
+pub use self::open::*;
 pub use self::ps::*;
 pub use self::restart::*;
+pub use self::rollback::*;
 pub use self::run::*;
 pub use self::shell::*;
 pub use self::stop::*;
+pub use self::task::*;
+pub use self::tmux::*;
@@ -0,0 +1,91 @@
+use crate::client::cli::ClientConfig;
+use crate::client::definitions::get_module_by_name;
+use crate::client::module::InnerDefinition;
+use crate::daemon::api::{ApiModuleRunStatus, ApiProbeStatus};
+use anyhow::{anyhow, bail, Context, Result};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+use std::{env, thread};
+
+/// How often the readiness of a service is checked before opening its URL.
+const READINESS_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The command opening URLs in the browser, unless `$BROWSER` is set.
+#[cfg(target_os = "macos")]
+const OPEN_COMMAND: &str = "open";
+#[cfg(not(target_os = "macos"))]
+const OPEN_COMMAND: &str = "xdg-open";
+
+/// Opens the URL of a running service in the browser, once its readiness
+/// probe passes.
+pub fn open_cmd(service: &str, cfg: &ClientConfig) -> Result<()> {
+    let module = get_module_by_name(service, cfg)?;
+    let url = match module.as_ref().map(|m| &m.inner) {
+        Some(InnerDefinition::Service(svc)) => svc.url.clone(),
+        Some(_) => bail!("Expected service with name {}", service),
+        None => bail!("Service with name {} not found", service),
+    };
+    let url = url.ok_or_else(|| {
+        anyhow!("The service {} does not define a url to open", service)
+    })?;
+    open_service_url(service, &url, cfg)
+}
+
+/// Opens the URL of a service in the browser, waiting until its readiness
+/// probe passes.
+pub(crate) fn open_service_url(
+    service: &str,
+    url: &str,
+    cfg: &ClientConfig,
+) -> Result<()> {
+    wait_until_ready(service, cfg)?;
+    let browser =
+        env::var("BROWSER").unwrap_or_else(|_| OPEN_COMMAND.to_string());
+    Command::new(&browser)
+        .arg(url)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| {
+            format!("Failed to open {} with '{}'", url, browser)
+        })?;
+    tprint!("Opened {} {}", cbold!(service), cdim!(url));
+    Ok(())
+}
+
+fn wait_until_ready(service: &str, cfg: &ClientConfig) -> Result<()> {
+    let mut waiting = false;
+    loop {
+        let status = cfg
+            .daemon()?
+            .status()?
+            .status
+            .into_iter()
+            .find(|m| m.name == service)
+            .filter(|m| m.status == ApiModuleRunStatus::RUNNING)
+            .ok_or_else(|| {
+                anyhow!(
+                    "The service {} is not running (deploy it with `cartel \
+                    deploy --open {}`)",
+                    service,
+                    service
+                )
+            })?;
+        match status.readiness_status {
+            Some(ApiProbeStatus::Pending) | Some(ApiProbeStatus::Failing) => {
+                if !waiting {
+                    tprint!("Waiting for {} to be ready", cbold!(service));
+                    waiting = true;
+                }
+                thread::sleep(READINESS_POLL_INTERVAL);
+            }
+            Some(ApiProbeStatus::RetriesExceeded)
+            | Some(ApiProbeStatus::Error) => {
+                bail!("The readiness probe of {} failed", service)
+            }
+            // Services without a readiness probe are ready once running.
+            Some(ApiProbeStatus::Successful) | None => return Ok(()),
+        }
+    }
+}
@@ -1,25 +1,400 @@
 use crate::client::cli::ClientConfig;
-use crate::client::request;
-use crate::daemon::api::{ApiModuleRunStatus, ApiProbeStatus};
-use anyhow::Result;
-use chrono::Local;
+use crate::client::definitions::read_module_definitions;
+use crate::client::module::{
+    group_services, InnerDefinition, ModuleDefinition,
+};
+use crate::daemon::api::{ApiModuleRunStatus, ApiModuleStatus, ApiProbeStatus};
+use crate::{config, glob};
+use anyhow::{anyhow, bail, Result};
+use chrono::{Local, TimeZone};
 use clap::ArgMatches;
-use console::Style;
+use console::{Style, Term};
+use indicatif::HumanBytes;
+use nix::sys::signal::Signal;
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::convert::TryFrom;
-use std::io;
-use std::io::Write;
+use std::io::{self, Write};
+use std::thread;
 use std::time::Duration;
 use tabwriter::TabWriter;
 
 pub struct PsOpts {
     pub color: bool,
+    /// Only print the names of the modules.
+    pub quiet: bool,
+    /// The columns to print, in order.
+    pub columns: Vec<Column>,
+    /// Only list modules with this status.
+    pub status: Option<ApiModuleRunStatus>,
+    /// Only list modules with a name matching this (glob) pattern.
+    pub name: Option<String>,
+    pub sort: Option<PsSort>,
+    /// List services under the groups they belong to.
+    pub by_group: bool,
+    /// Also list services which are defined but were never deployed.
+    pub all: bool,
+    /// Only list modules listening on this port.
+    pub port: Option<u16>,
+    /// Show when modules were started (or exited) as local timestamps,
+    /// rather than how long ago.
+    pub timestamps: bool,
+    /// Print the status of each module as JSON instead of a table.
+    pub json: bool,
+    /// Redraw the list with this interval, until interrupted.
+    pub watch: Option<Duration>,
+}
+
+/// A line of the table: a module known to the daemon, or (with `--all`) a
+/// service which is defined but was never deployed.
+enum Row<'a> {
+    Module(&'a ApiModuleStatus),
+    NotDeployed(&'a str),
+}
+
+impl Row<'_> {
+    fn name(&self) -> &str {
+        match self {
+            Row::Module(mod_status) => &mod_status.name,
+            Row::NotDeployed(name) => name,
+        }
+    }
+
+    fn module(&self) -> Option<&ApiModuleStatus> {
+        match self {
+            Row::Module(mod_status) => Some(mod_status),
+            Row::NotDeployed(_) => None,
+        }
+    }
+}
+
+/// A column of the table, which formats a value of the status of a module
+/// (or the summary of a group).
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Column {
+    Pid,
+    Name,
+    Liveness,
+    Status,
+    Ports,
+    Cpu,
+    Mem,
+    Restarts,
+    Exit,
+    Uptime,
+}
+
+/// The columns which can be selected with `--columns`, by name.
+const COLUMNS: &[(&str, Column)] = &[
+    ("pid", Column::Pid),
+    ("name", Column::Name),
+    ("liveness", Column::Liveness),
+    ("status", Column::Status),
+    ("ports", Column::Ports),
+    ("cpu", Column::Cpu),
+    ("mem", Column::Mem),
+    ("restarts", Column::Restarts),
+    ("exit", Column::Exit),
+    ("uptime", Column::Uptime),
+];
+
+const DEFAULT_COLUMNS: &[Column] = &[
+    Column::Pid,
+    Column::Name,
+    Column::Liveness,
+    Column::Status,
+    Column::Ports,
+    Column::Cpu,
+    Column::Mem,
+    Column::Uptime,
+];
+
+/// The columns of the wide output, which adds how often modules were
+/// restarted and how they last exited.
+const WIDE_COLUMNS: &[Column] = &[
+    Column::Pid,
+    Column::Name,
+    Column::Liveness,
+    Column::Status,
+    Column::Ports,
+    Column::Cpu,
+    Column::Mem,
+    Column::Restarts,
+    Column::Exit,
+    Column::Uptime,
+];
+
+impl Column {
+    /// Parses a comma separated list of column names.
+    fn parse_list<'a>(
+        names: impl IntoIterator<Item = &'a str>,
+    ) -> Result<Vec<Self>> {
+        names
+            .into_iter()
+            .map(|name| {
+                COLUMNS
+                    .iter()
+                    .find(|(known, _)| *known == name.trim())
+                    .map(|(_, column)| *column)
+                    .ok_or_else(|| {
+                        let known: Vec<_> =
+                            COLUMNS.iter().map(|(known, _)| *known).collect();
+                        anyhow!(
+                            "Unknown column '{}', expected one of {}",
+                            name,
+                            known.join(", ")
+                        )
+                    })
+            })
+            .collect()
+    }
+
+    fn header(self) -> &'static str {
+        match self {
+            Column::Pid => "pid",
+            Column::Name => "name",
+            Column::Liveness => "liveness",
+            Column::Status => "status",
+            Column::Ports => "ports",
+            Column::Cpu => "cpu",
+            Column::Mem => "mem",
+            Column::Restarts => "restarts",
+            Column::Exit => "exit",
+            Column::Uptime => "since",
+        }
+    }
+
+    /// Formats the value of the column for a row. Declared ports are those
+    /// the module is declared to listen on.
+    fn row_value(
+        self,
+        ps_opts: &PsOpts,
+        row: &Row,
+        declared: Option<&BTreeSet<u16>>,
+    ) -> String {
+        match row {
+            Row::Module(mod_status) => {
+                self.module_value(ps_opts, mod_status, declared)
+            }
+            Row::NotDeployed(name) => match self {
+                Column::Name => name.to_string(),
+                Column::Status => String::from("not deployed"),
+                Column::Ports => format_ports(&[], declared),
+                _ => String::from("-"),
+            },
+        }
+    }
+
+    fn module_value(
+        self,
+        ps_opts: &PsOpts,
+        mod_status: &ApiModuleStatus,
+        declared: Option<&BTreeSet<u16>>,
+    ) -> String {
+        match self {
+            Column::Pid => mod_status.pid.to_string(),
+            Column::Name => mod_status.name.clone(),
+            Column::Liveness => {
+                let formatted = match mod_status.liveness_status {
+                    Some(ApiProbeStatus::Pending) => "pending",
+                    Some(ApiProbeStatus::Successful) => "healthy",
+                    Some(ApiProbeStatus::Failing) => "failing",
+                    Some(ApiProbeStatus::Error) => "erroring",
+                    Some(_) => "-",
+                    None => "-",
+                };
+                // Failing probes are followed by the reason, eg. the
+                // connection refused.
+                match (mod_status.liveness_status, &mod_status.liveness_reason)
+                {
+                    (
+                        Some(ApiProbeStatus::Failing)
+                        | Some(ApiProbeStatus::Error),
+                        Some(reason),
+                    ) => format!("{} ({})", formatted, reason),
+                    _ => formatted.to_string(),
+                }
+            }
+            Column::Status => match mod_status.status {
+                ApiModuleRunStatus::RUNNING => "running",
+                ApiModuleRunStatus::STOPPED => "stopped",
+                ApiModuleRunStatus::WAITING => "waiting",
+                ApiModuleRunStatus::EXITED => "exited",
+            }
+            .to_string(),
+            Column::Ports => format_ports(
+                mod_status.ports.as_deref().unwrap_or_default(),
+                declared,
+            ),
+            Column::Cpu => format_cpu(mod_status.cpu_percent),
+            Column::Mem => format_mem(mod_status.rss_bytes),
+            Column::Restarts => mod_status.restarts.to_string(),
+            Column::Exit => {
+                match (mod_status.exit_code, mod_status.exit_signal) {
+                    (Some(code), _) => code.to_string(),
+                    (None, Some(signal)) => Signal::try_from(signal)
+                        .map(|signal| signal.as_str().to_string())
+                        .unwrap_or_else(|_| format!("signal {}", signal)),
+                    (None, None) => String::from("-"),
+                }
+            }
+            Column::Uptime => {
+                if mod_status.status == ApiModuleRunStatus::WAITING {
+                    String::from("N/A")
+                } else if ps_opts.timestamps {
+                    format_timestamp(mod_status.time_since_status)
+                } else {
+                    let now = u64::try_from(Local::now().timestamp()).unwrap();
+                    let dur =
+                        Duration::new(now - mod_status.time_since_status, 0);
+                    timeago::Formatter::new().convert(dur)
+                }
+            }
+        }
+    }
+
+    /// Formats the value of the column for the summary of a group.
+    fn group_value(self, summary: &GroupSummary) -> String {
+        match self {
+            Column::Name => summary.name.to_string(),
+            Column::Liveness => summary.health.to_string(),
+            Column::Status => {
+                format!("{}/{} running", summary.running, summary.total)
+            }
+            Column::Cpu => format_cpu(summary.cpu_percent),
+            Column::Mem => format_mem(summary.rss_bytes),
+            Column::Restarts => summary.restarts.to_string(),
+            Column::Pid | Column::Ports | Column::Exit | Column::Uptime => {
+                String::from("-")
+            }
+        }
+    }
+}
+
+/// Formats the ports a module listens on, followed by the ports it is
+/// declared to listen on but does not (in parentheses).
+fn format_ports(observed: &[u16], declared: Option<&BTreeSet<u16>>) -> String {
+    let formatted = observed
+        .iter()
+        .map(|port| port.to_string())
+        .chain(
+            declared
+                .into_iter()
+                .flatten()
+                .filter(|port| !observed.contains(port))
+                .map(|port| format!("({})", port)),
+        )
+        .collect::<Vec<_>>()
+        .join(",");
+    if formatted.is_empty() {
+        String::from("-")
+    } else {
+        formatted
+    }
+}
+
+/// Formats seconds since the epoch as a local timestamp.
+fn format_timestamp(secs: u64) -> String {
+    match Local.timestamp_opt(secs as i64, 0).single() {
+        Some(time) => time.format("%Y-%m-%d %H:%M:%S").to_string(),
+        None => String::from("-"),
+    }
+}
+
+fn format_cpu(cpu_percent: Option<f32>) -> String {
+    match cpu_percent {
+        Some(cpu) => format!("{:.1}%", cpu),
+        None => String::from("-"),
+    }
+}
+
+fn format_mem(rss_bytes: Option<u64>) -> String {
+    match rss_bytes {
+        Some(rss) => HumanBytes(rss).to_string(),
+        None => String::from("-"),
+    }
+}
+
+/// The order modules are listed in.
+pub enum PsSort {
+    /// Longest running (or stopped) first.
+    Uptime,
+    /// Most CPU first.
+    Cpu,
+    /// Most memory first.
+    Mem,
+    Name,
 }
 
 impl PsOpts {
-    pub fn from(matches: &ArgMatches) -> Self {
-        Self {
-            color: !matches.is_present("no-color"),
+    /// Builds the ps options from the CLI flags, falling back to the columns
+    /// from the client configuration.
+    pub fn from(
+        matches: &ArgMatches,
+        defaults: &config::ClientConfig,
+    ) -> Result<Self> {
+        let status = matches.value_of("status").map(|status| match status {
+            "running" => ApiModuleRunStatus::RUNNING,
+            "stopped" => ApiModuleRunStatus::STOPPED,
+            "waiting" => ApiModuleRunStatus::WAITING,
+            _ => ApiModuleRunStatus::EXITED,
+        });
+        let mut name = None;
+        for filter in matches.values_of("filter").into_iter().flatten() {
+            match filter.split_once('=') {
+                Some(("name", pattern)) => name = Some(pattern.to_string()),
+                _ => bail!(
+                    "Invalid filter '{}', expected name=<pattern>",
+                    filter
+                ),
+            }
         }
+        let sort = matches.value_of("sort").map(|sort| match sort {
+            "uptime" => PsSort::Uptime,
+            "cpu" => PsSort::Cpu,
+            "mem" => PsSort::Mem,
+            _ => PsSort::Name,
+        });
+        let watch = if matches.is_present("watch") {
+            let secs = matches.value_of("watch").unwrap_or("2");
+            match secs.parse::<f64>() {
+                Ok(secs) if secs > 0.0 => Some(Duration::from_secs_f64(secs)),
+                _ => bail!("Invalid interval '{}', expected seconds", secs),
+            }
+        } else {
+            None
+        };
+        let port = matches
+            .value_of("port")
+            .map(|port| {
+                port.parse::<u16>()
+                    .map_err(|_| anyhow!("Invalid port '{}'", port))
+            })
+            .transpose()?;
+        let columns = if let Some(columns) = matches.value_of("columns") {
+            Column::parse_list(columns.split(','))?
+        } else if matches.value_of("output") == Some("wide") {
+            WIDE_COLUMNS.to_vec()
+        } else if let Some(columns) = defaults.ps_columns() {
+            Column::parse_list(columns)
+                .map_err(|e| anyhow!("{} in client.ps_columns", e))?
+        } else {
+            DEFAULT_COLUMNS.to_vec()
+        };
+        Ok(Self {
+            color: !matches.is_present("no-color"),
+            port,
+            watch,
+            quiet: matches.is_present("quiet"),
+            columns,
+            by_group: matches.is_present("by-group"),
+            all: matches.is_present("all"),
+            timestamps: matches.is_present("timestamps"),
+            json: matches.value_of("output") == Some("json"),
+            status,
+            name,
+            sort,
+        })
     }
 }
 
@@ -56,60 +431,315 @@ fn get_line_style(
 }
 
 pub fn list_modules_cmd(ps_opts: &PsOpts, cfg: &ClientConfig) -> Result<()> {
-    let module_status = request::list_modules(&cfg.daemon_url)?;
-    let mut tw = TabWriter::new(io::stdout()).minwidth(8);
-
-    writeln!(
-        &mut tw,
-        "{}",
-        get_header_style(ps_opts)
-            .apply_to("pid\tname\tliveness\tstatus\tsince")
-    )?;
-
-    module_status.status.iter().try_for_each(|mod_status| {
-        let formatted_status = match mod_status.status {
-            ApiModuleRunStatus::RUNNING => "running",
-            ApiModuleRunStatus::STOPPED => "stopped",
-            ApiModuleRunStatus::WAITING => "waiting",
-            ApiModuleRunStatus::EXITED => "exited",
-        };
-        let formatted_liveness_status = match mod_status.liveness_status {
-            Some(ApiProbeStatus::Pending) => "pending",
-            Some(ApiProbeStatus::Successful) => "healthy",
-            Some(ApiProbeStatus::Failing) => "failing",
-            Some(ApiProbeStatus::Error) => "erroring",
-            Some(_) => "-",
-            None => "-",
-        };
+    let interval = match ps_opts.watch {
+        Some(interval) => interval,
+        None => return write_modules(io::stdout(), ps_opts, cfg),
+    };
 
-        let time_formatter = timeago::Formatter::new();
-        let now = u64::try_from(Local::now().timestamp()).unwrap();
-        let dur = Duration::new(now - mod_status.time_since_status, 0);
-        let formatted_time = if mod_status.status == ApiModuleRunStatus::WAITING
-        {
-            String::from("N/A")
-        } else {
-            time_formatter.convert(dur)
-        };
+    // Render each frame fully before replacing the previous one, so that
+    // the table does not flicker.
+    let mut term = Term::stdout();
+    let mut previous_lines = 0;
+    loop {
+        let mut frame = Vec::new();
+        if let Err(e) = write_modules(&mut frame, ps_opts, cfg) {
+            writeln!(frame, "Error: {:?}", e)?;
+        }
+        // When not writing to a terminal each frame is simply appended.
+        if term.features().is_attended() {
+            term.clear_last_lines(previous_lines)?;
+            previous_lines = frame.iter().filter(|&&b| b == b'\n').count();
+        }
+        term.write_all(&frame)?;
+        thread::sleep(interval);
+    }
+}
+
+fn write_modules<W: Write>(
+    mut out: W,
+    ps_opts: &PsOpts,
+    cfg: &ClientConfig,
+) -> Result<()> {
+    let mut module_status = cfg.daemon()?.status_with_ports()?;
+    module_status.status.retain(|m| {
+        ps_opts.status.map_or(true, |status| m.status == status)
+            && ps_opts
+                .name
+                .as_ref()
+                .map_or(true, |pattern| glob::matches(pattern, &m.name))
+            && ps_opts.port.map_or(true, |port| {
+                m.ports
+                    .as_ref()
+                    .map_or(false, |ports| ports.contains(&port))
+            })
+    });
+    if let (Some(port), true) = (ps_opts.port, module_status.status.is_empty())
+    {
+        bail!("No service is listening on port {}", port);
+    }
+    match ps_opts.sort {
+        Some(PsSort::Uptime) => {
+            module_status.status.sort_by_key(|m| m.time_since_status)
+        }
+        Some(PsSort::Cpu) => module_status.status.sort_by(|a, b| {
+            let cpu = |m: &ApiModuleStatus| m.cpu_percent.unwrap_or(-1.0);
+            cpu(b).total_cmp(&cpu(a))
+        }),
+        Some(PsSort::Mem) => {
+            module_status.status.sort_by_key(|m| Reverse(m.rss_bytes))
+        }
+        Some(PsSort::Name) => {
+            module_status.status.sort_by(|a, b| a.name.cmp(&b.name))
+        }
+        None => {}
+    }
 
-        let liveness_status = mod_status
-            .liveness_status
-            .unwrap_or(ApiProbeStatus::Successful);
+    // The module definitions are only needed to group services and list
+    // those never deployed, and otherwise only add the ports services are
+    // declared to listen on.
+    let module_defs = if ps_opts.by_group || ps_opts.all {
+        read_module_definitions(cfg)?
+    } else if ps_opts.quiet {
+        vec![]
+    } else {
+        read_module_definitions(cfg).unwrap_or_default()
+    };
 
-        writeln!(
-            &mut tw,
-            "{}",
-            get_line_style(ps_opts, mod_status.status, liveness_status)
-                .apply_to(format!(
-                    "{}\t{}\t{}\t{}\t{}",
-                    mod_status.pid,
-                    mod_status.name,
-                    formatted_liveness_status,
-                    formatted_status,
-                    formatted_time,
-                ))
-        )
-    })?;
+    let mut rows: Vec<_> =
+        module_status.status.iter().map(Row::Module).collect();
+    // Services which are filtered by status or port are all deployed.
+    if ps_opts.all && ps_opts.status.is_none() && ps_opts.port.is_none() {
+        let deployed: HashSet<_> = module_status
+            .status
+            .iter()
+            .map(|m| m.name.as_str())
+            .collect();
+        rows.extend(
+            module_defs
+                .iter()
+                .filter(|m| matches!(m.inner, InnerDefinition::Service(_)))
+                .map(|m| m.name.as_str())
+                .filter(|name| !deployed.contains(name))
+                .filter(|name| {
+                    ps_opts
+                        .name
+                        .as_ref()
+                        .map_or(true, |pattern| glob::matches(pattern, name))
+                })
+                .map(Row::NotDeployed),
+        );
+        if let Some(PsSort::Name) = ps_opts.sort {
+            rows.sort_by(|a, b| a.name().cmp(b.name()));
+        }
+    }
+
+    if ps_opts.quiet {
+        for row in rows {
+            writeln!(out, "{}", row.name())?;
+        }
+        return Ok(());
+    }
+    if ps_opts.json {
+        let json = rows
+            .iter()
+            .map(|row| match row {
+                Row::Module(mod_status) => serde_json::to_value(mod_status),
+                Row::NotDeployed(name) => Ok(serde_json::json!({
+                    "name": name,
+                    "status": "NOT_DEPLOYED",
+                })),
+            })
+            .collect::<serde_json::Result<Vec<_>>>()?;
+        writeln!(out, "{}", serde_json::to_string_pretty(&json)?)?;
+        return Ok(());
+    }
+
+    let declared_ports: HashMap<&str, BTreeSet<u16>> = module_defs
+        .iter()
+        .filter_map(|m| match &m.inner {
+            InnerDefinition::Service(svc) => {
+                Some((m.name.as_str(), svc.declared_ports()))
+            }
+            _ => None,
+        })
+        .collect();
+
+    let mut tw = TabWriter::new(out).minwidth(8);
+
+    let header = ps_opts
+        .columns
+        .iter()
+        .map(|column| column.header())
+        .collect::<Vec<_>>()
+        .join("\t");
+    writeln!(&mut tw, "{}", get_header_style(ps_opts).apply_to(header))?;
+
+    if ps_opts.by_group {
+        write_groups(&mut tw, ps_opts, &rows, &module_defs, &declared_ports)?;
+    } else {
+        for row in rows.iter() {
+            write_row(&mut tw, ps_opts, row, &declared_ports, "")?;
+        }
+    }
     tw.flush()?;
     Ok(())
 }
+
+fn write_row<W: Write>(
+    out: &mut W,
+    ps_opts: &PsOpts,
+    row: &Row,
+    declared_ports: &HashMap<&str, BTreeSet<u16>>,
+    indent: &str,
+) -> io::Result<()> {
+    let declared = declared_ports.get(row.name());
+    let line = ps_opts
+        .columns
+        .iter()
+        .map(|column| {
+            let value = column.row_value(ps_opts, row, declared);
+            if *column == Column::Name {
+                format!("{}{}", indent, value)
+            } else {
+                value
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\t");
+
+    let style = match row {
+        Row::Module(mod_status) => {
+            let liveness_status = mod_status
+                .liveness_status
+                .unwrap_or(ApiProbeStatus::Successful);
+            get_line_style(ps_opts, mod_status.status, liveness_status)
+        }
+        // Shown like stopped services.
+        Row::NotDeployed(_) => get_line_style(
+            ps_opts,
+            ApiModuleRunStatus::STOPPED,
+            ApiProbeStatus::Successful,
+        ),
+    };
+    writeln!(out, "{}", style.apply_to(line))?;
+
+    // Sidecars are shown under their service.
+    if let Row::Module(mod_status) = row {
+        let indent = format!("{}  ", indent);
+        for sidecar in &mod_status.sidecars {
+            let row = Row::Module(sidecar);
+            write_row(out, ps_opts, &row, declared_ports, &indent)?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes the services under each group (of the module definitions) they
+/// belong to, each group with a summary of its services, followed by any
+/// services which belong to no group.
+fn write_groups<W: Write>(
+    out: &mut W,
+    ps_opts: &PsOpts,
+    rows: &[Row],
+    module_defs: &[ModuleDefinition],
+    declared_ports: &HashMap<&str, BTreeSet<u16>>,
+) -> Result<()> {
+    let mut grouped = HashSet::new();
+    for group in module_defs
+        .iter()
+        .filter(|m| matches!(m.inner, InnerDefinition::Group(_)))
+    {
+        let services = group_services(&group.name, module_defs)?;
+        let members: Vec<_> = rows
+            .iter()
+            .filter(|row| services.contains(&row.name()))
+            .collect();
+        if members.is_empty() {
+            continue;
+        }
+        write_group_summary(out, ps_opts, &group.name, &members)?;
+        for row in members {
+            write_row(out, ps_opts, row, declared_ports, "  ")?;
+            grouped.insert(row.name());
+        }
+    }
+
+    let ungrouped: Vec<_> = rows
+        .iter()
+        .filter(|row| !grouped.contains(row.name()))
+        .collect();
+    if !ungrouped.is_empty() {
+        write_group_summary(out, ps_opts, "(ungrouped)", &ungrouped)?;
+        for row in ungrouped {
+            write_row(out, ps_opts, row, declared_ports, "  ")?;
+        }
+    }
+    Ok(())
+}
+
+/// The summary of the services of a group.
+struct GroupSummary<'a> {
+    name: &'a str,
+    /// Whether the services are all running and healthy ("healthy"), only
+    /// some of them are ("degraded") or none are running ("down").
+    health: &'static str,
+    running: usize,
+    total: usize,
+    cpu_percent: Option<f32>,
+    rss_bytes: Option<u64>,
+    restarts: u32,
+}
+
+/// Writes the line of a group, with how many of its services are running,
+/// their overall health and the total CPU and memory they use.
+fn write_group_summary<W: Write>(
+    out: &mut W,
+    ps_opts: &PsOpts,
+    name: &str,
+    members: &[&Row],
+) -> io::Result<()> {
+    let total = members.len();
+    // Services never deployed count towards the total only.
+    let members: Vec<_> =
+        members.iter().filter_map(|row| row.module()).collect();
+    let running = members
+        .iter()
+        .filter(|m| m.status == ApiModuleRunStatus::RUNNING)
+        .count();
+    let failing = members.iter().any(|m| {
+        matches!(
+            m.liveness_status,
+            Some(ApiProbeStatus::Failing) | Some(ApiProbeStatus::Error)
+        )
+    });
+    let health = if running == 0 {
+        "down"
+    } else if running < total || failing {
+        "degraded"
+    } else {
+        "healthy"
+    };
+    let summary = GroupSummary {
+        name,
+        health,
+        running,
+        total,
+        cpu_percent: members
+            .iter()
+            .any(|m| m.cpu_percent.is_some())
+            .then(|| members.iter().filter_map(|m| m.cpu_percent).sum()),
+        rss_bytes: members
+            .iter()
+            .any(|m| m.rss_bytes.is_some())
+            .then(|| members.iter().filter_map(|m| m.rss_bytes).sum()),
+        restarts: members.iter().map(|m| m.restarts).sum(),
+    };
+
+    let line = ps_opts
+        .columns
+        .iter()
+        .map(|column| column.group_value(&summary))
+        .collect::<Vec<_>>()
+        .join("\t");
+    writeln!(out, "{}", get_header_style(ps_opts).apply_to(line))
+}
@@ -0,0 +1,38 @@
+use crate::client::cli::ClientConfig;
+use crate::client::commands::lock::with_deploy_lock;
+use crate::client::emoji::{HOUR_GLASS, SUCCESS};
+use anyhow::Result;
+use console::style;
+
+/// Redeploys services with the definitions they were deployed with before
+/// their current ones.
+///
+/// If no services are given, the services changed by the last deploy are
+/// rolled back.
+pub fn rollback_cmd(
+    services: &[String],
+    cfg: &ClientConfig,
+    wait_for_lock: bool,
+) -> Result<()> {
+    with_deploy_lock(cfg.daemon()?, "rollback", wait_for_lock, || {
+        rollback(services, cfg)
+    })
+}
+
+fn rollback(services: &[String], cfg: &ClientConfig) -> Result<()> {
+    tprintstep!("Rolling back service(s)...", 1, 2, HOUR_GLASS);
+    let response = cfg.daemon()?.rollback(services)?;
+    let names = response
+        .rolled_back
+        .iter()
+        .map(|s| style(s).white().bold().to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    tprintstep!(
+        format!("{} {}", style("Rolled back").bold().green(), names),
+        2,
+        2,
+        SUCCESS
+    );
+    Ok(())
+}
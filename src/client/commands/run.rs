@@ -1,19 +1,192 @@
 use crate::client::cli::ClientConfig;
+use crate::client::commands::{deploy_cmd, DeployOptions};
 use crate::client::definitions::read_module_definitions;
-use crate::client::module::{module_by_name, InnerDefinition};
-use crate::client::process::run_task;
+use crate::client::module::{
+    module_by_name, InnerDefinition, ModuleDefinition, ServiceOrTaskDefinition,
+};
+use crate::client::process::{run_task, run_task_captured};
+use crate::client::progress::{SpinnerOptions, WaitResult, WaitUntil};
 use anyhow::{anyhow, bail, Result};
+use crossbeam_utils::thread;
+use indicatif::{MultiProgress, ProgressBar};
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::process::ExitStatus;
 
-pub fn run_task_cmd(task_name: &str, cfg: &ClientConfig) -> Result<()> {
+/// How many of the last lines of output of a task which failed are shown,
+/// when running tasks in parallel.
+const FAILED_OUTPUT_LINES: usize = 10;
+
+/// Runs tasks, deploying their dependencies first if given the options to
+/// deploy them with.
+///
+/// Tasks run one after the other, stopping at the first which fails. With
+/// `parallel` they all run at once instead, each with a spinner showing its
+/// latest line of output. Either way this fails if any of the tasks failed.
+pub fn run_tasks_cmd(
+    task_names: &[&str],
+    parallel: bool,
+    deploy_opts: Option<&mut DeployOptions>,
+    cfg: &ClientConfig,
+) -> Result<()> {
     let module_defs = read_module_definitions(cfg)?;
+    let tasks = task_names
+        .iter()
+        .map(|name| find_task(name, &module_defs))
+        .collect::<Result<Vec<_>>>()?;
+
+    if let Some(deploy_opts) = deploy_opts {
+        // The tasks themselves are run below, even if they depend on each
+        // other.
+        let mut dependencies: Vec<&str> = vec![];
+        for dependency in tasks.iter().flat_map(|t| t.dependency_names()) {
+            let dependency = module_by_name(&dependency, &module_defs)
+                .map(|m| m.name.as_str())
+                .ok_or_else(|| anyhow!("Unknown dependency {}", dependency))?;
+            if !task_names.contains(&dependency)
+                && !dependencies.contains(&dependency)
+            {
+                dependencies.push(dependency);
+            }
+        }
+        if !dependencies.is_empty() {
+            deploy_cmd(dependencies, cfg, deploy_opts)?;
+        }
+    }
+
+    if parallel && tasks.len() > 1 {
+        run_parallel(&tasks)
+    } else {
+        run_sequential(&tasks)
+    }
+}
+
+fn find_task<'a>(
+    task_name: &str,
+    module_defs: &'a [ModuleDefinition],
+) -> Result<&'a ServiceOrTaskDefinition> {
     let module_def =
-        module_by_name(task_name, &module_defs).ok_or_else(|| {
+        module_by_name(task_name, module_defs).ok_or_else(|| {
             anyhow!("Failed to find task with name '{}'", task_name)
         })?;
 
     if let InnerDefinition::Task(task) = &module_def.inner {
-        run_task(task)
+        Ok(task)
     } else {
         bail!("Module provided is a {}, not a task", module_def.kind)
     }
 }
+
+fn run_sequential(tasks: &[&ServiceOrTaskDefinition]) -> Result<()> {
+    for task in tasks {
+        if tasks.len() > 1 {
+            tprint!("{} {}", cdim!("Running task"), cbold!(&task.name));
+        }
+        let exit_status = run_task(task)?;
+        if !exit_status.success() {
+            bail!("Task {} failed with {}", task.name, describe(exit_status));
+        }
+    }
+    Ok(())
+}
+
+/// The outcome of a task run in parallel, with its last lines of output.
+struct TaskOutcome<'a> {
+    task: &'a ServiceOrTaskDefinition,
+    failure: Option<String>,
+    output: VecDeque<String>,
+}
+
+fn run_parallel(tasks: &[&ServiceOrTaskDefinition]) -> Result<()> {
+    let multiprogress = MultiProgress::new();
+    // All bars are added before joining, so that the join waits for them.
+    let bars: Vec<_> = tasks
+        .iter()
+        .map(|_| multiprogress.add(ProgressBar::new(u64::MAX)))
+        .collect();
+
+    let outcomes = thread::scope(|s| {
+        let workers: Vec<_> = tasks
+            .iter()
+            .zip(bars)
+            .map(|(task, pb)| s.spawn(move |_| run_with_spinner(task, pb)))
+            .collect();
+        multiprogress.join().unwrap();
+        workers
+            .into_iter()
+            .map(|worker| worker.join().expect("Task thread panicked"))
+            .collect::<Vec<_>>()
+    })
+    .expect("Failed to run tasks");
+
+    let failed: Vec<_> = outcomes
+        .iter()
+        .filter(|outcome| outcome.failure.is_some())
+        .collect();
+    for outcome in &failed {
+        let failure = outcome.failure.as_deref().unwrap_or("an error");
+        tprint!(
+            "\n{} {}",
+            cfail!(format!("Task {} failed with", outcome.task.name)),
+            failure
+        );
+        for line in &outcome.output {
+            tprint!("    {}", cdim!(line));
+        }
+    }
+    if !failed.is_empty() {
+        bail!("{} of {} task(s) failed", failed.len(), tasks.len());
+    }
+    Ok(())
+}
+
+fn run_with_spinner(
+    task: &ServiceOrTaskDefinition,
+    pb: ProgressBar,
+) -> TaskOutcome {
+    let message = format!("Running task {}", cbold!(&task.name));
+    let spin_opt = SpinnerOptions::new(message);
+    let tail_pb = pb.clone();
+    let wu = WaitUntil::new_multi(&spin_opt, pb);
+    let output = Mutex::new(VecDeque::with_capacity(FAILED_OUTPUT_LINES));
+
+    let result = wu.spin_until_status(|| {
+        let exit_status = run_task_captured(task, |line| {
+            if line.trim().is_empty() {
+                return;
+            }
+            let latest: String = line.trim().chars().take(80).collect();
+            tail_pb.set_message(format!(
+                "{} {}",
+                spin_opt.message,
+                cdim!(latest)
+            ));
+            let mut output = output.lock();
+            if output.len() == FAILED_OUTPUT_LINES {
+                output.pop_front();
+            }
+            output.push_back(line.to_string());
+        })?;
+        if exit_status.success() {
+            Ok(WaitResult::from(None, csuccess!("(Done)").to_string()))
+        } else {
+            let failure = describe(exit_status);
+            let status = cfail!(format!("(Failed with {})", failure));
+            Ok(WaitResult::from(Some(failure), status.to_string()))
+        }
+    });
+
+    TaskOutcome {
+        task,
+        failure: result.unwrap_or_else(|e| Some(e.to_string())),
+        output: output.into_inner(),
+    }
+}
+
+/// Describes how a task exited, eg. `exit code 1`.
+fn describe(exit_status: ExitStatus) -> String {
+    match exit_status.code() {
+        Some(code) => format!("exit code {}", code),
+        None => String::from("a signal"),
+    }
+}
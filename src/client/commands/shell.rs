@@ -1,23 +1,197 @@
 use crate::client::cli::ClientConfig;
+use crate::client::commands::events::record_event;
 use crate::client::definitions::read_module_definitions;
-use crate::client::module::{shell_for_service, InnerDefinition};
+use crate::client::module::{
+    module_by_name, shell_for_service, InnerDefinition, ModuleDefinition,
+    ShellDefinition,
+};
 use crate::client::process::run_shell;
+use crate::constants::TMPDIR_ENV;
 use anyhow::{anyhow, bail, Result};
+use std::collections::HashMap;
+use std::io::{self, Write};
+use tabwriter::TabWriter;
+use text_io::read;
 
+/// Opens a shell for a service.
+///
+/// With `service_env` the shell is run with the environment the running
+/// service was spawned with (as resolved by the daemon), rather than that of
+/// the client.
 pub fn open_shell(
     service_name: &str,
     shell_type: Option<&str>,
+    args: &[&str],
+    service_env: bool,
     cfg: &ClientConfig,
 ) -> Result<()> {
     let module_defs = read_module_definitions(cfg)?;
-    let module_def = shell_for_service(service_name, shell_type, &module_defs)
-        .ok_or_else(|| {
-            anyhow!("Failed to find shell for service '{}'", service_name)
-        })?;
+    let shell = match shell_type {
+        None => select_shell(service_name, &module_defs)?,
+        Some(_) => {
+            let module_def =
+                shell_for_service(service_name, shell_type, &module_defs)
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "Failed to find shell for service '{}'",
+                            service_name
+                        )
+                    })?;
+            match &module_def.inner {
+                InnerDefinition::Shell(shell) => shell,
+                _ => bail!(
+                    "Module provided is a {}, not a shell",
+                    module_def.kind
+                ),
+            }
+        }
+    };
 
-    if let InnerDefinition::Shell(shell) = &module_def.inner {
-        run_shell(shell)
+    let environment = if service_env {
+        Some(cfg.daemon()?.module_env(&shell.service)?.environment)
     } else {
-        bail!("Module provided is a {}, not a shell", module_def.kind)
+        None
+    };
+    let has_tmpdir = module_by_name(&shell.service, &module_defs).map_or(
+        false,
+        |m| match &m.inner {
+            InnerDefinition::Service(svc) => svc.tmpdir,
+            _ => false,
+        },
+    );
+    let tmpdir_env = match &environment {
+        // Already part of the environment of the service.
+        None if has_tmpdir => service_tmpdir_env(&shell.service, cfg),
+        _ => HashMap::new(),
+    };
+    let cmd_line = shell.cmd_line(args)?;
+    record_event("shell", &shell.service, &cmd_line, cfg);
+    run_shell(shell, &cmd_line, environment.as_ref(), &tmpdir_env)
+}
+
+/// Returns the temp directory of a running service (see `tmpdir`) as the
+/// variable it is exported to the service as, or nothing if the service is not
+/// running (or the daemon cannot be reached).
+pub(crate) fn service_tmpdir_env(
+    service_name: &str,
+    cfg: &ClientConfig,
+) -> HashMap<String, String> {
+    cfg.daemon()
+        .and_then(|daemon| daemon.module_env(service_name))
+        .map(|response| response.environment)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|(name, _)| name == TMPDIR_ENV)
+        .collect()
+}
+
+/// Lists the shells defined for all services, or only those of a service.
+pub fn list_shells_cmd(
+    service_name: Option<&str>,
+    cfg: &ClientConfig,
+) -> Result<()> {
+    let module_defs = read_module_definitions(cfg)?;
+    let shells: Vec<_> = shells(&module_defs)
+        .filter(|s| service_name.map_or(true, |name| s.service == name))
+        .collect();
+    if shells.is_empty() {
+        match service_name {
+            Some(name) => println!("No shells are defined for {}", name),
+            None => println!("No shells are defined"),
+        }
+        return Ok(());
+    }
+
+    let mut tw = TabWriter::new(io::stdout()).minwidth(8);
+    writeln!(&mut tw, "SERVICE\tTYPE\tCOMMAND")?;
+    for shell in shells {
+        writeln!(
+            &mut tw,
+            "{}\t{}\t{}",
+            shell.service,
+            describe_type(shell),
+            describe_command(shell)
+        )?;
+    }
+    tw.flush()?;
+    Ok(())
+}
+
+/// Selects the shell to open for a service when no type is given.
+///
+/// If the service defines several shells the user is asked to choose one.
+/// Without a terminal to ask on, the shell without a type is opened.
+fn select_shell<'a>(
+    service_name: &str,
+    module_defs: &'a [ModuleDefinition],
+) -> Result<&'a ShellDefinition> {
+    let shells: Vec<_> = shells(module_defs)
+        .filter(|s| s.service == service_name)
+        .collect();
+    match shells.as_slice() {
+        [] => bail!("Failed to find shell for service '{}'", service_name),
+        [shell] => return Ok(shell),
+        _ => {}
+    }
+
+    if !console::user_attended() {
+        return shells
+            .iter()
+            .find(|s| s.shell_type.is_empty())
+            .copied()
+            .ok_or_else(|| {
+                let types: Vec<_> =
+                    shells.iter().map(|s| describe_type(s)).collect();
+                anyhow!(
+                    "Service '{}' has several shells, choose one with --type \
+                    ({})",
+                    service_name,
+                    types.join(", ")
+                )
+            });
+    }
+
+    tprint!("{} has several shells:", cbold!(service_name));
+    for (idx, shell) in shells.iter().enumerate() {
+        tprint!(
+            "  {}) {} {}",
+            idx + 1,
+            describe_type(shell),
+            cdim!(describe_command(shell))
+        );
+    }
+    loop {
+        tprint!("{} (1-{})", cbold!("Which one to open?"), shells.len());
+        let line: String = read!("{}\n");
+        match line.trim().parse::<usize>() {
+            Ok(choice) if choice >= 1 && choice <= shells.len() => {
+                return Ok(shells[choice - 1]);
+            }
+            _ => continue,
+        }
+    }
+}
+
+fn shells(
+    module_defs: &[ModuleDefinition],
+) -> impl Iterator<Item = &ShellDefinition> {
+    module_defs.iter().filter_map(|m| match &m.inner {
+        InnerDefinition::Shell(shell) => Some(shell),
+        _ => None,
+    })
+}
+
+fn describe_type(shell: &ShellDefinition) -> &str {
+    if shell.shell_type.is_empty() {
+        "(default)"
+    } else {
+        &shell.shell_type
+    }
+}
+
+fn describe_command(shell: &ShellDefinition) -> String {
+    match &shell.shell {
+        Some(statement) if shell.command.is_empty() => statement.clone(),
+        _ => shell.command.join(" "),
     }
 }
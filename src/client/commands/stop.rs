@@ -1,27 +1,69 @@
 use crate::client::cli::ClientConfig;
+use crate::client::definitions::read_module_definitions;
 use crate::client::emoji::{HOUR_GLASS, SUCCESS};
 use crate::client::progress::{SpinnerOptions, WaitResult, WaitUntil};
-use crate::client::request;
+use crate::dependency::DependencyGraph;
 use anyhow::Result;
 use console::style;
+use std::collections::HashSet;
 
 pub fn stop_service_cmd(services: Vec<&str>, cfg: &ClientConfig) -> Result<()> {
     tprintstep!("Stopping service(s)...", 1, 2, HOUR_GLASS);
-    for service in services {
-        stop_service(service, cfg)?;
-    }
+    let ordered = teardown_order(&services, cfg);
+    stop_services(&ordered, cfg)?;
     tprintstep!(style("Service(s) stopped").bold().green(), 2, 2, SUCCESS);
     Ok(())
 }
 
-fn stop_service(service: &str, cfg: &ClientConfig) -> Result<()> {
-    let message = format!("Stopping {}", style(service).white().bold());
+/// Orders services so that dependents are stopped before their dependencies.
+///
+/// Services without a module definition are placed last, in the order they
+/// were given. If the module definitions cannot be read (eg. when not within a
+/// project directory) the original order is kept.
+pub(crate) fn teardown_order(
+    services: &[&str],
+    cfg: &ClientConfig,
+) -> Vec<String> {
+    let original = || services.iter().map(|s| s.to_string()).collect();
+
+    let module_defs = match read_module_definitions(cfg) {
+        Ok(module_defs) => module_defs,
+        Err(_) => return original(),
+    };
+
+    let (known, unknown): (Vec<&str>, Vec<&str>) = services
+        .iter()
+        .partition(|s| module_defs.iter().any(|m| m.name == **s));
+
+    let graph = DependencyGraph::from(&module_defs, &known);
+    let sorted = match graph.teardown_sort() {
+        Ok(sorted) => sorted,
+        Err(_) => return original(),
+    };
+
+    let selected: HashSet<&str> = known.iter().copied().collect();
+    sorted
+        .iter()
+        .map(|node| node.key.as_str())
+        .filter(|key| selected.contains(key))
+        .chain(unknown)
+        .map(String::from)
+        .collect()
+}
+
+fn stop_services(services: &[String], cfg: &ClientConfig) -> Result<()> {
+    let names = services
+        .iter()
+        .map(|s| style(s).white().bold().to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let message = format!("Stopping {}", names);
     let spin_opt = SpinnerOptions::new(message);
 
     let wu = WaitUntil::new(&spin_opt);
     wu.spin_until_status(|| {
         let status = style("(Stopped)").white().dim().bold().to_string();
-        request::stop_module(service, &cfg.daemon_url)?;
+        cfg.daemon()?.stop_many(services)?;
         Ok(WaitResult::from((), status))
     })?;
 
@@ -0,0 +1,90 @@
+use crate::client::cli::ClientConfig;
+use crate::client::definitions::read_module_definitions;
+use crate::client::module::{module_by_name, InnerDefinition};
+use crate::daemon::api::ApiTaskExecution;
+use anyhow::Result;
+use chrono::{Local, TimeZone};
+use console::style;
+use std::io::{self, Write};
+use std::time::Duration;
+use tabwriter::TabWriter;
+
+/// Lists the recent executions of a task, newest first.
+///
+/// If the task is defined in the module definitions only the executions of
+/// the task of that project are listed, as tasks of other projects may have
+/// the same name.
+pub fn task_history_cmd(task_name: &str, cfg: &ClientConfig) -> Result<()> {
+    let project = read_module_definitions(cfg).ok().and_then(|module_defs| {
+        match &module_by_name(task_name, &module_defs)?.inner {
+            InnerDefinition::Task(task) => task.project.clone(),
+            _ => None,
+        }
+    });
+    let history = cfg
+        .daemon()?
+        .task_history(Some(task_name), project.as_deref())?;
+    if history.executions.is_empty() {
+        println!("No runs of task {} were recorded", task_name);
+        return Ok(());
+    }
+
+    let mut tw = TabWriter::new(io::stdout()).minwidth(8);
+    writeln!(
+        &mut tw,
+        "STARTED\tDURATION\tEXIT\tATTEMPT\tDEPLOY\tLOG FILE"
+    )?;
+    for execution in &history.executions {
+        writeln!(
+            &mut tw,
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            format_timestamp(execution.started_at),
+            format_duration(execution),
+            format_exit(execution.exit_code),
+            execution.attempt,
+            execution
+                .deployment_id
+                .as_deref()
+                .map(|id| id.chars().take(8).collect())
+                .unwrap_or_else(|| String::from("-")),
+            execution.log_file
+        )?;
+    }
+    tw.flush()?;
+    Ok(())
+}
+
+/// Formats a UNIX timestamp (eg. when a task started), along with how long
+/// ago that was.
+pub(crate) fn format_timestamp(secs: u64) -> String {
+    match Local.timestamp_opt(secs as i64, 0).single() {
+        Some(time) => {
+            let ago = Duration::from_secs(
+                (Local::now().timestamp() - time.timestamp()).max(0) as u64,
+            );
+            format!(
+                "{} ({})",
+                time.format("%Y-%m-%d %H:%M:%S"),
+                timeago::Formatter::new().convert(ago)
+            )
+        }
+        None => String::from("-"),
+    }
+}
+
+fn format_duration(execution: &ApiTaskExecution) -> String {
+    let secs = execution.finished_at.saturating_sub(execution.started_at);
+    if secs < 60 {
+        format!("{}s", secs)
+    } else {
+        format!("{}m {:02}s", secs / 60, secs % 60)
+    }
+}
+
+fn format_exit(exit_code: Option<i32>) -> String {
+    match exit_code {
+        Some(0) => style("0").green().to_string(),
+        Some(code) => style(code).red().to_string(),
+        None => style("signal").red().to_string(),
+    }
+}
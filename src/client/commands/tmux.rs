@@ -0,0 +1,126 @@
+use crate::client::cli::ClientConfig;
+use crate::client::cmd::shell_quote;
+use crate::client::definitions::read_module_definitions;
+use crate::client::module::{
+    group_services, shell_for_service, InnerDefinition, ModuleDefinition,
+};
+use anyhow::{bail, Context, Result};
+use std::env;
+use std::os::unix::prelude::CommandExt;
+use std::process::{Command, Stdio};
+
+/// Opens a tmux session with one pane per service, following its logs.
+///
+/// If a group is given only its services (including those of nested groups)
+/// get a pane. With `shells` the panes of services which define a shell open
+/// it instead. If the session already exists it is attached to as is.
+pub fn tmux_cmd(
+    group: Option<&str>,
+    shells: bool,
+    cfg: &ClientConfig,
+) -> Result<()> {
+    let module_defs = read_module_definitions(cfg)?;
+    let services = match group {
+        Some(group) => group_services(group, &module_defs)?,
+        None => module_defs
+            .iter()
+            .filter(|m| matches!(m.inner, InnerDefinition::Service(_)))
+            .map(|m| m.name.as_str())
+            .collect(),
+    };
+    if services.is_empty() {
+        bail!("No services found to open panes for");
+    }
+
+    let session = session_name(group, &module_defs);
+    if !tmux_status(&["has-session", "-t", &session])? {
+        let exe = env::current_exe()?.display().to_string();
+        let cwd = env::current_dir()?.display().to_string();
+        for (idx, service) in services.iter().enumerate() {
+            let mut pane_cmd = vec![shell_quote(&exe)];
+            if let Some(file) = &cfg.module_file {
+                pane_cmd.push(format!("-f {}", shell_quote(file)));
+            }
+            if shells
+                && shell_for_service(service, None, &module_defs).is_some()
+            {
+                pane_cmd.push(format!("shell {}", shell_quote(service)));
+            } else {
+                pane_cmd.push(format!("logs -f {}", shell_quote(service)));
+            }
+            let pane_cmd = pane_cmd.join(" ");
+
+            if idx == 0 {
+                tmux(&[
+                    "new-session",
+                    "-d",
+                    "-s",
+                    &session,
+                    "-n",
+                    "cartel",
+                    "-c",
+                    &cwd,
+                    &pane_cmd,
+                ])?;
+                // Keep panes of services which are not running around.
+                tmux(&["set-option", "-t", &session, "remain-on-exit", "on"])?;
+                tmux(&[
+                    "set-option",
+                    "-t",
+                    &session,
+                    "pane-border-status",
+                    "top",
+                ])?;
+            } else {
+                tmux(&["split-window", "-t", &session, "-c", &cwd, &pane_cmd])?;
+                // Rebalance so that there is space for the next pane.
+                tmux(&["select-layout", "-t", &session, "tiled"])?;
+            }
+            tmux(&["select-pane", "-t", &session, "-T", service])?;
+        }
+    }
+
+    let attach = if env::var_os("TMUX").is_some() {
+        "switch-client"
+    } else {
+        "attach-session"
+    };
+    let err = Command::new("tmux").args([attach, "-t", &session]).exec();
+    Err(err).context("Failed to attach to the tmux session")
+}
+
+/// Returns the name of the tmux session, made of the project and group.
+fn session_name(
+    group: Option<&str>,
+    module_defs: &[ModuleDefinition],
+) -> String {
+    let project = module_defs.iter().find_map(|m| match &m.inner {
+        InnerDefinition::Service(def) => def.project.clone(),
+        _ => None,
+    });
+    let name = [Some("cartel"), project.as_deref(), group]
+        .iter()
+        .flatten()
+        .copied()
+        .collect::<Vec<_>>()
+        .join("-");
+    // tmux does not allow '.' and ':' in session names.
+    name.replace(['.', ':'], "_")
+}
+
+fn tmux(args: &[&str]) -> Result<()> {
+    if !tmux_status(args)? {
+        bail!("Failed to run tmux {}", args[0]);
+    }
+    Ok(())
+}
+
+fn tmux_status(args: &[&str]) -> Result<bool> {
+    let status = Command::new("tmux")
+        .args(args)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .context("Failed to run tmux (is it installed?)")?;
+    Ok(status.success())
+}
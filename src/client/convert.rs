@@ -1,7 +1,11 @@
-use crate::client::module::{ModuleKind, Probe, TermSignal};
+use crate::client::cmd::shell_to_cmd;
+use crate::client::module::{
+    LogSink, ModuleKind, OncePer, Probe, RunIf, TermSignal, Tooling,
+};
 use crate::daemon::api::{
-    ApiExeProbe, ApiLogLineProbe, ApiModuleKind, ApiNetworkProbe, ApiProbe,
-    ApiProbeStatus, ApiTermSignal,
+    ApiExeProbe, ApiLogLineProbe, ApiLogSink, ApiModuleKind, ApiNetworkProbe,
+    ApiOncePer, ApiProbe, ApiProbeStatus, ApiRunCondition, ApiTermSignal,
+    ApiTooling,
 };
 use crate::daemon::planner::MonitorStatus;
 
@@ -27,6 +31,26 @@ impl From<&TermSignal> for ApiTermSignal {
     }
 }
 
+impl From<&LogSink> for ApiLogSink {
+    fn from(sink: &LogSink) -> ApiLogSink {
+        match sink {
+            LogSink::File => ApiLogSink::File,
+            LogSink::Syslog => ApiLogSink::Syslog,
+            LogSink::Both => ApiLogSink::Both,
+        }
+    }
+}
+
+impl From<&Tooling> for ApiTooling {
+    fn from(tooling: &Tooling) -> ApiTooling {
+        ApiTooling {
+            direnv: tooling.direnv,
+            mise: tooling.mise,
+            nix_shell: None,
+        }
+    }
+}
+
 impl From<&MonitorStatus> for ApiProbeStatus {
     fn from(status: &MonitorStatus) -> Self {
         match status {
@@ -39,6 +63,25 @@ impl From<&MonitorStatus> for ApiProbeStatus {
     }
 }
 
+impl From<OncePer> for ApiOncePer {
+    fn from(once_per: OncePer) -> ApiOncePer {
+        match once_per {
+            OncePer::Always => ApiOncePer::Always,
+            OncePer::Deploy => ApiOncePer::Deploy,
+            OncePer::Day => ApiOncePer::Day,
+        }
+    }
+}
+
+impl From<&RunIf> for ApiRunCondition {
+    fn from(run_if: &RunIf) -> ApiRunCondition {
+        ApiRunCondition {
+            command: run_if.shell.as_deref().map(shell_to_cmd),
+            files_changed: run_if.files_changed.clone(),
+        }
+    }
+}
+
 impl From<&Probe> for ApiProbe {
     fn from(probe: &Probe) -> ApiProbe {
         match probe {
@@ -1,20 +1,22 @@
 use crate::client::cli::ClientConfig;
 use crate::client::module::{
-    InnerDefinition, ModuleDefinition, ModuleKind, Probe,
+    merge_env, Dependency, EnvValue, EnvironmentSet, InnerDefinition,
+    ModuleDefinition, ModuleKind, Probe, ProjectDefinition,
+    ServiceOrTaskDefinition,
 };
 use crate::client::validation::{
     validate_dependencies_exist, validate_fields, validate_modules_unique,
 };
-use crate::path;
+use crate::{glob, path};
 use anyhow::{bail, Context, Result};
 use serde::Deserialize;
 use serde_yaml::Value;
 use std::collections::HashMap;
 use std::env;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::Read;
 use std::option::Option;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 
 /// Parse one or more modules from the given string.
 ///
@@ -29,11 +31,18 @@ pub fn parse_from_yaml_str(
     source: &str,
     path: &Path,
 ) -> Result<Vec<ModuleDefinition>> {
+    let project = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .map(String::from);
     let mut parsed: Vec<ModuleDefinition> = vec![];
     for (idx, document) in
         serde_yaml::Deserializer::from_str(source).enumerate()
     {
         let value = Value::deserialize(document)?;
+        if is_project_document(&value) {
+            continue;
+        }
 
         // Attempt to retrieve and clone the name in an attempt to provide a
         // useful error message to the user.
@@ -60,7 +69,10 @@ pub fn parse_from_yaml_str(
             InnerDefinition::Service(ref mut def) => {
                 m.kind = ModuleKind::Service;
                 def.name = m.name.clone();
+                def.project = project.clone();
                 update_path(&mut def.working_dir, path)?;
+                update_env_set_paths(&mut def.environment_sets, path);
+                update_nix_shell_path(&mut def.nix_shell, path);
                 if let Some(Probe::Exec(ref mut exec)) = def.readiness_probe {
                     update_path(&mut exec.working_dir, path)?;
                 }
@@ -68,7 +80,10 @@ pub fn parse_from_yaml_str(
             InnerDefinition::Task(def) => {
                 m.kind = ModuleKind::Task;
                 def.name = m.name.clone();
+                def.project = project.clone();
                 update_path(&mut def.working_dir, path)?;
+                update_env_set_paths(&mut def.environment_sets, path);
+                update_nix_shell_path(&mut def.nix_shell, path);
                 if let Some(Probe::Exec(ref mut exec)) = def.readiness_probe {
                     update_path(&mut exec.working_dir, path)?;
                 }
@@ -92,6 +107,45 @@ pub fn parse_from_yaml_str(
     Ok(parsed)
 }
 
+/// The kind of the document declaring project-level settings (see
+/// [ProjectDefinition]), which is not a module.
+const PROJECT_KIND: &str = "Project";
+
+fn is_project_document(value: &Value) -> bool {
+    matches!(value.get("kind"), Some(Value::String(kind)) if kind == PROJECT_KIND)
+}
+
+/// Parse the project-level settings from the given string.
+///
+/// Returns the default settings if no document of kind `Project` is given.
+///
+/// # Arguments
+/// * `source` - The source string to parse from, with documents separated
+///   by '---'
+/// * `path` - The path to the *directory* of the module definitions file.
+pub fn parse_project_from_yaml_str(
+    source: &str,
+    path: &Path,
+) -> Result<ProjectDefinition> {
+    let mut project: Option<ProjectDefinition> = None;
+    for document in serde_yaml::Deserializer::from_str(source) {
+        let value = Value::deserialize(document)?;
+        if !is_project_document(&value) {
+            continue;
+        }
+        if project.is_some() {
+            bail!("Only one document of kind {} may be given", PROJECT_KIND);
+        }
+        project =
+            Some(serde_yaml::from_value(value).with_context(|| {
+                format!("In the {} document", PROJECT_KIND)
+            })?);
+    }
+    let mut project = project.unwrap_or_default();
+    update_path(&mut project.working_dir, path)?;
+    Ok(project)
+}
+
 /// Canonicalize the path in the given option.
 ///
 /// The incoming option's content is replaced by a new [String] containing the
@@ -121,6 +175,54 @@ fn update_path(o: &mut Option<String>, relative_to: &Path) -> Result<()> {
     Ok(())
 }
 
+/// Resolve the paths of environment sets given as dotenv files.
+///
+/// Paths are resolved relative to the directory of the module definitions
+/// file. The files themselves are only read once a set is activated, so they
+/// need not exist.
+fn update_env_set_paths(
+    environment_sets: &mut HashMap<String, EnvironmentSet>,
+    relative_to: &Path,
+) {
+    for env_set in environment_sets.values_mut() {
+        if let EnvironmentSet::File(file) = env_set {
+            let resolved = path::from_user_str(file.as_str())
+                .map(|p| relative_to.join(p))
+                .expect("Failed to locate users home dir");
+            *file = resolved.display().to_string();
+        }
+    }
+}
+
+/// Resolve the path of a nix shell given as a local file or flake.
+///
+/// Local paths (eg. `./shell.nix` or `.#backend`) are resolved relative to
+/// the directory of the module definitions file, while other flake references
+/// (eg. `github:owner/repo#shell`) are kept as is.
+fn update_nix_shell_path(nix_shell: &mut Option<String>, relative_to: &Path) {
+    if let Some(nix_shell) = nix_shell.as_mut() {
+        let (location, attr) = match nix_shell.split_once('#') {
+            Some((location, attr)) => (location, Some(attr)),
+            None => (nix_shell.as_str(), None),
+        };
+        if !location.starts_with(|c| c == '.' || c == '/' || c == '~') {
+            return;
+        }
+        let mut resolved = path::from_user_str(location)
+            .map(|p| relative_to.join(p))
+            .expect("Failed to locate users home dir")
+            .components()
+            .filter(|c| *c != Component::CurDir)
+            .collect::<PathBuf>()
+            .display()
+            .to_string();
+        if let Some(attr) = attr {
+            resolved = format!("{}#{}", resolved, attr);
+        }
+        *nix_shell = resolved;
+    }
+}
+
 /// Scans for the given file in the directory tree.
 ///
 /// Tries to discover `file_to_try` in the current directory or any of it's
@@ -270,6 +372,60 @@ fn open_override_file(
     }
 }
 
+/// The name of the project-local config file, placed next to the module
+/// definitions file.
+pub const PROJECT_CONFIG_FILE: &str = ".cartel.toml";
+
+/// Project-local client configuration.
+#[derive(Deserialize, Default)]
+pub struct ProjectConfig {
+    /// Environment sets activated when deploying, unless given with `--env`.
+    #[serde(default)]
+    pub active_envs: Vec<String>,
+}
+
+/// Reads the project-local config file next to the module definitions file.
+///
+/// Returns `None` if either the module definitions file or the project config
+/// file cannot be found.
+pub fn read_project_config(
+    cfg: &ClientConfig,
+) -> Result<Option<ProjectConfig>> {
+    let project_file =
+        locate_module_definitions_file(&cfg.module_file, &cfg.default_dir)
+            .and_then(|path| try_find_sibling(&path, PROJECT_CONFIG_FILE));
+    let project_file = match project_file {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+    let content = fs::read_to_string(&project_file).with_context(|| {
+        format!("Failed to read {}", project_file.display())
+    })?;
+    let project_config = toml::from_str(&content).with_context(|| {
+        format!("Failed to parse {}", project_file.display())
+    })?;
+    Ok(Some(project_config))
+}
+
+/// Reads the project-level settings from the module definitions file.
+///
+/// Returns the default settings if the module definitions file cannot be
+/// found.
+pub fn read_project_definition(
+    cfg: &ClientConfig,
+) -> Result<ProjectDefinition> {
+    let path = match locate_module_definitions_file(
+        &cfg.module_file,
+        &cfg.default_dir,
+    ) {
+        Some(path) => path.canonicalize()?,
+        None => return Ok(ProjectDefinition::default()),
+    };
+    let content = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    parse_project_from_yaml_str(&content, path.parent().unwrap())
+}
+
 /// Parse a module definition file.
 fn parse_module_def_file(
     mut file: File,
@@ -281,17 +437,132 @@ fn parse_module_def_file(
 
     let canonicalized = path.canonicalize()?;
 
-    let module_defs: Vec<ModuleDefinition> =
+    let mut module_defs: Vec<ModuleDefinition> =
         parse_from_yaml_str(&buffer, canonicalized.parent().unwrap())
             .with_context(|| "Failed to read module definitions")?;
 
     validate_modules_unique(&module_defs)?;
+    expand_dependency_patterns(&mut module_defs)?;
     validate_dependencies_exist(&module_defs)?;
     validate_fields(&module_defs)?;
 
     Ok(module_defs)
 }
 
+/// Expand wildcard patterns used in dependency lists.
+///
+/// Entries of `dependencies` and `after` (for services, tasks and groups) that
+/// contain a wildcard (eg. `infra-*`) are replaced by the names of all modules
+/// matching the pattern, in alphabetical order. A module never matches a
+/// pattern in its own dependency list.
+///
+/// # Errors
+///
+/// An error is returned if a pattern does not match any module.
+fn expand_dependency_patterns(modules: &mut [ModuleDefinition]) -> Result<()> {
+    // Only modules that can be depended upon are valid expansion targets.
+    let mut candidates: Vec<String> = modules
+        .iter()
+        .filter(|m| {
+            matches!(
+                m.kind,
+                ModuleKind::Service | ModuleKind::Task | ModuleKind::Group
+            )
+        })
+        .map(|m| m.name.clone())
+        .collect();
+    candidates.sort();
+
+    for module in modules.iter_mut() {
+        let name = module.name.as_str();
+        match &mut module.inner {
+            InnerDefinition::Service(def) | InnerDefinition::Task(def) => {
+                expand_patterns(&mut def.dependencies, name, &candidates)?;
+                expand_patterns(&mut def.after, name, &candidates)?;
+            }
+            InnerDefinition::Group(def) => {
+                expand_patterns(&mut def.dependencies, name, &candidates)?;
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// An entry of a dependency list, which may be a wildcard pattern.
+trait DependencyEntry: Sized {
+    fn name(&self) -> &str;
+    /// Create an entry with the same options but pointing to `name`.
+    fn renamed(&self, name: &str) -> Self;
+}
+
+impl DependencyEntry for String {
+    fn name(&self) -> &str {
+        self
+    }
+
+    fn renamed(&self, name: &str) -> Self {
+        name.to_string()
+    }
+}
+
+impl DependencyEntry for Dependency {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn renamed(&self, name: &str) -> Self {
+        Dependency {
+            name: name.to_string(),
+            ..self.clone()
+        }
+    }
+}
+
+/// Expand each wildcard pattern in `list` to the matching `candidates`.
+fn expand_patterns<D: DependencyEntry>(
+    list: &mut Vec<D>,
+    module_name: &str,
+    candidates: &[String],
+) -> Result<()> {
+    if !list.iter().any(|entry| glob::is_pattern(entry.name())) {
+        return Ok(());
+    }
+
+    let mut expanded: Vec<D> = Vec::with_capacity(list.len());
+    for entry in list.drain(..) {
+        if !glob::is_pattern(entry.name()) {
+            if !expanded.iter().any(|e| e.name() == entry.name()) {
+                expanded.push(entry);
+            }
+            continue;
+        }
+
+        let matching: Vec<_> = candidates
+            .iter()
+            .filter(|c| c.as_str() != module_name)
+            .filter(|c| glob::matches(entry.name(), c))
+            .collect();
+
+        if matching.is_empty() {
+            bail!(
+                "Dependency pattern '{}' of module '{}' does not match any \
+                module",
+                entry.name(),
+                module_name
+            );
+        }
+
+        for m in matching {
+            if !expanded.iter().any(|e| e.name() == m) {
+                expanded.push(entry.renamed(m));
+            }
+        }
+    }
+    *list = expanded;
+    Ok(())
+}
+
 /// Merge main module definitions with overrides.
 ///
 /// In the case of a name clash the override file takes priority when merging.
@@ -334,7 +605,7 @@ pub fn read_module_definitions(
     let (mod_def_file, path) =
         open_module_file(&cfg.module_file, &cfg.default_dir)?;
 
-    let module_defs = parse_module_def_file(mod_def_file, path.as_path())?;
+    let mut module_defs = parse_module_def_file(mod_def_file, path.as_path())?;
 
     if let Some((override_file, override_file_path)) =
         open_override_file(path.as_path(), cfg)?
@@ -342,12 +613,114 @@ pub fn read_module_definitions(
         let override_module_defs =
             parse_module_def_file(override_file, override_file_path.as_path())
                 .context("Failed while parsing overrides file")?;
-        return Ok(merge_module_definitions(module_defs, override_module_defs));
+        module_defs =
+            merge_module_definitions(module_defs, override_module_defs);
     }
 
+    resolve_environment_from(&mut module_defs)?;
+    resolve_sidecars(&mut module_defs)?;
     Ok(module_defs)
 }
 
+/// Resolves the `environment_from` of each service and task, so that its
+/// environment and environment sets start from those of the module it
+/// inherits from.
+///
+/// Variables of the inheriting module override inherited ones, and so do its
+/// environment sets when they have the same name as an inherited set. Modules
+/// may inherit from modules which themselves inherit from others.
+fn resolve_environment_from(
+    module_defs: &mut [ModuleDefinition],
+) -> Result<()> {
+    let definitions: HashMap<String, ServiceOrTaskDefinition> = module_defs
+        .iter()
+        .filter_map(|m| match &m.inner {
+            InnerDefinition::Service(def) | InnerDefinition::Task(def) => {
+                Some((m.name.clone(), def.clone()))
+            }
+            _ => None,
+        })
+        .collect();
+    for module in module_defs.iter_mut() {
+        if let InnerDefinition::Service(def) | InnerDefinition::Task(def) =
+            &mut module.inner
+        {
+            if def.environment_from.is_some() {
+                let (environment, environment_sets) =
+                    inherited_environment(def, &definitions, &mut vec![])?;
+                def.environment = environment;
+                def.environment_sets = environment_sets;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The environment and environment sets of a module with those of the
+/// modules it inherits from applied underneath.
+fn inherited_environment<'a>(
+    def: &'a ServiceOrTaskDefinition,
+    definitions: &'a HashMap<String, ServiceOrTaskDefinition>,
+    chain: &mut Vec<&'a str>,
+) -> Result<(HashMap<String, EnvValue>, HashMap<String, EnvironmentSet>)> {
+    let from = match &def.environment_from {
+        Some(from) => from,
+        None => {
+            return Ok((def.environment.clone(), def.environment_sets.clone()))
+        }
+    };
+    chain.push(&def.name);
+    if chain.contains(&from.as_str()) {
+        bail!(
+            "Modules inherit their environment from each other: {} -> {}",
+            chain.join(" -> "),
+            from
+        );
+    }
+    let parent = definitions.get(from).with_context(|| {
+        format!(
+            "Module '{}' in environment_from of '{}' is not a service or task",
+            from, def.name
+        )
+    })?;
+
+    let (mut environment, mut environment_sets) =
+        inherited_environment(parent, definitions, chain)?;
+    merge_env(&mut environment, &def.environment);
+    environment_sets.extend(def.environment_sets.clone());
+    Ok((environment, environment_sets))
+}
+
+/// Resolves the `sidecar_tasks` of each service to the definitions of the
+/// tasks.
+fn resolve_sidecars(module_defs: &mut [ModuleDefinition]) -> Result<()> {
+    let tasks: HashMap<String, ServiceOrTaskDefinition> = module_defs
+        .iter()
+        .filter_map(|m| match &m.inner {
+            InnerDefinition::Task(task) => Some((m.name.clone(), task.clone())),
+            _ => None,
+        })
+        .collect();
+    for module in module_defs.iter_mut() {
+        if let InnerDefinition::Service(service) = &mut module.inner {
+            let sidecars = service
+                .sidecar_tasks
+                .iter()
+                .map(|name| {
+                    tasks.get(name).cloned().with_context(|| {
+                        format!(
+                            "Sidecar '{}' of service '{}' is not a task",
+                            name, service.name
+                        )
+                    })
+                })
+                .collect::<Result<_>>()?;
+            service.sidecars = sidecars;
+        }
+    }
+    Ok(())
+}
+
 /// Retrieves a module definition by name.
 ///
 /// This causes a full module definitions parse so prefer calling
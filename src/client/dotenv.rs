@@ -0,0 +1,100 @@
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Reads the variables of a dotenv file.
+pub fn read_dotenv_file(path: &Path) -> Result<HashMap<String, String>> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    parse_dotenv(&content)
+        .with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+/// Parses the contents of a dotenv file.
+///
+/// Each line holds a `KEY=VALUE` pair, optionally prefixed with `export`.
+/// Values may be single quoted (taken literally) or double quoted (supporting
+/// `\n`, `\"` and `\\` escapes), while unquoted values end at a ` #` comment.
+/// Variables are not interpolated.
+pub fn parse_dotenv(content: &str) -> Result<HashMap<String, String>> {
+    let mut variables = HashMap::new();
+    for (idx, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) if !key.trim().is_empty() => {
+                (key.trim(), value.trim())
+            }
+            _ => bail!("Expected KEY=VALUE on line {}", idx + 1),
+        };
+        let value = parse_value(value).with_context(|| {
+            format!("In value of {} on line {}", key, idx + 1)
+        })?;
+        variables.insert(key.to_string(), value);
+    }
+    Ok(variables)
+}
+
+/// Quotes a value for a dotenv file, so that [parse_dotenv] reads it back
+/// as is.
+///
+/// Values consisting only of safe characters are returned as is, while the
+/// rest are double quoted.
+pub fn dotenv_quote(value: &str) -> String {
+    let is_safe = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:=@%+,".contains(c));
+    if is_safe {
+        return value.to_string();
+    }
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '\n' => quoted.push_str("\\n"),
+            '"' | '\\' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn parse_value(value: &str) -> Result<String> {
+    if let Some(quoted) = value.strip_prefix('\'') {
+        return match quoted.find('\'') {
+            Some(end) => Ok(quoted[..end].to_string()),
+            None => bail!("Missing closing quote"),
+        };
+    }
+
+    if let Some(quoted) = value.strip_prefix('"') {
+        let mut parsed = String::new();
+        let mut chars = quoted.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => return Ok(parsed),
+                '\\' => match chars.next() {
+                    Some('n') => parsed.push('\n'),
+                    Some(escaped) => parsed.push(escaped),
+                    None => break,
+                },
+                _ => parsed.push(c),
+            }
+        }
+        bail!("Missing closing quote");
+    }
+
+    let unquoted = match value.find(" #") {
+        Some(comment) => &value[..comment],
+        None => value,
+    };
+    Ok(unquoted.trim_end().to_string())
+}
@@ -0,0 +1,124 @@
+//! Rendering of structured (JSON) log lines.
+use crate::constants::TIME_KEYS;
+use console::style;
+use serde_json::{Map, Value};
+
+const LEVEL_KEYS: &[&str] = &["level", "lvl", "severity", "levelname"];
+const MESSAGE_KEYS: &[&str] = &["msg", "message", "event"];
+
+/// Renders a JSON log line in a human readable form.
+///
+/// The timestamp, level and message (if present) are printed first, followed
+/// by any remaining fields as `key=value` pairs. Lines which are not JSON
+/// objects are returned unchanged.
+pub fn pretty_print_line(line: &str) -> String {
+    if !line.trim_start().starts_with('{') {
+        return line.to_string();
+    }
+
+    match serde_json::from_str::<Map<String, Value>>(line) {
+        Ok(mut fields) => render(&mut fields),
+        Err(_) => line.to_string(),
+    }
+}
+
+fn render(fields: &mut Map<String, Value>) -> String {
+    let mut parts = Vec::new();
+
+    if let Some(time) = take_field(fields, TIME_KEYS) {
+        parts.push(style(time).dim().to_string());
+    }
+    if let Some(level) = take_field(fields, LEVEL_KEYS) {
+        parts.push(style_level(&level));
+    }
+    if let Some(message) = take_field(fields, MESSAGE_KEYS) {
+        parts.push(style(message).bold().to_string());
+    }
+    for (key, value) in fields.iter() {
+        parts.push(format!(
+            "{}{}",
+            style(format!("{}=", key)).dim(),
+            value_to_string(value)
+        ));
+    }
+
+    parts.join(" ")
+}
+
+/// Removes the first of the given keys present in the fields and returns its
+/// value as a string.
+fn take_field(
+    fields: &mut Map<String, Value>,
+    keys: &[&str],
+) -> Option<String> {
+    keys.iter()
+        .find_map(|key| fields.remove(*key))
+        .map(|value| value_to_string(&value))
+}
+
+fn value_to_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn style_level(level: &str) -> String {
+    let padded = format!("{:<5}", level.to_uppercase());
+    match level.to_lowercase().as_str() {
+        "error" | "err" | "fatal" | "critical" | "crit" | "panic" => {
+            style(padded).red().bold().to_string()
+        }
+        "warn" | "warning" => style(padded).yellow().bold().to_string(),
+        "info" => style(padded).green().bold().to_string(),
+        _ => style(padded).blue().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn plain(line: &str) -> String {
+        console::set_colors_enabled(false);
+        pretty_print_line(line)
+    }
+
+    #[test]
+    fn test_pretty_print_line_not_json() {
+        assert_eq!(plain("Listening on :8080"), "Listening on :8080");
+        assert_eq!(plain("{not json"), "{not json");
+        assert_eq!(plain("[1, 2]"), "[1, 2]");
+        assert_eq!(plain(""), "");
+    }
+
+    #[test]
+    fn test_pretty_print_line() {
+        assert_eq!(
+            plain(
+                r#"{"port":8080,"msg":"started","ts":"12:00:00","level":"info","ok":true}"#
+            ),
+            "12:00:00 INFO  started ok=true port=8080"
+        );
+        assert_eq!(
+            plain(
+                r#"{"severity":"error","message":"failed","err":{"code":1}}"#
+            ),
+            r#"ERROR failed err={"code":1}"#
+        );
+    }
+
+    #[test]
+    fn test_pretty_print_line_without_known_keys() {
+        assert_eq!(plain(r#"{"a":"b","c":null}"#), "a=b c=null");
+        assert_eq!(plain("{}"), "");
+    }
+
+    #[test]
+    fn test_pretty_print_line_first_key_wins() {
+        assert_eq!(
+            plain(r#"{"msg":"first","message":"second"}"#),
+            "first message=second"
+        );
+    }
+}
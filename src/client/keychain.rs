@@ -0,0 +1,31 @@
+use std::process::Command;
+
+/// Returns the command printing a secret from the keychain of the OS: the
+/// macOS Keychain, the Secret Service (eg. GNOME Keyring or KWallet) on Linux
+/// through `secret-tool`, or the Windows Credential Manager.
+#[cfg(target_os = "macos")]
+pub fn lookup_command(service: &str, account: &str) -> Command {
+    let mut cmd = Command::new("security");
+    cmd.args(["find-generic-password", "-s", service, "-a", account, "-w"]);
+    cmd
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+pub fn lookup_command(service: &str, account: &str) -> Command {
+    let mut cmd = Command::new("secret-tool");
+    cmd.args(["lookup", "service", service, "account", account]);
+    cmd
+}
+
+#[cfg(windows)]
+pub fn lookup_command(service: &str, account: &str) -> Command {
+    // The values are passed as arguments, so they need no quoting within the
+    // script.
+    let script = "[void][Windows.Security.Credentials.PasswordVault,\
+        Windows.Security.Credentials,ContentType=WindowsRuntime];\
+        $c = (New-Object Windows.Security.Credentials.PasswordVault)\
+        .Retrieve($args[0], $args[1]); $c.RetrievePassword(); $c.Password";
+    let mut cmd = Command::new("powershell");
+    cmd.args(["-NoProfile", "-Command", script, service, account]);
+    cmd
+}
@@ -0,0 +1,76 @@
+use crate::constants::MASKED_VALUE;
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+
+/// Values shorter than this are not masked, as replacing them would mangle
+/// unrelated output without hiding much.
+const MIN_MASKED_LEN: usize = 4;
+
+lazy_static! {
+    /// The values masked in output, resolved so far.
+    static ref MASKED: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+}
+
+/// Masks a value in all output printed afterwards (see [redact]).
+pub fn mask(value: &str) {
+    if value.len() >= MIN_MASKED_LEN {
+        MASKED.lock().insert(value.to_string());
+    }
+}
+
+/// Replaces the masked values in a line of output (eg. of the log of a
+/// service) with [MASKED_VALUE].
+pub fn redact(line: &str) -> Cow<str> {
+    let masked = MASKED.lock();
+    if !masked.iter().any(|value| line.contains(value.as_str())) {
+        return Cow::Borrowed(line);
+    }
+    // Longer values first, so that values containing others are masked
+    // whole.
+    let mut values: Vec<_> = masked.iter().collect();
+    values.sort_by_key(|value| std::cmp::Reverse(value.len()));
+    let mut line = line.to_string();
+    for value in values {
+        line = line.replace(value.as_str(), MASKED_VALUE);
+    }
+    Cow::Owned(line)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_redact_masked_values() {
+        mask("redact-token-1");
+        assert_eq!(
+            redact("token=redact-token-1 again redact-token-1"),
+            format!("token={} again {}", MASKED_VALUE, MASKED_VALUE)
+        );
+    }
+
+    #[test]
+    fn test_redact_leaves_other_lines() {
+        mask("redact-token-2");
+        let line = "nothing to hide";
+        assert!(matches!(redact(line), Cow::Borrowed(l) if l == line));
+    }
+
+    #[test]
+    fn test_redact_longer_values_first() {
+        mask("redact-pass");
+        mask("redact-pass-long");
+        assert_eq!(
+            redact("redact-pass-long redact-pass"),
+            format!("{} {}", MASKED_VALUE, MASKED_VALUE)
+        );
+    }
+
+    #[test]
+    fn test_short_values_are_not_masked() {
+        mask("abc");
+        assert_eq!(redact("abc abcd"), "abc abcd");
+    }
+}
@@ -1,13 +1,22 @@
 #[macro_use]
 pub mod terminal;
+pub mod api;
 pub mod cli;
 pub mod cmd;
 pub mod commands;
 pub mod convert;
 pub mod definitions;
+pub mod dotenv;
 pub mod emoji;
+pub mod json_log;
+pub mod keychain;
+pub mod mask;
 pub mod module;
 pub mod process;
 pub mod progress;
 pub mod request;
+pub mod resume;
+pub mod secrets;
+pub mod substitution;
+pub mod tunnel;
 pub mod validation;
@@ -1,10 +1,17 @@
-use crate::client::cmd::{shell_to_cmd, shell_to_cmd_interactive};
+use crate::client::cmd::{shell_quote, shell_to_cmd, shell_to_cmd_interactive};
+use crate::client::dotenv::read_dotenv_file;
+use crate::client::mask::mask;
+use crate::client::secrets::read_secret;
+use crate::client::substitution::{substitute, substituted_command};
 use crate::dependency::{
     DependencyEdge, DependencyNode, EdgeDirection, WithDependencies, WithKey,
 };
+use crate::{config, glob};
+use anyhow::{anyhow, bail, Context, Result};
 use serde::Deserialize;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::hash::{Hash, Hasher};
+use std::path::Path;
 use std::{fmt, iter};
 
 #[derive(Deserialize, Debug)]
@@ -64,6 +71,37 @@ impl Default for TermSignal {
     }
 }
 
+/// Where the daemon sends the output of a service / task.
+#[derive(Debug, Deserialize, PartialEq, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum LogSink {
+    /// The log file of the module.
+    File,
+    /// The system log (syslog / journald).
+    Syslog,
+    /// Both the log file and the system log.
+    Both,
+}
+
+impl Default for LogSink {
+    fn default() -> Self {
+        Self::File
+    }
+}
+
+/// Tools whose activation wrapper the service / task is spawned through, so
+/// that per-project tool versions and environments apply.
+#[derive(Debug, Deserialize, Default, PartialEq, Clone)]
+pub struct Tooling {
+    /// Spawn through `direnv exec`, loading the `.envrc` of the working dir.
+    #[serde(default)]
+    pub direnv: bool,
+    /// Spawn through `mise exec`, activating the tools of the working dir
+    /// (including those in asdf's `.tool-versions`).
+    #[serde(default)]
+    pub mise: bool,
+}
+
 impl Default for ModuleKind {
     fn default() -> Self {
         Self::Service
@@ -82,8 +120,140 @@ impl fmt::Display for ModuleKind {
     }
 }
 
+/// The value of an environment variable.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum EnvValue {
+    /// A value, or a command substituted when deployed if given as
+    /// `$(<command>)` (see [substitute]).
+    Value(String),
+    /// A secret read when deployed (see [read_secret]), given as
+    /// `{secret: "<reference>"}`.
+    Secret { secret: String },
+    /// A value given as `{value: "<value>", secret: true}`, which is masked
+    /// in output if it is a secret.
+    Annotated {
+        value: String,
+        #[serde(default)]
+        secret: bool,
+    },
+}
+
+impl EnvValue {
+    /// Returns the value, unless it is a secret.
+    pub fn plain(&self) -> Option<&str> {
+        match self {
+            EnvValue::Value(value) => Some(value),
+            EnvValue::Secret { .. } => None,
+            EnvValue::Annotated { value, secret } => {
+                Some(value.as_str()).filter(|_| !secret)
+            }
+        }
+    }
+
+    /// Whether the value is masked in output, as a secret or a value marked
+    /// with `secret: true`.
+    pub fn is_secret(&self) -> bool {
+        match self {
+            EnvValue::Value(_) => false,
+            EnvValue::Secret { .. } => true,
+            EnvValue::Annotated { secret, .. } => *secret,
+        }
+    }
+
+    /// Returns the value, reading secrets from their provider and running
+    /// substituted commands.
+    pub fn resolve(&self) -> Result<String> {
+        match self {
+            EnvValue::Value(value) | EnvValue::Annotated { value, .. } => {
+                match substituted_command(value) {
+                    Some(command) => substitute(command),
+                    None => Ok(value.clone()),
+                }
+            }
+            EnvValue::Secret { secret } => read_secret(secret),
+        }
+    }
+}
+
+impl From<String> for EnvValue {
+    fn from(value: String) -> Self {
+        EnvValue::Value(value)
+    }
+}
+
+/// Returns the values of the environment variables given, reading secrets
+/// from their providers and running substituted commands.
+///
+/// The values of masked variables (see [is_masked]) are masked in all output
+/// printed afterwards.
+pub fn resolve_env(
+    environment: &HashMap<String, EnvValue>,
+) -> Result<HashMap<String, String>> {
+    environment
+        .iter()
+        .map(|(key, value)| {
+            let resolved = value.resolve().with_context(|| {
+                format!("Failed to resolve the value of {}", key)
+            })?;
+            if is_masked(key, value) {
+                mask(&resolved);
+            }
+            Ok((key.clone(), resolved))
+        })
+        .collect()
+}
+
+/// Whether the value of an environment variable is masked in output, either
+/// as a secret or as its name matches a pattern of `client.masked_env`.
+pub fn is_masked(key: &str, value: &EnvValue) -> bool {
+    value.is_secret()
+        || config::PERSISTED_CONFIG
+            .client
+            .masked_env()
+            .iter()
+            .any(|pattern| glob::matches(pattern, key))
+}
+
+/// Returns the names of the masked environment variables (see [is_masked]).
+pub fn masked_keys(environment: &HashMap<String, EnvValue>) -> Vec<String> {
+    let mut keys: Vec<_> = environment
+        .iter()
+        .filter(|(key, value)| is_masked(key, value))
+        .map(|(key, _)| key.clone())
+        .collect();
+    keys.sort();
+    keys
+}
+
+/// A set of environment variables activated with `--env`.
+///
+/// Given either inline, or as the path to a dotenv file (eg. `.env.local`)
+/// which is only read when the set is activated.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum EnvironmentSet {
+    Inline(HashMap<String, EnvValue>),
+    File(String),
+}
+
+impl EnvironmentSet {
+    /// Returns the variables of the set, reading its dotenv file if needed.
+    pub fn load(&self) -> Result<HashMap<String, EnvValue>> {
+        match self {
+            EnvironmentSet::Inline(variables) => Ok(variables.clone()),
+            EnvironmentSet::File(path) => {
+                Ok(read_dotenv_file(Path::new(path))?
+                    .into_iter()
+                    .map(|(key, value)| (key, value.into()))
+                    .collect())
+            }
+        }
+    }
+}
+
 /// A definition of a module for version 1 (V1) of the daemon.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone)]
 pub struct ServiceOrTaskDefinition {
     #[serde(default = "String::default")]
     pub name: String,
@@ -98,14 +268,33 @@ pub struct ServiceOrTaskDefinition {
     pub termination_signal: TermSignal,
     /// The environment variables to create the process with.
     #[serde(default = "HashMap::new")]
-    pub environment: HashMap<String, String>,
+    pub environment: HashMap<String, EnvValue>,
     #[serde(default = "HashMap::new")]
-    pub environment_sets: HashMap<String, HashMap<String, String>>,
+    pub environment_sets: HashMap<String, EnvironmentSet>,
+    /// The name of a service / task whose environment (and environment sets)
+    /// this one inherits, with its own taking priority.
+    pub environment_from: Option<String>,
+    /// Environment variables which must be set (by the module or in the
+    /// environment `cartel` is run from) for the service / task to deploy.
+    #[serde(default = "Vec::new")]
+    pub required_env: Vec<String>,
+    /// Whether to spawn the service / task with the environment of a login
+    /// shell, overriding the `daemon.use_env_grabber` config option.
+    pub use_login_env: Option<bool>,
     /// A custom alternate log file path.
     pub log_file_path: Option<String>,
+    /// Where the output of the service / task is sent to.
+    #[serde(default = "LogSink::default")]
+    pub log_sink: LogSink,
+    /// The tools to activate before spawning the service / task.
+    #[serde(default)]
+    pub tooling: Tooling,
+    /// A nix shell (a `.nix` file or a flake reference) to run the service /
+    /// task in, through `nix develop`.
+    pub nix_shell: Option<String>,
     /// A list of dependencies of the service / task.
     #[serde(default = "Vec::new")]
-    pub dependencies: Vec<String>,
+    pub dependencies: Vec<Dependency>,
     /// A list of dependencies of the service / task that must be deployed
     /// sequentially.
     #[serde(default = "Vec::new")]
@@ -139,10 +328,53 @@ pub struct ServiceOrTaskDefinition {
     /// Duration in seconds before a task is considered as failed (currently
     /// only for tasks).
     pub timeout: Option<u64>,
+    /// The number of times a task is retried if it fails (only for tasks).
+    #[serde(default)]
+    pub retries: u32,
+    /// Duration in seconds to wait before retrying a failed task.
+    #[serde(default = "default_retry_delay")]
+    pub retry_delay: u64,
+    /// Whether the output of a task is shown once it completes, rather than
+    /// only if it fails (only for tasks).
+    #[serde(default)]
+    pub show_output: bool,
+    /// Conditions under which a task is skipped when deployed (only for
+    /// tasks).
+    pub run_if: Option<RunIf>,
+    /// How often a task runs when deployed repeatedly (only for tasks).
+    #[serde(default)]
+    pub once_per: OncePer,
     /// If enabled and a `shell` command is given, the process will be spawned
     /// in an interactive shell based on the one the client is running on.
     #[serde(default = "default_interactive_shell")]
     pub interactive_shell: bool,
+    /// The priority of the service / task within its dependency group. Modules
+    /// with a higher priority are started before others in the same group.
+    #[serde(default = "default_priority")]
+    pub priority: i32,
+    /// The names of the tasks run alongside a service, from when it becomes
+    /// healthy until it stops (only for services).
+    #[serde(default)]
+    pub sidecar_tasks: Vec<String>,
+    /// The definitions of the `sidecar_tasks`, resolved once all modules are
+    /// read.
+    #[serde(skip)]
+    pub sidecars: Vec<ServiceOrTaskDefinition>,
+    /// Whether the daemon keeps a terminal open for the input of a service,
+    /// which `attach --stdin` writes to (only for services).
+    #[serde(default)]
+    pub interactive: bool,
+    /// Whether the daemon creates a temp directory for the service, exported
+    /// as `CARTEL_TMPDIR` to it and to its shells (only for services).
+    #[serde(default)]
+    pub tmpdir: bool,
+    /// The URL the service is reached at, which `open` launches the browser
+    /// to (only for services).
+    pub url: Option<String>,
+    /// The project the service / task belongs to. This is the name of the
+    /// directory containing the module definitions file.
+    #[serde(skip_deserializing)]
+    pub project: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -161,14 +393,18 @@ pub struct ShellDefinition {
     pub command: Vec<String>,
     /// Alternative to `command`, where a shell executes the given statement.
     pub shell: Option<String>,
+    /// Commands run in a shell before the shell is opened (eg. to activate a
+    /// virtualenv), any changes to the environment carrying over to it.
+    #[serde(default)]
+    pub init: Vec<String>,
     /// The environment variables to create the process with.
     #[serde(default = "HashMap::new")]
-    pub environment: HashMap<String, String>,
+    pub environment: HashMap<String, EnvValue>,
     /// The working directory to execute the shell command in.
     pub working_dir: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum Probe {
     Exec(ExecutableProbe),
@@ -176,7 +412,7 @@ pub enum Probe {
     Net(NetworkProbe),
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone)]
 pub struct ExecutableProbe {
     /// Number of retries before the probe is considered failed.
     #[serde(default = "default_probe_retries")]
@@ -191,7 +427,7 @@ pub struct ExecutableProbe {
     pub working_dir: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone)]
 pub struct LogLineProbe {
     /// Number of retries before the probe is considered failed.
     #[serde(default = "default_probe_retries")]
@@ -200,7 +436,7 @@ pub struct LogLineProbe {
     pub line_regex: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone)]
 pub struct NetworkProbe {
     /// Number of retries before the probe is considered failed.
     #[serde(default = "default_probe_retries")]
@@ -211,6 +447,35 @@ pub struct NetworkProbe {
     pub port: u16,
 }
 
+/// How often a task runs, when deployed repeatedly.
+#[derive(Debug, Deserialize, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum OncePer {
+    /// The task runs every time it is deployed.
+    Always,
+    /// The task runs once per deploy, even if many modules depend on it.
+    Deploy,
+    /// The task runs once per day.
+    Day,
+}
+
+impl Default for OncePer {
+    fn default() -> Self {
+        Self::Always
+    }
+}
+
+/// Guards against deploying a task which has nothing to do.
+#[derive(Debug, Deserialize, Clone)]
+pub struct RunIf {
+    /// A shell command, the task is skipped if it exits with zero.
+    pub shell: Option<String>,
+    /// Files (glob patterns relative to the working directory) which the task
+    /// is skipped unless any of them changed since it last succeeded.
+    #[serde(default)]
+    pub files_changed: Vec<String>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct GroupDefinition {
     #[serde(default = "String::default")]
@@ -218,6 +483,14 @@ pub struct GroupDefinition {
     /// A list of dependencies of the group.
     #[serde(default = "Vec::new")]
     pub dependencies: Vec<String>,
+    /// A list of tasks to perform after all members of the group have been
+    /// deployed and had their readiness probes pass.
+    #[serde(default = "Vec::new")]
+    pub post_up: Vec<String>,
+    /// A list of tasks to perform after all members of the group have been
+    /// deployed.
+    #[serde(default = "Vec::new")]
+    pub post: Vec<String>,
     /// A list of checks to perform.
     #[serde(default = "Vec::new")]
     pub checks: Vec<String>,
@@ -257,16 +530,113 @@ pub struct SuggestedFixDefinition {
     pub working_dir: Option<String>,
 }
 
+/// Project-level settings, declared in a document of kind `Project` in the
+/// module definitions file.
+#[derive(Debug, Deserialize, Default)]
+pub struct ProjectDefinition {
+    /// Shell commands the client runs around deployments.
+    #[serde(default)]
+    pub hooks: Hooks,
+    /// The directory of the module definitions file, which hooks run in.
+    #[serde(skip_deserializing)]
+    pub working_dir: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct Hooks {
+    /// Runs before deploying any module, aborting the deploy if it fails.
+    pub pre_deploy: Option<String>,
+    /// Runs after all modules were deployed.
+    pub post_deploy: Option<String>,
+    /// Runs after `down` stopped all services.
+    pub post_down: Option<String>,
+}
+
+/// A point around deployments at which a project hook runs.
+#[derive(Debug, Clone, Copy)]
+pub enum Hook {
+    PreDeploy,
+    PostDeploy,
+    PostDown,
+}
+
+impl fmt::Display for Hook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Hook::PreDeploy => write!(f, "pre_deploy"),
+            Hook::PostDeploy => write!(f, "post_deploy"),
+            Hook::PostDown => write!(f, "post_down"),
+        }
+    }
+}
+
+impl ProjectDefinition {
+    /// Returns the shell command of the given hook, if the project declares
+    /// it.
+    pub fn hook(&self, hook: Hook) -> Option<&str> {
+        match hook {
+            Hook::PreDeploy => self.hooks.pre_deploy.as_deref(),
+            Hook::PostDeploy => self.hooks.post_deploy.as_deref(),
+            Hook::PostDown => self.hooks.post_down.as_deref(),
+        }
+    }
+}
+
+/// A dependency of a service or task on some other module.
+///
+/// Can be declared either as just the module name, or as a mapping which also
+/// allows configuring the dependency edge (eg. `wait_healthy`).
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(from = "DependencyDefinition")]
+pub struct Dependency {
+    /// The name of the module depended upon.
+    pub name: String,
+    /// Whether the dependent must wait for the dependency's readiness probe to
+    /// pass before being deployed.
+    pub wait_healthy: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum DependencyDefinition {
+    Name(String),
+    Detailed {
+        name: String,
+        #[serde(default = "default_wait_healthy")]
+        wait_healthy: bool,
+    },
+}
+
+impl From<DependencyDefinition> for Dependency {
+    fn from(def: DependencyDefinition) -> Self {
+        match def {
+            DependencyDefinition::Name(name) => Dependency::from(name),
+            DependencyDefinition::Detailed { name, wait_healthy } => {
+                Dependency { name, wait_healthy }
+            }
+        }
+    }
+}
+
+impl From<String> for Dependency {
+    fn from(name: String) -> Self {
+        Dependency {
+            name,
+            wait_healthy: default_wait_healthy(),
+        }
+    }
+}
+
 impl ServiceOrTaskDefinition {
     #[allow(clippy::too_many_arguments)]
     pub fn new(
         name: String,
         command: Vec<String>,
         shell: Option<String>,
-        environment: HashMap<String, String>,
-        environment_sets: HashMap<String, HashMap<String, String>>,
+        environment: HashMap<String, EnvValue>,
+        environment_sets: HashMap<String, EnvironmentSet>,
         log_file_path: Option<String>,
-        dependencies: Vec<String>,
+        dependencies: Vec<Dependency>,
         ordered_dependencies: Vec<String>,
         after: Vec<String>,
         post_up: Vec<String>,
@@ -279,6 +649,7 @@ impl ServiceOrTaskDefinition {
         liveness_probe: Option<Probe>,
         timeout: Option<u64>,
         interactive_shell: bool,
+        priority: i32,
     ) -> ServiceOrTaskDefinition {
         ServiceOrTaskDefinition {
             name,
@@ -288,6 +659,9 @@ impl ServiceOrTaskDefinition {
             environment,
             environment_sets,
             log_file_path,
+            log_sink: LogSink::default(),
+            tooling: Tooling::default(),
+            nix_shell: None,
             dependencies,
             ordered_dependencies,
             after,
@@ -299,10 +673,30 @@ impl ServiceOrTaskDefinition {
             readiness_probe,
             liveness_probe,
             timeout,
+            retries: 0,
+            retry_delay: default_retry_delay(),
+            show_output: false,
+            run_if: None,
+            once_per: OncePer::default(),
             interactive_shell,
+            priority,
+            sidecar_tasks: vec![],
+            sidecars: vec![],
+            interactive: false,
+            tmpdir: false,
+            url: None,
+            environment_from: None,
+            required_env: vec![],
+            use_login_env: None,
+            project: None,
         }
     }
 
+    /// The names of the modules this task or service depends on.
+    pub fn dependency_names(&self) -> Vec<String> {
+        self.dependencies.iter().map(|d| d.name.clone()).collect()
+    }
+
     /// Get the execution command of this task or service.
     ///
     /// If no command was provided then the `shell` field is used to get an
@@ -318,6 +712,27 @@ impl ServiceOrTaskDefinition {
             self.command.clone()
         }
     }
+
+    /// Returns the ports the service is declared to listen on, from its net
+    /// probes and `PORT` variable.
+    pub fn declared_ports(&self) -> BTreeSet<u16> {
+        let mut ports: BTreeSet<u16> =
+            [&self.readiness_probe, &self.liveness_probe]
+                .iter()
+                .filter_map(|probe| match probe {
+                    Some(Probe::Net(probe)) => Some(probe.port),
+                    _ => None,
+                })
+                .collect();
+        if let Some(port) =
+            self.environment.get("PORT").and_then(EnvValue::plain)
+        {
+            if let Ok(port) = port.parse() {
+                ports.insert(port);
+            }
+        }
+        ports
+    }
 }
 
 impl CheckDefinition {
@@ -367,15 +782,120 @@ impl ShellDefinition {
     ///
     /// If no command was provided then the `shell` field is used to get an
     /// appropriate command line that invokes a shell.
-    pub fn cmd_line(&self) -> Vec<String> {
-        if self.command.is_empty() {
-            shell_to_cmd(self.shell.as_ref().unwrap())
+    ///
+    /// If `init` commands are given these are run first in a shell, which then
+    /// replaces itself with the command (provided all succeeded).
+    ///
+    /// The arguments given replace the placeholders in the command, `{args}`
+    /// with all of them and `{1}`, `{2}` etc. with each one. Without any
+    /// placeholders they are appended to the command instead.
+    pub fn cmd_line(&self, args: &[&str]) -> Result<Vec<String>> {
+        let (command, statement) = if self.command.is_empty() {
+            let statement = self.shell.as_ref().unwrap();
+            let (mut statement, found) =
+                replace_placeholders(statement, args, shell_quote)?;
+            if !found {
+                for arg in args {
+                    statement.push(' ');
+                    statement.push_str(&shell_quote(arg));
+                }
+            }
+            (vec![], Some(statement))
         } else {
-            self.command.clone()
+            (self.command_with_args(args)?, None)
+        };
+
+        if self.init.is_empty() {
+            return Ok(match statement {
+                Some(statement) => shell_to_cmd(&statement),
+                None => command,
+            });
         }
+
+        let command = statement.unwrap_or_else(|| {
+            let args: Vec<_> =
+                command.iter().map(|arg| shell_quote(arg)).collect();
+            format!("exec {}", args.join(" "))
+        });
+        let mut statements = self.init.clone();
+        statements.push(command);
+        Ok(shell_to_cmd(&statements.join(" && ")))
+    }
+
+    /// Returns the `command` of the shell with the placeholders of the
+    /// arguments replaced (see [ShellDefinition::cmd_line]).
+    fn command_with_args(&self, args: &[&str]) -> Result<Vec<String>> {
+        let mut command = vec![];
+        let mut found = false;
+        for part in &self.command {
+            if part == ARGS_PLACEHOLDER {
+                command.extend(args.iter().map(|arg| arg.to_string()));
+                found = true;
+                continue;
+            }
+            let (part, part_found) =
+                replace_placeholders(part, args, |arg| arg.to_string())?;
+            command.push(part);
+            found |= part_found;
+        }
+        if !found {
+            command.extend(args.iter().map(|arg| arg.to_string()));
+        }
+        Ok(command)
     }
 }
 
+/// The placeholder replaced by all the arguments given to a shell.
+const ARGS_PLACEHOLDER: &str = "{args}";
+
+/// Replaces the placeholders of the arguments given to a shell in part of its
+/// command (see [ShellDefinition::cmd_line]), formatting each argument with
+/// `format`. Returns whether any placeholders were found.
+fn replace_placeholders(
+    template: &str,
+    args: &[&str],
+    format: fn(&str) -> String,
+) -> Result<(String, bool)> {
+    let mut result = String::new();
+    let mut found = false;
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let name = after.find('}').map(|end| &after[..end]);
+        let replacement = match name {
+            Some("args") => {
+                let args: Vec<_> = args.iter().map(|arg| format(arg)).collect();
+                args.join(" ")
+            }
+            Some(n)
+                if !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()) =>
+            {
+                let arg = n
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|idx| idx.checked_sub(1))
+                    .and_then(|idx| args.get(idx))
+                    .ok_or_else(|| {
+                        anyhow!("The shell expects at least {} argument(s)", n)
+                    })?;
+                format(arg)
+            }
+            // Not a placeholder (eg. braces in a shell statement).
+            _ => {
+                result.push('{');
+                rest = after;
+                continue;
+            }
+        };
+        result.push_str(&replacement);
+        found = true;
+        rest = &after[name.map_or(0, str::len) + 1..];
+    }
+    result.push_str(rest);
+    Ok((result, found))
+}
+
 impl Hash for ModuleDefinition {
     fn hash<S: Hasher>(&self, state: &mut S) {
         self.name.hash(state);
@@ -390,6 +910,9 @@ impl PartialEq for ModuleDefinition {
 
 #[derive(Copy, Clone, PartialEq, PartialOrd, Debug)]
 pub enum ModuleMarker {
+    /// Every edge pointing to the module opted out of waiting for its
+    /// readiness (`wait_healthy: false`).
+    NoWait = 0,
     Instant = 1,
     WaitProbe = 2,
 }
@@ -410,6 +933,10 @@ impl WithDependencies<ModuleMarker> for ServiceOrTaskDefinition {
     fn is_group(&self) -> bool {
         false
     }
+
+    fn priority(&self) -> i32 {
+        self.priority
+    }
 }
 
 impl WithKey for ServiceOrTaskDefinition {
@@ -451,6 +978,20 @@ impl EdgeList for GroupDefinition {
                 direction: EdgeDirection::To,
                 marker: ModuleMarker::WaitProbe,
             })
+            .chain(self.post_up.iter().map(|key| DependencyEdge {
+                edge_src: self.name.clone(),
+                edge_dst: key.clone(),
+                is_weak: false,
+                direction: EdgeDirection::From,
+                marker: ModuleMarker::WaitProbe,
+            }))
+            .chain(self.post.iter().map(|key| DependencyEdge {
+                edge_src: self.name.clone(),
+                edge_dst: key.clone(),
+                is_weak: false,
+                direction: EdgeDirection::From,
+                marker: ModuleMarker::Instant,
+            }))
             .collect()
     }
 }
@@ -460,12 +1001,16 @@ impl EdgeList for ServiceOrTaskDefinition {
         let edges: Vec<DependencyEdge<ModuleMarker>> = self
             .dependencies
             .iter()
-            .map(|key| DependencyEdge {
+            .map(|dep| DependencyEdge {
                 edge_src: self.key(),
-                edge_dst: key.clone(),
+                edge_dst: dep.name.clone(),
                 is_weak: false,
                 direction: EdgeDirection::To,
-                marker: ModuleMarker::WaitProbe,
+                marker: if dep.wait_healthy {
+                    ModuleMarker::WaitProbe
+                } else {
+                    ModuleMarker::NoWait
+                },
             })
             .chain(
                 self.ordered_dependencies
@@ -536,6 +1081,14 @@ impl WithDependencies<ModuleMarker> for ModuleDefinition {
     fn is_group(&self) -> bool {
         self.kind == ModuleKind::Group
     }
+
+    fn priority(&self) -> i32 {
+        match &self.inner {
+            InnerDefinition::Task(task) => task.priority,
+            InnerDefinition::Service(service) => service.priority,
+            _ => default_priority(),
+        }
+    }
 }
 
 impl WithKey for ModuleDefinition {
@@ -552,6 +1105,10 @@ fn default_probe_retries() -> u32 {
     5
 }
 
+fn default_wait_healthy() -> bool {
+    true
+}
+
 fn default_always_await_readiness_probe() -> bool {
     true
 }
@@ -560,6 +1117,14 @@ fn default_interactive_shell() -> bool {
     false
 }
 
+fn default_priority() -> i32 {
+    0
+}
+
+fn default_retry_delay() -> u64 {
+    1
+}
+
 pub fn module_names(modules: &[ModuleDefinition]) -> Vec<&str> {
     modules.iter().map(|m| m.name.as_str()).collect()
 }
@@ -592,8 +1157,8 @@ pub fn remove_checks(
 }
 
 pub fn merge_env(
-    base: &mut HashMap<String, String>,
-    delta: &HashMap<String, String>,
+    base: &mut HashMap<String, EnvValue>,
+    delta: &HashMap<String, EnvValue>,
 ) {
     for (key, val) in delta.iter() {
         base.insert(key.clone(), val.clone());
@@ -621,6 +1186,39 @@ pub fn module_by_name<'a>(
     modules.iter().find(|m| m.name == name)
 }
 
+/// Returns the services of a group, including those of nested groups.
+pub fn group_services<'a>(
+    group: &str,
+    module_defs: &'a [ModuleDefinition],
+) -> Result<Vec<&'a str>> {
+    let mut services = vec![];
+    let mut pending = vec![group];
+    let mut visited = vec![];
+    while let Some(name) = pending.pop() {
+        if visited.contains(&name) {
+            continue;
+        }
+        visited.push(name);
+        let module = module_defs
+            .iter()
+            .find(|m| m.name == name)
+            .ok_or_else(|| anyhow!("Module with name '{}' not found", name))?;
+        match &module.inner {
+            InnerDefinition::Group(g) => {
+                pending.extend(g.dependencies.iter().rev().map(String::as_str))
+            }
+            InnerDefinition::Service(_) if name != group => {
+                services.push(module.name.as_str())
+            }
+            _ if name == group => {
+                bail!("Module '{}' is a {}, not a group", name, module.kind)
+            }
+            _ => {}
+        }
+    }
+    Ok(services)
+}
+
 pub fn shell_for_service<'a>(
     service_name: &str,
     shell_type: Option<&str>,
@@ -1,13 +1,19 @@
+use crate::client::cmd::shell_to_cmd;
 use crate::client::module::{
-    CheckDefinition, ServiceOrTaskDefinition, ShellDefinition,
-    SuggestedFixDefinition,
+    resolve_env, CheckDefinition, Hook, ProjectDefinition,
+    ServiceOrTaskDefinition, ShellDefinition, SuggestedFixDefinition,
 };
 use crate::command_builder::CommandBuilder;
 use crate::path;
 use anyhow::{bail, Context, Result};
-use std::process::ExitStatus;
-
-pub fn run_task(task_definition: &ServiceOrTaskDefinition) -> Result<()> {
+use crossbeam_utils::thread;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read};
+use std::process::{ExitStatus, Stdio};
+
+fn task_command(
+    task_definition: &ServiceOrTaskDefinition,
+) -> Result<CommandBuilder> {
     let working_dir = task_definition
         .working_dir
         .as_deref()
@@ -16,17 +22,61 @@ pub fn run_task(task_definition: &ServiceOrTaskDefinition) -> Result<()> {
     let cmd_line = task_definition.cmd_line();
     let mut cmd = CommandBuilder::new(&cmd_line);
 
-    cmd.env(&task_definition.environment)
+    cmd.env(&resolve_env(&task_definition.environment)?)
         .work_dir(working_dir.as_deref());
+    Ok(cmd)
+}
 
-    cmd.build()
+pub fn run_task(
+    task_definition: &ServiceOrTaskDefinition,
+) -> Result<ExitStatus> {
+    let exit_status = task_command(task_definition)?
+        .build()
         .spawn()
         .with_context(|| {
             format!("Unable to run task '{}'", task_definition.name)
         })?
         .wait()?;
 
-    Ok(())
+    Ok(exit_status)
+}
+
+/// Runs a task with its output captured rather than shown, passing each line
+/// it writes (to stdout or stderr) to `on_line`.
+pub fn run_task_captured<F>(
+    task_definition: &ServiceOrTaskDefinition,
+    on_line: F,
+) -> Result<ExitStatus>
+where
+    F: Fn(&str) + Sync,
+{
+    let mut cmd = task_command(task_definition)?;
+    cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+    let mut child = cmd.build().spawn().with_context(|| {
+        format!("Unable to run task '{}'", task_definition.name)
+    })?;
+
+    let mut outputs: Vec<Box<dyn Read + Send>> = vec![];
+    if let Some(stdout) = child.stdout.take() {
+        outputs.push(Box::new(stdout));
+    }
+    if let Some(stderr) = child.stderr.take() {
+        outputs.push(Box::new(stderr));
+    }
+    let on_line = &on_line;
+    thread::scope(|s| {
+        for output in outputs {
+            s.spawn(move |_| {
+                for line in BufReader::new(output).lines().map_while(Result::ok)
+                {
+                    on_line(&line);
+                }
+            });
+        }
+    })
+    .expect("Task output thread panicked");
+
+    Ok(child.wait()?)
 }
 
 pub fn run_check(check_definition: &CheckDefinition) -> Result<ExitStatus> {
@@ -53,16 +103,30 @@ pub fn run_check(check_definition: &CheckDefinition) -> Result<ExitStatus> {
     Ok(check_result)
 }
 
-pub fn run_shell(shell_definition: &ShellDefinition) -> Result<()> {
+/// Runs a shell (with the command line given, see
+/// [ShellDefinition::cmd_line]), with the environment of its service if given
+/// (in place of that of the client).
+///
+/// The `service_vars` provided by the daemon for the service (eg. its temp
+/// directory) are set in either case.
+pub fn run_shell(
+    shell_definition: &ShellDefinition,
+    cmd_line: &[String],
+    service_env: Option<&HashMap<String, String>>,
+    service_vars: &HashMap<String, String>,
+) -> Result<()> {
     let working_dir = shell_definition
         .working_dir
         .as_deref()
         .and_then(path::from_user_str);
 
-    let cmd_line = shell_definition.cmd_line();
-    let mut cmd = CommandBuilder::new(&cmd_line);
+    let mut cmd = CommandBuilder::new(cmd_line);
 
-    cmd.env(&shell_definition.environment)
+    if let Some(service_env) = service_env {
+        cmd.env_clear().env(service_env);
+    }
+    cmd.env(service_vars)
+        .env(&resolve_env(&shell_definition.environment)?)
         .work_dir(working_dir.as_deref());
 
     cmd.build()
@@ -97,3 +161,33 @@ pub fn apply_suggested_fix(
 
     Ok(())
 }
+
+/// Runs a project hook, if the project declares it, failing if it exits with
+/// a non-zero exit code.
+pub fn run_hook(hook: Hook, project: &ProjectDefinition) -> Result<()> {
+    let shell = match project.hook(hook) {
+        Some(shell) => shell,
+        None => return Ok(()),
+    };
+    tprint!(
+        "      {} {}",
+        cdim!(format!("Running {} hook:", hook)),
+        shell
+    );
+
+    let cmd_line = shell_to_cmd(shell);
+    let mut cmd = CommandBuilder::new(&cmd_line);
+
+    cmd.work_dir(project.working_dir.as_deref());
+
+    let hook_result = cmd
+        .build()
+        .spawn()
+        .with_context(|| format!("Unable to run the {} hook", hook))?
+        .wait()?;
+    if !hook_result.success() {
+        bail!("The {} hook failed ({})", hook, hook_result);
+    }
+
+    Ok(())
+}
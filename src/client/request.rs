@@ -1,77 +1,54 @@
+use crate::client::api::CartelApiClient;
 use crate::client::commands::DeployOptions;
 use crate::client::module::{
-    merge_env, InnerDefinition, ModuleDefinition, ModuleKind,
-    ServiceOrTaskDefinition,
+    masked_keys, merge_env, resolve_env, EnvValue, InnerDefinition,
+    ModuleDefinition, ModuleKind, ServiceOrTaskDefinition,
 };
 use crate::daemon::api::*;
-use anyhow::{anyhow, bail, Result};
+use anyhow::{bail, Context, Result};
 use core::convert::Into;
-use reqwest::blocking::Client;
 use std::collections::HashMap;
-use std::time::Duration;
+use std::thread;
+use std::time::{Duration, Instant};
 
-use serde::{Deserialize, Serialize};
+/// How often the status of a task running in the background is polled.
+const TASK_POLL_INTERVAL: Duration = Duration::from_millis(250);
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(untagged)]
-pub enum DeploymentResponse {
-    Ok(ApiDeploymentResponse),
-    Err(ErrorResponse),
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(untagged)]
-pub enum TaskDeploymentResponse {
-    Ok(ApiTaskDeploymentResponse),
-    Err(ErrorResponse),
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(untagged)]
-pub enum OperationResponse {
-    Ok(ApiOperationResponse),
-    Err(ErrorResponse),
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(untagged)]
-pub enum LogInfoResponse {
-    Ok(ApiLogResponse),
-    Err(ErrorResponse),
-}
-
-fn client(timeout: &Option<u64>) -> Client {
-    reqwest::blocking::Client::builder()
-        .timeout(Duration::from_secs(timeout.unwrap_or(180)))
-        .build()
-        .unwrap()
-}
-
-fn build_env_arg(
+/// Returns the environment of a module with the active environment sets
+/// applied.
+pub fn build_env(
     svc: &ServiceOrTaskDefinition,
     opts: &DeployOptions,
-) -> HashMap<String, String> {
+) -> Result<HashMap<String, EnvValue>> {
     let mut base_env = svc.environment.clone();
-    opts.active_envs.iter().for_each(|key| {
-        if svc.environment_sets.contains_key(key) {
-            let env_set = svc.environment_sets.get(key).unwrap();
-            merge_env(&mut base_env, env_set);
+    for key in opts.active_envs.iter() {
+        if let Some(env_set) = svc.environment_sets.get(key) {
+            let env_set = env_set.load().with_context(|| {
+                format!("In environment set {} of {}", key, svc.name)
+            })?;
+            merge_env(&mut base_env, &env_set);
         }
-    });
-    base_env
+    }
+    Ok(base_env)
 }
 
 fn build_svc_module_definition(
     module_definition: &ServiceOrTaskDefinition,
     opts: &DeployOptions,
-) -> ApiModuleDefinition {
-    ApiModuleDefinition {
+) -> Result<ApiModuleDefinition> {
+    let environment = build_env(module_definition, opts)?;
+    Ok(ApiModuleDefinition {
         kind: ApiModuleKind::Service,
         name: module_definition.name.clone(),
         command: module_definition.cmd_line(),
-        environment: build_env_arg(module_definition, opts),
+        environment: resolve_env(&environment)?,
         log_file_path: module_definition.log_file_path.clone(),
-        dependencies: module_definition.dependencies.clone(),
+        log_sink: (&module_definition.log_sink).into(),
+        tooling: ApiTooling {
+            nix_shell: module_definition.nix_shell.clone(),
+            ..(&module_definition.tooling).into()
+        },
+        dependencies: module_definition.dependency_names(),
         working_dir: module_definition.working_dir.clone(),
         termination_signal: (&module_definition.termination_signal).into(),
         readiness_probe: module_definition
@@ -82,50 +59,82 @@ fn build_svc_module_definition(
             .liveness_probe
             .as_ref()
             .map(Into::into),
-    }
+        project: module_definition.project.clone(),
+        sidecars: module_definition
+            .sidecars
+            .iter()
+            .map(|sidecar| build_task_module_definition(sidecar, opts))
+            .collect::<Result<_>>()?,
+        interactive: module_definition.interactive,
+        tmpdir: module_definition.tmpdir,
+        use_login_env: module_definition.use_login_env,
+        masked_env: masked_keys(&environment),
+    })
 }
 
 fn build_task_module_definition(
     task_definition: &ServiceOrTaskDefinition,
     opts: &DeployOptions,
-) -> ApiModuleDefinition {
-    ApiModuleDefinition {
+) -> Result<ApiModuleDefinition> {
+    let environment = build_env(task_definition, opts)?;
+    Ok(ApiModuleDefinition {
         kind: ApiModuleKind::Task,
         name: task_definition.name.clone(),
         command: task_definition.cmd_line(),
-        environment: build_env_arg(task_definition, opts),
+        environment: resolve_env(&environment)?,
         log_file_path: task_definition.log_file_path.clone(),
-        dependencies: task_definition.dependencies.clone(),
+        log_sink: (&task_definition.log_sink).into(),
+        tooling: ApiTooling {
+            nix_shell: task_definition.nix_shell.clone(),
+            ..(&task_definition.tooling).into()
+        },
+        dependencies: task_definition.dependency_names(),
         working_dir: task_definition.working_dir.clone(),
-        termination_signal: ApiTermSignal::KILL,
+        termination_signal: (&task_definition.termination_signal).into(),
         readiness_probe: None,
         liveness_probe: None,
-    }
+        project: task_definition.project.clone(),
+        sidecars: vec![],
+        interactive: false,
+        tmpdir: false,
+        use_login_env: task_definition.use_login_env,
+        masked_env: masked_keys(&environment),
+    })
 }
 
 fn build_deploy_command(
     module_definition: &ServiceOrTaskDefinition,
     opts: &DeployOptions,
-) -> ApiDeploymentCommand {
-    ApiDeploymentCommand {
-        module_definition: build_svc_module_definition(module_definition, opts),
-        force: opts.force_deploy,
-    }
+) -> Result<ApiDeploymentCommand> {
+    Ok(ApiDeploymentCommand {
+        module_definition: build_svc_module_definition(
+            module_definition,
+            opts,
+        )?,
+        force: opts.is_forced(&module_definition.name),
+        deployment_id: Some(opts.deployment_id.clone()),
+    })
 }
 
 fn build_task_deploy_command(
     task_definition: &ServiceOrTaskDefinition,
     opts: &DeployOptions,
-) -> ApiTaskDeploymentCommand {
-    ApiTaskDeploymentCommand {
-        task_definition: build_task_module_definition(task_definition, opts),
-    }
+) -> Result<ApiTaskDeploymentCommand> {
+    Ok(ApiTaskDeploymentCommand {
+        task_definition: build_task_module_definition(task_definition, opts)?,
+        retries: task_definition.retries,
+        retry_delay: task_definition.retry_delay,
+        deployment_id: Some(opts.deployment_id.clone()),
+        run_if: task_definition.run_if.as_ref().map(Into::into),
+        timeout: task_definition.timeout,
+        once_per: task_definition.once_per.into(),
+    })
 }
 
 fn build_get_plan_request(
     modules: &[&ModuleDefinition],
     opts: &DeployOptions,
-) -> ApiGetPlanRequest {
+) -> Result<ApiGetPlanRequest> {
     let modules = modules
         .iter()
         .filter(|m| m.kind == ModuleKind::Service)
@@ -135,180 +144,109 @@ fn build_get_plan_request(
             }
             _ => unreachable!(),
         })
-        .collect();
-    ApiGetPlanRequest { modules }
+        .collect::<Result<_>>()?;
+    Ok(ApiGetPlanRequest { modules })
 }
 
 fn build_get_log_file_request(
     module_name: &str,
     module_kind: &ModuleKind,
+    project: Option<&str>,
+    run: Option<usize>,
 ) -> ApiLogFileRequest {
     ApiLogFileRequest {
         module_name: module_name.to_string(),
         module_kind: module_kind.into(),
+        project: project.map(String::from),
+        run,
     }
 }
 
 pub fn deploy_module(
     module_definition: &ServiceOrTaskDefinition,
     deploy_opts: &DeployOptions,
-    daemon_url: &str,
+    daemon: &CartelApiClient,
 ) -> Result<ApiDeploymentResponse> {
-    let client = reqwest::blocking::Client::new();
-    let command = build_deploy_command(module_definition, deploy_opts);
-
-    let deployment_result: DeploymentResponse = client
-        .post(&(daemon_url.to_owned() + "/deploy"))
-        .json(&command)
-        .send()?
-        .json()?;
-
-    match deployment_result {
-        DeploymentResponse::Ok(r) => Ok(r),
-        DeploymentResponse::Err(e) => bail!(e.message),
-    }
+    let command = build_deploy_command(module_definition, deploy_opts)?;
+    daemon.deploy(&command)
 }
 
 pub fn deploy_task(
     task_definition: &ServiceOrTaskDefinition,
     deploy_opts: &DeployOptions,
-    daemon_url: &str,
-) -> Result<ApiTaskDeploymentResponse> {
-    let client = client(&task_definition.timeout);
-    let command = build_task_deploy_command(task_definition, deploy_opts);
-
-    let deployment_result: TaskDeploymentResponse = client
-        .post(&(daemon_url.to_owned() + "/tasks/deploy"))
-        .json(&command)
-        .send()
-        .map_err(|e| {
-            if e.is_timeout() {
-                anyhow!(task_took_too_long_msg(&task_definition.name))
-            } else {
-                e.into()
+    daemon: &CartelApiClient,
+) -> Result<ApiTaskRunStatusResponse> {
+    let command = build_task_deploy_command(task_definition, deploy_opts)?;
+    let deadline =
+        Instant::now() + daemon.task_timeout(task_definition.timeout, &command);
+    let run = daemon.start_task(&command)?;
+    loop {
+        let status = daemon.task_run(&run.id)?;
+        match status.status {
+            ApiTaskRunStatus::Succeeded
+            | ApiTaskRunStatus::Skipped
+            | ApiTaskRunStatus::Failed => return Ok(status),
+            ApiTaskRunStatus::Running if Instant::now() >= deadline => {
+                bail!(task_took_too_long_msg(&task_definition.name))
             }
-        })?
-        .json()?;
-
-    match deployment_result {
-        TaskDeploymentResponse::Ok(r) => Ok(r),
-        TaskDeploymentResponse::Err(e) => bail!(e.message),
-    }
-}
-
-pub fn stop_module(
-    module_name: &str,
-    daemon_url: &str,
-) -> Result<ApiOperationResponse> {
-    let client = reqwest::blocking::Client::new();
-    let command = ApiOperationCommand {
-        operation: ApiModuleOperation::STOP,
-        module_name: module_name.to_string(),
-    };
-
-    let operation_result: OperationResponse = client
-        .post(&(daemon_url.to_owned() + "/operation"))
-        .json(&command)
-        .send()?
-        .json()?;
-
-    match operation_result {
-        OperationResponse::Ok(r) => Ok(r),
-        OperationResponse::Err(e) => bail!(e.message),
-    }
-}
-
-pub fn stop_all(daemon_url: &str) -> Result<ApiOperationResponse> {
-    let client = reqwest::blocking::Client::new();
-
-    let operation_result: OperationResponse = client
-        .post(&(daemon_url.to_owned() + "/stop_all"))
-        .send()?
-        .json()?;
-
-    match operation_result {
-        OperationResponse::Ok(r) => Ok(r),
-        OperationResponse::Err(e) => bail!(e.message),
-    }
-}
-
-pub fn restart_module(
-    module_name: &str,
-    daemon_url: &str,
-) -> Result<ApiOperationResponse> {
-    let client = reqwest::blocking::Client::new();
-    let command = ApiOperationCommand {
-        operation: ApiModuleOperation::RESTART,
-        module_name: module_name.to_string(),
-    };
-
-    let operation_result: OperationResponse = client
-        .post(&(daemon_url.to_owned() + "/operation"))
-        .json(&command)
-        .send()?
-        .json()?;
-
-    match operation_result {
-        OperationResponse::Ok(r) => Ok(r),
-        OperationResponse::Err(e) => bail!(e.message),
+            ApiTaskRunStatus::Running => thread::sleep(TASK_POLL_INTERVAL),
+        }
     }
 }
 
-pub fn list_modules(daemon_url: &str) -> Result<ApiModuleStatusResponse> {
-    let client = reqwest::blocking::Client::new();
-    let status = client
-        .get(&(daemon_url.to_owned() + "/status"))
-        .send()?
-        .json()?;
-
-    Ok(status)
-}
-
 pub fn log_file_path(
     module_name: &str,
     module_kind: &ModuleKind,
-    daemon_url: &str,
+    project: Option<&str>,
+    run: Option<usize>,
+    daemon: &CartelApiClient,
 ) -> Result<ApiLogResponse> {
-    let client = reqwest::blocking::Client::new();
-    let request = build_get_log_file_request(module_name, module_kind);
-    let status: LogInfoResponse = client
-        .post(&(daemon_url.to_owned() + "/log_file"))
-        .json(&request)
-        .send()?
-        .json()?;
-
-    match status {
-        LogInfoResponse::Ok(r) => Ok(r),
-        LogInfoResponse::Err(e) => bail!(e.message),
-    }
+    let request =
+        build_get_log_file_request(module_name, module_kind, project, run);
+    daemon.log_file(&request)
 }
 
-pub fn poll_health(
-    monitor_handle: &str,
-    daemon_url: &str,
-) -> Result<ApiHealthResponse> {
-    let client = reqwest::blocking::Client::new();
-    let health = client
-        .get(&(daemon_url.to_owned() + "/health/" + monitor_handle))
-        .send()?
-        .json()?;
+pub fn read_task_logs(
+    task_definition: &ServiceOrTaskDefinition,
+    offset: u64,
+    modified_after: Option<u64>,
+    daemon: &CartelApiClient,
+) -> Result<ApiLogReadResponse> {
+    let request = ApiLogReadRequest {
+        module_kind: ApiModuleKind::Task,
+        project: task_definition.project.clone(),
+        log_file_path: task_definition.log_file_path.clone(),
+        offset,
+        modified_after,
+        from_end: false,
+    };
+    daemon.read_logs(&task_definition.name, &request)
+}
 
-    Ok(health)
+/// Returns the offset of the end of the log file of a task, or 0 if it does
+/// not exist yet.
+pub fn task_log_end(
+    task_definition: &ServiceOrTaskDefinition,
+    daemon: &CartelApiClient,
+) -> Result<u64> {
+    let request = ApiLogReadRequest {
+        module_kind: ApiModuleKind::Task,
+        project: task_definition.project.clone(),
+        log_file_path: task_definition.log_file_path.clone(),
+        offset: 0,
+        modified_after: None,
+        from_end: true,
+    };
+    Ok(daemon.read_logs(&task_definition.name, &request)?.offset)
 }
 
 pub fn get_plan(
     modules: &[&ModuleDefinition],
     opts: &DeployOptions,
-    daemon_url: &str,
+    daemon: &CartelApiClient,
 ) -> Result<ApiGetPlanResponse> {
-    let client = reqwest::blocking::Client::new();
-    let request = build_get_plan_request(modules, opts);
-    let get_plan_result = client
-        .post(&(daemon_url.to_owned() + "/get_plan"))
-        .json(&request)
-        .send()?
-        .json()?;
-    Ok(get_plan_result)
+    let request = build_get_plan_request(modules, opts)?;
+    daemon.plan(&request)
 }
 
 fn task_took_too_long_msg(task_name: &str) -> String {
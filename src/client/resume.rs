@@ -0,0 +1,86 @@
+use crate::path;
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+/// The directory within the state directory holding the progress of deploys
+/// which did not complete.
+const DEPLOYS_DIR: &str = "deploys";
+
+/// The modules completed by a deploy which did not complete, so that it can
+/// be resumed from the first incomplete group.
+#[derive(Serialize, Deserialize, Default)]
+pub struct DeployProgress {
+    /// Identifies the deploy, see [plan_key].
+    #[serde(skip)]
+    key: String,
+    pub completed: HashSet<String>,
+}
+
+/// Returns a key identifying the plan of a deploy, made of the project, the
+/// active environment sets and the modules in each group.
+///
+/// The module definitions are left out, so that a deploy can be resumed
+/// after fixing the one which failed. The key is a SHA-256 digest, which
+/// unlike the std hashers stays the same across Rust releases.
+pub fn plan_key(
+    project_dir: Option<&str>,
+    active_envs: &[String],
+    groups: &[Vec<&str>],
+) -> String {
+    let plan = serde_json::to_vec(&(project_dir, active_envs, groups))
+        .expect("Failed to serialize deploy plan");
+    let digest = format!("{:x}", Sha256::digest(&plan));
+    digest[..16].to_string()
+}
+
+fn progress_file_path(key: &str) -> Result<PathBuf> {
+    path::state_dir()
+        .map(|dir| dir.join(DEPLOYS_DIR).join(format!("{}.json", key)))
+        .ok_or_else(|| anyhow!("Failed to locate users state dir"))
+}
+
+impl DeployProgress {
+    pub fn new(key: String) -> DeployProgress {
+        DeployProgress {
+            key,
+            completed: HashSet::new(),
+        }
+    }
+
+    /// Reads the progress of the deploy with the given key, if it did not
+    /// complete.
+    pub fn read(key: &str) -> Result<Option<DeployProgress>> {
+        let path = progress_file_path(key)?;
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let mut progress: DeployProgress = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+        progress.key = key.to_string();
+        Ok(Some(progress))
+    }
+
+    /// Records the progress, so that the deploy can be resumed.
+    pub fn write(&self) -> Result<()> {
+        let path = progress_file_path(&self.key)?;
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(&path, serde_json::to_string(self)?).with_context(|| {
+            format!("Failed to write deploy progress {}", path.display())
+        })
+    }
+
+    /// Removes the progress once the deploy completed.
+    pub fn remove(&self) -> Result<()> {
+        match fs::remove_file(progress_file_path(&self.key)?) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
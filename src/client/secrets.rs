@@ -0,0 +1,256 @@
+use crate::client::keychain;
+use anyhow::{anyhow, bail, Context, Result};
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::process::Command;
+
+/// The keychain service of secrets referenced without one.
+const DEFAULT_KEYCHAIN_SERVICE: &str = "cartel";
+
+lazy_static! {
+    /// The secrets read so far, so that each is read once per command (eg.
+    /// when obtaining the plan of a deploy, and deploying).
+    static ref SECRETS: Mutex<HashMap<String, String>> =
+        Mutex::new(HashMap::new());
+}
+
+/// A source secrets are read from.
+///
+/// Each provider handles the references starting with its scheme (eg.
+/// `vault:secret/app#password`), while those without a known scheme are read
+/// from the keychain of the OS.
+pub trait SecretProvider: Sync {
+    /// The scheme of the references the provider handles, without the colon.
+    fn scheme(&self) -> &'static str;
+
+    /// Reads a secret, given its reference without the scheme.
+    fn read(&self, reference: &str) -> Result<String>;
+}
+
+/// Reads secrets from the keychain of the OS (see [keychain]), referenced as
+/// `<service>/<account>` or by account alone within the `cartel` service.
+struct KeychainProvider;
+
+impl SecretProvider for KeychainProvider {
+    fn scheme(&self) -> &'static str {
+        "keychain"
+    }
+
+    fn read(&self, reference: &str) -> Result<String> {
+        let (service, account) = match reference.split_once('/') {
+            Some((service, account)) => (service, account),
+            None => (DEFAULT_KEYCHAIN_SERVICE, reference),
+        };
+        if service.is_empty() || account.is_empty() {
+            bail!("Invalid secret, expected <service>/<account>");
+        }
+        run_tool(&mut keychain::lookup_command(service, account))
+    }
+}
+
+/// Reads secrets from HashiCorp Vault with the `vault` CLI, referenced as
+/// `vault:<path>#<field>` (the field defaulting to `value`). The address and
+/// token of Vault are taken from the environment as usual (eg. `VAULT_ADDR`).
+struct VaultProvider;
+
+impl SecretProvider for VaultProvider {
+    fn scheme(&self) -> &'static str {
+        "vault"
+    }
+
+    fn read(&self, reference: &str) -> Result<String> {
+        let (path, field) =
+            reference.split_once('#').unwrap_or((reference, "value"));
+        run_tool(
+            Command::new("vault")
+                .args(["kv", "get"])
+                .arg(format!("-field={}", field))
+                .arg(path),
+        )
+    }
+}
+
+/// Reads secrets from 1Password with the `op` CLI, referenced by their secret
+/// reference (`op://<vault>/<item>/<field>`).
+struct OnePasswordProvider;
+
+impl SecretProvider for OnePasswordProvider {
+    fn scheme(&self) -> &'static str {
+        "op"
+    }
+
+    fn read(&self, reference: &str) -> Result<String> {
+        run_tool(
+            Command::new("op")
+                .arg("read")
+                .arg(format!("op:{}", reference)),
+        )
+    }
+}
+
+/// Reads secrets from AWS Secrets Manager with the `aws` CLI, referenced as
+/// `aws-sm:<secret id>`, or `aws-sm:<secret id>#<key>` for a key of a secret
+/// stored as JSON.
+struct AwsSecretsManagerProvider;
+
+impl SecretProvider for AwsSecretsManagerProvider {
+    fn scheme(&self) -> &'static str {
+        "aws-sm"
+    }
+
+    fn read(&self, reference: &str) -> Result<String> {
+        let (secret_id, key) = match reference.split_once('#') {
+            Some((secret_id, key)) => (secret_id, Some(key)),
+            None => (reference, None),
+        };
+        let secret = run_tool(
+            Command::new("aws")
+                .args(["secretsmanager", "get-secret-value", "--secret-id"])
+                .arg(secret_id)
+                .args(["--query", "SecretString", "--output", "text"]),
+        )?;
+        match key {
+            Some(key) => json_secret_key(&secret, key),
+            None => Ok(secret),
+        }
+    }
+}
+
+/// Returns the value of a key of a secret stored as a JSON object.
+fn json_secret_key(secret: &str, key: &str) -> Result<String> {
+    let values: HashMap<String, serde_json::Value> =
+        serde_json::from_str(secret)
+            .map_err(|_| anyhow!("The secret is not a JSON object"))?;
+    match values.get(key) {
+        Some(serde_json::Value::String(value)) => Ok(value.clone()),
+        Some(value) => Ok(value.to_string()),
+        None => bail!("The secret has no key '{}'", key),
+    }
+}
+
+/// The providers secrets can be read from.
+fn providers() -> &'static [&'static dyn SecretProvider] {
+    &[
+        &KeychainProvider,
+        &VaultProvider,
+        &OnePasswordProvider,
+        &AwsSecretsManagerProvider,
+    ]
+}
+
+/// Reads a secret from the provider of the scheme of its reference (see
+/// [SecretProvider]), or from the keychain of the OS if it has none.
+pub fn read_secret(reference: &str) -> Result<String> {
+    if let Some(secret) = SECRETS.lock().get(reference) {
+        return Ok(secret.clone());
+    }
+
+    let provider = reference.split_once(':').and_then(|(scheme, rest)| {
+        providers()
+            .iter()
+            .find(|p| p.scheme() == scheme)
+            .map(|p| (*p, rest))
+    });
+    let secret = match provider {
+        Some((provider, rest)) => provider.read(rest),
+        None => KeychainProvider.read(reference),
+    }
+    .with_context(|| format!("Failed to read the secret '{}'", reference))?;
+
+    SECRETS.lock().insert(reference.to_string(), secret.clone());
+    Ok(secret)
+}
+
+/// Runs the tool printing a secret, returning its output.
+fn run_tool(cmd: &mut Command) -> Result<String> {
+    let program = cmd.get_program().to_string_lossy().into_owned();
+    let output = cmd
+        .output()
+        .with_context(|| format!("Failed to run '{}'", program))?;
+    if !output.status.success() {
+        bail!(
+            "'{}' failed to read it ({})",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let secret = String::from_utf8(output.stdout)
+        .map_err(|_| anyhow!("The secret is not UTF-8"))?;
+    // The tools print a newline after the secret.
+    Ok(secret
+        .strip_suffix('\n')
+        .map(|s| s.strip_suffix('\r').unwrap_or(s))
+        .unwrap_or(&secret)
+        .to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn shell(script: &str) -> Command {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(script);
+        cmd
+    }
+
+    #[test]
+    fn test_run_tool_strips_trailing_newline() {
+        assert_eq!(
+            run_tool(&mut shell("printf 's3cret\\n'")).unwrap(),
+            "s3cret"
+        );
+        assert_eq!(
+            run_tool(&mut shell("printf 's3cret\\r\\n'")).unwrap(),
+            "s3cret"
+        );
+        assert_eq!(run_tool(&mut shell("printf 'a\\nb\\n'")).unwrap(), "a\nb");
+    }
+
+    #[test]
+    fn test_run_tool_failure() {
+        let error =
+            run_tool(&mut shell("echo denied >&2; exit 1")).unwrap_err();
+        assert_eq!(error.to_string(), "'sh' failed to read it (denied)");
+    }
+
+    #[test]
+    fn test_run_tool_rejects_non_utf8() {
+        let error = run_tool(&mut shell("printf '\\377'")).unwrap_err();
+        assert_eq!(error.to_string(), "The secret is not UTF-8");
+    }
+
+    #[test]
+    fn test_json_secret_key() {
+        let secret = r#"{"user": "admin", "port": 5432}"#;
+        assert_eq!(json_secret_key(secret, "user").unwrap(), "admin");
+        assert_eq!(json_secret_key(secret, "port").unwrap(), "5432");
+        assert!(json_secret_key(secret, "password").is_err());
+        assert!(json_secret_key("plain", "user").is_err());
+    }
+
+    #[test]
+    fn test_keychain_rejects_invalid_reference() {
+        assert!(KeychainProvider.read("/account").is_err());
+        assert!(KeychainProvider.read("service/").is_err());
+    }
+
+    #[test]
+    fn test_read_secret_is_cached() {
+        let reference = "vault:secret/test-cached#value";
+        SECRETS
+            .lock()
+            .insert(reference.to_string(), "cached".to_string());
+        assert_eq!(read_secret(reference).unwrap(), "cached");
+    }
+
+    #[test]
+    fn test_providers_have_unique_schemes() {
+        let mut schemes: Vec<_> =
+            providers().iter().map(|p| p.scheme()).collect();
+        schemes.sort_unstable();
+        schemes.dedup();
+        assert_eq!(schemes.len(), providers().len());
+    }
+}
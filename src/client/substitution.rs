@@ -0,0 +1,169 @@
+use crate::client::cmd::shell_to_cmd;
+use anyhow::{anyhow, bail, Context, Result};
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long a substituted command may run for.
+const SUBSTITUTION_TIMEOUT: Duration = Duration::from_secs(30);
+/// How often to check whether a substituted command has exited.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+lazy_static! {
+    /// The output of the commands substituted so far, so that each is run
+    /// once per command (eg. when obtaining the plan of a deploy, and
+    /// deploying).
+    static ref OUTPUTS: Mutex<HashMap<String, String>> =
+        Mutex::new(HashMap::new());
+}
+
+/// Returns the command of a value given as a command substitution (eg.
+/// `$(aws ecr get-login-password)`), if it is one.
+pub fn substituted_command(value: &str) -> Option<&str> {
+    value
+        .trim()
+        .strip_prefix("$(")
+        .and_then(|v| v.strip_suffix(')'))
+        .map(str::trim)
+        .filter(|cmd| !cmd.is_empty())
+}
+
+/// Runs a substituted command, returning its output without trailing
+/// newlines as a shell would.
+///
+/// The command is killed if it does not exit within
+/// [SUBSTITUTION_TIMEOUT], and its output is no longer waited for after then
+/// (eg. if a process it left in the background holds on to it).
+pub fn substitute(command: &str) -> Result<String> {
+    if let Some(output) = OUTPUTS.lock().get(command) {
+        return Ok(output.clone());
+    }
+
+    let output = run(command)
+        .with_context(|| format!("Failed to substitute '$({})'", command))?;
+
+    OUTPUTS.lock().insert(command.to_string(), output.clone());
+    Ok(output)
+}
+
+fn run(command: &str) -> Result<String> {
+    let cmd_line = shell_to_cmd(command);
+    let mut child = Command::new(&cmd_line[0])
+        .args(&cmd_line[1..])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to run the command")?;
+    // The pipes are read while waiting, so that a command with plenty of
+    // output does not block on writing it.
+    let stdout = read_in_background(child.stdout.take());
+    let stderr = read_in_background(child.stderr.take());
+
+    let deadline = Instant::now() + SUBSTITUTION_TIMEOUT;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            bail!(
+                "The command did not exit within {}s",
+                SUBSTITUTION_TIMEOUT.as_secs()
+            );
+        }
+        thread::sleep(POLL_INTERVAL);
+    };
+
+    let stdout = read_output(&stdout, deadline)?;
+    if !status.success() {
+        let stderr = read_output(&stderr, deadline).unwrap_or_default();
+        bail!(
+            "The command failed with {} ({})",
+            status,
+            String::from_utf8_lossy(&stderr).trim()
+        );
+    }
+    let output = String::from_utf8(stdout)
+        .context("The output of the command is not UTF-8")?;
+    Ok(output.trim_end_matches(&['\n', '\r'][..]).to_string())
+}
+
+fn read_in_background<R: Read + Send + 'static>(
+    pipe: Option<R>,
+) -> Receiver<Vec<u8>> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buf);
+        }
+        let _ = tx.send(buf);
+    });
+    rx
+}
+
+/// Waits for the output read from a pipe until the deadline, as the pipe
+/// stays open while processes left in the background hold on to it.
+fn read_output(
+    output: &Receiver<Vec<u8>>,
+    deadline: Instant,
+) -> Result<Vec<u8>> {
+    output
+        .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+        .map_err(|_| {
+            anyhow!(
+                "The output of the command was not closed within {}s (eg. \
+                by a process left in the background)",
+                SUBSTITUTION_TIMEOUT.as_secs()
+            )
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_substituted_command() {
+        assert_eq!(substituted_command("$(whoami)"), Some("whoami"));
+        assert_eq!(
+            substituted_command("  $( aws ecr get-login-password ) "),
+            Some("aws ecr get-login-password")
+        );
+        assert_eq!(
+            substituted_command("$(echo $(date))"),
+            Some("echo $(date)")
+        );
+    }
+
+    #[test]
+    fn test_substituted_command_not_substituted() {
+        assert_eq!(substituted_command("plain"), None);
+        assert_eq!(substituted_command("$()"), None);
+        assert_eq!(substituted_command("$(  )"), None);
+        assert_eq!(substituted_command("$(whoami"), None);
+        assert_eq!(substituted_command("prefix $(whoami)"), None);
+        assert_eq!(substituted_command("$HOME"), None);
+    }
+
+    #[test]
+    fn test_substitute_trims_trailing_newlines() {
+        assert_eq!(substitute("printf 'a b\\n\\r\\n'").unwrap(), "a b");
+    }
+
+    #[test]
+    fn test_substitute_failure() {
+        let error = run("echo oops >&2; exit 3").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The command failed with exit status: 3 (oops)"
+        );
+    }
+}
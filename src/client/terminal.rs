@@ -30,7 +30,11 @@ macro_rules! texit {
 #[macro_export]
 macro_rules! teprinterr {
     ( $x:expr ) => {
-        eprintln!("{} {:?}", console::style("Error:").bold().red(), $x);
+        eprintln!(
+            "{} {}",
+            console::style("Error:").bold().red(),
+            $crate::client::mask::redact(&format!("{:?}", $x))
+        );
     };
 }
 
@@ -0,0 +1,72 @@
+use anyhow::{bail, Context, Result};
+use std::net::{TcpListener, TcpStream};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long to wait for the tunnel to accept connections.
+const TUNNEL_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// A local port forwarded to the daemon on a remote host through `ssh`.
+///
+/// The tunnel is closed when dropped.
+pub struct SshTunnel {
+    /// The local port forwarded to the remote daemon.
+    pub port: u16,
+    ssh: Child,
+}
+
+impl SshTunnel {
+    /// Opens a tunnel through `destination` (eg. `user@devbox`) to `host` and
+    /// `port` as resolved on the remote host.
+    ///
+    /// Authentication is left to ssh, which runs in batch mode so that it
+    /// fails rather than prompting (eg. for a password) while commands wait.
+    pub fn open(destination: &str, host: &str, port: u16) -> Result<SshTunnel> {
+        // Let the OS pick a free port, which is released for ssh to bind.
+        let local_port = TcpListener::bind("127.0.0.1:0")?.local_addr()?.port();
+        let forward = format!("127.0.0.1:{}:{}:{}", local_port, host, port);
+        let ssh = Command::new("ssh")
+            .args([
+                "-N",
+                "-o",
+                "BatchMode=yes",
+                "-o",
+                "ExitOnForwardFailure=yes",
+                "-L",
+                &forward,
+                destination,
+            ])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .spawn()
+            .context("Failed to run ssh (is it installed?)")?;
+        let mut tunnel = SshTunnel {
+            port: local_port,
+            ssh,
+        };
+
+        let started = Instant::now();
+        while TcpStream::connect(("127.0.0.1", local_port)).is_err() {
+            if let Some(status) = tunnel.ssh.try_wait()? {
+                bail!(
+                    "Failed to open ssh tunnel to {} ({})",
+                    destination,
+                    status
+                );
+            }
+            if started.elapsed() > TUNNEL_TIMEOUT {
+                bail!("Timed out opening ssh tunnel to {}", destination);
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        Ok(tunnel)
+    }
+}
+
+impl Drop for SshTunnel {
+    fn drop(&mut self) {
+        let _ = self.ssh.kill();
+        let _ = self.ssh.wait();
+    }
+}
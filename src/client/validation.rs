@@ -1,6 +1,11 @@
-use crate::client::module::{InnerDefinition, ModuleDefinition, Probe};
+use crate::client::commands::DeployOptions;
+use crate::client::module::{
+    InnerDefinition, LogSink, ModuleDefinition, Probe, ServiceOrTaskDefinition,
+};
+use crate::client::request::build_env;
 use anyhow::{bail, Context, Result};
 use std::collections::HashSet;
+use std::{env, iter};
 
 pub fn non_existant_modules<'a>(
     module_names: &HashSet<&str>,
@@ -75,6 +80,20 @@ pub fn validate_fields(modules: &[ModuleDefinition]) -> Result<()> {
                         svc_or_task.name
                     );
                 }
+                if svc_or_task.log_sink == LogSink::Syslog
+                    && [
+                        &svc_or_task.readiness_probe,
+                        &svc_or_task.liveness_probe,
+                    ]
+                    .iter()
+                    .any(|probe| matches!(probe, Some(Probe::LogLine(_))))
+                {
+                    bail!(
+                        "Log line probes for {} require the output to be \
+                        written to the log file (log_sink: file or both)",
+                        svc_or_task.name
+                    );
+                }
                 if let Some(Probe::Exec(probe)) = &svc_or_task.liveness_probe {
                     if probe.shell.is_some() && !probe.command.is_empty() {
                         bail!(
@@ -166,12 +185,27 @@ pub fn validate_dependencies_exist(modules: &[ModuleDefinition]) -> Result<()> {
         match module.inner {
             InnerDefinition::Group(ref grp) => {
                 validate_module_names_exist(&module_names, &grp.dependencies)
+                    .with_context(|| {
+                    format!(
+                        "Failed resolving dependencies of group '{}'",
+                        module.name
+                    )
+                })?;
+                validate_module_names_exist(&module_names, &grp.post_up)
                     .with_context(|| {
                         format!(
-                            "Failed resolving dependencies of group '{}'",
+                            "Failed resolving post_up dependencies of group \
+                            '{}'",
                             module.name
                         )
-                    })?
+                    })?;
+                validate_module_names_exist(&module_names, &grp.post)
+                    .with_context(|| {
+                        format!(
+                            "Failed resolving post dependencies of group '{}'",
+                            module.name
+                        )
+                    })?;
             }
             InnerDefinition::Shell(ref shell) => {
                 let service_name = vec![shell.service.clone()];
@@ -187,7 +221,7 @@ pub fn validate_dependencies_exist(modules: &[ModuleDefinition]) -> Result<()> {
             | InnerDefinition::Task(ref svc_or_task) => {
                 validate_module_names_exist(
                     &module_names,
-                    &svc_or_task.dependencies,
+                    &svc_or_task.dependency_names(),
                 )
                 .with_context(|| {
                     format!(
@@ -228,3 +262,60 @@ pub fn validate_dependencies_exist(modules: &[ModuleDefinition]) -> Result<()> {
     }
     Ok(())
 }
+
+/// Ensures the environment variables required by the services and tasks
+/// about to be deployed (and their sidecars) are set, either by the module
+/// itself (with the active environment sets applied) or in the environment
+/// `cartel` is run from.
+///
+/// All missing variables are reported at once, so that they can be set
+/// before anything is deployed.
+pub fn validate_required_env<T: AsRef<ModuleDefinition>>(
+    modules: &[T],
+    opts: &DeployOptions,
+) -> Result<()> {
+    let mut missing = vec![];
+    for module in modules {
+        if let InnerDefinition::Service(svc) | InnerDefinition::Task(svc) =
+            &module.as_ref().inner
+        {
+            for svc in iter::once(svc).chain(&svc.sidecars) {
+                let names = missing_env(svc, opts)?;
+                if !names.is_empty() {
+                    missing.push(format!(
+                        "  {}: {}",
+                        svc.name,
+                        names.join(", ")
+                    ));
+                }
+            }
+        }
+    }
+    if !missing.is_empty() {
+        bail!(
+            "Missing required environment variables:\n{}\nSet them in the \
+            environment of the modules, or the environment cartel is run from.",
+            missing.join("\n")
+        );
+    }
+    Ok(())
+}
+
+fn missing_env<'a>(
+    svc: &'a ServiceOrTaskDefinition,
+    opts: &DeployOptions,
+) -> Result<Vec<&'a str>> {
+    if svc.required_env.is_empty() {
+        return Ok(vec![]);
+    }
+    let environment = build_env(svc, opts)?;
+    Ok(svc
+        .required_env
+        .iter()
+        .filter(|name| {
+            !environment.contains_key(name.as_str())
+                && env::var_os(name.as_str()).is_none()
+        })
+        .map(String::as_str)
+        .collect())
+}
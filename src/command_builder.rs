@@ -38,6 +38,12 @@ impl CommandBuilder {
         self
     }
 
+    /// Clear the environment variables the process would inherit.
+    pub fn env_clear(&mut self) -> &mut Self {
+        self.command.env_clear();
+        self
+    }
+
     /// Set the process's standard output (stdout) handle.
     pub fn stdout<T>(&mut self, stdout: T) -> &mut Self
     where
@@ -68,6 +74,12 @@ impl CommandBuilder {
         self
     }
 
+    /// Set the process's standard input (stdin) handle from a [File].
+    pub fn stdin_file(&mut self, stdin: File) -> &mut Self {
+        self.command.stdin(Stdio::from(stdin));
+        self
+    }
+
     /// Set the process's standard output (stdout) handle from a [File].
     pub fn stdout_file(&mut self, stdout: File) -> &mut Self {
         self.command.stdout(Stdio::from(stdout));
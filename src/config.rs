@@ -1,9 +1,13 @@
-use crate::constants::PROJECT_DIR;
-use anyhow::{bail, Context, Result};
+use crate::constants::{CONFIG_FILE, LEGACY_PROJECT_DIR, LOG_DIR};
+use crate::path;
+use anyhow::{anyhow, bail, Context, Result};
 use lazy_static::lazy_static;
 use phf::phf_map;
 use serde::de::{Error, Unexpected};
 use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::env;
 use std::fs::{self, OpenOptions};
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
@@ -13,14 +17,79 @@ lazy_static! {
     pub static ref PERSISTED_CONFIG: PersistedConfig = PersistedConfig::new();
 }
 
+/// The prefix of the environment variables overriding configuration options.
+const ENV_PREFIX: &str = "CARTEL_";
+
+/// The port the daemon listens on unless configured otherwise.
+pub const DEFAULT_DAEMON_PORT: u16 = 13754;
+
+/// The address the daemon listens on unless configured otherwise.
+pub const DEFAULT_DAEMON_ADDRESS: &str = "127.0.0.1";
+
+/// The name of the context referring to the local daemon.
+pub const DEFAULT_CONTEXT: &str = "default";
+
 #[derive(Deserialize)]
 pub struct DaemonConfig {
     /// The port to reach the daemon at.
-    pub port: Option<String>,
+    #[serde(default)]
+    #[serde(deserialize_with = "integer_from_any")]
+    pub port: Option<u16>,
+    /// The address the daemon listens on. Defaults to the loopback address,
+    /// so that only local clients can reach it.
+    pub address: Option<String>,
     /// Turn on the experimental env grabber.
     #[serde(default)]
     #[serde(deserialize_with = "bool_from_enabled_disabled")]
     pub use_env_grabber: Option<bool>,
+    /// The directory where log files are written to.
+    pub log_dir: Option<String>,
+    /// The number of days after which log files are removed.
+    #[serde(default)]
+    #[serde(deserialize_with = "integer_from_any")]
+    pub log_retention_days: Option<u64>,
+    /// The maximum total size of all log files (eg. `500M`, `2G`). The oldest
+    /// log files are removed once the limit is exceeded.
+    pub log_max_total_size: Option<String>,
+    /// The size (eg. `100M`) after which a log file is rotated.
+    pub log_rotate_size: Option<String>,
+    /// Compress rotated log files using gzip.
+    #[serde(default)]
+    #[serde(deserialize_with = "bool_from_enabled_disabled")]
+    pub log_compress_rotated: Option<bool>,
+    /// The token clients must present to use the API. When unset, requests
+    /// are not authenticated.
+    pub auth_token: Option<String>,
+    /// Run the daemon under a supervisor which restarts it if it crashes.
+    #[serde(default)]
+    #[serde(deserialize_with = "bool_from_enabled_disabled")]
+    pub supervised: Option<bool>,
+}
+
+impl DaemonConfig {
+    /// Returns the directory log files are written to.
+    ///
+    /// Defaults to the `logs` directory within the state directory (see
+    /// [path::state_dir]) unless configured otherwise.
+    pub fn log_dir(&self) -> PathBuf {
+        match &self.log_dir {
+            Some(dir) => path::from_user_str(dir)
+                .expect("Failed to locate users home dir"),
+            None => default_log_dir(),
+        }
+    }
+}
+
+/// A raw option value as found in the TOML file.
+///
+/// Options used to be stored as strings only, so integers and booleans are
+/// also accepted in their string form.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawValue {
+    Integer(i64),
+    Boolean(bool),
+    String(String),
 }
 
 fn bool_from_enabled_disabled<'de, D>(
@@ -29,20 +98,55 @@ fn bool_from_enabled_disabled<'de, D>(
 where
     D: Deserializer<'de>,
 {
-    let deserialized = String::deserialize(deserializer)?;
-    let result = if deserialized == "enabled" {
-        true
-    } else if deserialized == "disabled" {
-        false
-    } else {
-        return Err(Error::invalid_value(
-            Unexpected::Str(&deserialized),
-            &"\"enabled\" or \"disabled\"",
-        ));
+    let result = match RawValue::deserialize(deserializer)? {
+        RawValue::Boolean(b) => b,
+        RawValue::String(s) => match parse_bool(&s) {
+            Some(b) => b,
+            None => {
+                return Err(Error::invalid_value(
+                    Unexpected::Str(&s),
+                    &"true, false, \"enabled\" or \"disabled\"",
+                ))
+            }
+        },
+        RawValue::Integer(i) => {
+            return Err(Error::invalid_type(
+                Unexpected::Signed(i),
+                &"a boolean",
+            ))
+        }
     };
     Ok(Some(result))
 }
 
+fn integer_from_any<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: TryFrom<i64>,
+{
+    let integer = match RawValue::deserialize(deserializer)? {
+        RawValue::Integer(i) => i,
+        RawValue::String(s) => s.trim().parse::<i64>().map_err(|_| {
+            Error::invalid_value(Unexpected::Str(&s), &"an integer")
+        })?,
+        RawValue::Boolean(b) => {
+            return Err(Error::invalid_type(Unexpected::Bool(b), &"an integer"))
+        }
+    };
+    T::try_from(integer).map(Some).map_err(|_| {
+        Error::invalid_value(Unexpected::Signed(integer), &"a valid integer")
+    })
+}
+
+/// Parses a boolean option, also accepting the legacy enabled/disabled form.
+fn parse_bool(value: &str) -> Option<bool> {
+    match value {
+        "true" | "enabled" => Some(true),
+        "false" | "disabled" => Some(false),
+        _ => None,
+    }
+}
+
 #[derive(Deserialize)]
 pub struct ClientConfig {
     /// The default directory to use when searching for module definitions.
@@ -52,12 +156,126 @@ pub struct ClientConfig {
     #[serde(default)]
     #[serde(deserialize_with = "bool_from_enabled_disabled")]
     pub use_current_shell: Option<bool>,
+    /// The number of threads to deploy with, unless given with `--threads`.
+    #[serde(default)]
+    #[serde(deserialize_with = "integer_from_any")]
+    pub default_threads: Option<u8>,
+    /// Wait for all readiness checks to complete when deploying.
+    #[serde(default)]
+    #[serde(deserialize_with = "bool_from_enabled_disabled")]
+    pub default_wait: Option<bool>,
+    /// Skip running checks when deploying.
+    #[serde(default)]
+    #[serde(deserialize_with = "bool_from_enabled_disabled")]
+    pub skip_checks: Option<bool>,
+    /// Print the plan and ask before deploying.
+    #[serde(default)]
+    #[serde(deserialize_with = "bool_from_enabled_disabled")]
+    pub confirm_deploy: Option<bool>,
+    /// A comma separated list of environment sets to activate when deploying,
+    /// unless given with `--env`.
+    pub active_envs: Option<String>,
+    /// The context (see [ContextConfig]) to use unless given with
+    /// `--context`.
+    pub current_context: Option<String>,
+    /// The number of times requests to the daemon are retried when the
+    /// connection fails.
+    #[serde(default)]
+    #[serde(deserialize_with = "integer_from_any")]
+    pub retry_attempts: Option<u32>,
+    /// The delay in milliseconds before the first retry, doubled for each
+    /// following one.
+    #[serde(default)]
+    #[serde(deserialize_with = "integer_from_any")]
+    pub retry_backoff_ms: Option<u64>,
+    /// Also retry requests which are not safe to repeat (such as restarting
+    /// a module).
+    #[serde(default)]
+    #[serde(deserialize_with = "bool_from_enabled_disabled")]
+    pub retry_non_idempotent: Option<bool>,
+    /// Start the daemon without asking when it is not running. If disabled
+    /// the client does not offer to start it either.
+    #[serde(default)]
+    #[serde(deserialize_with = "bool_from_enabled_disabled")]
+    pub auto_start_daemon: Option<bool>,
+    /// Seconds to wait for a connection to the daemon.
+    #[serde(default)]
+    #[serde(deserialize_with = "integer_from_any")]
+    pub connect_timeout_secs: Option<u64>,
+    /// Seconds to wait for the daemon to respond, unless given with
+    /// `--timeout`.
+    #[serde(default)]
+    #[serde(deserialize_with = "integer_from_any")]
+    pub request_timeout_secs: Option<u64>,
+    /// Seconds to wait for tasks without a `timeout` of their own.
+    #[serde(default)]
+    #[serde(deserialize_with = "integer_from_any")]
+    pub task_timeout_secs: Option<u64>,
+    /// A comma separated list of the columns `ps` shows, unless given with
+    /// `--columns`.
+    pub ps_columns: Option<String>,
+    /// A comma separated list of patterns (eg. `*_TOKEN`) of environment
+    /// variables whose values are masked in output, as if they were marked
+    /// with `secret: true`.
+    pub masked_env: Option<String>,
+}
+
+impl ClientConfig {
+    /// Returns the environment sets activated by default.
+    pub fn active_envs(&self) -> Vec<String> {
+        self.active_envs
+            .iter()
+            .flat_map(|envs| envs.split(','))
+            .map(str::trim)
+            .filter(|env| !env.is_empty())
+            .map(String::from)
+            .collect()
+    }
+
+    /// Returns the columns `ps` shows by default, if configured.
+    pub fn ps_columns(&self) -> Option<Vec<&str>> {
+        let columns: Vec<_> = self
+            .ps_columns
+            .iter()
+            .flat_map(|columns| columns.split(','))
+            .map(str::trim)
+            .filter(|column| !column.is_empty())
+            .collect();
+        Some(columns).filter(|columns| !columns.is_empty())
+    }
+
+    /// Returns the patterns of the environment variables which are masked.
+    pub fn masked_env(&self) -> Vec<&str> {
+        self.masked_env
+            .iter()
+            .flat_map(|patterns| patterns.split(','))
+            .map(str::trim)
+            .filter(|pattern| !pattern.is_empty())
+            .collect()
+    }
+}
+
+/// A named daemon the client can talk to, defined in the `[contexts]` table.
+#[derive(Deserialize)]
+pub struct ContextConfig {
+    /// The base URL of the daemon (eg. `http://devbox:13754`).
+    pub url: String,
+    /// The token to authenticate with (see `daemon.auth_token`).
+    pub auth_token: Option<String>,
+    /// A PEM file with the certificate to trust for `https` URLs (eg. that
+    /// of a self-signed certificate).
+    pub ca_cert: Option<String>,
+    /// An SSH destination (eg. `user@devbox`) to tunnel requests through,
+    /// in which case the URL is resolved on the remote host.
+    pub ssh: Option<String>,
 }
 
 #[derive(Deserialize)]
 pub struct PersistedConfig {
     pub client: ClientConfig,
     pub daemon: DaemonConfig,
+    #[serde(default)]
+    pub contexts: BTreeMap<String, ContextConfig>,
 }
 
 impl PersistedConfig {
@@ -78,16 +296,66 @@ impl Default for PersistedConfig {
     }
 }
 
-fn default_config_file_path() -> PathBuf {
-    let mut config_path =
-        dirs::home_dir().expect("Failed to locate users home dir");
-    config_path.push(PROJECT_DIR);
-    config_path.push("config.toml");
-    config_path
+/// Returns the path of the config file.
+pub fn config_file_path() -> PathBuf {
+    path::config_dir()
+        .expect("Failed to locate users config dir")
+        .join(CONFIG_FILE)
+}
+
+fn default_log_dir() -> PathBuf {
+    path::state_dir()
+        .expect("Failed to locate users state dir")
+        .join(LOG_DIR)
+}
+
+/// Moves the config file and logs out of the legacy `~/.cartel` directory.
+///
+/// Each is only moved if it does not exist in the new location yet. Any other
+/// files (such as `launch-daemon.sh`) are left in place, in which case the
+/// legacy directory is kept.
+fn migrate_legacy_layout() -> Result<()> {
+    let legacy_dir = match dirs::home_dir() {
+        Some(home) => home.join(LEGACY_PROJECT_DIR),
+        None => return Ok(()),
+    };
+    if !legacy_dir.is_dir() {
+        return Ok(());
+    }
+
+    let moves = [
+        (legacy_dir.join(CONFIG_FILE), config_file_path()),
+        (legacy_dir.join(LOG_DIR), default_log_dir()),
+    ];
+    for (from, to) in moves.iter() {
+        if !from.exists() || to.exists() {
+            continue;
+        }
+        fs::create_dir_all(to.parent().unwrap())?;
+        match fs::rename(from, to) {
+            Ok(_) => {}
+            // Another process (eg. the daemon) may have migrated it already
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => {
+                return Err(e).with_context(|| {
+                    format!(
+                        "Failed to move {} to {}",
+                        from.display(),
+                        to.display()
+                    )
+                })
+            }
+        }
+    }
+
+    // Only succeeds if the directory is now empty
+    let _ = fs::remove_dir(&legacy_dir);
+    Ok(())
 }
 
 pub fn create_config_if_not_exists() -> Result<()> {
-    let path = default_config_file_path();
+    migrate_legacy_layout()?;
+    let path = config_file_path();
 
     // Ensure the main project directory exists, creating all path components if
     // any are missing
@@ -112,26 +380,76 @@ pub fn create_config_if_not_exists() -> Result<()> {
     }
 }
 
+/// Returns the environment variable overriding the given option.
+///
+/// For example `daemon.port` is overridden by `CARTEL_DAEMON_PORT`.
+pub fn env_var_name(key: &str) -> String {
+    format!("{}{}", ENV_PREFIX, key.replace('.', "_").to_uppercase())
+}
+
+/// Returns the value of the given option if overridden by its environment
+/// variable.
+pub fn env_override(key: &str) -> Option<String> {
+    env::var(env_var_name(key)).ok()
+}
+
+/// Overrides the options in the given document with those set through
+/// environment variables.
+fn apply_env_overrides(document: &mut toml::Value) -> Result<()> {
+    for (flat_key, option) in KEY_TO_PATH.entries() {
+        let value = match env_override(flat_key) {
+            Some(value) => value,
+            None => continue,
+        };
+        let [namespace, key] = option.path;
+        let table = document
+            .as_table_mut()
+            .and_then(|root| {
+                root.entry(namespace)
+                    .or_insert_with(|| toml::Value::Table(Default::default()))
+                    .as_table_mut()
+            })
+            .ok_or_else(|| {
+                anyhow!("Expected '{}' to be a table in config file", namespace)
+            })?;
+        table.insert(key.to_string(), toml::Value::String(value));
+    }
+    Ok(())
+}
+
 /// Reads the persisted configuration from the given path.
 ///
+/// The configuration is layered: options set in the file are overridden by
+/// their `CARTEL_*` environment variables (see [env_var_name]), and any
+/// options set in neither are left to their defaults.
+///
 /// The file is expected to be of TOML format and conform to the schema. An
 /// error will be returned if the file does not exist.
 pub fn read_persisted_config_from_path(path: &Path) -> Result<PersistedConfig> {
     let toml_content = fs::read_to_string(path)
         .with_context(|| "Failed to read config file")?;
-    let document = toml::from_str::<PersistedConfig>(&toml_content)
+    let mut document = toml::from_str::<toml::Value>(&toml_content)
         .with_context(|| "Failed to parse TOML config file")?;
-    Ok(document)
+    apply_env_overrides(&mut document)?;
+    let config = document.try_into::<PersistedConfig>().with_context(|| {
+        format!(
+            "Invalid configuration in config file or {}* environment \
+            variables",
+            ENV_PREFIX
+        )
+    })?;
+    Ok(config)
 }
 
 /// Reads the persisted configuration from the default location.
 ///
-/// The default location is defined in src/constants.rs as `PROJECT_DIR`.
+/// The default location is `config.toml` within the directory returned by
+/// [path::config_dir].
 ///
 /// The file is expected to be of TOML format and conform to the schema. An
 /// error will be returned if the file does not exist.
 pub fn read_persisted_config() -> Result<PersistedConfig> {
-    let default_path = default_config_file_path();
+    let default_path = config_file_path();
     let config = read_persisted_config_from_path(&default_path)?;
     Ok(config)
 }
@@ -156,7 +474,7 @@ pub fn read_persisted_config_as_editable_from_path(
 /// The file is expected to be of TOML format and conform to the schema. If the
 /// file does not exist, it will be created.
 pub fn read_persisted_config_as_editable() -> Result<EditableConfig> {
-    let default_path = default_config_file_path();
+    let default_path = config_file_path();
     read_persisted_config_as_editable_from_path(&default_path)
 }
 
@@ -165,11 +483,203 @@ pub struct EditableConfig {
     path: PathBuf,
 }
 
-static KEY_TO_PATH: phf::Map<&'static str, [&'static str; 2]> = phf_map! {
-    "daemon.port" => ["daemon", "port"],
-    "daemon.use_env_grabber" => ["daemon", "use_env_grabber"],
-    "client.default_dir" => ["client", "default_dir"],
-    "client.use_current_shell" => ["client", "use_current_shell"],
+/// The type of value a configuration option holds.
+#[derive(PartialEq)]
+pub enum OptionKind {
+    String,
+    Integer,
+    Boolean,
+}
+
+/// A configuration option which can be edited through the CLI.
+pub struct ConfigOption {
+    /// The table and key of the option within the TOML file.
+    pub path: [&'static str; 2],
+    /// The type of value the option holds.
+    pub kind: OptionKind,
+    /// The value used when the option is unset (if any).
+    pub default: Option<&'static str>,
+    /// A short description of what the option controls.
+    pub description: &'static str,
+}
+
+impl ConfigOption {
+    /// Returns the value used when the option is unset (if any).
+    ///
+    /// The default log directory depends on the users state dir, so it is
+    /// resolved here instead of being listed in the option table.
+    pub fn default_value(&self) -> Option<String> {
+        match self.path {
+            ["daemon", "log_dir"] => path::state_dir()
+                .map(|dir| dir.join(LOG_DIR).display().to_string()),
+            _ => self.default.map(String::from),
+        }
+    }
+}
+
+static KEY_TO_PATH: phf::Map<&'static str, ConfigOption> = phf_map! {
+    "daemon.port" => ConfigOption {
+        path: ["daemon", "port"],
+        kind: OptionKind::Integer,
+        default: Some("13754"),
+        description: "The port the daemon listens on",
+    },
+    "daemon.use_env_grabber" => ConfigOption {
+        path: ["daemon", "use_env_grabber"],
+        kind: OptionKind::Boolean,
+        default: Some("false"),
+        description: "Run modules with the environment of a login shell",
+    },
+    "daemon.log_dir" => ConfigOption {
+        path: ["daemon", "log_dir"],
+        kind: OptionKind::String,
+        default: None,
+        description: "The directory log files are written to",
+    },
+    "daemon.log_retention_days" => ConfigOption {
+        path: ["daemon", "log_retention_days"],
+        kind: OptionKind::Integer,
+        default: None,
+        description: "Remove log files not written to for this many days",
+    },
+    "daemon.log_max_total_size" => ConfigOption {
+        path: ["daemon", "log_max_total_size"],
+        kind: OptionKind::String,
+        default: None,
+        description: "Remove the oldest log files above this size (eg. 2G)",
+    },
+    "daemon.log_rotate_size" => ConfigOption {
+        path: ["daemon", "log_rotate_size"],
+        kind: OptionKind::String,
+        default: None,
+        description: "Rotate log files larger than this size (eg. 100M)",
+    },
+    "daemon.log_compress_rotated" => ConfigOption {
+        path: ["daemon", "log_compress_rotated"],
+        kind: OptionKind::Boolean,
+        default: Some("false"),
+        description: "Compress rotated log files using gzip",
+    },
+    "daemon.address" => ConfigOption {
+        path: ["daemon", "address"],
+        kind: OptionKind::String,
+        default: Some(DEFAULT_DAEMON_ADDRESS),
+        description: "The address the daemon listens on",
+    },
+    "daemon.auth_token" => ConfigOption {
+        path: ["daemon", "auth_token"],
+        kind: OptionKind::String,
+        default: None,
+        description: "Token clients must present to use the daemon",
+    },
+    "daemon.supervised" => ConfigOption {
+        path: ["daemon", "supervised"],
+        kind: OptionKind::Boolean,
+        default: Some("false"),
+        description: "Restart the daemon if it crashes",
+    },
+    "client.current_context" => ConfigOption {
+        path: ["client", "current_context"],
+        kind: OptionKind::String,
+        default: Some(DEFAULT_CONTEXT),
+        description: "The daemon context to use (see config contexts)",
+    },
+    "client.default_dir" => ConfigOption {
+        path: ["client", "default_dir"],
+        kind: OptionKind::String,
+        default: None,
+        description: "Directory of the module definitions to fall back to",
+    },
+    "client.use_current_shell" => ConfigOption {
+        path: ["client", "use_current_shell"],
+        kind: OptionKind::Boolean,
+        default: Some("false"),
+        description: "Use the current shell (instead of bash) for commands",
+    },
+    "client.default_threads" => ConfigOption {
+        path: ["client", "default_threads"],
+        kind: OptionKind::Integer,
+        default: Some("4"),
+        description: "The number of threads to deploy with",
+    },
+    "client.default_wait" => ConfigOption {
+        path: ["client", "default_wait"],
+        kind: OptionKind::Boolean,
+        default: Some("false"),
+        description: "Wait for all readiness checks when deploying",
+    },
+    "client.skip_checks" => ConfigOption {
+        path: ["client", "skip_checks"],
+        kind: OptionKind::Boolean,
+        default: Some("false"),
+        description: "Skip running checks when deploying",
+    },
+    "client.confirm_deploy" => ConfigOption {
+        path: ["client", "confirm_deploy"],
+        kind: OptionKind::Boolean,
+        default: Some("false"),
+        description: "Print the plan and ask before deploying",
+    },
+    "client.active_envs" => ConfigOption {
+        path: ["client", "active_envs"],
+        kind: OptionKind::String,
+        default: None,
+        description: "Environment sets to activate when deploying (eg. a,b)",
+    },
+    "client.retry_attempts" => ConfigOption {
+        path: ["client", "retry_attempts"],
+        kind: OptionKind::Integer,
+        default: Some("3"),
+        description: "Times to retry daemon requests on connection failures",
+    },
+    "client.retry_backoff_ms" => ConfigOption {
+        path: ["client", "retry_backoff_ms"],
+        kind: OptionKind::Integer,
+        default: Some("200"),
+        description: "Delay before retrying, doubled for each retry (ms)",
+    },
+    "client.retry_non_idempotent" => ConfigOption {
+        path: ["client", "retry_non_idempotent"],
+        kind: OptionKind::Boolean,
+        default: Some("false"),
+        description: "Also retry requests unsafe to repeat (eg. restarts)",
+    },
+    "client.auto_start_daemon" => ConfigOption {
+        path: ["client", "auto_start_daemon"],
+        kind: OptionKind::Boolean,
+        default: None,
+        description: "Start the daemon without asking when it is not running",
+    },
+    "client.connect_timeout_secs" => ConfigOption {
+        path: ["client", "connect_timeout_secs"],
+        kind: OptionKind::Integer,
+        default: None,
+        description: "Seconds to wait for a connection to the daemon",
+    },
+    "client.request_timeout_secs" => ConfigOption {
+        path: ["client", "request_timeout_secs"],
+        kind: OptionKind::Integer,
+        default: Some("30"),
+        description: "Seconds to wait for the daemon to respond",
+    },
+    "client.task_timeout_secs" => ConfigOption {
+        path: ["client", "task_timeout_secs"],
+        kind: OptionKind::Integer,
+        default: Some("180"),
+        description: "Seconds to wait for tasks without their own timeout",
+    },
+    "client.ps_columns" => ConfigOption {
+        path: ["client", "ps_columns"],
+        kind: OptionKind::String,
+        default: None,
+        description: "Columns of ps to show (eg. pid,name,cpu,mem,uptime)",
+    },
+    "client.masked_env" => ConfigOption {
+        path: ["client", "masked_env"],
+        kind: OptionKind::String,
+        default: None,
+        description: "Environment variables to mask in output (eg. *_TOKEN)",
+    },
 };
 
 impl EditableConfig {
@@ -180,11 +690,14 @@ impl EditableConfig {
         }
     }
 
+    fn get_option_def(key: &str) -> Result<&'static ConfigOption> {
+        KEY_TO_PATH
+            .get(key)
+            .ok_or_else(|| anyhow!("Unsupported setting '{}'", key))
+    }
+
     fn get_option_path(key: &str) -> Result<(&'static str, &'static str)> {
-        if !KEY_TO_PATH.contains_key(key) {
-            bail!("Unsupported setting '{}'", key);
-        }
-        let [namespace, key] = KEY_TO_PATH.get(key).unwrap();
+        let [namespace, key] = Self::get_option_def(key)?.path;
         Ok((namespace, key))
     }
 
@@ -196,7 +709,13 @@ impl EditableConfig {
         match &self.raw_toml[namespace][key] {
             Item::None => Ok(None),
             Item::Value(Value::String(s)) => Ok(Some(s.value().to_string())),
-            _ => bail!("Unsupported format for key '{}'. All options should be strings.", key),
+            Item::Value(Value::Integer(i)) => Ok(Some(i.value().to_string())),
+            Item::Value(Value::Boolean(b)) => Ok(Some(b.value().to_string())),
+            _ => bail!(
+                "Unsupported format for key '{}'. Options should be strings, \
+                integers or booleans.",
+                key
+            ),
         }
     }
 
@@ -207,7 +726,8 @@ impl EditableConfig {
 
     pub fn get_all_options(&self) -> Result<Vec<(String, Option<String>)>> {
         let mut options = vec![];
-        for (flat_key, [namespace, key]) in KEY_TO_PATH.entries() {
+        for (flat_key, option) in KEY_TO_PATH.entries() {
+            let [namespace, key] = option.path;
             options.push((
                 flat_key.to_string(),
                 self.read_option_raw(namespace, key)?,
@@ -216,9 +736,43 @@ impl EditableConfig {
         Ok(options)
     }
 
+    /// Returns all known options along with their current values, sorted by
+    /// key.
+    pub fn list_options(
+        &self,
+    ) -> Result<Vec<(&'static str, &'static ConfigOption, Option<String>)>>
+    {
+        let mut options = vec![];
+        for (flat_key, option) in KEY_TO_PATH.entries() {
+            let [namespace, key] = option.path;
+            options.push((
+                *flat_key,
+                option,
+                self.read_option_raw(namespace, key)?,
+            ));
+        }
+        options.sort_by_key(|(flat_key, _, _)| *flat_key);
+        Ok(options)
+    }
+
+    /// Sets an option, storing the value as the type the option holds.
     pub fn set_option(&mut self, key: &str, new_val: String) -> Result<()> {
-        let (namespace, key) = Self::get_option_path(key)?;
-        self.raw_toml[namespace][key] = value(new_val);
+        let option = Self::get_option_def(key)?;
+        let new_value = match option.kind {
+            OptionKind::String => value(new_val),
+            OptionKind::Integer => {
+                value(new_val.trim().parse::<i64>().map_err(|_| {
+                    anyhow!("Expected an integer for '{}'", key)
+                })?)
+            }
+            OptionKind::Boolean => {
+                value(parse_bool(&new_val).ok_or_else(|| {
+                    anyhow!("Expected true or false for '{}'", key)
+                })?)
+            }
+        };
+        let [namespace, key] = option.path;
+        self.raw_toml[namespace][key] = new_value;
         Ok(())
     }
 
@@ -229,23 +783,55 @@ impl EditableConfig {
     }
 
     pub fn toggle_option(&mut self, key: &str) -> Result<String> {
-        let (namespace, key) = Self::get_option_path(key)?;
-        let new_value = match &self.raw_toml[namespace][key] {
-            Item::None => "enabled",
+        let option = Self::get_option_def(key)?;
+        if option.kind != OptionKind::Boolean {
+            bail!("Option '{}' is not a boolean", key);
+        }
+        let [namespace, key] = option.path;
+        let enabled = match &self.raw_toml[namespace][key] {
+            Item::Value(Value::Boolean(b)) => *b.value(),
             Item::Value(Value::String(s)) => {
-                if s.value() == "enabled" {
-                    "disabled"
-                } else {
-                    "enabled"
-                }
+                parse_bool(s.value()).unwrap_or(false)
             }
-            _ => "enabled",
+            _ => false,
         };
-        self.raw_toml[namespace][key] = value(new_value);
-        Ok(new_value.to_string())
+        self.raw_toml[namespace][key] = value(!enabled);
+        Ok((!enabled).to_string())
+    }
+
+    /// Returns the names of the contexts defined in the file.
+    pub fn context_names(&self) -> Vec<String> {
+        match self.raw_toml["contexts"].as_table() {
+            Some(table) => table.iter().map(|(k, _)| k.to_string()).collect(),
+            None => vec![],
+        }
+    }
+
+    /// Sets the context used by default, resetting to the local daemon for
+    /// the `default` context.
+    pub fn use_context(&mut self, name: &str) -> Result<()> {
+        if name == DEFAULT_CONTEXT {
+            return self.remove_option("client.current_context");
+        }
+        if !self.context_names().iter().any(|c| c == name) {
+            bail!(
+                "Unknown context '{}'. Contexts are defined in the [contexts] \
+                table of the config file",
+                name
+            );
+        }
+        self.set_option("client.current_context", name.to_string())
     }
 
+    /// Saves the configuration, unless the changes made it invalid (eg. an
+    /// integer out of the range of its option), which would fail every
+    /// command reading it afterwards.
     pub fn save(&self) -> Result<()> {
+        let content = self.raw_toml.to_string();
+        toml::from_str::<PersistedConfig>(&content).with_context(|| {
+            "Invalid configuration, the change was not saved"
+        })?;
+
         let mut file = OpenOptions::new()
             .write(true)
             .create(false)
@@ -253,7 +839,59 @@ impl EditableConfig {
             .open(&self.path)
             .with_context(|| "While saving new TOML config")?;
 
-        file.write_all(self.raw_toml.to_string().as_bytes())?;
+        file.write_all(content.as_bytes())?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn config_file(name: &str, content: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!(
+            "cartel-config-test-{}-{}.toml",
+            name,
+            std::process::id()
+        ));
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_save_rejects_out_of_range_port() {
+        let path = config_file("port", "[daemon]\nport = 8000\n\n[client]");
+        let mut editable =
+            read_persisted_config_as_editable_from_path(&path).unwrap();
+
+        editable
+            .set_option("daemon.port", "70000".to_string())
+            .unwrap();
+        assert!(editable.save().is_err());
+        let config = read_persisted_config_from_path(&path).unwrap();
+        assert_eq!(config.daemon.port, Some(8000));
+
+        editable
+            .set_option("daemon.port", "9000".to_string())
+            .unwrap();
+        editable.save().unwrap();
+        let config = read_persisted_config_from_path(&path).unwrap();
+        assert_eq!(config.daemon.port, Some(9000));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_save_rejects_negative_threads() {
+        let path = config_file("threads", "[daemon]\n\n[client]");
+        let mut editable =
+            read_persisted_config_as_editable_from_path(&path).unwrap();
+
+        editable
+            .set_option("client.default_threads", "-1".to_string())
+            .unwrap();
+        assert!(editable.save().is_err());
+        let config = read_persisted_config_from_path(&path).unwrap();
+        assert_eq!(config.client.default_threads, None);
+        fs::remove_file(&path).unwrap();
+    }
+}
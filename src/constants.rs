@@ -1 +1,21 @@
-pub const PROJECT_DIR: &str = ".cartel";
+/// The directory (within the home directory) used before adopting the XDG base
+/// directory layout.
+pub const LEGACY_PROJECT_DIR: &str = ".cartel";
+/// The name of the directory within the config and state directories.
+pub const APP_DIR: &str = "cartel";
+pub const CONFIG_FILE: &str = "config.toml";
+pub const LOG_DIR: &str = "logs";
+pub const TMP_DIR: &str = "tmp";
+/// The variable the temp directory of a service (with `tmpdir`) is exported
+/// as.
+pub const TMPDIR_ENV: &str = "CARTEL_TMPDIR";
+/// Set for a daemon run by a supervisor (see `daemon.supervised`), which
+/// does not supervise itself again. Unlike `CARTEL_DAEMON_SUPERVISED`, it
+/// does not override that option.
+pub const SUPERVISED_ENV: &str = "CARTEL_SUPERVISOR";
+/// What the values of secret environment variables are replaced with in
+/// output.
+pub const MASKED_VALUE: &str = "********";
+/// The fields of a JSON log line which may hold its timestamp.
+pub const TIME_KEYS: &[&str] =
+    &["time", "timestamp", "ts", "@timestamp", "asctime"];
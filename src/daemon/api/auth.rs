@@ -0,0 +1,71 @@
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::uri::Origin;
+use rocket::http::Method;
+use rocket::{Data, Request};
+use sha2::{Digest, Sha256};
+
+/// The route unauthorized requests are redirected to.
+pub const UNAUTHORIZED_PATH: &str = "/api/v1/unauthorized";
+
+/// Rejects requests which do not present the configured auth token.
+///
+/// Fairings cannot respond to requests directly, so unauthorized requests are
+/// rerouted to a handler which always fails.
+pub struct TokenAuth {
+    /// The digest of the expected `Authorization` header.
+    expected: Vec<u8>,
+}
+
+impl TokenAuth {
+    pub fn new(token: &str) -> TokenAuth {
+        TokenAuth {
+            expected: Sha256::digest(format!("Bearer {}", token).as_bytes())
+                .to_vec(),
+        }
+    }
+
+    /// Compares the digests of the headers in constant time, so that the
+    /// time taken does not reveal how much of the token was guessed right.
+    fn is_expected(&self, header: &str) -> bool {
+        Sha256::digest(header.as_bytes())
+            .iter()
+            .zip(&self.expected)
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+    }
+}
+
+impl Fairing for TokenAuth {
+    fn info(&self) -> Info {
+        Info {
+            name: "Token authentication",
+            kind: Kind::Request,
+        }
+    }
+
+    fn on_request(&self, request: &mut Request, _: &Data) {
+        let authorized = request
+            .headers()
+            .get_one("Authorization")
+            .map_or(false, |header| self.is_expected(header));
+        if !authorized {
+            request.set_method(Method::Get);
+            request.set_uri(Origin::parse(UNAUTHORIZED_PATH).unwrap());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_is_expected() {
+        let auth = TokenAuth::new("s3cret");
+        assert!(auth.is_expected("Bearer s3cret"));
+        assert!(!auth.is_expected("Bearer s3cre"));
+        assert!(!auth.is_expected("Bearer s3cret2"));
+        assert!(!auth.is_expected("s3cret"));
+        assert!(!auth.is_expected(""));
+    }
+}
@@ -0,0 +1,44 @@
+use crate::daemon::api::error::ApiError;
+use crate::daemon::panic::panic_message;
+use log::error;
+use rocket::handler::{Handler, Outcome};
+use rocket::{Data, Request, Route};
+use std::panic::{self, AssertUnwindSafe};
+
+/// A handler which runs the handler of a route, turning a panic into an
+/// internal error response instead of dropping the connection.
+#[derive(Clone)]
+struct CatchPanic(Box<dyn Handler>);
+
+impl Handler for CatchPanic {
+    fn handle<'r>(&self, request: &'r Request, data: Data) -> Outcome<'r> {
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            self.0.handle(request, data)
+        }));
+        match result {
+            Ok(outcome) => outcome,
+            Err(payload) => {
+                let message = panic_message(payload.as_ref());
+                error!(
+                    "Handler of {} {} panicked: {}",
+                    request.method(),
+                    request.uri(),
+                    message
+                );
+                Outcome::from(request, ApiError::Internal(message.to_string()))
+            }
+        }
+    }
+}
+
+/// Wraps the handlers of `routes` so that a panic while handling a request
+/// is returned as an error to the client, while the daemon keeps serving.
+pub fn catch_panics(routes: Vec<Route>) -> Vec<Route> {
+    routes
+        .into_iter()
+        .map(|mut route| {
+            route.handler = Box::new(CatchPanic(route.handler));
+            route
+        })
+        .collect()
+}
@@ -0,0 +1,106 @@
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use rocket::data::{self, FromDataSimple};
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{ContentType, Header, Status};
+use rocket::{Data, Outcome, Request, Response};
+use serde::de::DeserializeOwned;
+use std::io::{self, Cursor, Read, Write};
+
+/// Payloads smaller than this (in bytes) are not worth compressing.
+pub const COMPRESSION_THRESHOLD: usize = 16 * 1024;
+
+/// The limit of the (decompressed) size of a request body, matching that of
+/// the JSON data guard.
+const BODY_LIMIT: u64 = 1 << 20;
+
+/// A JSON request body, which may be gzip compressed.
+pub struct CompressedJson<T>(pub T);
+
+impl<T> CompressedJson<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: DeserializeOwned> FromDataSimple for CompressedJson<T> {
+    type Error = io::Error;
+
+    fn from_data(
+        request: &Request,
+        data: Data,
+    ) -> data::Outcome<Self, Self::Error> {
+        let limit = request.limits().get("json").unwrap_or(BODY_LIMIT);
+        let gzipped = request
+            .headers()
+            .get_one("Content-Encoding")
+            .map_or(false, |encoding| encoding.eq_ignore_ascii_case("gzip"));
+        let body = data.open().take(limit);
+        let mut reader: Box<dyn Read> = if gzipped {
+            Box::new(GzDecoder::new(body).take(limit))
+        } else {
+            Box::new(body)
+        };
+
+        let mut json = Vec::new();
+        if let Err(e) = reader.read_to_end(&mut json) {
+            return Outcome::Failure((Status::BadRequest, e));
+        }
+        match serde_json::from_slice(&json) {
+            Ok(value) => Outcome::Success(CompressedJson(value)),
+            Err(e) if e.is_data() => {
+                Outcome::Failure((Status::UnprocessableEntity, e.into()))
+            }
+            Err(e) => Outcome::Failure((Status::BadRequest, e.into())),
+        }
+    }
+}
+
+/// Compresses large JSON responses for clients which accept gzip.
+pub struct GzipResponses;
+
+impl Fairing for GzipResponses {
+    fn info(&self) -> Info {
+        Info {
+            name: "Gzip compression",
+            kind: Kind::Response,
+        }
+    }
+
+    fn on_response(&self, request: &Request, response: &mut Response) {
+        let accepts_gzip = request
+            .headers()
+            .get("Accept-Encoding")
+            .flat_map(|value| value.split(','))
+            .any(|encoding| encoding.trim().starts_with("gzip"));
+        if !accepts_gzip
+            || response.content_type() != Some(ContentType::JSON)
+            || response.headers().contains("Content-Encoding")
+        {
+            return;
+        }
+
+        let body = match response.body_bytes() {
+            Some(body) => body,
+            None => return,
+        };
+        if body.len() < COMPRESSION_THRESHOLD {
+            response.set_sized_body(Cursor::new(body));
+            return;
+        }
+        match gzip(&body) {
+            Ok(compressed) => {
+                response.set_sized_body(Cursor::new(compressed));
+                response.set_header(Header::new("Content-Encoding", "gzip"));
+            }
+            Err(_) => response.set_sized_body(Cursor::new(body)),
+        }
+    }
+}
+
+fn gzip(payload: &[u8]) -> io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(payload)?;
+    encoder.finish()
+}
@@ -1,27 +1,53 @@
 use crate::daemon::api::handlers::*;
+use crate::daemon::deploy_lock::LockHolder;
+use crate::daemon::events::Event;
 use crate::daemon::executor::RunStatus;
 use crate::daemon::logs::log_file_module;
-use crate::daemon::module::{ModuleDefinition, ModuleKind, TermSignal};
+use crate::daemon::module::{
+    LogSink, ModuleDefinition, ModuleKind, OncePer, RetryPolicy, RunCondition,
+    TaskOptions, TermSignal, Tooling,
+};
 use crate::daemon::monitor::{
     ExecMonitor, LogLineMonitor, Monitor, MonitorTask, NetMonitor,
 };
-use crate::daemon::planner::{Plan, PlannedAction};
+use crate::daemon::planner::{DefinitionHashes, Plan, PlannedAction};
+use crate::daemon::task_runs::{TaskExecution, TaskRun, TaskRunStatus};
 use crate::path;
 use anyhow::Result;
 use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+fn from_sidecars(src: Vec<ApiModuleDefinition>) -> Vec<Arc<ModuleDefinition>> {
+    src.into_iter()
+        .map(|s| {
+            // Unlike tasks, sidecars are stopped along with their service.
+            let termination_signal = s.termination_signal.clone().into();
+            let mut definition = from_task(s);
+            definition.termination_signal = termination_signal;
+            Arc::new(definition)
+        })
+        .collect()
+}
 
 pub fn from_task(src: ApiModuleDefinition) -> ModuleDefinition {
-    ModuleDefinition::new(
+    let mut module_definition = ModuleDefinition::new(
         ModuleKind::Task,
         src.name,
         src.command,
         src.environment,
         src.log_file_path,
+        src.log_sink.into(),
         src.dependencies,
         src.working_dir.and_then(path::from_user_str),
+        src.tooling.into(),
         TermSignal::KILL,
         None,
-    )
+        src.project,
+    );
+    module_definition.masked_env = src.masked_env.into_iter().collect();
+    module_definition.use_login_env = src.use_login_env;
+    module_definition
 }
 
 pub fn from_service_with_monitor(
@@ -36,10 +62,13 @@ pub fn from_service_with_monitor(
         src.command,
         src.environment,
         src.log_file_path,
+        src.log_sink.into(),
         src.dependencies,
         src.working_dir.and_then(path::from_user_str),
+        src.tooling.into(),
         src.termination_signal.into(),
         None, // assigned below
+        src.project,
     );
 
     let log_file_path = log_file_module(&module_definition)?;
@@ -51,22 +80,36 @@ pub fn from_service_with_monitor(
 
     // Only store liveness as readiness only affects the service temporarily
     module_definition.liveness_probe = liveness_monitor;
+    module_definition.sidecars = from_sidecars(src.sidecars);
+    module_definition.interactive = src.interactive;
+    module_definition.tmpdir = src.tmpdir;
+    module_definition.masked_env = src.masked_env.into_iter().collect();
+    module_definition.use_login_env = src.use_login_env;
 
     Ok((module_definition, readiness_monitor))
 }
 
 pub fn from_task_or_service(src: ApiModuleDefinition) -> ModuleDefinition {
-    ModuleDefinition::new(
+    let mut module_definition = ModuleDefinition::new(
         src.kind.into(),
         src.name,
         src.command,
         src.environment,
         src.log_file_path,
+        src.log_sink.into(),
         src.dependencies,
         src.working_dir.and_then(path::from_user_str),
+        src.tooling.into(),
         src.termination_signal.into(),
         None, // assumed not needed in any code using this
-    )
+        src.project,
+    );
+    module_definition.sidecars = from_sidecars(src.sidecars);
+    module_definition.interactive = src.interactive;
+    module_definition.tmpdir = src.tmpdir;
+    module_definition.masked_env = src.masked_env.into_iter().collect();
+    module_definition.use_login_env = src.use_login_env;
+    module_definition
 }
 
 impl From<RunStatus> for ApiModuleRunStatus {
@@ -80,6 +123,104 @@ impl From<RunStatus> for ApiModuleRunStatus {
     }
 }
 
+/// Returns the options to run the task of the given command with.
+pub fn task_options(src: &ApiTaskDeploymentCommand) -> TaskOptions {
+    TaskOptions {
+        retry_policy: RetryPolicy {
+            retries: src.retries,
+            delay: Duration::from_secs(src.retry_delay),
+        },
+        run_if: src.run_if.clone().map(Into::into),
+        timeout: src.timeout.map(Duration::from_secs),
+        once_per: src.once_per.into(),
+    }
+}
+
+impl From<ApiOncePer> for OncePer {
+    fn from(once_per: ApiOncePer) -> OncePer {
+        match once_per {
+            ApiOncePer::Always => OncePer::Always,
+            ApiOncePer::Deploy => OncePer::Deploy,
+            ApiOncePer::Day => OncePer::Day,
+        }
+    }
+}
+
+impl From<ApiRunCondition> for RunCondition {
+    fn from(src: ApiRunCondition) -> RunCondition {
+        RunCondition {
+            command: src.command,
+            files_changed: src.files_changed,
+        }
+    }
+}
+
+impl From<TaskRun> for ApiTaskRunStatusResponse {
+    fn from(run: TaskRun) -> ApiTaskRunStatusResponse {
+        let (status, error, skip_reason) = match run.status {
+            TaskRunStatus::Running => (ApiTaskRunStatus::Running, None, None),
+            TaskRunStatus::Succeeded => {
+                (ApiTaskRunStatus::Succeeded, None, None)
+            }
+            TaskRunStatus::Skipped(reason) => {
+                (ApiTaskRunStatus::Skipped, None, Some(reason))
+            }
+            TaskRunStatus::Failed(e) => {
+                (ApiTaskRunStatus::Failed, Some(e), None)
+            }
+        };
+        ApiTaskRunStatusResponse {
+            task_name: run.task_name,
+            status,
+            error,
+            skip_reason,
+            output: run.output,
+        }
+    }
+}
+
+impl From<TaskExecution> for ApiTaskExecution {
+    fn from(src: TaskExecution) -> ApiTaskExecution {
+        ApiTaskExecution {
+            task_name: src.task_name,
+            project: src.project,
+            started_at: src.started_at,
+            finished_at: src.finished_at,
+            exit_code: src.exit_code,
+            log_file: src.log_file.display().to_string(),
+            attempt: src.attempt,
+            deployment_id: src.deployment_id,
+        }
+    }
+}
+
+impl From<Event> for ApiEvent {
+    fn from(src: Event) -> ApiEvent {
+        ApiEvent {
+            time: src.time,
+            kind: src.kind,
+            module_name: src.module_name,
+            command: src.command,
+            user: src.user,
+            host: src.host,
+            address: src.address,
+        }
+    }
+}
+
+impl From<LockHolder> for ApiLockHolder {
+    fn from(src: LockHolder) -> ApiLockHolder {
+        ApiLockHolder {
+            user: src.user,
+            host: src.host,
+            pid: src.pid,
+            operation: src.operation,
+            acquired_at: src.acquired_at,
+            expires_at: src.expires_at,
+        }
+    }
+}
+
 impl From<ApiModuleKind> for ModuleKind {
     fn from(src: ApiModuleKind) -> Self {
         match src {
@@ -103,6 +244,36 @@ impl From<Plan> for ApiGetPlanResponse {
     fn from(mut src: Plan) -> Self {
         ApiGetPlanResponse {
             plan: src.plan.drain().map(|(k, v)| (k, v.into())).collect(),
+            hashes: src.hashes.drain().map(|(k, v)| (k, v.into())).collect(),
+        }
+    }
+}
+
+impl From<DefinitionHashes> for ApiDefinitionHashes {
+    fn from(src: DefinitionHashes) -> Self {
+        ApiDefinitionHashes {
+            requested: src.requested,
+            deployed: src.deployed,
+        }
+    }
+}
+
+impl From<ApiTooling> for Tooling {
+    fn from(tooling: ApiTooling) -> Tooling {
+        Tooling {
+            direnv: tooling.direnv,
+            mise: tooling.mise,
+            nix_shell: tooling.nix_shell,
+        }
+    }
+}
+
+impl From<ApiLogSink> for LogSink {
+    fn from(sink: ApiLogSink) -> LogSink {
+        match sink {
+            ApiLogSink::File => LogSink::File,
+            ApiLogSink::Syslog => LogSink::Syslog,
+            ApiLogSink::Both => LogSink::Both,
         }
     }
 }
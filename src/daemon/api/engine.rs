@@ -1,48 +1,93 @@
+use crate::config::{DEFAULT_DAEMON_ADDRESS, DEFAULT_DAEMON_PORT};
+use crate::daemon::api::auth::TokenAuth;
+use crate::daemon::api::catch_panic::catch_panics;
+use crate::daemon::api::compression::GzipResponses;
 use crate::daemon::api::handlers;
+use crate::daemon::api::idempotency::IdempotencyCache;
 use crate::daemon::Core;
-use log::info;
+use log::{info, warn};
 use rocket::config::{Environment, LoggingLevel};
 use rocket::Config;
+use std::net::IpAddr;
 use std::sync::Arc;
 
 pub struct CoreState {
     pub core: Arc<Core>,
+    pub idempotency: IdempotencyCache,
 }
 
 pub fn start(core: &Arc<Core>) {
     let config = core.config();
-    let port: u16 = config
+    let port: u16 = config.daemon.port.unwrap_or(DEFAULT_DAEMON_PORT);
+    let address = config
         .daemon
-        .port
-        .as_ref()
-        .and_then(|p| p.parse::<u16>().ok())
-        .unwrap_or(13754);
+        .address
+        .as_deref()
+        .unwrap_or(DEFAULT_DAEMON_ADDRESS);
+    let is_loopback = address
+        .parse::<IpAddr>()
+        .map_or(address == "localhost", |ip| ip.is_loopback());
+    if !is_loopback && config.daemon.auth_token.is_none() {
+        warn!(
+            "Listening on {} without an auth token, anyone who can reach it \
+            can run commands through the daemon",
+            address
+        );
+    }
 
     let cfg = Config::build(Environment::Production)
-        .address("127.0.0.1")
+        .address(address)
         .port(port)
         .log_level(LoggingLevel::Normal)
         .workers(4)
         .unwrap();
 
+    let mut server = rocket::custom(cfg).attach(GzipResponses);
+    if let Some(token) = &config.daemon.auth_token {
+        info!("Requiring an auth token for API requests");
+        server = server.attach(TokenAuth::new(token));
+    }
+
     info!("Starting API listener");
-    rocket::custom(cfg)
+    server
         .manage(CoreState {
             core: Arc::clone(core),
+            idempotency: IdempotencyCache::default(),
         })
         .mount(
             "/",
-            routes![
+            catch_panics(routes![
                 handlers::index,
+                handlers::unauthorized,
                 handlers::health,
                 handlers::deploy,
                 handlers::deploy_task,
+                handlers::run_task,
+                handlers::task_run,
+                handlers::task_history,
                 handlers::status,
                 handlers::stop_all,
+                handlers::stop_many,
                 handlers::module_operation,
+                handlers::rollback,
+                handlers::write_stdin,
+                handlers::forward,
+                handlers::module_env,
+                handlers::refresh_env,
+                handlers::env_status,
+                handlers::daemon_health,
+                handlers::upgrade_daemon,
+                handlers::acquire_lock,
+                handlers::renew_lock,
+                handlers::release_lock,
+                handlers::lock_status,
+                handlers::record_event,
+                handlers::events,
                 handlers::log_file,
+                handlers::search_logs,
+                handlers::read_logs,
                 handlers::get_plan
-            ],
+            ]),
         )
         .launch();
 }
@@ -7,6 +7,9 @@ use serde::{Deserialize, Serialize};
 #[derive(Debug)]
 pub enum ApiError {
     DeploymentError(Error),
+    Unauthorized,
+    /// Handling the request panicked, with the message of the panic.
+    Internal(String),
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -18,12 +21,10 @@ pub struct ErrorResponse {
 
 pub type ApiResult<T> = Result<Json<T>, ApiError>;
 
-impl<'r> Responder<'r> for ApiError {
-    fn respond_to(
-        self,
-        req: &rocket::Request<'_>,
-    ) -> Result<Response<'r>, Status> {
-        let message = match self {
+impl ApiError {
+    /// The message of the error, as presented to clients.
+    pub fn message(&self) -> String {
+        match self {
             ApiError::DeploymentError(error) => {
                 if error.chain().len() > 1 {
                     format!("{}: {}", error.to_string(), error.root_cause())
@@ -31,15 +32,34 @@ impl<'r> Responder<'r> for ApiError {
                     error.to_string()
                 }
             }
+            ApiError::Unauthorized => {
+                String::from("Missing or invalid auth token")
+            }
+            ApiError::Internal(message) => {
+                format!("Internal error in the daemon: {}", message)
+            }
+        }
+    }
+}
+
+impl<'r> Responder<'r> for ApiError {
+    fn respond_to(
+        self,
+        req: &rocket::Request<'_>,
+    ) -> Result<Response<'r>, Status> {
+        let status = match self {
+            ApiError::DeploymentError(_) => Status::BadRequest,
+            ApiError::Unauthorized => Status::Unauthorized,
+            ApiError::Internal(_) => Status::InternalServerError,
         };
         Json(ErrorResponse {
             status: String::from("error"),
-            message,
+            message: self.message(),
             code: 100,
         })
         .respond_to(req)
         .map(|mut res| {
-            res.set_status(Status::BadRequest);
+            res.set_status(status);
             res
         })
     }
@@ -1,13 +1,43 @@
+use crate::daemon::api::compression::CompressedJson;
 use crate::daemon::api::convert::*;
 use crate::daemon::api::engine::CoreState;
 use crate::daemon::api::error::*;
-use crate::daemon::monitor::MonitorType;
-use crate::daemon::planner::MonitorStatus;
+use crate::daemon::api::idempotency::IdempotencyKey;
+use crate::daemon::events::Event;
+use crate::daemon::executor::RunStatus;
+use crate::daemon::forward::Forward;
+use crate::daemon::handover::{check_upgrade, upgrade_in_background};
+use crate::daemon::logs::{log_directory_available_space, LogQuery};
+use crate::daemon::monitor::{monitor_module_name, MonitorType};
+use crate::daemon::planner::{MonitorStatus, PsStatus};
+use crate::daemon::ports::listening_ports;
+use crate::daemon::resources::ResourceUsageHolder;
+use crate::daemon::sidecars::start_sidecars_when_ready;
+use crate::daemon::task_runs::{
+    run_task_in_background, task_output, TaskOutcome,
+};
+use crate::daemon::time::epoch_now;
+use anyhow::anyhow;
+use log::warn;
 use rocket::State;
 use rocket_contrib::json::Json;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::ffi::OsString;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How long to wait after deploying a service to check if it exited.
+const EXIT_GRACE_PERIOD: Duration = Duration::from_millis(300);
+
+/// The number of log lines returned when a service fails to deploy.
+const FAILURE_LOG_LINES: usize = 30;
+
+/// How long the daemon health check waits for each internal component
+/// (eg. the monitor runtime) to respond.
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(1);
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum ApiModuleKind {
@@ -22,11 +52,36 @@ pub struct ApiModuleDefinition {
     pub command: Vec<String>,
     pub environment: HashMap<String, String>,
     pub log_file_path: Option<String>,
+    #[serde(default)]
+    pub log_sink: ApiLogSink,
+    #[serde(default)]
+    pub tooling: ApiTooling,
     pub dependencies: Vec<String>,
     pub working_dir: Option<String>,
     pub termination_signal: ApiTermSignal,
     pub readiness_probe: Option<ApiProbe>,
     pub liveness_probe: Option<ApiProbe>,
+    #[serde(default)]
+    pub project: Option<String>,
+    /// Tasks run alongside a service while it runs.
+    #[serde(default)]
+    pub sidecars: Vec<ApiModuleDefinition>,
+    /// Whether the service reads its input from a terminal which can be
+    /// written to (see [ApiStdinCommand]).
+    #[serde(default)]
+    pub interactive: bool,
+    /// Whether the service gets a temp directory of its own, exported as
+    /// `CARTEL_TMPDIR`.
+    #[serde(default)]
+    pub tmpdir: bool,
+    /// The names of the environment variables whose values are secrets,
+    /// which are redacted when the definition is logged.
+    #[serde(default)]
+    pub masked_env: Vec<String>,
+    /// Whether to spawn the module with the environment of a login shell,
+    /// overriding `daemon.use_env_grabber`.
+    #[serde(default)]
+    pub use_login_env: Option<bool>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -64,20 +119,164 @@ pub enum ApiTermSignal {
     INT,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum ApiLogSink {
+    File,
+    Syslog,
+    Both,
+}
+
+impl Default for ApiLogSink {
+    fn default() -> Self {
+        Self::File
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ApiTooling {
+    pub direnv: bool,
+    pub mise: bool,
+    pub nix_shell: Option<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ApiDeploymentCommand {
     pub module_definition: ApiModuleDefinition,
     pub force: bool,
+    /// Identifies the deploy (eg. a run of `cartel deploy`) the service is
+    /// deployed as part of.
+    #[serde(default)]
+    pub deployment_id: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ApiTaskDeploymentCommand {
     pub task_definition: ApiModuleDefinition,
+    /// How many times the task is retried if it fails.
+    #[serde(default)]
+    pub retries: u32,
+    /// The seconds to wait between attempts.
+    #[serde(default)]
+    pub retry_delay: u64,
+    /// Identifies the deploy the task is run as part of.
+    #[serde(default)]
+    pub deployment_id: Option<String>,
+    /// Guards against running the task if it has nothing to do.
+    #[serde(default)]
+    pub run_if: Option<ApiRunCondition>,
+    /// The seconds each attempt may run for before the task is killed.
+    #[serde(default)]
+    pub timeout: Option<u64>,
+    /// How often the task runs, when deployed repeatedly.
+    #[serde(default)]
+    pub once_per: ApiOncePer,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub enum ApiOncePer {
+    Always,
+    Deploy,
+    Day,
+}
+
+impl Default for ApiOncePer {
+    fn default() -> Self {
+        Self::Always
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ApiRunCondition {
+    /// A command, the task is skipped if it exits with zero.
+    pub command: Option<Vec<String>>,
+    /// Patterns of files, the task is skipped if none of them changed since
+    /// it last succeeded.
+    #[serde(default)]
+    pub files_changed: Vec<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ApiTaskDeploymentResponse {
     pub success: bool,
+    /// The last lines of output of the task.
+    #[serde(default)]
+    pub output: Vec<String>,
+    /// Why the task was skipped (if it was).
+    #[serde(default)]
+    pub skip_reason: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiTaskRunResponse {
+    /// Identifies the run, to poll for its status.
+    pub id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub enum ApiTaskRunStatus {
+    Running,
+    Succeeded,
+    Skipped,
+    Failed,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiTaskRunStatusResponse {
+    pub task_name: String,
+    pub status: ApiTaskRunStatus,
+    /// Why the task failed (if it did).
+    pub error: Option<String>,
+    /// Why the task was skipped (if it was).
+    #[serde(default)]
+    pub skip_reason: Option<String>,
+    /// The last lines of output of the task, once finished.
+    #[serde(default)]
+    pub output: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiTaskExecution {
+    pub task_name: String,
+    pub project: Option<String>,
+    pub started_at: u64,
+    pub finished_at: u64,
+    pub exit_code: Option<i32>,
+    pub log_file: String,
+    pub attempt: u32,
+    pub deployment_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiTaskHistoryResponse {
+    /// The executions of tasks, newest first.
+    pub executions: Vec<ApiTaskExecution>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiEventCommand {
+    /// What was done, eg. `shell` or `exec`.
+    pub kind: String,
+    pub module_name: String,
+    pub command: Vec<String>,
+    pub user: String,
+    pub host: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiEvent {
+    pub time: u64,
+    pub kind: String,
+    pub module_name: String,
+    pub command: Vec<String>,
+    pub user: String,
+    pub host: String,
+    pub address: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiEventsResponse {
+    /// The recorded events, newest first.
+    pub events: Vec<ApiEvent>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -86,6 +285,170 @@ pub struct ApiOperationCommand {
     pub operation: ApiModuleOperation,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiStopManyCommand {
+    pub modules: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiModuleEnvResponse {
+    /// The environment the module was spawned with.
+    pub environment: HashMap<String, String>,
+    /// The environment variables given by the definition the module was
+    /// deployed with (rather than inherited).
+    #[serde(default)]
+    pub defined: HashMap<String, String>,
+    /// The names of the environment variables whose values are secrets.
+    #[serde(default)]
+    pub masked_env: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiEnvRefreshResponse {
+    /// The names of the variables added to the login environment.
+    pub added: Vec<String>,
+    /// The names of the variables removed from the login environment.
+    pub removed: Vec<String>,
+    /// The names of the variables whose values changed.
+    pub changed: Vec<String>,
+    /// When the environment was refreshed, as a UNIX timestamp.
+    pub refreshed_at: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiEnvStatusResponse {
+    /// Whether the env-grabber thread periodically refreshes the login
+    /// environment (the `daemon.use_env_grabber` option).
+    pub grabber_enabled: bool,
+    /// When the login environment was last refreshed, as a UNIX timestamp,
+    /// if it has been.
+    pub refreshed_at: Option<u64>,
+    /// The number of variables in the login environment.
+    pub variables: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiDaemonHealthResponse {
+    /// When the daemon was started, as a UNIX timestamp.
+    pub started_at: u64,
+    /// The number of seconds the daemon has been running for.
+    pub uptime: u64,
+    /// The number of modules tracked by the daemon, or None if it was too
+    /// busy to count them.
+    pub tracked_modules: Option<usize>,
+    /// The number of readiness and liveness probes being polled.
+    pub monitors: usize,
+    /// The number of commands waiting to be handled by the monitor runtime.
+    pub monitor_queue_depth: usize,
+    /// Whether the monitor runtime responded in time.
+    pub monitor_runtime_alive: bool,
+    /// Whether the env-grabber is enabled, see [ApiEnvStatusResponse].
+    pub env_grabber_enabled: bool,
+    /// When the login environment was last refreshed, as a UNIX timestamp,
+    /// if it has been.
+    pub env_refreshed_at: Option<u64>,
+    /// The directory log files are written to.
+    pub log_dir: String,
+    /// The number of bytes available in the log directory, if known.
+    pub log_dir_available: Option<u64>,
+    /// Identifies this run of the daemon, which changes once it is restarted
+    /// or upgraded.
+    #[serde(default)]
+    pub instance_id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiUpgradeCommand {
+    /// The path of the daemon executable to replace the daemon with.
+    pub executable: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiUpgradeResponse {
+    /// The version of the new daemon.
+    pub version: String,
+    /// The number of running services kept running by the new daemon.
+    pub running_services: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiLockCommand {
+    pub user: String,
+    pub host: String,
+    pub pid: u32,
+    /// What the client is about to do (eg. `deploy backend`).
+    pub operation: String,
+    /// How many seconds the lock is held for unless renewed.
+    pub ttl: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiLockRenewCommand {
+    /// How many seconds the lock is held for unless renewed again.
+    pub ttl: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ApiLockHolder {
+    pub user: String,
+    pub host: String,
+    pub pid: u32,
+    pub operation: String,
+    pub acquired_at: u64,
+    pub expires_at: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiLockResponse {
+    /// The id to renew and release the lock with, if it was acquired.
+    pub id: Option<String>,
+    /// Who holds the lock, which is the client itself if it was acquired.
+    pub holder: ApiLockHolder,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiLockStatusResponse {
+    pub holder: Option<ApiLockHolder>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiStdinCommand {
+    /// The input written to the service, as is.
+    pub input: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiForwardCommand {
+    /// The port listened to on the daemon's host.
+    pub local_port: u16,
+    pub remote_host: String,
+    pub remote_port: u16,
+    /// An ssh destination to forward through, instead of using `socat`.
+    pub via: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiForwardResponse {
+    pub success: bool,
+    /// The name of the process forwarding the port.
+    pub name: String,
+    pub exit_code: Option<i32>,
+    /// The last lines of output of the forwarding process, if it exited.
+    pub log_tail: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiRollbackCommand {
+    /// The modules to roll back, or none to roll back the services changed
+    /// by the last deploy.
+    pub modules: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiRollbackResponse {
+    pub rolled_back: Vec<String>,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ApiOperationResponse {
     pub success: bool,
@@ -96,6 +459,12 @@ pub struct ApiDeploymentResponse {
     pub success: bool,
     pub deployed: bool,
     pub monitor: Option<String>,
+    /// The exit code of a service which exited right after being deployed.
+    #[serde(default)]
+    pub exit_code: Option<i32>,
+    /// The last lines of the log of a service which failed to deploy.
+    #[serde(default)]
+    pub log_tail: Vec<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -123,14 +492,56 @@ pub struct ApiModuleStatus {
     pub pid: u32,
     pub status: ApiModuleRunStatus,
     pub liveness_status: Option<ApiProbeStatus>,
+    /// Why the liveness probe last failed (eg. the connection was refused).
+    #[serde(default)]
+    pub liveness_reason: Option<String>,
+    /// The status of the readiness probe of the last deploy of a service,
+    /// while it is known (up to 10 minutes after it completes).
+    #[serde(default)]
+    pub readiness_status: Option<ApiProbeStatus>,
     pub exit_code: Option<i32>,
     pub time_since_status: u64,
+    /// The resident set size (in bytes) of the process group of a running
+    /// module.
+    #[serde(default)]
+    pub rss_bytes: Option<u64>,
+    /// The CPU used by the process group of a running module, as a
+    /// percentage of one core.
+    #[serde(default)]
+    pub cpu_percent: Option<f32>,
+    /// The signal which terminated the last run of the module.
+    #[serde(default)]
+    pub exit_signal: Option<i32>,
+    /// The number of times the module was started again since it was first
+    /// deployed.
+    #[serde(default)]
+    pub restarts: u32,
+    /// The TCP ports the process group of a running module listens on, if
+    /// asked for.
+    #[serde(default)]
+    pub ports: Option<Vec<u16>>,
+    /// When the last run of the module was started (in seconds since the
+    /// epoch).
+    #[serde(default)]
+    pub started_at: Option<u64>,
+    /// When the last run of the module exited or was stopped (in seconds
+    /// since the epoch).
+    #[serde(default)]
+    pub exited_at: Option<u64>,
+    /// The statuses of the tasks run alongside a service.
+    #[serde(default)]
+    pub sidecars: Vec<ApiModuleStatus>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ApiLogFileRequest {
     pub module_name: String,
     pub module_kind: ApiModuleKind,
+    #[serde(default)]
+    pub project: Option<String>,
+    /// A previous run of a task (`1` being the last run).
+    #[serde(default)]
+    pub run: Option<usize>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -138,6 +549,49 @@ pub struct ApiLogResponse {
     pub log_file_path: OsString,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiLogSearchRequest {
+    pub module_kind: ApiModuleKind,
+    #[serde(default)]
+    pub project: Option<String>,
+    pub log_file_path: Option<String>,
+    pub pattern: String,
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+    pub max_results: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiLogReadRequest {
+    pub module_kind: ApiModuleKind,
+    #[serde(default)]
+    pub project: Option<String>,
+    pub log_file_path: Option<String>,
+    pub offset: u64,
+    pub modified_after: Option<u64>,
+    /// Only return the offset of the end of the file, to follow it from there.
+    #[serde(default)]
+    pub from_end: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiLogReadResponse {
+    pub data: String,
+    pub offset: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiLogLine {
+    pub line_number: u64,
+    pub line: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiLogSearchResponse {
+    pub matches: Vec<ApiLogLine>,
+    pub truncated: bool,
+}
+
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy)]
 #[serde(rename_all = "snake_case")]
 pub enum ApiProbeStatus {
@@ -151,6 +605,12 @@ pub enum ApiProbeStatus {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ApiHealthResponse {
     pub probe_status: Option<ApiProbeStatus>,
+    /// Why the last check of the probe failed, if it did.
+    #[serde(default)]
+    pub reason: Option<String>,
+    /// The last lines of the log of the service, if its probe gave up.
+    #[serde(default)]
+    pub log_tail: Vec<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -169,13 +629,35 @@ pub struct ApiGetPlanRequest {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ApiGetPlanResponse {
     pub plan: HashMap<String, ApiPlannedAction>,
+    /// The content hashes of the definition of each service.
+    #[serde(default)]
+    pub hashes: HashMap<String, ApiDefinitionHashes>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiDefinitionHashes {
+    /// The hash of the definition in the request.
+    pub requested: String,
+    /// The hash of the definition the service is deployed with, if it was
+    /// deployed.
+    pub deployed: Option<String>,
 }
 
-#[allow(clippy::unnecessary_unwrap)]
 #[post("/api/v1/deploy", data = "<command>")]
 pub(crate) fn deploy(
-    command: Json<ApiDeploymentCommand>,
+    command: CompressedJson<ApiDeploymentCommand>,
+    key: Option<IdempotencyKey>,
     core_state: State<CoreState>,
+) -> ApiResult<ApiDeploymentResponse> {
+    core_state
+        .idempotency
+        .process(key, || deploy_service(command, &core_state))
+}
+
+#[allow(clippy::unnecessary_unwrap)]
+fn deploy_service(
+    command: CompressedJson<ApiDeploymentCommand>,
+    core_state: &CoreState,
 ) -> ApiResult<ApiDeploymentResponse> {
     let planner = core_state.core.planner();
     let command = command.into_inner();
@@ -184,7 +666,27 @@ pub(crate) fn deploy(
         from_service_with_monitor(command.module_definition)?;
     let module_name = module_def.name.clone();
 
-    let deployed = planner.deploy(module_def, command.force)?;
+    let deployed = planner.deploy(
+        module_def,
+        command.force,
+        command.deployment_id.as_deref(),
+    )?;
+
+    // Catch services which exit right away (eg. due to a bad command or
+    // configuration), so their output can be shown to the user.
+    if deployed {
+        std::thread::sleep(EXIT_GRACE_PERIOD);
+        planner.collect_dead();
+        if let Some(code) = planner.exit_code(&module_name) {
+            return Ok(Json(ApiDeploymentResponse {
+                success: false,
+                deployed,
+                monitor: None,
+                exit_code: Some(code),
+                log_tail: planner.log_tail(&module_name, FAILURE_LOG_LINES),
+            }));
+        }
+    }
 
     let monitor_key = if deployed && monitor.is_some() {
         let monitor_key = planner.create_monitor(
@@ -197,23 +699,135 @@ pub(crate) fn deploy(
         None
     };
 
+    if deployed {
+        start_sidecars_when_ready(&core_state.core, module_name);
+    } else if let Err(e) = planner.start_sidecars(&module_name) {
+        // The service was deployed before, so its readiness is not checked.
+        warn!("Failed to start sidecars of {}: {:#}", module_name, e);
+    }
+
     Ok(Json(ApiDeploymentResponse {
         success: true,
         deployed,
         monitor: monitor_key,
+        exit_code: None,
+        log_tail: vec![],
     }))
 }
 
 #[post("/api/v1/tasks/deploy", data = "<task>")]
 pub(crate) fn deploy_task(
-    task: Json<ApiTaskDeploymentCommand>,
+    task: CompressedJson<ApiTaskDeploymentCommand>,
+    key: Option<IdempotencyKey>,
     core_state: State<CoreState>,
 ) -> ApiResult<ApiTaskDeploymentResponse> {
-    let cmd = task.into_inner();
-    let planner = core_state.core.planner();
-    let task = cmd.task_definition;
-    planner.deploy_task(&from_task(task))?;
-    Ok(Json(ApiTaskDeploymentResponse { success: true }))
+    core_state.idempotency.process(key, || {
+        let cmd = task.into_inner();
+        let planner = core_state.core.planner();
+        let options = task_options(&cmd);
+        let outcome = planner.deploy_task(
+            &from_task(cmd.task_definition),
+            &options,
+            cmd.deployment_id.as_deref(),
+        )?;
+        let (output, skip_reason) = match outcome {
+            TaskOutcome::Ran(execution) => {
+                (task_output(&execution.log_file), None)
+            }
+            TaskOutcome::Skipped(reason) => (vec![], Some(reason)),
+        };
+        Ok(Json(ApiTaskDeploymentResponse {
+            success: true,
+            output,
+            skip_reason,
+        }))
+    })
+}
+
+/// Starts running a task in the background, returning the id of the run
+/// rather than waiting for the task to finish.
+#[post("/api/v1/tasks/run", data = "<task>")]
+pub(crate) fn run_task(
+    task: CompressedJson<ApiTaskDeploymentCommand>,
+    key: Option<IdempotencyKey>,
+    core_state: State<CoreState>,
+) -> ApiResult<ApiTaskRunResponse> {
+    core_state.idempotency.process(key, || {
+        let cmd = task.into_inner();
+        let options = task_options(&cmd);
+        let id = run_task_in_background(
+            &core_state.core,
+            from_task(cmd.task_definition),
+            options,
+            cmd.deployment_id,
+        );
+        Ok(Json(ApiTaskRunResponse { id }))
+    })
+}
+
+#[get("/api/v1/tasks/history?<task>&<project>")]
+pub(crate) fn task_history(
+    task: Option<String>,
+    project: Option<String>,
+    core_state: State<CoreState>,
+) -> ApiResult<ApiTaskHistoryResponse> {
+    let executions = core_state
+        .core
+        .planner()
+        .task_history()
+        .executions(task.as_deref(), project.as_deref())
+        .into_iter()
+        .map(Into::into)
+        .collect();
+    Ok(Json(ApiTaskHistoryResponse { executions }))
+}
+
+#[post("/api/v1/events", data = "<command>")]
+pub(crate) fn record_event(
+    command: Json<ApiEventCommand>,
+    address: Option<SocketAddr>,
+    core_state: State<CoreState>,
+) -> ApiResult<ApiOperationResponse> {
+    let command = command.into_inner();
+    core_state.core.events.record(Event {
+        time: epoch_now(),
+        kind: command.kind,
+        module_name: command.module_name,
+        command: command.command,
+        user: command.user,
+        host: command.host,
+        address: address.map(|address| address.ip().to_string()),
+    });
+
+    Ok(Json(ApiOperationResponse { success: true }))
+}
+
+#[get("/api/v1/events?<module>")]
+pub(crate) fn events(
+    module: Option<String>,
+    core_state: State<CoreState>,
+) -> ApiResult<ApiEventsResponse> {
+    let events = core_state
+        .core
+        .events
+        .events(module.as_deref())
+        .into_iter()
+        .map(Into::into)
+        .collect();
+    Ok(Json(ApiEventsResponse { events }))
+}
+
+#[get("/api/v1/tasks/<id>", rank = 2)]
+pub(crate) fn task_run(
+    id: String,
+    core_state: State<CoreState>,
+) -> ApiResult<ApiTaskRunStatusResponse> {
+    let run = core_state
+        .core
+        .task_runs
+        .get(&id)
+        .ok_or_else(|| anyhow!("No task run with id {}", id))?;
+    Ok(Json(run.into()))
 }
 
 #[post("/api/v1/operation", data = "<module>")]
@@ -230,11 +844,234 @@ pub(crate) fn module_operation(
         }
         ApiModuleOperation::RESTART => {
             planner.restart_module(&module.module_name)?;
+            start_sidecars_when_ready(&core_state.core, module.module_name);
         }
     };
     Ok(Json(ApiOperationResponse { success: true }))
 }
 
+#[post("/api/v1/rollback", data = "<command>")]
+pub(crate) fn rollback(
+    command: Json<ApiRollbackCommand>,
+    core_state: State<CoreState>,
+) -> ApiResult<ApiRollbackResponse> {
+    let command = command.into_inner();
+    let planner = core_state.core.planner();
+    let rolled_back = planner.rollback(&command.modules)?;
+    for module_name in &rolled_back {
+        start_sidecars_when_ready(&core_state.core, module_name.clone());
+    }
+
+    Ok(Json(ApiRollbackResponse { rolled_back }))
+}
+
+#[get("/api/v1/modules/<module_name>/env")]
+pub(crate) fn module_env(
+    module_name: String,
+    core_state: State<CoreState>,
+) -> ApiResult<ApiModuleEnvResponse> {
+    let planner = core_state.core.planner();
+    let (environment, definition) = planner.module_environment(&module_name)?;
+
+    Ok(Json(ApiModuleEnvResponse {
+        environment,
+        defined: definition.environment.clone(),
+        masked_env: definition.masked_env.iter().cloned().collect(),
+    }))
+}
+
+#[post("/api/v1/env/refresh")]
+pub(crate) fn refresh_env(
+    core_state: State<CoreState>,
+) -> ApiResult<ApiEnvRefreshResponse> {
+    let env_holder = &core_state.core.env_holder;
+    let changes = env_holder.refresh()?;
+
+    Ok(Json(ApiEnvRefreshResponse {
+        added: changes.added,
+        removed: changes.removed,
+        changed: changes.changed,
+        refreshed_at: env_holder.refreshed_at(),
+    }))
+}
+
+#[get("/api/v1/env")]
+pub(crate) fn env_status(
+    core_state: State<CoreState>,
+) -> Json<ApiEnvStatusResponse> {
+    let env_holder = &core_state.core.env_holder;
+
+    Json(ApiEnvStatusResponse {
+        grabber_enabled: core_state
+            .core
+            .config()
+            .daemon
+            .use_env_grabber
+            .unwrap_or(false),
+        refreshed_at: env_holder.refreshed_at(),
+        variables: env_holder.variable_count(),
+    })
+}
+
+#[get("/api/v1/daemon/health")]
+pub(crate) fn daemon_health(
+    core_state: State<CoreState>,
+) -> Json<ApiDaemonHealthResponse> {
+    let core = &core_state.core;
+    let planner = core.planner();
+    let monitor_handle = planner.monitor_handle();
+    let log_dir_available = match log_directory_available_space() {
+        Ok(available) => Some(available),
+        Err(e) => {
+            warn!("{:#}", e);
+            None
+        }
+    };
+
+    Json(ApiDaemonHealthResponse {
+        started_at: core.started_at,
+        uptime: epoch_now().saturating_sub(core.started_at),
+        tracked_modules: planner.tracked_module_count(HEALTH_CHECK_TIMEOUT),
+        monitors: monitor_handle.active_monitors(),
+        monitor_queue_depth: monitor_handle.queued_commands(),
+        monitor_runtime_alive: monitor_handle
+            .is_runtime_alive(HEALTH_CHECK_TIMEOUT),
+        env_grabber_enabled: core
+            .config()
+            .daemon
+            .use_env_grabber
+            .unwrap_or(false),
+        env_refreshed_at: core.env_holder.refreshed_at(),
+        log_dir: core.config().daemon.log_dir().display().to_string(),
+        log_dir_available,
+        instance_id: core.instance_id.clone(),
+    })
+}
+
+#[post("/api/v1/daemon/upgrade", data = "<command>")]
+pub(crate) fn upgrade_daemon(
+    command: Json<ApiUpgradeCommand>,
+    core_state: State<CoreState>,
+) -> ApiResult<ApiUpgradeResponse> {
+    let core = &core_state.core;
+    let executable = PathBuf::from(&command.executable);
+    let version = check_upgrade(core, &executable)?;
+    let running_services = core
+        .planner()
+        .module_status()
+        .iter()
+        .filter(|m| m.status == RunStatus::RUNNING)
+        .count();
+    upgrade_in_background(Arc::clone(core), executable);
+
+    Ok(Json(ApiUpgradeResponse {
+        version,
+        running_services,
+    }))
+}
+
+#[post("/api/v1/lock", data = "<command>")]
+pub(crate) fn acquire_lock(
+    command: Json<ApiLockCommand>,
+    core_state: State<CoreState>,
+) -> ApiResult<ApiLockResponse> {
+    let command = command.into_inner();
+    let result = core_state.core.deploy_lock.acquire(
+        command.user,
+        command.host,
+        command.pid,
+        command.operation,
+        Duration::from_secs(command.ttl),
+    );
+    Ok(Json(match result {
+        Ok(holder) => ApiLockResponse {
+            id: Some(holder.id.clone()),
+            holder: holder.into(),
+        },
+        Err(holder) => ApiLockResponse {
+            id: None,
+            holder: holder.into(),
+        },
+    }))
+}
+
+#[post("/api/v1/lock/<id>/renew", data = "<command>")]
+pub(crate) fn renew_lock(
+    id: String,
+    command: Json<ApiLockRenewCommand>,
+    core_state: State<CoreState>,
+) -> ApiResult<ApiLockHolder> {
+    let ttl = Duration::from_secs(command.ttl);
+    match core_state.core.deploy_lock.renew(&id, ttl) {
+        Some(holder) => Ok(Json(holder.into())),
+        None => Err(anyhow!("The deployment lock is no longer held").into()),
+    }
+}
+
+#[delete("/api/v1/lock/<id>")]
+pub(crate) fn release_lock(
+    id: String,
+    core_state: State<CoreState>,
+) -> ApiResult<ApiOperationResponse> {
+    core_state.core.deploy_lock.release(&id);
+
+    Ok(Json(ApiOperationResponse { success: true }))
+}
+
+#[get("/api/v1/lock")]
+pub(crate) fn lock_status(
+    core_state: State<CoreState>,
+) -> Json<ApiLockStatusResponse> {
+    Json(ApiLockStatusResponse {
+        holder: core_state.core.deploy_lock.holder().map(Into::into),
+    })
+}
+
+#[post("/api/v1/modules/<module_name>/stdin", data = "<command>")]
+pub(crate) fn write_stdin(
+    module_name: String,
+    command: Json<ApiStdinCommand>,
+    core_state: State<CoreState>,
+) -> ApiResult<ApiOperationResponse> {
+    let planner = core_state.core.planner();
+    planner.write_stdin(&module_name, command.input.as_bytes())?;
+
+    Ok(Json(ApiOperationResponse { success: true }))
+}
+
+#[post("/api/v1/modules/<module_name>/forwards", data = "<command>")]
+pub(crate) fn forward(
+    module_name: String,
+    command: Json<ApiForwardCommand>,
+    core_state: State<CoreState>,
+) -> ApiResult<ApiForwardResponse> {
+    let command = command.into_inner();
+    let planner = core_state.core.planner();
+    let forward = Forward {
+        local_port: command.local_port,
+        remote_host: command.remote_host,
+        remote_port: command.remote_port,
+        via: command.via,
+    };
+    let name = planner.start_forward(&module_name, &forward)?;
+
+    // Catch forwards which fail right away (eg. the port is in use).
+    std::thread::sleep(EXIT_GRACE_PERIOD);
+    planner.collect_dead();
+    let (exit_code, log_tail) =
+        match planner.sidecar_exit(&module_name, &name, FAILURE_LOG_LINES) {
+            Some((code, log_tail)) => (Some(code), log_tail),
+            None => (None, vec![]),
+        };
+
+    Ok(Json(ApiForwardResponse {
+        success: exit_code.is_none(),
+        name,
+        exit_code,
+        log_tail,
+    }))
+}
+
 #[post("/api/v1/stop_all")]
 pub(crate) fn stop_all(
     core_state: State<CoreState>,
@@ -245,51 +1082,184 @@ pub(crate) fn stop_all(
     Ok(Json(ApiOperationResponse { success: true }))
 }
 
+#[post("/api/v1/stop_many", data = "<command>")]
+pub(crate) fn stop_many(
+    command: Json<ApiStopManyCommand>,
+    core_state: State<CoreState>,
+) -> ApiResult<ApiOperationResponse> {
+    let command = command.into_inner();
+    let planner = core_state.core.planner();
+    planner.stop_many(&command.modules)?;
+
+    Ok(Json(ApiOperationResponse { success: true }))
+}
+
 #[allow(clippy::unnecessary_wraps)]
-#[get("/api/v1/status")]
+#[get("/api/v1/status?<ports>")]
 pub(crate) fn status(
+    ports: Option<bool>,
     core_state: State<CoreState>,
 ) -> ApiResult<ApiModuleStatusResponse> {
     let planner = core_state.core.planner();
-    let status = planner
-        .module_status()
+    let resources = &core_state.core.resources;
+    let modules = planner.module_status();
+
+    // Listening ports are only looked up if asked for, as this is costly.
+    let mut listening = if ports.unwrap_or(false) {
+        let pgids = modules
+            .iter()
+            .flat_map(|m| std::iter::once(m).chain(m.sidecars.iter()))
+            .filter(|m| m.status == RunStatus::RUNNING)
+            .map(|m| m.pid)
+            .collect();
+        Some(listening_ports(&pgids)?)
+    } else {
+        None
+    };
+
+    let status = modules
         .into_iter()
-        .map(|m| ApiModuleStatus {
-            name: m.name,
-            pid: m.pid,
-            time_since_status: m.time_since_status,
-            exit_code: m.exit_code,
-            liveness_status: m.liveness_status.as_ref().map(|s| s.into()),
-            status: ApiModuleRunStatus::from(m.status),
-        })
+        .map(|m| api_module_status(m, resources, &mut listening))
         .collect();
 
     Ok(Json(ApiModuleStatusResponse { status }))
 }
 
+fn api_module_status(
+    m: PsStatus,
+    resources: &ResourceUsageHolder,
+    listening: &mut Option<HashMap<u32, BTreeSet<u16>>>,
+) -> ApiModuleStatus {
+    let usage = match m.status {
+        RunStatus::RUNNING => resources.get(m.pid),
+        _ => None,
+    };
+    let ports = listening.as_mut().map(|listening| match m.status {
+        RunStatus::RUNNING => listening
+            .remove(&m.pid)
+            .map(|ports| ports.into_iter().collect())
+            .unwrap_or_default(),
+        _ => vec![],
+    });
+    ApiModuleStatus {
+        name: m.name,
+        pid: m.pid,
+        time_since_status: m.time_since_status,
+        started_at: m.started_at,
+        exited_at: m.exited_at,
+        exit_code: m.exit_code,
+        exit_signal: m.exit_signal,
+        restarts: m.restarts,
+        liveness_status: m.liveness_status.as_ref().map(|s| s.into()),
+        liveness_reason: m.liveness_reason,
+        readiness_status: m.readiness_status.as_ref().map(|s| s.into()),
+        status: ApiModuleRunStatus::from(m.status),
+        ports,
+        rss_bytes: usage.map(|u| u.rss_bytes),
+        cpu_percent: usage.map(|u| u.cpu_percent),
+        sidecars: m
+            .sidecars
+            .into_iter()
+            .map(|s| api_module_status(s, resources, listening))
+            .collect(),
+    }
+}
+
 #[post("/api/v1/log_file", data = "<request>")]
 pub(crate) fn log_file(
     request: Json<ApiLogFileRequest>,
     core_state: State<CoreState>,
 ) -> ApiResult<ApiLogResponse> {
     let request = request.into_inner();
-    let log_file_path = core_state
-        .core
-        .planner()
-        .log_path(&request.module_name, &request.module_kind.into())?;
+    let planner = core_state.core.planner();
+    let log_file_path = match (request.run, &request.module_kind) {
+        (Some(run), ApiModuleKind::Task) => planner.task_run_log_path(
+            &request.module_name,
+            request.project.as_deref(),
+            run,
+        )?,
+        _ => planner.log_path(
+            &request.module_name,
+            &request.module_kind.into(),
+            request.project.as_deref(),
+        )?,
+    };
 
     Ok(Json(ApiLogResponse { log_file_path }))
 }
 
+#[post("/api/v1/logs/<module_name>/search", data = "<request>")]
+pub(crate) fn search_logs(
+    module_name: String,
+    request: Json<ApiLogSearchRequest>,
+    core_state: State<CoreState>,
+) -> ApiResult<ApiLogSearchResponse> {
+    let request = request.into_inner();
+    let query = LogQuery {
+        pattern: request.pattern,
+        since: request.since,
+        until: request.until,
+        max_results: request.max_results.unwrap_or(1000),
+    };
+    let result = core_state.core.planner().search_logs(
+        &module_name,
+        &request.module_kind.into(),
+        request.project.as_deref(),
+        request.log_file_path.as_deref(),
+        &query,
+    )?;
+
+    let matches = result
+        .matches
+        .into_iter()
+        .map(|m| ApiLogLine {
+            line_number: m.line_number,
+            line: m.line,
+        })
+        .collect();
+
+    Ok(Json(ApiLogSearchResponse {
+        matches,
+        truncated: result.truncated,
+    }))
+}
+
+#[post("/api/v1/logs/<module_name>/read", data = "<request>")]
+pub(crate) fn read_logs(
+    module_name: String,
+    request: Json<ApiLogReadRequest>,
+    core_state: State<CoreState>,
+) -> ApiResult<ApiLogReadResponse> {
+    let request = request.into_inner();
+    let chunk = core_state.core.planner().read_logs(
+        &module_name,
+        &request.module_kind.into(),
+        request.project.as_deref(),
+        request.log_file_path.as_deref(),
+        request.offset,
+        request.modified_after,
+        request.from_end,
+    )?;
+
+    Ok(Json(ApiLogReadResponse {
+        data: chunk.data,
+        offset: chunk.offset,
+    }))
+}
+
 #[get("/api/v1/health/<monitor_key>")]
 pub(crate) fn health(
     monitor_key: String,
     core_state: State<CoreState>,
 ) -> Json<ApiHealthResponse> {
-    let status = core_state
+    let (status, reason) = match core_state
         .core
         .planner()
-        .monitor_status(monitor_key.as_str());
+        .monitor_result(monitor_key.as_str())
+    {
+        Some(result) => (Some(result.status), result.reason),
+        None => (None, None),
+    };
 
     let probe_status = match status {
         Some(MonitorStatus::Pending) => Some(ApiProbeStatus::Pending),
@@ -302,12 +1272,26 @@ pub(crate) fn health(
         None => None,
     };
 
-    Json(ApiHealthResponse { probe_status })
+    let log_tail = match (&probe_status, monitor_module_name(&monitor_key)) {
+        (Some(ApiProbeStatus::RetriesExceeded), Some(module_name)) => {
+            core_state
+                .core
+                .planner()
+                .log_tail(module_name, FAILURE_LOG_LINES)
+        }
+        _ => vec![],
+    };
+
+    Json(ApiHealthResponse {
+        probe_status,
+        reason,
+        log_tail,
+    })
 }
 
 #[post("/api/v1/get_plan", data = "<request>")]
 pub(crate) fn get_plan(
-    request: Json<ApiGetPlanRequest>,
+    request: CompressedJson<ApiGetPlanRequest>,
     core_state: State<CoreState>,
 ) -> Json<ApiGetPlanResponse> {
     let planner = core_state.core.planner();
@@ -327,3 +1311,8 @@ pub(crate) fn get_plan(
 pub(crate) fn index() -> &'static str {
     "Daemon service"
 }
+
+#[get("/api/v1/unauthorized")]
+pub(crate) fn unauthorized() -> Result<(), ApiError> {
+    Err(ApiError::Unauthorized)
+}
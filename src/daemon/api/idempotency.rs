@@ -0,0 +1,246 @@
+use crate::daemon::api::error::{ApiError, ApiResult};
+use anyhow::anyhow;
+use parking_lot::{Condvar, Mutex};
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket_contrib::json::Json;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// The header clients send the idempotency key of a request in.
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+/// How long the response to a request is kept after it was processed.
+const KEY_TTL: Duration = Duration::from_secs(600);
+
+/// A key identifying a request, which stays the same when it is retried.
+pub struct IdempotencyKey(String);
+
+impl<'a, 'r> FromRequest<'a, 'r> for IdempotencyKey {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> Outcome<Self, Self::Error> {
+        match request.headers().get_one(IDEMPOTENCY_KEY_HEADER) {
+            Some(key) => Outcome::Success(IdempotencyKey(key.to_string())),
+            None => Outcome::Forward(()),
+        }
+    }
+}
+
+enum Entry {
+    /// The request is still being processed.
+    Pending,
+    /// The request was processed, with the serialized response or error.
+    Done {
+        at: Instant,
+        result: Result<Value, String>,
+    },
+}
+
+/// A short-lived cache of the responses to requests with an idempotency key.
+///
+/// A request repeating the key of one which was already processed gets the
+/// same response, without being processed again. If the first request is
+/// still being processed the repeated one waits for it to finish.
+#[derive(Default)]
+pub struct IdempotencyCache {
+    entries: Mutex<HashMap<String, Entry>>,
+    processed: Condvar,
+}
+
+impl IdempotencyCache {
+    /// Processes a request using `handler`, unless a request with the same
+    /// key was processed already.
+    pub fn process<T, F>(
+        &self,
+        key: Option<IdempotencyKey>,
+        handler: F,
+    ) -> ApiResult<T>
+    where
+        T: Serialize + DeserializeOwned,
+        F: FnOnce() -> ApiResult<T>,
+    {
+        let key = match key {
+            Some(IdempotencyKey(key)) => key,
+            None => return handler(),
+        };
+
+        {
+            let mut entries = self.entries.lock();
+            loop {
+                match entries.get(&key) {
+                    Some(Entry::Pending) => self.processed.wait(&mut entries),
+                    Some(Entry::Done { result, .. }) => return replay(result),
+                    None => break,
+                }
+            }
+            entries.retain(|_, entry| match entry {
+                Entry::Done { at, .. } => at.elapsed() < KEY_TTL,
+                Entry::Pending => true,
+            });
+            entries.insert(key.clone(), Entry::Pending);
+        }
+
+        let mut pending = PendingKey {
+            cache: self,
+            key,
+            result: None,
+        };
+        let response = handler();
+        pending.result = match &response {
+            Ok(json) => serde_json::to_value(&json.0).ok().map(Ok),
+            Err(e) => Some(Err(e.message())),
+        };
+        response
+    }
+}
+
+/// Completes the entry of a request once it was processed, or removes it
+/// if processing failed without a response (eg. the handler panicked).
+struct PendingKey<'a> {
+    cache: &'a IdempotencyCache,
+    key: String,
+    result: Option<Result<Value, String>>,
+}
+
+impl Drop for PendingKey<'_> {
+    fn drop(&mut self) {
+        let mut entries = self.cache.entries.lock();
+        match self.result.take() {
+            Some(result) => {
+                let entry = Entry::Done {
+                    at: Instant::now(),
+                    result,
+                };
+                entries.insert(std::mem::take(&mut self.key), entry);
+            }
+            None => {
+                entries.remove(&self.key);
+            }
+        }
+        self.cache.processed.notify_all();
+    }
+}
+
+fn replay<T: DeserializeOwned>(result: &Result<Value, String>) -> ApiResult<T> {
+    match result {
+        Ok(value) => serde_json::from_value(value.clone())
+            .map(Json)
+            .map_err(|e| ApiError::from(anyhow::Error::from(e))),
+        Err(message) => Err(ApiError::from(anyhow!(message.clone()))),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::panic::{self, AssertUnwindSafe};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    fn key(key: &str) -> Option<IdempotencyKey> {
+        Some(IdempotencyKey(key.to_string()))
+    }
+
+    #[test]
+    fn test_replays_processed_request() {
+        let cache = IdempotencyCache::default();
+        let calls = AtomicUsize::new(0);
+        let handler = || {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Ok(Json(42))
+        };
+
+        assert_eq!(cache.process(key("a"), handler).unwrap().0, 42);
+        assert_eq!(cache.process(key("a"), handler).unwrap().0, 42);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_replays_error() {
+        let cache = IdempotencyCache::default();
+        let failing = || -> ApiResult<u32> { Err(anyhow!("Failed").into()) };
+        let succeeding = || -> ApiResult<u32> { Ok(Json(1)) };
+
+        assert!(cache.process(key("a"), failing).is_err());
+        let replayed = cache.process(key("a"), succeeding).unwrap_err();
+        assert_eq!(replayed.message(), "Failed");
+    }
+
+    #[test]
+    fn test_processes_requests_without_key() {
+        let cache = IdempotencyCache::default();
+        let calls = AtomicUsize::new(0);
+        let handler = || {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Ok(Json(()))
+        };
+
+        cache.process(None, handler).unwrap();
+        cache.process(None, handler).unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert!(cache.entries.lock().is_empty());
+    }
+
+    #[test]
+    fn test_waits_for_pending_request() {
+        let cache = Arc::new(IdempotencyCache::default());
+        cache.entries.lock().insert("a".to_string(), Entry::Pending);
+
+        let waiting = {
+            let cache = Arc::clone(&cache);
+            thread::spawn(move || {
+                cache.process(key("a"), || -> ApiResult<u32> { Ok(Json(2)) })
+            })
+        };
+        thread::sleep(Duration::from_millis(100));
+        assert!(!waiting.is_finished());
+
+        {
+            let mut entries = cache.entries.lock();
+            entries.insert(
+                "a".to_string(),
+                Entry::Done {
+                    at: Instant::now(),
+                    result: Ok(Value::from(1)),
+                },
+            );
+            cache.processed.notify_all();
+        }
+        assert_eq!(waiting.join().unwrap().unwrap().0, 1);
+    }
+
+    #[test]
+    fn test_prunes_expired_entries() {
+        let cache = IdempotencyCache::default();
+        let expired = Instant::now() - KEY_TTL - Duration::from_secs(1);
+        cache.entries.lock().insert(
+            "old".to_string(),
+            Entry::Done {
+                at: expired,
+                result: Ok(Value::from(1)),
+            },
+        );
+
+        cache.process(key("new"), || Ok(Json(2))).unwrap();
+
+        let entries = cache.entries.lock();
+        assert!(!entries.contains_key("old"));
+        assert!(entries.contains_key("new"));
+    }
+
+    #[test]
+    fn test_removes_key_when_handler_panics() {
+        let cache = IdempotencyCache::default();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            cache.process(key("a"), || -> ApiResult<u32> { panic!("Handler") })
+        }));
+        assert!(result.is_err());
+        assert!(cache.entries.lock().is_empty());
+
+        assert_eq!(cache.process(key("a"), || Ok(Json(3))).unwrap().0, 3);
+    }
+}
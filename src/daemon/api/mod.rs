@@ -1,7 +1,13 @@
+mod auth;
+mod catch_panic;
+mod compression;
 mod convert;
 pub mod engine;
 pub mod error;
 mod handlers;
+mod idempotency;
 
+pub use compression::COMPRESSION_THRESHOLD;
 pub use error::ErrorResponse;
 pub use handlers::*;
+pub use idempotency::IDEMPOTENCY_KEY_HEADER;
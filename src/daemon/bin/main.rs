@@ -1,16 +1,14 @@
 extern crate cartel;
 use std::error::Error;
 
+use cartel::config::PERSISTED_CONFIG;
 use cartel::daemon::cli::cli_app;
 use cartel::daemon::core::start_daemon;
+use cartel::daemon::daemon_log;
+use cartel::daemon::supervisor::{supervise, take_supervised_mark};
 use cartel::detach::detach_tty;
 
-pub fn init() {
-    env_logger::init()
-}
-
 fn main() -> Result<(), Box<dyn Error>> {
-    init();
     let config = cli_app()?;
     #[cfg(unix)]
     {
@@ -19,6 +17,14 @@ fn main() -> Result<(), Box<dyn Error>> {
             detach_tty(args, false);
         }
     }
-    start_daemon()?;
+    daemon_log::init();
+    // A daemon run by the supervisor does not supervise itself again.
+    if !take_supervised_mark()
+        && (config.supervised
+            || PERSISTED_CONFIG.daemon.supervised.unwrap_or(false))
+    {
+        supervise();
+    }
+    start_daemon(&config)?;
     Ok(())
 }
@@ -1,8 +1,17 @@
 use anyhow::Result;
 use clap::{crate_version, App, Arg};
+use std::path::PathBuf;
 
 pub struct DaemonCliConfig {
     pub detach_tty: bool,
+    /// The port to listen on, overriding the `daemon.port` option.
+    pub port: Option<u16>,
+    /// The state handed over by the daemon this one replaced, if it was
+    /// upgraded (see [handover](crate::daemon::handover)).
+    pub handover: Option<PathBuf>,
+    /// Whether to run the daemon under a supervisor restarting it if it
+    /// crashes, overriding the `daemon.supervised` option.
+    pub supervised: bool,
 }
 
 pub fn cli_app() -> Result<DaemonCliConfig> {
@@ -16,9 +25,44 @@ pub fn cli_app() -> Result<DaemonCliConfig> {
                 .help("Detatches the daemon from its controlling terminal")
                 .takes_value(false),
         )
+        .arg(
+            Arg::with_name("port")
+                .short("p")
+                .long("port")
+                .value_name("PORT")
+                .help(
+                    "The port to listen on (overrides the daemon.port option)",
+                )
+                .takes_value(true)
+                .validator(|v| {
+                    v.parse::<u16>()
+                        .map(|_| ())
+                        .map_err(|_| String::from("Expected a valid port"))
+                }),
+        )
+        .arg(
+            Arg::with_name("supervised")
+                .long("supervised")
+                .help(
+                    "Restarts the daemon if it crashes (overrides the \
+                    daemon.supervised option)",
+                )
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("handover")
+                .long("handover")
+                .value_name("FILE")
+                .help("Takes over the modules of the daemon this one replaced")
+                .takes_value(true)
+                .hidden(true),
+        )
         .get_matches();
 
     Ok(DaemonCliConfig {
         detach_tty: matches.is_present("detach_tty"),
+        port: matches.value_of("port").map(|p| p.parse().unwrap()),
+        handover: matches.value_of_os("handover").map(PathBuf::from),
+        supervised: matches.is_present("supervised"),
     })
 }
@@ -1,9 +1,18 @@
+use crate::daemon::deploy_lock::DeployLock;
+use crate::daemon::events::EventLog;
 use crate::daemon::monitor::{self, MonitorHandle};
 use crate::daemon::planner::Planner;
-use crate::daemon::{api, env_grabber, signal};
+use crate::daemon::resources::{resource_sampler_thread, ResourceUsageHolder};
+use crate::daemon::task_runs::TaskRuns;
+use crate::daemon::time::epoch_now;
+use crate::daemon::{api, env_grabber, handover, signal, tmpdir};
 
 use crate::config::{self, PersistedConfig};
+use crate::daemon::cli::DaemonCliConfig;
 use crate::daemon::env_grabber::{env_grabber_thread, CurrentEnvHolder};
+use crate::daemon::retention::{log_retention_thread, RetentionPolicy};
+use crate::discovery;
+use log::warn;
 use std::error::Error;
 use std::sync::Arc;
 
@@ -11,6 +20,18 @@ use std::sync::Arc;
 pub struct Core {
     pub planner: Planner,
     pub config: Arc<PersistedConfig>,
+    pub resources: ResourceUsageHolder,
+    pub task_runs: TaskRuns,
+    pub events: EventLog,
+    /// The environment of the login shell of the user (see [env_grabber]).
+    pub env_holder: Arc<CurrentEnvHolder>,
+    /// When the daemon was started, as a UNIX timestamp.
+    pub started_at: u64,
+    /// Held by clients while they deploy or tear down modules.
+    pub deploy_lock: DeployLock,
+    /// Identifies this run of the daemon, which changes once it is restarted
+    /// or upgraded.
+    pub instance_id: String,
 }
 
 impl Core {
@@ -28,8 +49,19 @@ impl Core {
         cfg: Arc<PersistedConfig>,
     ) -> Core {
         Core {
-            planner: Planner::new(monitor_handle, env_holder, Arc::clone(&cfg)),
+            planner: Planner::new(
+                monitor_handle,
+                Arc::clone(&env_holder),
+                Arc::clone(&cfg),
+            ),
             config: cfg,
+            resources: ResourceUsageHolder::default(),
+            task_runs: TaskRuns::default(),
+            events: EventLog::default(),
+            env_holder,
+            started_at: epoch_now(),
+            deploy_lock: DeployLock::default(),
+            instance_id: uuid::Uuid::new_v4().to_string(),
         }
     }
 
@@ -45,10 +77,14 @@ impl Core {
 }
 
 /// Start the daemon
-pub fn start_daemon() -> Result<(), Box<dyn Error>> {
+pub fn start_daemon(cli: &DaemonCliConfig) -> Result<(), Box<dyn Error>> {
     let monitor = monitor::MonitorState::new();
     config::create_config_if_not_exists()?;
-    let cfg = Arc::new(config::read_persisted_config()?);
+    let mut cfg = config::read_persisted_config()?;
+    if cli.port.is_some() {
+        cfg.daemon.port = cli.port;
+    }
+    let cfg = Arc::new(cfg);
 
     // Create the Tokio async runtime and pass a handle to it so that it can be
     // invoked from a sync context from within the API handlers.
@@ -72,6 +108,35 @@ pub fn start_daemon() -> Result<(), Box<dyn Error>> {
         env_grabber_thread(Arc::clone(&env_holder));
     }
 
+    // Periodically clean up old log files, if a retention policy is set.
+    if let Some(policy) = RetentionPolicy::from_config(&cfg.daemon)? {
+        log_retention_thread(Arc::clone(&core), policy);
+    }
+
+    match &cli.handover {
+        // The daemon was upgraded, take over the modules of the previous one.
+        Some(handover_file) => handover::adopt(&core, handover_file),
+        // Take over the modules left running if the previous daemon crashed.
+        None => {
+            if handover::recover(&core) {
+                handover::orphan_collector_thread(Arc::clone(&core));
+            } else {
+                // No services are running yet, so any temp dirs were left
+                // behind.
+                tmpdir::prune_tmpdirs();
+            }
+        }
+    }
+
+    // Sample the memory and CPU used by running modules, shown by `ps`.
+    resource_sampler_thread(Arc::clone(&core));
+
+    // Let clients find the daemon, even if started on a different port.
+    let port = cfg.daemon.port.unwrap_or(config::DEFAULT_DAEMON_PORT);
+    if let Err(e) = discovery::write_endpoint(port) {
+        warn!("{:?}", e);
+    }
+
     // Start the API.
     api::engine::start(&core);
 
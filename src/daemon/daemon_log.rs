@@ -0,0 +1,138 @@
+use crate::config::PERSISTED_CONFIG;
+use crate::daemon::logs::{log_directory, rotated_log_path, rotated_segments};
+use chrono::{Local, Utc};
+use env_logger::{Builder, Env};
+use log::{Log, Metadata, Record};
+use parking_lot::Mutex;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// The name of the file the daemon writes its own logs to, within the log
+/// directory.
+const DAEMON_LOG_FILE: &str = "daemon.log";
+
+/// The size (in bytes) above which the daemon log file is rotated.
+const MAX_LOG_FILE_SIZE: u64 = 10 * 1024 * 1024;
+
+/// The number of rotated segments of the daemon log file which are kept.
+const ROTATED_SEGMENTS_KEPT: usize = 3;
+
+/// Returns the path of the file the daemon writes its own logs to.
+pub fn daemon_log_path() -> PathBuf {
+    PERSISTED_CONFIG.daemon.log_dir().join(DAEMON_LOG_FILE)
+}
+
+/// Sets up the logger of the daemon.
+///
+/// Records are filtered through `RUST_LOG` (by default the `info` level for
+/// the records of cartel itself) and written both to stderr, as long as the
+/// daemon is attached to a terminal, and to the daemon log file (see
+/// [daemon_log_path]), which is rotated once it grows too large.
+pub fn init() {
+    let stderr =
+        Builder::from_env(Env::default().default_filter_or("cartel=info"))
+            .build();
+    let file = match DaemonLogFile::open() {
+        Ok(file) => Some(file),
+        Err(e) => {
+            eprintln!("Failed to open the daemon log file: {}", e);
+            None
+        }
+    };
+    let max_level = stderr.filter();
+    let logger = DaemonLogger {
+        stderr,
+        file: Mutex::new(file),
+    };
+    if log::set_boxed_logger(Box::new(logger)).is_ok() {
+        log::set_max_level(max_level);
+    }
+}
+
+struct DaemonLogger {
+    /// Filters the records, and prints them to stderr.
+    stderr: env_logger::Logger,
+    file: Mutex<Option<DaemonLogFile>>,
+}
+
+impl Log for DaemonLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.stderr.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.stderr.matches(record) {
+            return;
+        }
+        self.stderr.log(record);
+
+        let line = format!(
+            "[{} {:<5} {}] {}\n",
+            Utc::now().format("%Y-%m-%dT%H:%M:%SZ"),
+            record.level(),
+            record.target(),
+            record.args()
+        );
+        if let Some(file) = self.file.lock().as_mut() {
+            // Logging from within the logger would deadlock.
+            if let Err(e) = file.write(line.as_bytes()) {
+                eprintln!("Failed to write to the daemon log file: {}", e);
+            }
+        }
+    }
+
+    fn flush(&self) {
+        self.stderr.flush();
+    }
+}
+
+/// The daemon log file, along with how much was written to it.
+struct DaemonLogFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+}
+
+impl DaemonLogFile {
+    fn open() -> io::Result<DaemonLogFile> {
+        log_directory().map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        let path = daemon_log_path();
+        let file = open_append(&path)?;
+        let size = file.metadata()?.len();
+        Ok(DaemonLogFile { path, file, size })
+    }
+
+    fn write(&mut self, line: &[u8]) -> io::Result<()> {
+        if self.size + line.len() as u64 > MAX_LOG_FILE_SIZE {
+            // The log retention of the daemon may have truncated the file.
+            self.size = self.file.metadata()?.len();
+            if self.size + line.len() as u64 > MAX_LOG_FILE_SIZE {
+                self.rotate()?;
+            }
+        }
+        self.file.write_all(line)?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    /// Moves the log file to a new rotated segment, deleting the oldest
+    /// segments beyond those kept.
+    fn rotate(&mut self) -> io::Result<()> {
+        fs::rename(&self.path, rotated_log_path(&self.path, Local::now()))?;
+        self.file = open_append(&self.path)?;
+        self.size = 0;
+
+        let segments = rotated_segments(&self.path)
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        let excess = segments.len().saturating_sub(ROTATED_SEGMENTS_KEPT);
+        for segment in &segments[..excess] {
+            fs::remove_file(segment)?;
+        }
+        Ok(())
+    }
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
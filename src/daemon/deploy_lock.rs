@@ -0,0 +1,158 @@
+use crate::daemon::time::epoch_now;
+use parking_lot::Mutex;
+use std::time::Duration;
+
+/// Who holds the deployment lock, and until when.
+#[derive(Debug, Clone)]
+pub struct LockHolder {
+    /// Identifies the holder when renewing or releasing the lock.
+    pub id: String,
+    pub user: String,
+    pub host: String,
+    pub pid: u32,
+    /// What the holder is doing (eg. `deploy backend`).
+    pub operation: String,
+    pub acquired_at: u64,
+    /// When the lock expires unless renewed, as a UNIX timestamp.
+    pub expires_at: u64,
+}
+
+/// A lock held by a client while it deploys or tears down modules, so that
+/// clients sharing a daemon do not interleave their operations.
+///
+/// The lock expires unless its holder keeps renewing it, so that a client
+/// which crashed does not keep it forever.
+#[derive(Default)]
+pub struct DeployLock {
+    holder: Mutex<Option<LockHolder>>,
+}
+
+impl DeployLock {
+    /// Acquires the lock, unless someone else holds it.
+    ///
+    /// Returns the new holder if the lock was acquired, or the current one
+    /// if not.
+    pub fn acquire(
+        &self,
+        user: String,
+        host: String,
+        pid: u32,
+        operation: String,
+        ttl: Duration,
+    ) -> Result<LockHolder, LockHolder> {
+        let mut holder = self.holder.lock();
+        let now = epoch_now();
+        if let Some(current) = holder.as_ref().filter(|h| h.expires_at > now) {
+            return Err(current.clone());
+        }
+        let acquired = LockHolder {
+            id: uuid::Uuid::new_v4().to_string(),
+            user,
+            host,
+            pid,
+            operation,
+            acquired_at: now,
+            expires_at: now + ttl.as_secs(),
+        };
+        *holder = Some(acquired.clone());
+        Ok(acquired)
+    }
+
+    /// Extends the lock held by `id` for another `ttl`.
+    ///
+    /// Returns None if `id` no longer holds the lock (eg. as it expired and
+    /// was acquired by someone else).
+    pub fn renew(&self, id: &str, ttl: Duration) -> Option<LockHolder> {
+        let mut holder = self.holder.lock();
+        match holder.as_mut() {
+            Some(current) if current.id == id => {
+                current.expires_at = epoch_now() + ttl.as_secs();
+                Some(current.clone())
+            }
+            _ => None,
+        }
+    }
+
+    /// Releases the lock, if it is held by `id`.
+    pub fn release(&self, id: &str) {
+        let mut holder = self.holder.lock();
+        if holder.as_ref().map_or(false, |h| h.id == id) {
+            *holder = None;
+        }
+    }
+
+    /// Returns who holds the lock, if anyone.
+    pub fn holder(&self) -> Option<LockHolder> {
+        let now = epoch_now();
+        self.holder
+            .lock()
+            .as_ref()
+            .filter(|h| h.expires_at > now)
+            .cloned()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TTL: Duration = Duration::from_secs(30);
+
+    fn acquire(
+        lock: &DeployLock,
+        ttl: Duration,
+    ) -> Result<LockHolder, LockHolder> {
+        lock.acquire(
+            "me".to_string(),
+            "host".to_string(),
+            1,
+            "deploy".to_string(),
+            ttl,
+        )
+    }
+
+    #[test]
+    fn test_acquire_held_lock() {
+        let lock = DeployLock::default();
+        let holder = acquire(&lock, TTL).unwrap();
+        assert_eq!(holder.expires_at, holder.acquired_at + TTL.as_secs());
+
+        let current = acquire(&lock, TTL).unwrap_err();
+        assert_eq!(current.id, holder.id);
+        assert_eq!(lock.holder().unwrap().id, holder.id);
+    }
+
+    #[test]
+    fn test_acquire_expired_lock() {
+        let lock = DeployLock::default();
+        let expired = acquire(&lock, Duration::from_secs(0)).unwrap();
+        assert!(lock.holder().is_none());
+
+        let holder = acquire(&lock, TTL).unwrap();
+        assert_ne!(holder.id, expired.id);
+        assert!(lock.renew(&expired.id, TTL).is_none());
+    }
+
+    #[test]
+    fn test_renew() {
+        let lock = DeployLock::default();
+        let holder = acquire(&lock, Duration::from_secs(0)).unwrap();
+
+        let renewed = lock.renew(&holder.id, TTL).unwrap();
+        assert!(renewed.expires_at > holder.expires_at);
+        assert_eq!(lock.holder().unwrap().id, holder.id);
+        assert!(lock.renew("other", TTL).is_none());
+    }
+
+    #[test]
+    fn test_release() {
+        let lock = DeployLock::default();
+        let holder = acquire(&lock, TTL).unwrap();
+
+        lock.release("other");
+        assert!(lock.holder().is_some());
+        lock.release(&holder.id);
+        assert!(lock.holder().is_none());
+        assert!(acquire(&lock, TTL).is_ok());
+    }
+}
@@ -1,6 +1,7 @@
+use crate::daemon::time::epoch_now;
 use crate::shell::interactive_shell_cmd_line;
 use anyhow::Result;
-use log::{debug, info};
+use log::{debug, info, warn};
 use parking_lot::RwLock;
 use std::collections::HashMap;
 use std::io::{BufRead, BufReader};
@@ -10,6 +11,49 @@ use std::time::Duration;
 
 pub struct CurrentEnvHolder {
     environment: RwLock<HashMap<String, String>>,
+    /// When the environment was last grabbed, as a UNIX timestamp.
+    refreshed_at: RwLock<Option<u64>>,
+}
+
+/// The names of the environment variables which changed between two grabs.
+#[derive(Debug, Default)]
+pub struct EnvChanges {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<String>,
+}
+
+impl EnvChanges {
+    fn between(
+        old: &HashMap<String, String>,
+        new: &HashMap<String, String>,
+    ) -> EnvChanges {
+        let mut changes = EnvChanges::default();
+        for (key, value) in new {
+            match old.get(key) {
+                None => changes.added.push(key.clone()),
+                Some(old_value) if old_value != value => {
+                    changes.changed.push(key.clone())
+                }
+                Some(_) => {}
+            }
+        }
+        changes.removed = old
+            .keys()
+            .filter(|key| !new.contains_key(*key))
+            .cloned()
+            .collect();
+        changes.added.sort();
+        changes.removed.sort();
+        changes.changed.sort();
+        changes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.changed.is_empty()
+    }
 }
 
 impl Default for CurrentEnvHolder {
@@ -22,15 +66,56 @@ impl CurrentEnvHolder {
     pub fn new() -> CurrentEnvHolder {
         CurrentEnvHolder {
             environment: RwLock::new(HashMap::new()),
+            refreshed_at: RwLock::new(None),
         }
     }
     pub fn replace(&self, new_env: HashMap<String, String>) {
         *self.environment.write() = new_env;
+        *self.refreshed_at.write() = Some(epoch_now());
+    }
+
+    /// Grabs the environment now, returning what changed since it was last
+    /// grabbed.
+    pub fn refresh(&self) -> Result<EnvChanges> {
+        let new_env = grab_env()?;
+        let changes = EnvChanges::between(&self.environment.read(), &new_env);
+        self.replace(new_env);
+        Ok(changes)
+    }
+
+    /// Returns when the environment was last grabbed, if it has been.
+    pub fn refreshed_at(&self) -> Option<u64> {
+        *self.refreshed_at.read()
+    }
+
+    /// Returns the number of variables in the grabbed environment.
+    pub fn variable_count(&self) -> usize {
+        self.environment.read().len()
     }
 
     pub fn read(&self) -> HashMap<String, String> {
         self.environment.read().clone()
     }
+
+    /// Reads the environment, grabbing it first if it has not been yet (eg.
+    /// as the env-grabber thread is disabled, but a module opted in with
+    /// `use_login_env`).
+    pub fn read_or_grab(&self) -> HashMap<String, String> {
+        let environment = self.read();
+        if !environment.is_empty() {
+            return environment;
+        }
+        match grab_env() {
+            Ok(environment) => {
+                self.replace(environment.clone());
+                environment
+            }
+            Err(e) => {
+                warn!("Failed to grab the login environment: {:#}", e);
+                environment
+            }
+        }
+    }
 }
 
 pub fn grab_env() -> Result<HashMap<String, String>> {
@@ -75,8 +160,16 @@ pub fn env_grabber_thread(current_env_holder: Arc<CurrentEnvHolder>) {
     std::thread::spawn(move || loop {
         std::thread::sleep(Duration::from_millis(5000));
         debug!("env-grab started");
-        let new_env = grab_env().expect("failed");
-        current_env_holder.replace(new_env);
+        match current_env_holder.refresh() {
+            Ok(changes) if !changes.is_empty() => info!(
+                "Login environment changed: {} added, {} removed, {} changed",
+                changes.added.len(),
+                changes.removed.len(),
+                changes.changed.len()
+            ),
+            Ok(_) => {}
+            Err(e) => warn!("Failed to grab the login environment: {:#}", e),
+        }
         debug!("env-grab done");
     });
 }
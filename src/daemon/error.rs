@@ -1,6 +1,22 @@
 use std::ffi::OsString;
 use thiserror::Error;
 
+fn after_attempts(attempts: u32) -> String {
+    if attempts > 1 {
+        format!(" after {} attempts", attempts)
+    } else {
+        String::new()
+    }
+}
+
+fn timed_out_after(timeout: u64, attempts: u32) -> String {
+    if attempts > 1 {
+        format!("after {} attempts of {}s each", attempts, timeout)
+    } else {
+        format!("after {}s", timeout)
+    }
+}
+
 /// Daemon enumerates all possible errors returned by this library.
 #[derive(Error, Debug)]
 pub enum DaemonError {
@@ -14,6 +30,16 @@ pub enum DaemonError {
     #[error("Module with name '{0}' is not running or doesn't exist.")]
     NotRunning(String),
 
+    /// Represents the case when attempting to write to the input of a module
+    /// which does not read it from a terminal.
+    #[error("Module '{0}' is not interactive, set `interactive: true` to write to its input")]
+    NotInteractive(String),
+
+    /// Represents the case when attempting to roll back a module which was
+    /// never redeployed with a different definition.
+    #[error("Module '{0}' has no previous definition to roll back to")]
+    NoPreviousDefinition(String),
+
     /// Represents the case some of the module in the given subset of
     /// modules do not exist. For example, trying to deploy a set of modules
     /// where one doesn't exist.
@@ -25,12 +51,25 @@ pub enum DaemonError {
     #[error("Executable probe command timed out")]
     ExecProbeTimeout,
 
-    #[error("Task {task_name:?} failed with exit code {code:?}. Use \"cartel logs {task_name}\" \
-     or view {log_file:?} for more details.")]
+    #[error("Task {task_name:?} failed with exit code {code:?}{}. Use \"cartel logs {task_name}\" \
+     or view {log_file:?} for more details.", after_attempts(*.attempts))]
     TaskFailed {
         task_name: String,
         code: i32,
         log_file: OsString,
+        /// How many times the task was run, including retries.
+        attempts: u32,
+    },
+
+    #[error("Task {task_name:?} timed out {} and was killed. Use \"cartel logs {task_name}\" \
+     or view {log_file:?} for more details.", timed_out_after(*.timeout, *.attempts))]
+    TaskTimedOut {
+        task_name: String,
+        /// The timeout of each attempt, in seconds.
+        timeout: u64,
+        log_file: OsString,
+        /// How many times the task was run, including retries.
+        attempts: u32,
     },
 
     /// Represents a failure to read from input.
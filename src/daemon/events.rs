@@ -0,0 +1,91 @@
+use crate::daemon::time::epoch_now;
+use log::info;
+use nix::unistd::gethostname;
+use parking_lot::RwLock;
+use std::collections::VecDeque;
+
+/// How many events are kept in the log.
+const EVENTS_SIZE: usize = 1000;
+
+/// A manual intervention on a service, such as opening a shell for it, or an
+/// action of the daemon on it.
+#[derive(Debug, Clone)]
+pub struct Event {
+    /// When the event was recorded, in seconds since the epoch.
+    pub time: u64,
+    /// What was done, eg. `shell` or `exec`.
+    pub kind: String,
+    /// The service it was done to.
+    pub module_name: String,
+    /// The command which was run.
+    pub command: Vec<String>,
+    /// The user and host of the client, as reported by it.
+    pub user: String,
+    pub host: String,
+    /// The address the request came from.
+    pub address: Option<String>,
+}
+
+impl Event {
+    /// An event caused by the daemon itself rather than by a client, such as
+    /// the log retention deleting the log file of a service.
+    pub fn daemon(
+        kind: &str,
+        module_name: &str,
+        command: Vec<String>,
+    ) -> Event {
+        let mut buf = [0u8; 256];
+        let host = gethostname(&mut buf)
+            .map(|host| host.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Event {
+            time: epoch_now(),
+            kind: kind.to_string(),
+            module_name: module_name.to_string(),
+            command,
+            user: String::from("daemon"),
+            host,
+            address: None,
+        }
+    }
+}
+
+/// Holds the latest events, oldest first, so that daemons shared by many
+/// users have a trail of manual interventions.
+///
+/// Events are kept in memory, but are also written to the log of the daemon.
+#[derive(Default)]
+pub struct EventLog {
+    events: RwLock<VecDeque<Event>>,
+}
+
+impl EventLog {
+    pub fn record(&self, event: Event) {
+        info!(
+            "Event: {} by {}@{} ({}) on {}: {:?}",
+            event.kind,
+            event.user,
+            event.host,
+            event.address.as_deref().unwrap_or("unknown address"),
+            event.module_name,
+            event.command
+        );
+        let mut events = self.events.write();
+        if events.len() == EVENTS_SIZE {
+            events.pop_front();
+        }
+        events.push_back(event);
+    }
+
+    /// Returns the recorded events, newest first, optionally only those of
+    /// a module.
+    pub fn events(&self, module_name: Option<&str>) -> Vec<Event> {
+        self.events
+            .read()
+            .iter()
+            .rev()
+            .filter(|e| module_name.map_or(true, |name| e.module_name == name))
+            .cloned()
+            .collect()
+    }
+}
@@ -1,35 +1,56 @@
 use crate::daemon::env_grabber::CurrentEnvHolder;
 use crate::daemon::error::DaemonError;
+use crate::daemon::handover::{self, HandedOverModule};
 use crate::daemon::logs::log_file_module;
-use crate::daemon::module::{ModuleDefinition, TermSignal};
+use crate::daemon::module::{LogSink, ModuleDefinition, TermSignal};
 use crate::daemon::monitor::{monitor_key, MonitorType};
 use crate::daemon::planner::{Monitor, MonitorHandle};
+use crate::daemon::stdin::open_stdin_pty;
+use crate::daemon::syslog::LogOutput;
 use crate::daemon::time::epoch_now;
-use crate::process::{CommandExt, Process};
+use crate::daemon::tmpdir::{create_tmpdir, remove_tmpdir};
+use crate::process::{is_orphan_exit, CommandExt, GroupChild, Process};
 
 use crate::command_builder::CommandBuilder;
-use anyhow::{Context, Result};
-use log::info;
+use crate::constants::TMPDIR_ENV;
+use anyhow::{bail, Context, Result};
+use log::{info, warn};
+use nix::fcntl::{fcntl, FcntlArg, FdFlag};
+use nix::unistd::dup;
+use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
 use std::collections::HashMap;
 use std::ffi::OsString;
-use std::fs::File;
+use std::fs::{File, OpenOptions};
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::os::unix::process::ExitStatusExt;
 use std::path::Path;
 use std::process::ExitStatus;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How long a sidecar is given to exit after being sent its termination
+/// signal, before it is killed.
+const SIDECAR_STOP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often a stopping sidecar is checked for having exited.
+const SIDECAR_STOP_INTERVAL: Duration = Duration::from_millis(50);
 
 pub struct Executor {
     module_map: HashMap<String, ModuleStatus>,
     cfg: Arc<ExecutorConfig>,
     monitor_handle: MonitorHandle,
     env_holder: Arc<CurrentEnvHolder>,
+    /// Shared with each module being stopped (see [Stopping]), to tell
+    /// whether any are.
+    stopping: Arc<()>,
 }
 
 pub struct ExecutorConfig {
     pub use_env_grabber_env: bool,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum RunStatus {
     RUNNING,
     WAITING,
@@ -47,8 +68,50 @@ pub struct ModuleStatus {
     pub exit_status: Option<ExitStatus>,
     pub log_file_path: OsString,
     pub monitor_key: Option<String>,
+    /// The key of the readiness monitor of the last deploy of a service, if
+    /// it has a readiness probe.
+    pub readiness_key: Option<String>,
+    /// The readiness probe of the last deploy of a service, checked again
+    /// when it is restarted.
+    pub readiness_probe: Option<Monitor>,
+    /// The number of times the module was started again after it was first
+    /// started (eg. by being restarted or redeployed).
+    pub restarts: u32,
+    /// The definition the module was deployed with before being redeployed
+    /// with a different one, if it was.
+    pub previous_definition: Option<Arc<ModuleDefinition>>,
+    /// The tasks run alongside a service (see [ModuleDefinition::sidecars]).
+    pub sidecars: Vec<ModuleStatus>,
 
     child: Option<Process>,
+    /// The master end of the terminal an interactive service reads its input
+    /// from.
+    stdin: Option<File>,
+    /// The environment the module was spawned with, including that inherited
+    /// from the daemon.
+    environment: HashMap<String, String>,
+}
+
+/// A module signalled to stop, whose process may still be shutting down.
+pub struct Stopping {
+    module_definition: Arc<ModuleDefinition>,
+    process: Option<Process>,
+    _token: Arc<()>,
+}
+
+impl Stopping {
+    /// Waits for the process of the module to exit, and removes its temp
+    /// directory.
+    pub fn wait(self) -> Result<()> {
+        if let Some(mut process) = self.process {
+            match process.wait() {
+                Err(e) if !is_orphan_exit(&e) => return Err(e.into()),
+                _ => {}
+            }
+        }
+        remove_tmpdir(&self.module_definition);
+        Ok(())
+    }
 }
 
 impl ModuleStatus {
@@ -65,7 +128,14 @@ impl ModuleStatus {
             exit_time: 0,
             exit_status: None,
             monitor_key: None,
+            readiness_key: None,
+            readiness_probe: None,
             log_file_path: log_file_path.as_os_str().to_os_string(),
+            restarts: 0,
+            previous_definition: None,
+            sidecars: vec![],
+            stdin: None,
+            environment: HashMap::new(),
         }
     }
 }
@@ -81,6 +151,7 @@ impl Executor {
             monitor_handle,
             env_holder,
             cfg,
+            stopping: Arc::new(()),
         }
     }
 
@@ -113,12 +184,20 @@ impl Executor {
     /// mapped to `STOPPED` (i.e. stopped by the user).
     pub fn collect(&mut self) {
         let mut expired_probes = vec![];
+        let mut collected = false;
 
         for module in self.running_modules_mut() {
             if let Some(process) = &mut module.child {
-                if let Ok(Some(status)) = process.try_wait() {
+                let exit_status = match process.try_wait() {
+                    Ok(Some(status)) => Some(Some(status)),
+                    // Taken over from a daemon which crashed.
+                    Err(e) if is_orphan_exit(&e) => Some(None),
+                    _ => None,
+                };
+                if let Some(status) = exit_status {
+                    collected = true;
                     module.exit_time = epoch_now();
-                    module.exit_status = Option::from(status);
+                    module.exit_status = status;
                     module.status = match module.status {
                         RunStatus::RUNNING => RunStatus::EXITED,
                         _ => RunStatus::STOPPED,
@@ -129,7 +208,7 @@ impl Executor {
                     info!(
                         "Collecting dead process ({}) with exit-code {:#?}",
                         module.pid,
-                        status.code().unwrap_or(-1)
+                        status.and_then(|s| s.code()).unwrap_or(-1)
                     );
                 }
             }
@@ -140,48 +219,54 @@ impl Executor {
             self.monitor_handle
                 .remove_monitor(handle, MonitorType::Liveness);
         }
-    }
 
-    /// Redeploys a module with a newer module definition.
-    pub fn redeploy_module(
-        &mut self,
-        module: Arc<ModuleDefinition>,
-    ) -> Result<()> {
-        info!("Redeploying module: {}", module.name);
-        self.stop_module(&module.name)?;
-        self.run_module(module)
-    }
-
-    /// Restarts a module (re-using the same module definition).
-    pub fn restart_module(&mut self, module_name: &str) -> Result<()> {
-        info!("Restarting module: {}", module_name);
-        let existing = {
-            let module = self.module_status_by_name(module_name);
-            Arc::clone(
-                &module
-                    .ok_or_else(|| {
-                        DaemonError::NotFound(module_name.to_string())
-                    })?
-                    .module_definition,
-            )
-        };
-        self.stop_module(module_name)?;
-        self.run_module(existing)
+        for module in self.module_map.values_mut() {
+            for sidecar in module.sidecars.iter_mut() {
+                if let Some(process) = &mut sidecar.child {
+                    if sidecar.status != RunStatus::RUNNING {
+                        continue;
+                    }
+                    let exit_status = match process.try_wait() {
+                        Ok(Some(status)) => Some(Some(status)),
+                        Err(e) if is_orphan_exit(&e) => Some(None),
+                        _ => None,
+                    };
+                    if let Some(status) = exit_status {
+                        collected = true;
+                        sidecar.exit_time = epoch_now();
+                        sidecar.exit_status = status;
+                        sidecar.status = RunStatus::EXITED;
+                    }
+                }
+            }
+            // Sidecars stop along with their service.
+            if module.status != RunStatus::RUNNING {
+                if let Err(e) = Self::stop_sidecars(module) {
+                    info!("Failed to stop sidecars: {:#}", e);
+                }
+            }
+        }
+        if collected {
+            self.persist();
+        }
     }
 
     /// Stops a module by name.
     ///
+    /// The module is signalled to stop, but its process is waited on through
+    /// the returned [Stopping], so that the executor is not held while it
+    /// shuts down.
+    ///
     /// Note: This will not stop dependent modules.
-    pub fn stop_module(&mut self, name: &str) -> Result<()> {
+    pub fn stop_module(&mut self, name: &str) -> Result<Stopping> {
         info!("Stopping module: {}", name);
         match self.module_map.get_mut(name) {
             Some(module) => {
-                if let Some(process) = &mut module.child {
-                    // Bail if already stopped
-                    if module.status != RunStatus::RUNNING {
-                        return Ok(());
-                    }
-
+                Self::stop_sidecars(module)?;
+                // Nothing to signal if already stopped.
+                let running = module.status == RunStatus::RUNNING;
+                let process = if running { module.child.take() } else { None };
+                let process = if let Some(mut process) = process {
                     module.status = RunStatus::STOPPED;
                     module.exit_time = epoch_now();
 
@@ -196,21 +281,31 @@ impl Executor {
                     let module_name = module.module_definition.name.clone();
 
                     // Signal child process to die
-                    match module.module_definition.termination_signal {
-                        TermSignal::KILL => process.kill(),
-                        TermSignal::TERM => process.terminate(),
-                        TermSignal::INT => process.interrupt(),
-                    }
+                    let signalled = send_termination_signal(
+                        &mut process,
+                        &module.module_definition.termination_signal,
+                    )
                     .with_context(|| {
                         format!(
                             "Failed to signal process {} to stop",
                             module_name
                         )
-                    })?;
-
-                    process.wait()?;
-                }
-                Ok(())
+                    });
+                    if let Err(e) = signalled {
+                        module.child = Some(process);
+                        return Err(e);
+                    }
+                    Some(process)
+                } else {
+                    None
+                };
+                let stopping = Stopping {
+                    module_definition: Arc::clone(&module.module_definition),
+                    process,
+                    _token: Arc::clone(&self.stopping),
+                };
+                self.persist();
+                Ok(stopping)
             }
             None => Err(DaemonError::NotRunning(name.to_string()).into()),
         }
@@ -234,6 +329,17 @@ impl Executor {
             &self.env_holder,
             self.cfg.use_env_grabber_env,
         );
+        let environment_variables = if module.tmpdir {
+            let dir = create_tmpdir(&module)?;
+            let mut environment = environment_variables.into_owned();
+            environment.insert(
+                TMPDIR_ENV.to_string(),
+                dir.to_string_lossy().into_owned(),
+            );
+            Cow::Owned(environment)
+        } else {
+            environment_variables
+        };
 
         let module_entry = self
             .module_map
@@ -242,48 +348,424 @@ impl Executor {
                 ModuleStatus::empty_from(&module, log_file_path)
             });
 
-        let (stdout_file, stderr_file) =
-            Self::prepare_log_files(log_file_path)?;
-
-        let mut cmd = CommandBuilder::new(&module.command);
-        cmd.env(&environment_variables)
-            .stdout_file(stdout_file)
-            .stderr_file(stderr_file)
-            .work_dir(module.working_dir.as_deref());
-
-        let child = cmd.build().group_spawn().with_context(|| {
-            format!("Failed to run service '{}'", module.name)
-        })?;
+        let (stdin, slave) = if module.interactive {
+            let (master, slave) = open_stdin_pty()?;
+            (Some(master), Some(slave))
+        } else {
+            (None, None)
+        };
+        let child =
+            Self::spawn(&module, log_file_path, &environment_variables, slave)
+                .with_context(|| {
+                    format!("Failed to run service '{}'", module.name)
+                })?;
 
+        if module_entry.status != RunStatus::WAITING {
+            module_entry.restarts += 1;
+        }
         module_entry.status = RunStatus::RUNNING;
         module_entry.pid = child.id();
         module_entry.child = Some(Process::groupped(child));
+        module_entry.stdin = stdin;
+        module_entry.environment = std::env::vars()
+            .chain(environment_variables.into_owned())
+            .collect();
         module_entry.uptime = epoch_now();
-        module_entry.module_definition = Arc::clone(&module);
+        let previous = std::mem::replace(
+            &mut module_entry.module_definition,
+            Arc::clone(&module),
+        );
+        if previous.content_hash() != module.content_hash() {
+            module_entry.previous_definition = Some(previous);
+        }
         module_entry.monitor_key = liveness_probe;
+        module_entry.readiness_key = None;
+        module_entry.readiness_probe = None;
 
         info!(
             "Process ({}) started, for module {}",
             module_entry.pid, module_entry.module_definition.name
         );
+        self.persist();
+
+        Ok(())
+    }
+
+    /// Returns the environment a running module was spawned with, along with
+    /// the definition it was deployed with.
+    pub fn environment(
+        &self,
+        name: &str,
+    ) -> Result<(HashMap<String, String>, Arc<ModuleDefinition>)> {
+        let module = self
+            .module_map
+            .get(name)
+            .ok_or_else(|| DaemonError::NotFound(name.to_string()))?;
+        if module.status != RunStatus::RUNNING {
+            bail!(DaemonError::NotRunning(name.to_string()));
+        }
+        Ok((
+            module.environment.clone(),
+            Arc::clone(&module.module_definition),
+        ))
+    }
 
+    /// Returns the terminal a running interactive service reads its input
+    /// from.
+    pub fn stdin(&self, name: &str) -> Result<File> {
+        let module = self
+            .module_map
+            .get(name)
+            .ok_or_else(|| DaemonError::NotFound(name.to_string()))?;
+        if module.status != RunStatus::RUNNING {
+            bail!(DaemonError::NotRunning(name.to_string()));
+        }
+        match &module.stdin {
+            Some(stdin) => Ok(stdin.try_clone()?),
+            None => bail!(DaemonError::NotInteractive(name.to_string())),
+        }
+    }
+
+    /// Starts the tasks run alongside a service (see
+    /// [ModuleDefinition::sidecars]), unless they are running already.
+    ///
+    /// Does nothing if the service is not running.
+    pub fn start_sidecars(&mut self, name: &str) -> Result<()> {
+        let module = self
+            .module_map
+            .get_mut(name)
+            .ok_or_else(|| DaemonError::NotFound(name.to_string()))?;
+        if module.status != RunStatus::RUNNING {
+            return Ok(());
+        }
+
+        let definitions = module.module_definition.sidecars.clone();
+        // Stopped sidecars of a previous definition which are no longer
+        // defined (or port forwards).
+        module.sidecars.retain(|sidecar| {
+            sidecar.status == RunStatus::RUNNING
+                || definitions
+                    .iter()
+                    .any(|d| d.name == sidecar.module_definition.name)
+        });
+        for definition in definitions {
+            let running = module.sidecars.iter().any(|s| {
+                s.module_definition.name == definition.name
+                    && s.status == RunStatus::RUNNING
+            });
+            if !running {
+                Self::start_sidecar(
+                    module,
+                    &definition,
+                    &self.env_holder,
+                    self.cfg.use_env_grabber_env,
+                )?;
+            }
+        }
+        self.persist();
         Ok(())
     }
 
-    /// Perform cleanup by attempting to kill all running child processes.
-    pub fn cleanup(&mut self) -> Result<()> {
-        let module_names: Vec<String> = self
+    /// Sets the readiness monitor of a deployed service.
+    pub fn set_readiness(&mut self, name: &str, key: String, probe: Monitor) {
+        if let Some(module) = self.module_map.get_mut(name) {
+            module.readiness_key = Some(key);
+            module.readiness_probe = Some(probe);
+        }
+    }
+
+    /// Starts a process forwarding a port for a running service, which is run
+    /// like its sidecars until the service stops.
+    pub fn start_forward(
+        &mut self,
+        name: &str,
+        forward: Arc<ModuleDefinition>,
+    ) -> Result<()> {
+        let module = self
+            .module_map
+            .get_mut(name)
+            .ok_or_else(|| DaemonError::NotFound(name.to_string()))?;
+        if module.status != RunStatus::RUNNING {
+            bail!(DaemonError::NotRunning(name.to_string()));
+        }
+        if module.sidecars.iter().any(|s| {
+            s.module_definition.name == forward.name
+                && s.status == RunStatus::RUNNING
+        }) {
+            bail!("{} of service {} is already running", forward.name, name);
+        }
+        Self::start_sidecar(
+            module,
+            &forward,
+            &self.env_holder,
+            self.cfg.use_env_grabber_env,
+        )?;
+        self.persist();
+        Ok(())
+    }
+
+    /// Checks whether the running modules can be handed over to a daemon
+    /// replacing this one (see [handover](crate::daemon::handover)).
+    ///
+    /// The output of modules logging to syslog is forwarded by a thread of
+    /// the daemon, without which they would fail to write it.
+    pub fn check_handover(&self) -> Result<()> {
+        // Their processes would not be waited on by the new daemon.
+        if Arc::strong_count(&self.stopping) > 1 {
+            bail!("Modules are being stopped, try again once they stopped");
+        }
+        let forwarded: Vec<&str> = self
             .running_modules()
-            .map(|m| m.module_definition.name.clone())
+            .flat_map(|m| std::iter::once(m).chain(m.sidecars.iter()))
+            .filter(|m| {
+                m.status == RunStatus::RUNNING
+                    && m.module_definition.log_sink != LogSink::File
+            })
+            .map(|m| m.module_definition.name.as_str())
             .collect();
-        for module in module_names {
-            self.stop_module(module.as_str())?;
+        if !forwarded.is_empty() {
+            bail!(
+                "The output of {} is forwarded to syslog by the daemon, \
+                stop them first",
+                forwarded.join(", ")
+            );
         }
         Ok(())
     }
+
+    /// Returns the state of the modules, to hand over to a daemon replacing
+    /// this one.
+    pub fn handover(&self) -> Result<Vec<HandedOverModule>> {
+        self.check_handover()?;
+        self.module_map
+            .values()
+            .map(|module| Self::hand_over(module, true))
+            .collect()
+    }
+
+    /// Takes over the modules handed over by the daemon this one replaced,
+    /// whose running processes are still children of the daemon.
+    pub fn adopt(&mut self, modules: Vec<HandedOverModule>) {
+        for module in modules {
+            let mut status = Self::adopted(module);
+            if status.status == RunStatus::RUNNING {
+                status.monitor_key =
+                    self.maybe_create_liveness_probe(&status.module_definition);
+            }
+            info!(
+                "Took over module {} ({:?})",
+                status.module_definition.name, status.status
+            );
+            self.module_map
+                .insert(status.module_definition.name.clone(), status);
+        }
+        self.persist();
+    }
+
+    /// Returns the names of the running modules.
+    pub fn running_module_names(&self) -> Vec<String> {
+        self.running_modules()
+            .map(|m| m.module_definition.name.clone())
+            .collect()
+    }
 }
 
 impl Executor {
+    /// Spawns the process of a module in a process group of its own.
+    fn spawn(
+        module: &ModuleDefinition,
+        log_file_path: &Path,
+        environment_variables: &HashMap<String, String>,
+        stdin: Option<File>,
+    ) -> Result<GroupChild> {
+        let output = Self::prepare_log_output(module, log_file_path)?;
+
+        let mut cmd = CommandBuilder::new(&module.spawn_command());
+        if let Some(stdin) = stdin {
+            cmd.stdin_file(stdin);
+        }
+        cmd.env(environment_variables)
+            .stdout_file(output.stdout)
+            .stderr_file(output.stderr)
+            .work_dir(module.working_dir.as_deref());
+
+        Ok(cmd.build().group_spawn()?)
+    }
+
+    /// Starts a sidecar of a service, replacing its previous run if any.
+    fn start_sidecar(
+        module: &mut ModuleStatus,
+        definition: &Arc<ModuleDefinition>,
+        env_holder: &CurrentEnvHolder,
+        use_env_grabber_env: bool,
+    ) -> Result<()> {
+        info!(
+            "Starting sidecar {} of service {}",
+            definition.name, module.module_definition.name
+        );
+        let log_file_path = log_file_module(definition)?;
+        let mut environment_variables = Self::environment_variables(
+            definition,
+            env_holder,
+            use_env_grabber_env,
+        );
+        // Sidecars share the temp directory of their service.
+        if let Some(dir) = module.environment.get(TMPDIR_ENV) {
+            environment_variables
+                .to_mut()
+                .insert(TMPDIR_ENV.to_string(), dir.clone());
+        }
+        let child = Self::spawn(
+            definition,
+            &log_file_path,
+            &environment_variables,
+            None,
+        )
+        .with_context(|| {
+            format!("Failed to run sidecar '{}'", definition.name)
+        })?;
+
+        let mut sidecar = ModuleStatus::empty_from(definition, &log_file_path);
+        let existing = module
+            .sidecars
+            .iter()
+            .position(|s| s.module_definition.name == definition.name);
+        if let Some(idx) = existing {
+            sidecar.restarts = module.sidecars[idx].restarts + 1;
+            module.sidecars.remove(idx);
+        }
+        sidecar.status = RunStatus::RUNNING;
+        sidecar.pid = child.id();
+        sidecar.child = Some(Process::groupped(child));
+        sidecar.uptime = epoch_now();
+        module.sidecars.push(sidecar);
+        Ok(())
+    }
+
+    /// Stops the running sidecars of a service.
+    fn stop_sidecars(module: &mut ModuleStatus) -> Result<()> {
+        for sidecar in module.sidecars.iter_mut() {
+            if sidecar.status != RunStatus::RUNNING {
+                continue;
+            }
+            let name = &sidecar.module_definition.name;
+            if let Some(process) = &mut sidecar.child {
+                info!("Stopping sidecar: {}", name);
+                sidecar.status = RunStatus::STOPPED;
+                sidecar.exit_time = epoch_now();
+                send_termination_signal(
+                    process,
+                    &sidecar.module_definition.termination_signal,
+                )
+                .with_context(|| {
+                    format!("Failed to signal sidecar {} to stop", name)
+                })?;
+                sidecar.exit_status = Self::wait_for_sidecar(process, name);
+            }
+        }
+        Ok(())
+    }
+
+    /// Waits for a signalled sidecar to exit, killing it if it does not exit
+    /// in time.
+    fn wait_for_sidecar(
+        process: &mut Process,
+        name: &str,
+    ) -> Option<ExitStatus> {
+        let deadline = Instant::now() + SIDECAR_STOP_TIMEOUT;
+        while Instant::now() < deadline {
+            match process.try_wait() {
+                Ok(Some(status)) => return Some(status),
+                Ok(None) => std::thread::sleep(SIDECAR_STOP_INTERVAL),
+                Err(_) => return None,
+            }
+        }
+        warn!(
+            "Sidecar {} did not exit within {:?}, killing it",
+            name, SIDECAR_STOP_TIMEOUT
+        );
+        process.kill().ok();
+        process.wait().ok()
+    }
+
+    /// Persists the state of the modules, for a daemon started after this
+    /// one crashed to take them over (see [handover::recover]).
+    fn persist(&self) {
+        let modules = self
+            .module_map
+            .values()
+            .map(|module| Self::hand_over(module, false))
+            .collect::<Result<Vec<_>>>()
+            .and_then(|modules| handover::persist_state(&modules));
+        if let Err(e) = modules {
+            warn!("Failed to persist the state of the modules: {:#}", e);
+        }
+    }
+
+    /// Returns the state of a module, keeping the terminal of an interactive
+    /// service open across `exec` if `keep_stdin` is set.
+    fn hand_over(
+        module: &ModuleStatus,
+        keep_stdin: bool,
+    ) -> Result<HandedOverModule> {
+        // Duplicated, as the original is closed on `exec`.
+        let stdin_fd = match &module.stdin {
+            Some(stdin) if keep_stdin => Some(
+                dup(stdin.as_raw_fd()).context("Failed to keep stdin open")?,
+            ),
+            _ => None,
+        };
+        Ok(HandedOverModule {
+            definition: Arc::clone(&module.module_definition),
+            previous_definition: module.previous_definition.clone(),
+            status: module.status.clone(),
+            pid: module.pid,
+            uptime: module.uptime,
+            exit_time: module.exit_time,
+            exit_status: module.exit_status.map(ExitStatus::into_raw),
+            log_file_path: module.log_file_path.clone(),
+            restarts: module.restarts,
+            environment: module.environment.clone(),
+            stdin_fd,
+            sidecars: module
+                .sidecars
+                .iter()
+                .map(|sidecar| Self::hand_over(sidecar, keep_stdin))
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    fn adopted(module: HandedOverModule) -> ModuleStatus {
+        let running = module.status == RunStatus::RUNNING;
+        let stdin = module.stdin_fd.map(|fd| {
+            fcntl(fd, FcntlArg::F_SETFD(FdFlag::FD_CLOEXEC)).ok();
+            // Safety: The descriptor was handed over for this module only.
+            unsafe { File::from_raw_fd(fd) }
+        });
+        ModuleStatus {
+            module_definition: module.definition,
+            status: module.status,
+            pid: module.pid,
+            uptime: module.uptime,
+            exit_time: module.exit_time,
+            exit_status: module.exit_status.map(ExitStatus::from_raw),
+            log_file_path: module.log_file_path,
+            monitor_key: None,
+            readiness_key: None,
+            readiness_probe: None,
+            restarts: module.restarts,
+            previous_definition: module.previous_definition,
+            sidecars: module.sidecars.into_iter().map(Self::adopted).collect(),
+            child: if running {
+                Some(Process::groupped(GroupChild::adopt(module.pid)))
+            } else {
+                None
+            },
+            stdin,
+            environment: module.environment,
+        }
+    }
+
     fn running_modules(&self) -> impl Iterator<Item = &ModuleStatus> {
         self.module_map
             .values()
@@ -314,9 +796,11 @@ impl Executor {
         env_holder: &CurrentEnvHolder,
         use_env_grabber_env: bool,
     ) -> Cow<'a, HashMap<String, String>> {
-        if use_env_grabber_env {
-            let merged_environment =
-                Self::merge_envs(env_holder.read(), &module.environment);
+        if module.use_login_env.unwrap_or(use_env_grabber_env) {
+            let merged_environment = Self::merge_envs(
+                env_holder.read_or_grab(),
+                &module.environment,
+            );
             Cow::Owned(merged_environment)
         } else {
             Cow::Borrowed(&module.environment)
@@ -346,15 +830,31 @@ impl Executor {
         monitor_key
     }
 
-    pub(super) fn prepare_log_files(
+    pub(super) fn prepare_log_output(
+        module: &ModuleDefinition,
         log_file_path: &Path,
-    ) -> Result<(File, File)> {
-        let stdout_file = File::create(log_file_path)
-            .with_context(|| "Failed to create log file")?;
-        let stderr_file = stdout_file
-            .try_clone()
+    ) -> Result<LogOutput> {
+        // Opened in append mode so that the log file can be safely truncated
+        // while the service is running (see [crate::daemon::retention]).
+        let log_file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(log_file_path)
+            .and_then(|file| file.set_len(0).map(|_| file))
             .with_context(|| "Failed to create log file")?;
-        Ok((stdout_file, stderr_file))
+        LogOutput::new(&module.name, log_file, &module.log_sink)
+    }
+}
+
+/// Sends the termination signal of a module to its process.
+fn send_termination_signal(
+    process: &mut Process,
+    signal: &TermSignal,
+) -> std::io::Result<()> {
+    match signal {
+        TermSignal::KILL => process.kill(),
+        TermSignal::TERM => process.terminate(),
+        TermSignal::INT => process.interrupt(),
     }
 }
 
@@ -365,61 +865,205 @@ pub mod task_executor {
     use crate::daemon::error::DaemonError;
     use crate::daemon::executor::ExecutorConfig;
     use crate::daemon::logs::log_file_module;
-    use crate::daemon::module::{ModuleDefinition, ModuleKind};
+    use crate::daemon::module::{ModuleDefinition, ModuleKind, TaskOptions};
+    use crate::daemon::run_condition::{self, FileHashes, Guard};
+    use crate::daemon::syslog::join_forwarder;
+    use crate::daemon::task_runs::{TaskExecution, TaskHistory, TaskOutcome};
+    use crate::daemon::time::epoch_now;
+    use crate::process::CommandExt;
     use anyhow::{Context, Result};
+    use log::{info, warn};
+    use std::path::PathBuf;
     use std::process::ExitStatus;
     use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    /// How often a task with a timeout is checked for having exited.
+    const TASK_WAIT_INTERVAL: Duration = Duration::from_millis(100);
+
+    /// How long to wait for the output of a task to be forwarded to syslog
+    /// once it exited.
+    const FORWARDER_TIMEOUT: Duration = Duration::from_secs(5);
 
     /// Executes a task and waits for it until it is finished.
     ///
     /// The task will block the current thread, and report its exit status on
-    /// completion. If the task exits with any code other than zero then an
-    /// Error is thrown.
+    /// completion. If the task exits with any code other than zero it is run
+    /// again, as many times as the retry policy allows, before an Error is
+    /// thrown. Each attempt writes to a log file of its own, and is recorded
+    /// in the task history. The successful attempt is returned.
+    ///
+    /// The task is not run at all if its run condition says it has nothing to
+    /// do.
     pub fn execute_task(
         task_definition: &ModuleDefinition,
+        options: &TaskOptions,
+        deployment_id: Option<&str>,
+        history: &TaskHistory,
+        file_hashes: &FileHashes,
         cfg: &ExecutorConfig,
         env_holder: Arc<CurrentEnvHolder>,
-    ) -> Result<ExitStatus> {
+    ) -> Result<TaskOutcome> {
         assert!(task_definition.kind == ModuleKind::Task);
+        let files_hash = match &options.run_if {
+            Some(condition) => {
+                let environment = Executor::environment_variables(
+                    task_definition,
+                    &env_holder,
+                    cfg.use_env_grabber_env,
+                );
+                match run_condition::check(
+                    task_definition,
+                    condition,
+                    file_hashes,
+                    &environment,
+                )? {
+                    Guard::Run(files_hash) => files_hash,
+                    Guard::Skip(reason) => {
+                        info!(
+                            "Skipping task {} ({})",
+                            task_definition.name, reason
+                        );
+                        return Ok(TaskOutcome::Skipped(reason));
+                    }
+                }
+            }
+            None => None,
+        };
+        let retry_policy = &options.retry_policy;
+        let mut attempts = 1;
+        loop {
+            let started_at = epoch_now();
+            let attempt =
+                run_task(task_definition, options.timeout, cfg, &env_holder)?;
+            let execution = TaskExecution {
+                task_name: task_definition.name.clone(),
+                project: task_definition.project.clone(),
+                started_at,
+                finished_at: epoch_now(),
+                exit_code: attempt.exit_status.code(),
+                log_file: attempt.log_file.clone(),
+                attempt: attempts,
+                deployment_id: deployment_id.map(String::from),
+            };
+            history.record(execution.clone());
+            if attempt.exit_status.success() {
+                if let Some(hash) = files_hash {
+                    file_hashes.record(task_definition, hash);
+                }
+                return Ok(TaskOutcome::Ran(execution));
+            }
+            if attempts > retry_policy.retries {
+                let task_name = task_definition.name.clone();
+                let log_file = attempt.log_file.into_os_string();
+                return Err(match options.timeout {
+                    Some(timeout) if attempt.timed_out => {
+                        DaemonError::TaskTimedOut {
+                            task_name,
+                            timeout: timeout.as_secs(),
+                            log_file,
+                            attempts,
+                        }
+                    }
+                    _ => DaemonError::TaskFailed {
+                        task_name,
+                        code: attempt.exit_status.code().unwrap_or(-1),
+                        log_file,
+                        attempts,
+                    },
+                }
+                .into());
+            }
+            info!(
+                "Task {} failed (attempt {} of {}), retrying in {:?}",
+                task_definition.name,
+                attempts,
+                retry_policy.retries + 1,
+                retry_policy.delay
+            );
+            std::thread::sleep(retry_policy.delay);
+            attempts += 1;
+        }
+    }
+
+    /// How a single attempt at running a task ended.
+    struct Attempt {
+        exit_status: ExitStatus,
+        /// Whether the task was killed for running past its timeout.
+        timed_out: bool,
+        log_file: PathBuf,
+    }
+
+    /// Runs a task once, killing its process group if it runs for longer
+    /// than the timeout.
+    fn run_task(
+        task_definition: &ModuleDefinition,
+        timeout: Option<Duration>,
+        cfg: &ExecutorConfig,
+        env_holder: &CurrentEnvHolder,
+    ) -> Result<Attempt> {
         let log_file_pathbuf = log_file_module(task_definition)?;
         let log_file_path = log_file_pathbuf.as_path();
         let environment_vars = Executor::environment_variables(
             task_definition,
-            &env_holder,
+            env_holder,
             cfg.use_env_grabber_env,
         );
 
-        let (stdout_file, stderr_file) =
-            Executor::prepare_log_files(log_file_path)?;
+        let output =
+            Executor::prepare_log_output(task_definition, log_file_path)?;
 
-        let mut cmd = CommandBuilder::new(&task_definition.command);
+        let mut cmd = CommandBuilder::new(&task_definition.spawn_command());
         cmd.env(&environment_vars)
-            .stdout_file(stdout_file)
-            .stderr_file(stderr_file)
+            .stdout_file(output.stdout)
+            .stderr_file(output.stderr)
             .work_dir(task_definition.working_dir.as_deref());
 
-        let exit_status = cmd
-            .build()
-            .spawn()
-            .with_context(|| {
-                format!(
-                    "Failed to start task {}",
-                    &task_definition.command.join(" ")
-                )
-            })?
-            .wait()
+        let mut child = cmd.build().group_spawn().with_context(|| {
+            format!(
+                "Failed to start task {}",
+                &task_definition.command.join(" ")
+            )
+        })?;
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut timed_out = false;
+        let exit_status = loop {
+            let exit_status = match deadline {
+                Some(_) => child.try_wait(),
+                None => child.wait().map(Some),
+            }
             .with_context(|| {
                 format!("Task {} failed to execute", task_definition.name)
             })?;
+            match (exit_status, deadline) {
+                (Some(exit_status), _) => break exit_status,
+                (None, Some(deadline)) if Instant::now() >= deadline => {
+                    info!(
+                        "Task {} timed out, killing it",
+                        task_definition.name
+                    );
+                    timed_out = true;
+                    child.kill()?;
+                    break child.wait()?;
+                }
+                (None, _) => std::thread::sleep(TASK_WAIT_INTERVAL),
+            }
+        };
 
-        if !exit_status.success() {
-            return Err(DaemonError::TaskFailed {
-                task_name: task_definition.name.clone(),
-                code: exit_status.code().unwrap_or(-1),
-                log_file: log_file_path.as_os_str().to_os_string(),
+        // Make sure all of the output was forwarded before reporting back.
+        if let Some(forwarder) = output.forwarder {
+            if !join_forwarder(forwarder, FORWARDER_TIMEOUT) {
+                warn!(
+                    "Output of task {} is still being forwarded, processes it \
+                    started in the background hold on to it",
+                    task_definition.name
+                );
             }
-            .into());
         }
-        Ok(exit_status)
+        Ok(Attempt {
+            exit_status,
+            timed_out,
+            log_file: log_file_pathbuf,
+        })
     }
 }
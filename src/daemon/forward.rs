@@ -0,0 +1,66 @@
+use crate::daemon::module::{
+    LogSink, ModuleDefinition, ModuleKind, TermSignal, Tooling,
+};
+use std::collections::HashMap;
+
+/// A port forwarded for a service.
+#[derive(Debug, Clone)]
+pub struct Forward {
+    /// The port listened to on the daemon's host.
+    pub local_port: u16,
+    /// The host connections are forwarded to (relative to `via` if given).
+    pub remote_host: String,
+    /// The port connections are forwarded to.
+    pub remote_port: u16,
+    /// An ssh destination (`[user@]host`) to forward through with `ssh -L`.
+    /// Connections are forwarded using `socat` when absent.
+    pub via: Option<String>,
+}
+
+impl Forward {
+    /// The name of the process forwarding the port, unique per service.
+    pub fn name(&self) -> String {
+        format!("forward-{}", self.local_port)
+    }
+
+    fn command(&self) -> Vec<String> {
+        match &self.via {
+            Some(via) => vec![
+                "ssh".to_string(),
+                "-N".to_string(),
+                "-o".to_string(),
+                "ExitOnForwardFailure=yes".to_string(),
+                "-L".to_string(),
+                format!(
+                    "{}:{}:{}",
+                    self.local_port, self.remote_host, self.remote_port
+                ),
+                via.clone(),
+            ],
+            None => vec![
+                "socat".to_string(),
+                format!("TCP-LISTEN:{},fork,reuseaddr", self.local_port),
+                format!("TCP:{}:{}", self.remote_host, self.remote_port),
+            ],
+        }
+    }
+
+    /// Returns the definition of the process forwarding the port, which is
+    /// run as a sidecar of the service.
+    pub fn definition(&self, service: &ModuleDefinition) -> ModuleDefinition {
+        ModuleDefinition::new(
+            ModuleKind::Task,
+            self.name(),
+            self.command(),
+            HashMap::new(),
+            None,
+            LogSink::File,
+            vec![],
+            None,
+            Tooling::default(),
+            TermSignal::KILL,
+            None,
+            service.project.clone(),
+        )
+    }
+}
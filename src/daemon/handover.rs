@@ -0,0 +1,406 @@
+use crate::constants::SUPERVISED_ENV;
+use crate::daemon::executor::RunStatus;
+use crate::daemon::module::ModuleDefinition;
+use crate::daemon::supervisor::is_supervised;
+use crate::daemon::time::epoch_now;
+use crate::daemon::Core;
+use crate::path;
+use anyhow::{anyhow, bail, Context, Error, Result};
+use log::{error, info};
+use nix::unistd::close;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs::{DirBuilder, OpenOptions, Permissions};
+use std::io::{self, Write};
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt, PermissionsExt};
+use std::os::unix::io::RawFd;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+use std::time::Duration;
+use std::{env, fs, thread};
+
+/// The name of the file the state of the modules is handed over in, within
+/// the state directory.
+const HANDOVER_FILE: &str = "handover.json";
+
+/// The name of the file the state of the modules is persisted in, within the
+/// state directory, for a daemon started after this one crashed to take them
+/// over.
+const STATE_FILE: &str = "modules.json";
+
+/// How far apart (in seconds) the start of a process may be from when the
+/// daemon recorded starting it, for it to be taken over after a crash.
+const START_TIME_TOLERANCE: u64 = 5;
+
+/// How often to check whether the modules taken over after a crash exited.
+const ORPHAN_COLLECT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The name the daemon reports along with its version.
+const DAEMON_NAME: &str = "cartel-daemon";
+
+/// How long to wait before replacing the daemon, so that the response to the
+/// upgrade request is sent first.
+const HANDOVER_DELAY: Duration = Duration::from_millis(500);
+
+/// The state of a module, as handed over to the daemon replacing this one,
+/// or persisted for the daemon started after this one crashed.
+#[derive(Serialize, Deserialize)]
+pub struct HandedOverModule {
+    pub definition: Arc<ModuleDefinition>,
+    pub previous_definition: Option<Arc<ModuleDefinition>>,
+    pub status: RunStatus,
+    pub pid: u32,
+    pub uptime: u64,
+    pub exit_time: u64,
+    /// The raw wait status the module exited with, if it did.
+    pub exit_status: Option<i32>,
+    pub log_file_path: OsString,
+    pub restarts: u32,
+    pub environment: HashMap<String, String>,
+    /// The descriptor of the terminal an interactive service reads its input
+    /// from, which is kept open across the `exec`.
+    pub stdin_fd: Option<RawFd>,
+    pub sidecars: Vec<HandedOverModule>,
+}
+
+fn handover_file_path() -> Result<PathBuf> {
+    path::state_dir()
+        .map(|dir| dir.join(HANDOVER_FILE))
+        .ok_or_else(|| anyhow!("Failed to locate users state dir"))
+}
+
+fn state_file_path() -> Result<PathBuf> {
+    path::state_dir()
+        .map(|dir| dir.join(STATE_FILE))
+        .ok_or_else(|| anyhow!("Failed to locate users state dir"))
+}
+
+/// Checks whether the daemon can be replaced by the given executable without
+/// stopping any of its modules, returning the version of the new daemon.
+pub fn check_upgrade(core: &Core, executable: &Path) -> Result<String> {
+    let version = daemon_version(executable)?;
+    if let Some(holder) = core.deploy_lock.holder() {
+        bail!(
+            "A deployment is in progress ({} by {}@{})",
+            holder.operation,
+            holder.user,
+            holder.host
+        );
+    }
+    let tasks = core.task_runs.running();
+    if !tasks.is_empty() {
+        bail!("Tasks are running in the background: {}", tasks.join(", "));
+    }
+    core.planner().check_handover()?;
+    Ok(version)
+}
+
+/// Returns the version of a daemon executable, failing if it is not one.
+///
+/// Once the daemon is replaced there is no going back, so the executable is
+/// run first to make sure it is a daemon at all.
+fn daemon_version(executable: &Path) -> Result<String> {
+    let output = Command::new(executable)
+        .arg("--version")
+        .output()
+        .with_context(|| format!("Failed to run {}", executable.display()))?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    match stdout.trim().strip_prefix(DAEMON_NAME) {
+        Some(version) if output.status.success() => {
+            Ok(version.trim().to_string())
+        }
+        _ => bail!("{} is not a cartel daemon", executable.display()),
+    }
+}
+
+/// Replaces the daemon with the given executable in the background, once
+/// the response to the upgrade request was sent.
+///
+/// The new daemon replaces this one using `exec`, so that the processes of
+/// the modules remain its children and keep running. The daemon keeps
+/// running as is if that fails.
+pub fn upgrade_in_background(core: Arc<Core>, executable: PathBuf) {
+    thread::spawn(move || {
+        thread::sleep(HANDOVER_DELAY);
+        info!("Upgrading the daemon to {}", executable.display());
+        let e = core.planner().exec_handover(&executable);
+        error!("Failed to upgrade the daemon: {:#}", e);
+    });
+}
+
+/// Writes the state of the modules and replaces the current process with
+/// the given executable, which takes them over (see [adopt]).
+///
+/// Only returns (with the reason why) if that failed.
+pub fn exec(executable: &Path, modules: &[HandedOverModule]) -> Error {
+    let e = write_and_exec(executable, modules).unwrap_or_else(|e| e);
+    for fd in stdin_fds(modules) {
+        close(fd).ok();
+    }
+    if let Ok(path) = handover_file_path() {
+        fs::remove_file(path).ok();
+    }
+    e
+}
+
+fn write_and_exec(
+    executable: &Path,
+    modules: &[HandedOverModule],
+) -> Result<Error> {
+    let path = handover_file_path()?;
+    write_private(&path, &serde_json::to_string(modules)?).with_context(
+        || format!("Failed to write handover file {}", path.display()),
+    )?;
+
+    let mut command = Command::new(executable);
+    command
+        .args(daemon_args(env::args_os().skip(1)))
+        .arg("--handover")
+        .arg(&path);
+    if is_supervised() {
+        command.env(SUPERVISED_ENV, "1");
+    }
+    let e = command.exec();
+    Ok(Error::new(e)
+        .context(format!("Failed to execute {}", executable.display())))
+}
+
+/// Returns the arguments the daemon was started with, except for those which
+/// do not apply once it has started (eg. `--detach`).
+fn daemon_args(mut args: impl Iterator<Item = OsString>) -> Vec<OsString> {
+    let mut kept = vec![];
+    while let Some(arg) = args.next() {
+        if arg == "--handover" {
+            args.next();
+        } else if arg != "-d" && arg != "--detach" {
+            kept.push(arg);
+        }
+    }
+    kept
+}
+
+fn stdin_fds(modules: &[HandedOverModule]) -> Vec<RawFd> {
+    modules
+        .iter()
+        .flat_map(|module| {
+            module
+                .stdin_fd
+                .into_iter()
+                .chain(stdin_fds(&module.sidecars))
+        })
+        .collect()
+}
+
+/// Takes over the modules of the daemon this one replaced, from the state it
+/// handed over.
+pub fn adopt(core: &Core, handover_file: &Path) {
+    let modules = fs::read_to_string(handover_file)
+        .map_err(Error::from)
+        .and_then(|content| {
+            serde_json::from_str::<Vec<HandedOverModule>>(&content)
+                .map_err(Error::from)
+        });
+    fs::remove_file(handover_file).ok();
+    match modules {
+        Ok(modules) => {
+            info!("Taking over {} modules", modules.len());
+            core.planner().adopt(modules);
+        }
+        Err(e) => error!("Failed to read the handed over state: {:#}", e),
+    }
+}
+
+/// Persists the state of the modules, for a daemon started after this one
+/// crashed to take them over (see [recover]).
+pub fn persist_state(modules: &[HandedOverModule]) -> Result<()> {
+    let path = state_file_path()?;
+    // Written in full before replacing the previous state, which is then
+    // never found half written.
+    let mut tmp_path = path.clone().into_os_string();
+    tmp_path.push(".tmp");
+    write_private(Path::new(&tmp_path), &serde_json::to_string(modules)?)
+        .and_then(|_| fs::rename(&tmp_path, &path))
+        .with_context(|| {
+            format!("Failed to write state file {}", path.display())
+        })
+}
+
+/// Writes a file only the user can read, creating the directories leading to
+/// it accessible by the user only, as the state of the modules holds their
+/// environments (including secrets).
+fn write_private(path: &Path, content: &str) -> io::Result<()> {
+    DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(path.parent().unwrap())?;
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    // The mode only applies to new files, while a file left behind (eg. by
+    // an earlier version) may be readable by others.
+    file.set_permissions(Permissions::from_mode(0o600))?;
+    file.write_all(content.as_bytes())
+}
+
+/// Removes the persisted state of the modules, once they were stopped along
+/// with the daemon.
+pub fn remove_state() {
+    if let Ok(path) = state_file_path() {
+        fs::remove_file(path).ok();
+    }
+}
+
+/// Takes over the modules left running by a daemon which crashed, from the
+/// state it persisted.
+///
+/// Their processes are no longer children of the daemon, so once they exit
+/// their exit status is unknown. Returns whether any modules were running.
+pub fn recover(core: &Core) -> bool {
+    let path = match state_file_path() {
+        Ok(path) if path.exists() => path,
+        _ => return false,
+    };
+    let modules =
+        fs::read_to_string(&path)
+            .map_err(Error::from)
+            .and_then(|content| {
+                serde_json::from_str::<Vec<HandedOverModule>>(&content)
+                    .map_err(Error::from)
+            });
+    let mut modules = match modules {
+        Ok(modules) => modules,
+        Err(e) => {
+            error!("Failed to read the persisted state: {:#}", e);
+            return false;
+        }
+    };
+
+    let mut running = false;
+    for module in modules.iter_mut() {
+        mark_exited(module);
+        running |= module.status == RunStatus::RUNNING;
+    }
+    info!("Recovering {} modules after a crash", modules.len());
+    core.planner().adopt(modules);
+    running
+}
+
+/// Periodically collects the modules taken over after a crash which exited.
+///
+/// Their processes are not children of the daemon, which is therefore not
+/// signalled when they exit.
+pub fn orphan_collector_thread(core: Arc<Core>) {
+    info!("Starting orphan collector thread");
+    thread::spawn(move || loop {
+        thread::sleep(ORPHAN_COLLECT_INTERVAL);
+        core.planner().collect_dead();
+    });
+}
+
+/// Marks a module whose process is no longer running (or was replaced by
+/// another one with the same pid) as exited, along with its sidecars.
+fn mark_exited(module: &mut HandedOverModule) {
+    if module.status == RunStatus::RUNNING
+        && !is_same_process(module.pid, module.uptime)
+    {
+        info!(
+            "Process ({}) of module {} is no longer running",
+            module.pid, module.definition.name
+        );
+        module.status = RunStatus::EXITED;
+        module.exit_time = epoch_now();
+        module.exit_status = None;
+    }
+    for sidecar in module.sidecars.iter_mut() {
+        mark_exited(sidecar);
+    }
+}
+
+/// Returns whether a process is running and was started around the given
+/// time, as a UNIX timestamp.
+fn is_same_process(pid: u32, started_at: u64) -> bool {
+    let output = match Command::new("ps")
+        .args(["-o", "etime=", "-p", &pid.to_string()])
+        .output()
+    {
+        Ok(output) if output.status.success() => output,
+        _ => return false,
+    };
+    match parse_elapsed(String::from_utf8_lossy(&output.stdout).trim()) {
+        Some(elapsed) => {
+            let process_started_at = epoch_now().saturating_sub(elapsed);
+            process_started_at.max(started_at)
+                - process_started_at.min(started_at)
+                <= START_TIME_TOLERANCE
+        }
+        None => false,
+    }
+}
+
+/// Parses the time elapsed since a process started, as reported by `ps` in
+/// the `[[dd-]hh:]mm:ss` format, into seconds.
+fn parse_elapsed(elapsed: &str) -> Option<u64> {
+    let (days, time) = match elapsed.split_once('-') {
+        Some((days, time)) => (days.parse::<u64>().ok()?, time),
+        None => (0, elapsed),
+    };
+    let seconds = time.split(':').try_fold(0, |total, part| {
+        part.parse::<u64>().ok().map(|part| total * 60 + part)
+    })?;
+    Some(days * 24 * 60 * 60 + seconds)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_elapsed() {
+        assert_eq!(parse_elapsed("00:05"), Some(5));
+        assert_eq!(parse_elapsed("12:34"), Some(12 * 60 + 34));
+        assert_eq!(parse_elapsed("01:02:03"), Some(3600 + 2 * 60 + 3));
+        assert_eq!(
+            parse_elapsed("2-01:02:03"),
+            Some(2 * 24 * 3600 + 3600 + 2 * 60 + 3)
+        );
+    }
+
+    #[test]
+    fn test_parse_elapsed_invalid() {
+        assert_eq!(parse_elapsed(""), None);
+        assert_eq!(parse_elapsed("abc"), None);
+        assert_eq!(parse_elapsed("1:xx"), None);
+        assert_eq!(parse_elapsed("x-01:02"), None);
+    }
+
+    #[test]
+    fn test_write_private() {
+        let dir = env::temp_dir()
+            .join(format!("cartel-handover-test-{}", std::process::id()));
+        let path = dir.join("state").join("modules.json");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("existing"), "").unwrap();
+        fs::set_permissions(
+            dir.join("existing"),
+            Permissions::from_mode(0o644),
+        )
+        .unwrap();
+
+        write_private(&path, "{}").unwrap();
+        write_private(&dir.join("existing"), "{}").unwrap();
+
+        let mode =
+            |path: &Path| fs::metadata(path).unwrap().permissions().mode();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{}");
+        assert_eq!(mode(&path) & 0o777, 0o600);
+        assert_eq!(mode(path.parent().unwrap()) & 0o777, 0o700);
+        assert_eq!(mode(&dir.join("existing")) & 0o777, 0o600);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,35 +1,73 @@
+use crate::config::PERSISTED_CONFIG;
+use crate::constants::TIME_KEYS;
 use crate::daemon::module::{ModuleDefinition, ModuleKind};
-use anyhow::{Context, Result};
-use dirs::home_dir;
-use std::fs;
-use std::path::PathBuf;
-
-const PROJECT_DIR: &str = ".cartel";
-const LOG_DIR: &str = "logs";
-
-/// Returns the default log directory as a `PathBuf`.
-pub fn default_log_directory() -> Result<PathBuf> {
-    let home_dir = home_dir()
-        .expect("Failed to get home dir")
-        .join(PROJECT_DIR)
-        .join(LOG_DIR);
-    fs::create_dir_all(home_dir.as_path()).with_context(|| {
-        format!("Failed to create log dir ~/{}/{}", PROJECT_DIR, LOG_DIR)
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use grep_regex::RegexMatcher;
+use grep_searcher::{Searcher, SearcherBuilder, Sink, SinkContext, SinkMatch};
+use nix::sys::statvfs::statvfs;
+use serde_json::{Map, Value};
+use std::collections::VecDeque;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use std::{fs, io};
+
+/// The maximum number of bytes returned by a single [read_log_chunk].
+const MAX_CHUNK_SIZE: u64 = 64 * 1024;
+
+/// The format of the timestamp in the log file name of a task run.
+const TASK_RUN_FORMAT: &str = "%Y%m%d%H%M%S%3f";
+
+/// The number of runs of a task whose log files are kept.
+const TASK_RUNS_KEPT: usize = 20;
+
+/// Returns the log directory as a `PathBuf`.
+///
+/// This is the `logs` directory within the state directory (eg.
+/// `~/.local/state/cartel/logs`) unless configured otherwise through the
+/// `daemon.log_dir` option.
+pub fn log_directory() -> Result<PathBuf> {
+    let log_dir = PERSISTED_CONFIG.daemon.log_dir();
+    fs::create_dir_all(log_dir.as_path()).with_context(|| {
+        format!("Failed to create log dir {}", log_dir.display())
     })?;
-    Ok(home_dir)
+    Ok(log_dir)
+}
+
+/// Returns the number of bytes available to the daemon on the file system
+/// of the log directory.
+pub fn log_directory_available_space() -> Result<u64> {
+    let log_dir = log_directory()?;
+    let stat = statvfs(log_dir.as_path()).with_context(|| {
+        format!("Failed to read the file system of {}", log_dir.display())
+    })?;
+    Ok(stat.blocks_available() as u64 * stat.fragment_size() as u64)
 }
 
 /// Returns the log file path for a given module name.
 ///
 /// The log path is differentiated based on the module kind. For example a
 /// service will get a different log file than a task with the same name.
+///
+/// Modules belonging to a project are namespaced within a directory named
+/// after the project (eg. `<log_dir>/<project>/<module>.service.log`).
+///
+/// Each run of a task is logged to its own file, in which case the log file
+/// of the last run is returned (see [task_run_log_paths]).
 pub fn log_file_path(
     module_name: &str,
     module_kind: &ModuleKind,
+    project: Option<&str>,
 ) -> Result<PathBuf> {
-    let base = default_log_directory()?;
+    let base = project_log_directory(project)?;
     let path = match module_kind {
-        ModuleKind::Task => base.join(format!("{}.task.log", module_name)),
+        ModuleKind::Task => {
+            match task_run_log_paths(module_name, project)?.pop() {
+                Some(last_run) => last_run,
+                None => base.join(format!("{}.task.log", module_name)),
+            }
+        }
         ModuleKind::Service => {
             base.join(format!("{}.service.log", module_name))
         }
@@ -40,10 +78,558 @@ pub fn log_file_path(
 /// Returns the log file path to use for the module.
 ///
 /// If the module has a custom path then that will be used instead. Otherwise
-/// the path is computed according to [log_file_path].
+/// services use the path computed according to [log_file_path], while tasks
+/// get a new log file for every run (eg. `<task>-20210828100000000.task.log`).
 pub fn log_file_module(module: &ModuleDefinition) -> Result<PathBuf> {
-    match &module.log_file_path {
-        Some(m) => Ok(PathBuf::from(&m)),
-        _ => log_file_path(&module.name, &module.kind),
+    match (&module.log_file_path, &module.kind) {
+        (Some(m), _) => Ok(PathBuf::from(&m)),
+        (None, ModuleKind::Task) => {
+            new_task_run_log_path(&module.name, module.project.as_deref())
+        }
+        (None, ModuleKind::Service) => {
+            log_file_path(&module.name, &module.kind, module.project.as_deref())
+        }
+    }
+}
+
+/// Returns the log files of the previous runs of a task, oldest first.
+pub fn task_run_log_paths(
+    task_name: &str,
+    project: Option<&str>,
+) -> Result<Vec<PathBuf>> {
+    let base = project_log_directory(project)?;
+    let prefix = format!("{}-", task_name);
+    let mut runs = Vec::new();
+    for entry in fs::read_dir(&base)? {
+        let path = entry?.path();
+        let name = match path.file_name() {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => continue,
+        };
+        let is_run = name
+            .strip_prefix(&prefix)
+            .and_then(|rest| rest.strip_suffix(".task.log"))
+            .map_or(false, |timestamp| {
+                timestamp.len() == 17
+                    && timestamp.chars().all(|c| c.is_ascii_digit())
+            });
+        if is_run {
+            runs.push(path);
+        }
+    }
+    runs.sort();
+    Ok(runs)
+}
+
+/// Returns the path of a new log file for a task run.
+///
+/// Log files of older runs are removed, keeping the last [TASK_RUNS_KEPT].
+fn new_task_run_log_path(
+    task_name: &str,
+    project: Option<&str>,
+) -> Result<PathBuf> {
+    let runs = task_run_log_paths(task_name, project)?;
+    for old_run in runs.iter().rev().skip(TASK_RUNS_KEPT - 1) {
+        fs::remove_file(old_run).with_context(|| {
+            format!("Failed to remove old task log {}", old_run.display())
+        })?;
+    }
+
+    let base = project_log_directory(project)?;
+    Ok(base.join(format!(
+        "{}-{}.task.log",
+        task_name,
+        Local::now().format(TASK_RUN_FORMAT)
+    )))
+}
+
+/// Returns the log directory of a project, creating it if it doesn't exist.
+fn project_log_directory(project: Option<&str>) -> Result<PathBuf> {
+    let mut base = log_directory()?;
+    if let Some(project) = project {
+        base.push(project);
+        fs::create_dir_all(base.as_path()).with_context(|| {
+            format!("Failed to create log dir {}", base.display())
+        })?;
+    }
+    Ok(base)
+}
+
+/// The extension of rotated log files compressed using gzip.
+pub const COMPRESSED_EXTENSION: &str = "gz";
+
+/// Returns the path a log file is rotated to at the given time.
+///
+/// Rotated segments are named after the log file followed by the rotation
+/// time, so that sorting them by name also sorts them chronologically (eg.
+/// `api.service.log.20210828100000`).
+pub fn rotated_log_path(log_file: &Path, time: DateTime<Local>) -> PathBuf {
+    let mut name = log_file.as_os_str().to_os_string();
+    name.push(format!(".{}", time.format("%Y%m%d%H%M%S")));
+    PathBuf::from(name)
+}
+
+/// Returns the rotated segments of a log file, oldest first.
+pub fn rotated_segments(log_file: &Path) -> Result<Vec<PathBuf>> {
+    let (dir, file_name) = match (log_file.parent(), log_file.file_name()) {
+        (Some(dir), Some(file_name)) => (dir, file_name.to_string_lossy()),
+        _ => return Ok(Vec::new()),
+    };
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let prefix = format!("{}.", file_name);
+    let mut segments = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = match path.file_name() {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => continue,
+        };
+        let suffix = match name.strip_prefix(&prefix) {
+            Some(suffix) => suffix,
+            None => continue,
+        };
+        let timestamp = suffix
+            .strip_suffix(&format!(".{}", COMPRESSED_EXTENSION))
+            .unwrap_or(suffix);
+        if timestamp.len() == 14
+            && timestamp.chars().all(|c| c.is_ascii_digit())
+        {
+            segments.push(path);
+        }
+    }
+    segments.sort();
+    Ok(segments)
+}
+
+/// Returns the last `lines` lines of a log file.
+///
+/// Only the end of the file is read, so very long lines may be cut short. A
+/// file which does not exist yields no lines.
+pub fn tail_log_file(path: &Path, lines: usize) -> Result<Vec<String>> {
+    let mut file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e.into()),
+    };
+
+    let len = file.metadata()?.len();
+    file.seek(SeekFrom::Start(len.saturating_sub(MAX_CHUNK_SIZE)))?;
+    let mut buffer = Vec::new();
+    file.read_to_end(&mut buffer)?;
+
+    let content = String::from_utf8_lossy(&buffer);
+    let all_lines: Vec<&str> = content.lines().collect();
+    Ok(all_lines[all_lines.len().saturating_sub(lines)..]
+        .iter()
+        .map(|line| line.to_string())
+        .collect())
+}
+
+/// A chunk of a log file.
+pub struct LogChunk {
+    pub data: String,
+    /// The offset to read the next chunk from.
+    pub offset: u64,
+}
+
+/// Reads the log file starting at `offset`.
+///
+/// Used to follow a log file while it is being written to. If the file was
+/// truncated since the last read, reading restarts from the beginning. A file
+/// which does not exist yet, or was last modified before `modified_after`
+/// (epoch milliseconds), yields an empty chunk. The latter avoids returning
+/// the output of a previous run before the file gets truncated by a new one.
+pub fn read_log_chunk(
+    path: &Path,
+    offset: u64,
+    modified_after: Option<u64>,
+) -> Result<LogChunk> {
+    let empty = LogChunk {
+        data: String::new(),
+        offset,
+    };
+
+    let mut file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(empty),
+        Err(e) => return Err(e.into()),
+    };
+
+    let metadata = file.metadata()?;
+    if let Some(modified_after) = modified_after {
+        let modified = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        if modified < modified_after {
+            return Ok(empty);
+        }
+    }
+
+    let offset = if metadata.len() < offset { 0 } else { offset };
+    file.seek(SeekFrom::Start(offset))?;
+
+    let mut buffer = Vec::new();
+    file.take(MAX_CHUNK_SIZE).read_to_end(&mut buffer)?;
+
+    Ok(LogChunk {
+        offset: offset + buffer.len() as u64,
+        data: String::from_utf8_lossy(&buffer).into_owned(),
+    })
+}
+
+/// Returns the size of a log file, to follow it from its end (see
+/// [read_log_chunk]). A file which does not exist yet has a size of 0.
+pub fn log_file_end(path: &Path) -> Result<u64> {
+    match fs::metadata(path) {
+        Ok(metadata) => Ok(metadata.len()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(0),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// A query for searching through a log file.
+pub struct LogQuery {
+    /// The regex that lines must match.
+    pub pattern: String,
+    /// Only include lines logged at or after this time (in epoch seconds).
+    pub since: Option<i64>,
+    /// Only include lines logged at or before this time (in epoch seconds).
+    pub until: Option<i64>,
+    /// The maximum number of lines to return, the newest being kept.
+    pub max_results: usize,
+}
+
+/// A single line matching a [LogQuery].
+pub struct LogMatch {
+    pub line_number: u64,
+    pub line: String,
+}
+
+/// The result of searching a log file.
+pub struct LogSearchResult {
+    pub matches: VecDeque<LogMatch>,
+    /// Whether more lines matched than `max_results`, in which case only the
+    /// newest are returned.
+    pub truncated: bool,
+}
+
+/// Searches a log file for lines matching the query.
+///
+/// Log files carry no timestamps of their own, so the time range is applied
+/// using a timestamp at the start of each line (eg. `2021-08-28T10:00:00Z` or
+/// `2021-08-28 10:00:00`), or the time field of JSON lines (eg. `time` or
+/// `ts`). Lines without one (such as stack traces) inherit the timestamp of the
+/// closest line above them that has one, and are left out if there is none.
+/// Lines are assumed to be in chronological order, so the search stops at the
+/// first line logged after `until`. Fails if a time range is given but no line
+/// has a timestamp, rather than matching nothing.
+pub fn search_log_file(
+    path: &Path,
+    query: &LogQuery,
+) -> Result<LogSearchResult> {
+    let matcher = RegexMatcher::new(&query.pattern).with_context(|| {
+        format!("Invalid search pattern '{}'", query.pattern)
+    })?;
+    let has_time_range = query.since.is_some() || query.until.is_some();
+
+    let mut sink = LogSearchSink {
+        query,
+        has_time_range,
+        last_timestamp: None,
+        lines: 0,
+        result: LogSearchResult {
+            matches: VecDeque::new(),
+            truncated: false,
+        },
+    };
+
+    // In passthru mode non-matching lines are also fed to the sink, which
+    // lets it keep track of timestamps.
+    SearcherBuilder::new()
+        .line_number(true)
+        .passthru(has_time_range)
+        .build()
+        .search_path(&matcher, path, &mut sink)
+        .with_context(|| format!("Failed to search log file {:?}", path))?;
+
+    if has_time_range && sink.lines > 0 && sink.last_timestamp.is_none() {
+        bail!(
+            "The log lines have no timestamps, so they cannot be filtered by \
+            time"
+        );
+    }
+    Ok(sink.result)
+}
+
+struct LogSearchSink<'q> {
+    query: &'q LogQuery,
+    has_time_range: bool,
+    last_timestamp: Option<i64>,
+    /// The number of lines seen.
+    lines: u64,
+    result: LogSearchResult,
+}
+
+impl<'q> LogSearchSink<'q> {
+    fn observe(&mut self, line: &str) {
+        self.lines += 1;
+        if let Some(timestamp) = parse_timestamp(line) {
+            self.last_timestamp = Some(timestamp);
+        }
+    }
+
+    fn past_until(&self) -> bool {
+        match (self.last_timestamp, self.query.until) {
+            (Some(ts), Some(until)) => ts > until,
+            _ => false,
+        }
+    }
+
+    fn in_range(&self) -> bool {
+        match self.last_timestamp {
+            Some(ts) => {
+                self.query.since.map_or(true, |since| ts >= since)
+                    && self.query.until.map_or(true, |until| ts <= until)
+            }
+            // Logged at an unknown time, before the first timestamp.
+            None => !self.has_time_range,
+        }
+    }
+}
+
+impl<'q> Sink for LogSearchSink<'q> {
+    type Error = io::Error;
+
+    fn matched(
+        &mut self,
+        _searcher: &Searcher,
+        mat: &SinkMatch<'_>,
+    ) -> Result<bool, io::Error> {
+        let line = String::from_utf8_lossy(mat.bytes()).trim_end().to_string();
+        self.observe(&line);
+
+        if self.past_until() {
+            return Ok(false);
+        }
+        if !self.in_range() {
+            return Ok(true);
+        }
+
+        if self.result.matches.len() >= self.query.max_results {
+            self.result.truncated = true;
+            if self.result.matches.pop_front().is_none() {
+                return Ok(true);
+            }
+        }
+
+        self.result.matches.push_back(LogMatch {
+            line_number: mat.line_number().unwrap_or(0),
+            line,
+        });
+        Ok(true)
+    }
+
+    fn context(
+        &mut self,
+        _searcher: &Searcher,
+        context: &SinkContext<'_>,
+    ) -> Result<bool, io::Error> {
+        self.observe(&String::from_utf8_lossy(context.bytes()));
+        Ok(!self.past_until())
+    }
+}
+
+/// Parses a timestamp at the start of a log line into epoch seconds.
+///
+/// Supports RFC 3339 timestamps and `YYYY-MM-DD HH:MM:SS` timestamps (which
+/// are assumed to be in local time), optionally wrapped in square brackets.
+/// The timestamp of a JSON line is taken from its time field instead.
+fn parse_timestamp(line: &str) -> Option<i64> {
+    let line = line.trim_start();
+    if line.starts_with('{') {
+        return parse_json_timestamp(line);
+    }
+    let line = line.trim_start_matches('[');
+    let first_token = line.split_whitespace().next()?.trim_end_matches(']');
+
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(first_token) {
+        return Some(datetime.timestamp());
+    }
+
+    let prefix = line.get(..19)?.replacen('T', " ", 1);
+    NaiveDateTime::parse_from_str(&prefix, "%Y-%m-%d %H:%M:%S")
+        .ok()
+        .and_then(|naive| Local.from_local_datetime(&naive).single())
+        .map(|datetime| datetime.timestamp())
+}
+
+/// Parses the time field of a JSON log line (see [TIME_KEYS]) into epoch
+/// seconds, either a timestamp or a number of seconds since the epoch.
+fn parse_json_timestamp(line: &str) -> Option<i64> {
+    let fields = serde_json::from_str::<Map<String, Value>>(line).ok()?;
+    match TIME_KEYS.iter().find_map(|key| fields.get(*key))? {
+        Value::String(time) if !time.starts_with('{') => parse_timestamp(time),
+        Value::Number(number) => {
+            let seconds = number.as_f64()?;
+            // Larger numbers are taken to be in milliseconds, as seconds
+            // they would be thousands of years away.
+            if seconds > 1e11 {
+                Some((seconds / 1000.0) as i64)
+            } else {
+                Some(seconds as i64)
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// 2021-08-28T10:00:00Z
+    const T0: i64 = 1630144800;
+
+    fn query(since: Option<i64>, until: Option<i64>) -> LogQuery {
+        LogQuery {
+            pattern: String::new(),
+            since,
+            until,
+            max_results: 1000,
+        }
+    }
+
+    fn search(
+        name: &str,
+        content: &str,
+        query: &LogQuery,
+    ) -> Result<Vec<String>> {
+        let path = std::env::temp_dir().join(format!(
+            "cartel-test-{}-{}.log",
+            name,
+            std::process::id()
+        ));
+        fs::write(&path, content)?;
+        let result = search_log_file(&path, query);
+        fs::remove_file(&path)?;
+        Ok(result?.matches.into_iter().map(|m| m.line).collect())
+    }
+
+    #[test]
+    fn test_parse_timestamp() {
+        assert_eq!(parse_timestamp("2021-08-28T10:00:00Z started"), Some(T0));
+        assert_eq!(parse_timestamp("[2021-08-28T10:00:00Z] started"), Some(T0));
+        assert_eq!(
+            parse_timestamp("2021-08-28T12:00:00+02:00 started"),
+            Some(T0)
+        );
+        let local = Local
+            .from_local_datetime(
+                &NaiveDateTime::parse_from_str(
+                    "2021-08-28 10:00:00",
+                    "%Y-%m-%d %H:%M:%S",
+                )
+                .unwrap(),
+            )
+            .single()
+            .map(|datetime| datetime.timestamp());
+        assert_eq!(parse_timestamp("2021-08-28 10:00:00,123 started"), local);
+        assert_eq!(parse_timestamp("  at Main.main(Main.java:1)"), None);
+        assert_eq!(parse_timestamp(""), None);
+    }
+
+    #[test]
+    fn test_parse_json_timestamp() {
+        assert_eq!(
+            parse_timestamp(r#"{"ts":"2021-08-28T10:00:00Z","msg":"started"}"#),
+            Some(T0)
+        );
+        assert_eq!(parse_timestamp(r#"{"time":1630144800}"#), Some(T0));
+        assert_eq!(parse_timestamp(r#"{"time":1630144800.5}"#), Some(T0));
+        assert_eq!(parse_timestamp(r#"{"timestamp":1630144800123}"#), Some(T0));
+        assert_eq!(parse_timestamp(r#"{"msg":"started"}"#), None);
+        assert_eq!(parse_timestamp(r#"{"time":"soon"}"#), None);
+        assert_eq!(parse_timestamp("{not json"), None);
+    }
+
+    #[test]
+    fn test_search_log_file_time_range() {
+        let content = "preamble\n\
+            2021-08-28T10:00:00Z first\n  at stack\n\
+            2021-08-28T10:05:00Z second\n  at trace\n\
+            2021-08-28T10:10:00Z third\n";
+
+        assert_eq!(
+            search("since", content, &query(Some(T0 + 60), None)).unwrap(),
+            vec![
+                "2021-08-28T10:05:00Z second",
+                "  at trace",
+                "2021-08-28T10:10:00Z third"
+            ]
+        );
+        // Lines before the first timestamp are left out.
+        assert_eq!(
+            search("until", content, &query(Some(T0), Some(T0 + 300))).unwrap(),
+            vec![
+                "2021-08-28T10:00:00Z first",
+                "  at stack",
+                "2021-08-28T10:05:00Z second",
+                "  at trace"
+            ]
+        );
+        assert_eq!(
+            search("all", content, &query(None, None)).unwrap().len(),
+            6
+        );
+    }
+
+    #[test]
+    fn test_search_log_file_json_time_range() {
+        let content = "{\"ts\":1630144800,\"msg\":\"first\"}\n\
+            {\"ts\":1630145100,\"msg\":\"second\"}\n";
+        assert_eq!(
+            search("json", content, &query(Some(T0 + 60), None)).unwrap(),
+            vec!["{\"ts\":1630145100,\"msg\":\"second\"}"]
+        );
+    }
+
+    #[test]
+    fn test_search_log_file_without_timestamps() {
+        let content = "Listening on :8080\nGET /\n";
+        assert!(search("none", content, &query(Some(T0), None)).is_err());
+        assert_eq!(
+            search("none-all", content, &query(None, None))
+                .unwrap()
+                .len(),
+            2
+        );
+        assert!(search("empty", "", &query(Some(T0), None))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_search_log_file_keeps_newest() {
+        let path = std::env::temp_dir()
+            .join(format!("cartel-test-newest-{}.log", std::process::id()));
+        fs::write(&path, "1\n2\n3\n4\n5\n").unwrap();
+        let result = search_log_file(
+            &path,
+            &LogQuery {
+                max_results: 2,
+                ..query(None, None)
+            },
+        );
+        fs::remove_file(&path).unwrap();
+        let result = result.unwrap();
+        assert!(result.truncated);
+        let lines: Vec<_> =
+            result.matches.into_iter().map(|m| m.line).collect();
+        assert_eq!(lines, vec!["4", "5"]);
     }
 }
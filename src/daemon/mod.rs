@@ -1,15 +1,31 @@
 pub mod api;
 pub mod cli;
 pub mod core;
+pub mod daemon_log;
+pub mod deploy_lock;
 pub mod env_grabber;
 pub mod error;
+pub mod events;
 pub mod executor;
+pub mod forward;
+pub mod handover;
 pub mod logs;
 pub mod module;
 pub mod monitor;
+pub mod panic;
 pub mod planner;
+pub mod ports;
+pub mod resources;
+pub mod retention;
+pub mod run_condition;
+pub mod sidecars;
 pub mod signal;
+pub mod stdin;
+pub mod supervisor;
+pub mod syslog;
+pub mod task_runs;
 pub mod time;
+pub mod tmpdir;
 
 pub use self::core::Core;
 pub use self::module::ModuleDefinition;
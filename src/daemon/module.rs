@@ -1,11 +1,15 @@
-use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
 use std::hash::{Hash, Hasher};
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 
 use crate::daemon::planner::Monitor;
+use serde::{Deserialize, Serialize};
 
 /// The type of the module.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub enum ModuleKind {
     /// A task is a module with a limited lifetime, used to perform some
     /// temporary operation or some setup.
@@ -18,7 +22,7 @@ pub enum ModuleKind {
 /// The choice of terminating signal to use when terminating the process.
 ///
 /// Note: Only implemented for Unix based systems.
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub enum TermSignal {
     /// Translates to SIGKILL on Unix based systems.
     KILL,
@@ -28,17 +32,102 @@ pub enum TermSignal {
     INT,
 }
 
-#[derive(Debug)]
+/// Where the output of a module is sent to.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub enum LogSink {
+    /// The log file of the module.
+    File,
+    /// The system log (syslog / journald).
+    Syslog,
+    /// Both the log file and the system log.
+    Both,
+}
+
+/// Tools whose activation wrapper a module is spawned through.
+#[derive(Debug, PartialEq, Clone, Default, Hash, Serialize, Deserialize)]
+pub struct Tooling {
+    pub direnv: bool,
+    pub mise: bool,
+    /// A `.nix` file (optionally followed by `#<attr>`) or a flake reference.
+    pub nix_shell: Option<String>,
+}
+
+/// How often a task exiting with a failure is retried, and how long to wait
+/// between attempts.
+#[derive(Debug, Clone, Default)]
+pub struct RetryPolicy {
+    pub retries: u32,
+    pub delay: Duration,
+}
+
+/// How often a task runs, when deployed repeatedly.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum OncePer {
+    /// The task runs every time it is deployed.
+    Always,
+    /// The task runs once per deploy, however many modules depend on it.
+    Deploy,
+    /// The task runs once per (local) day.
+    Day,
+}
+
+impl Default for OncePer {
+    fn default() -> Self {
+        Self::Always
+    }
+}
+
+/// Guards against running a task which has nothing to do.
+#[derive(Debug, Clone, Default)]
+pub struct RunCondition {
+    /// A command, the task is skipped if it exits with zero.
+    pub command: Option<Vec<String>>,
+    /// Patterns of files (relative to the working directory), the task is
+    /// skipped if none of them changed since it last succeeded.
+    pub files_changed: Vec<String>,
+}
+
+/// How a task is run, beyond what its definition describes.
+#[derive(Debug, Clone, Default)]
+pub struct TaskOptions {
+    pub retry_policy: RetryPolicy,
+    pub run_if: Option<RunCondition>,
+    /// How long each attempt may run for before the task is killed.
+    pub timeout: Option<Duration>,
+    pub once_per: OncePer,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
 pub struct ModuleDefinition {
     pub kind: ModuleKind,
     pub name: String,
     pub command: Vec<String>,
     pub environment: HashMap<String, String>,
     pub log_file_path: Option<String>,
+    pub log_sink: LogSink,
     pub dependencies: Vec<String>,
     pub working_dir: Option<PathBuf>,
+    pub tooling: Tooling,
     pub termination_signal: TermSignal,
     pub liveness_probe: Option<Monitor>,
+    /// The project the module belongs to (if any).
+    pub project: Option<String>,
+    /// Tasks run alongside a service while it runs, started once it is
+    /// ready.
+    pub sidecars: Vec<Arc<ModuleDefinition>>,
+    /// Whether the service reads its input from a terminal the daemon keeps
+    /// open, which clients can write to.
+    pub interactive: bool,
+    /// Whether the service gets a temp directory of its own, exported as
+    /// `CARTEL_TMPDIR` and removed when the service stops.
+    pub tmpdir: bool,
+    /// The names of the environment variables whose values are secrets, so
+    /// that clients can mask them (eg. when comparing environments).
+    pub masked_env: HashSet<String>,
+    /// Whether the module is spawned with the environment of a login shell
+    /// (see [env_grabber](crate::daemon::env_grabber)), overriding
+    /// `daemon.use_env_grabber`.
+    pub use_login_env: Option<bool>,
 }
 
 impl Hash for ModuleDefinition {
@@ -61,10 +150,13 @@ impl ModuleDefinition {
         command: Vec<String>,
         environment: HashMap<String, String>,
         log_file_path: Option<String>,
+        log_sink: LogSink,
         dependencies: Vec<String>,
         working_dir: Option<PathBuf>,
+        tooling: Tooling,
         termination_signal: TermSignal,
         liveness_probe: Option<Monitor>,
+        project: Option<String>,
     ) -> ModuleDefinition {
         ModuleDefinition {
             kind,
@@ -72,10 +164,92 @@ impl ModuleDefinition {
             command,
             environment,
             log_file_path,
+            log_sink,
             dependencies,
             working_dir,
+            tooling,
             termination_signal,
             liveness_probe,
+            project,
+            sidecars: vec![],
+            interactive: false,
+            tmpdir: false,
+            masked_env: HashSet::new(),
+            use_login_env: None,
+        }
+    }
+
+    /// Returns a hash of the parts of the definition which affect how the
+    /// module runs. A deployed module with a different hash than that of its
+    /// new definition is out of date.
+    pub fn content_hash(&self) -> String {
+        let mut hasher = DefaultHasher::new();
+        self.command.hash(&mut hasher);
+        let mut environment: Vec<_> = self.environment.iter().collect();
+        environment.sort();
+        environment.hash(&mut hasher);
+        self.log_file_path.hash(&mut hasher);
+        self.working_dir.hash(&mut hasher);
+        self.tooling.hash(&mut hasher);
+        self.interactive.hash(&mut hasher);
+        self.tmpdir.hash(&mut hasher);
+        self.use_login_env.hash(&mut hasher);
+        for sidecar in &self.sidecars {
+            sidecar.name.hash(&mut hasher);
+            sidecar.content_hash().hash(&mut hasher);
+        }
+        format!("{:016x}", hasher.finish())
+    }
+
+    /// Returns the command to spawn the module with.
+    ///
+    /// The command is wrapped in the activation commands of the enabled
+    /// tooling, with `direnv` applied outermost so that the `.envrc` may
+    /// configure `nix` and `mise`.
+    pub fn spawn_command(&self) -> Vec<String> {
+        let mut command = Vec::new();
+        if self.tooling.direnv {
+            let dir = self
+                .working_dir
+                .as_ref()
+                .map(|dir| dir.display().to_string())
+                .unwrap_or_else(|| String::from("."));
+            command.extend(vec![
+                String::from("direnv"),
+                String::from("exec"),
+                dir,
+            ]);
+        }
+        if let Some(nix_shell) = &self.tooling.nix_shell {
+            command.extend(nix_develop_command(nix_shell));
+        }
+        if self.tooling.mise {
+            command.extend(vec![
+                String::from("mise"),
+                String::from("exec"),
+                String::from("--"),
+            ]);
+        }
+        command.extend(self.command.iter().cloned());
+        command
+    }
+}
+
+/// Returns the `nix develop` command line to run a command in a nix shell.
+///
+/// Shells ending in `.nix` are evaluated as a file (with an optional
+/// attribute after `#`), anything else as a flake reference.
+fn nix_develop_command(nix_shell: &str) -> Vec<String> {
+    let mut command = vec![String::from("nix"), String::from("develop")];
+    match nix_shell.split_once('#') {
+        Some((file, attr)) if file.ends_with(".nix") => {
+            command.extend(vec![String::from("-f"), file.into(), attr.into()])
+        }
+        _ if nix_shell.ends_with(".nix") => {
+            command.extend(vec![String::from("-f"), nix_shell.into()])
         }
+        _ => command.push(nix_shell.into()),
     }
+    command.push(String::from("-c"));
+    command
 }
@@ -1,13 +1,18 @@
+use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-#[derive(Debug, Clone)]
+/// How often readiness monitors are checked.
+pub const READINESS_POLL_INTERVAL: Duration = Duration::from_secs(4);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum MonitorTask {
     Executable(ExecMonitor),
     LogLine(LogLineMonitor),
     Net(NetMonitor),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Monitor {
     /// The number of retries before the monitor is considered failed.
     pub retries: u32,
@@ -16,13 +21,21 @@ pub struct Monitor {
     pub task: MonitorTask,
 }
 
-#[derive(Debug, Clone)]
+impl Monitor {
+    /// Returns how long a readiness monitor may take before its retries are
+    /// exceeded, doubled to allow for slow checks and a busy monitor runtime.
+    pub fn readiness_timeout(&self) -> Duration {
+        READINESS_POLL_INTERVAL * 2 * (self.retries + 1)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExecMonitor {
     pub command: Vec<String>,
     pub working_dir: Option<String>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NetMonitor {
     pub hostname: String,
     pub port: u16,
@@ -43,7 +56,7 @@ impl ExecMonitor {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LogLineMonitor {
     pub line_regex: String,
     pub file_path: PathBuf,
@@ -5,4 +5,4 @@ mod state;
 
 pub use self::commands::*;
 pub use self::runtime::*;
-pub use self::state::{MonitorState, MonitorStatus};
+pub use self::state::{MonitorResult, MonitorState, MonitorStatus};
@@ -1,14 +1,21 @@
 use crate::daemon::error::DaemonError;
 use crate::daemon::monitor::commands::*;
-use crate::daemon::monitor::state::{MonitorState, MonitorStatus};
+use crate::daemon::monitor::state::{
+    MonitorResult, MonitorState, MonitorStatus,
+};
+use crate::daemon::panic::panic_message;
 use crate::daemon::time::epoch_now;
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
 use grep_matcher::Matcher;
 use grep_regex::RegexMatcher;
 use grep_searcher::sinks::UTF8;
 use grep_searcher::Searcher;
-use log::{debug, info};
+use log::{debug, error, info};
+use nix::sys::signal::Signal;
 use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io::ErrorKind;
+use std::os::unix::process::ExitStatusExt;
 use std::process::Stdio;
 use std::sync::Arc;
 use std::time::Duration;
@@ -17,29 +24,47 @@ use tokio::process;
 use tokio::sync::mpsc;
 use tokio::time::timeout;
 
-pub(super) async fn readiness_poll_tickr(tx: mpsc::Sender<MonitorCommand>) {
-    let mut interval =
-        tokio::time::interval(tokio::time::Duration::from_secs(4));
+/// The outcome of a single check of a monitor.
+#[derive(Debug)]
+enum PollOutcome {
+    Passed,
+    /// The check did not pass, with a short reason why.
+    Failed(String),
+}
+
+pub(super) async fn readiness_poll_tickr(
+    tx: mpsc::Sender<MonitorCommand>,
+    monitor_state: Arc<MonitorState>,
+) {
+    let mut interval = tokio::time::interval(READINESS_POLL_INTERVAL);
     loop {
         interval.tick().await;
+        monitor_state.command_queued();
         tx.send(MonitorCommand::PollReadinessCheck)
             .await
             .expect("Failed to transmit to monitor runtime");
     }
 }
 
-pub(super) async fn liveness_poll_tickr(tx: mpsc::Sender<MonitorCommand>) {
+pub(super) async fn liveness_poll_tickr(
+    tx: mpsc::Sender<MonitorCommand>,
+    monitor_state: Arc<MonitorState>,
+) {
     let mut interval =
         tokio::time::interval(tokio::time::Duration::from_secs(5));
     loop {
         interval.tick().await;
+        monitor_state.command_queued();
         tx.send(MonitorCommand::PollLivenessCheck)
             .await
             .expect("Failed to transmit to monitor runtime");
     }
 }
 
-pub(super) async fn cleanup_tickr(tx: mpsc::Sender<MonitorCommand>) {
+pub(super) async fn cleanup_tickr(
+    tx: mpsc::Sender<MonitorCommand>,
+    monitor_state: Arc<MonitorState>,
+) {
     let mut interval =
         tokio::time::interval(tokio::time::Duration::from_secs(300));
     // Skip the first tick as it is instant
@@ -47,6 +72,7 @@ pub(super) async fn cleanup_tickr(tx: mpsc::Sender<MonitorCommand>) {
 
     loop {
         interval.tick().await;
+        monitor_state.command_queued();
         tx.send(MonitorCommand::CleanupIdleMonitors)
             .await
             .expect("Failed to transmit to monitor runtime");
@@ -64,6 +90,7 @@ pub(super) async fn channel_rx(
     let mut attempt_count: HashMap<String, u32> = HashMap::new();
 
     while let Some(message) = rx.recv().await {
+        monitor_state.command_received();
         match message {
             MonitorCommand::NewMonitor {
                 key,
@@ -151,41 +178,50 @@ pub(super) async fn channel_rx(
                 });
             }
         }
+        monitor_state.set_active_monitors(
+            readiness_monitor_list.len() + liveness_monitor_list.len(),
+        );
     }
 }
 
 async fn poll_readiness_check(
     monitor_list: &mut Vec<(String, Monitor)>,
     attempt_count: &mut HashMap<String, u32>,
-) -> Vec<(String, MonitorStatus)> {
+) -> Vec<(String, MonitorResult)> {
     let poll_results = poll_monitors(monitor_list).await;
-    let mut status: Vec<(String, MonitorStatus)> = Vec::new();
+    let mut status: Vec<(String, MonitorResult)> = Vec::new();
 
     for (idx, (key, result)) in poll_results.into_iter().enumerate().rev() {
         let retries = monitor_list[idx].1.retries;
         let attempts = *attempt_count.entry(key.to_string()).or_insert(1);
-        let is_error = result.is_err();
-        let poll_successful = result.unwrap_or(false);
 
-        if is_error {
-            // If the poll errored remove and set status to error
-            monitor_list.swap_remove(idx);
-            attempt_count.remove_entry(&key);
-            status.push((key, MonitorStatus::Error));
-        } else if poll_successful {
-            // If the poll succeeded remove and set status to error
-            monitor_list.swap_remove(idx);
-            attempt_count.remove_entry(&key);
-            status.push((key, MonitorStatus::Successful));
-        } else if attempts >= retries {
-            // If it failed too many times remove and update status
-            monitor_list.swap_remove(idx);
-            attempt_count.remove_entry(&key);
-            status.push((key, MonitorStatus::RetriesExceeded));
-        } else {
-            // If it failed we want to track how many times it's failed
-            *attempt_count.get_mut(&key).unwrap() += 1;
-            status.push((key, MonitorStatus::Pending));
+        match result {
+            Err(e) => {
+                // If the poll errored remove and set status to error
+                monitor_list.swap_remove(idx);
+                attempt_count.remove_entry(&key);
+                status.push((key, result_of(MonitorStatus::Error, e)));
+            }
+            Ok(PollOutcome::Passed) => {
+                // If the poll succeeded remove and set status to successful
+                monitor_list.swap_remove(idx);
+                attempt_count.remove_entry(&key);
+                status.push((key, passed()));
+            }
+            Ok(PollOutcome::Failed(reason)) if attempts >= retries => {
+                // If it failed too many times remove and update status
+                monitor_list.swap_remove(idx);
+                attempt_count.remove_entry(&key);
+                status.push((
+                    key,
+                    result_of(MonitorStatus::RetriesExceeded, reason),
+                ));
+            }
+            Ok(PollOutcome::Failed(reason)) => {
+                // If it failed we want to track how many times it's failed
+                *attempt_count.get_mut(&key).unwrap() += 1;
+                status.push((key, result_of(MonitorStatus::Pending, reason)));
+            }
         }
     }
     status
@@ -193,59 +229,94 @@ async fn poll_readiness_check(
 
 async fn poll_liveness_check(
     monitor_list: &mut Vec<(String, Monitor)>,
-) -> Vec<(String, MonitorStatus)> {
+) -> Vec<(String, MonitorResult)> {
     let poll_results = poll_monitors(monitor_list).await;
-    let mut status: Vec<(String, MonitorStatus)> = Vec::new();
+    let mut status: Vec<(String, MonitorResult)> = Vec::new();
 
     for (idx, (key, result)) in poll_results.into_iter().enumerate().rev() {
-        let is_error = result.is_err();
-        let poll_successful = result.unwrap_or(false);
-
-        if is_error {
-            // If the poll errored remove and set status to error
-            monitor_list.swap_remove(idx);
-            status.push((key, MonitorStatus::Error));
-        } else if !poll_successful {
-            // If the poll failed set its status to failing
-            status.push((key, MonitorStatus::Failing));
-        } else {
-            // Otherwise set it as successful
-            status.push((key, MonitorStatus::Successful));
+        match result {
+            Err(e) => {
+                // If the poll errored remove and set status to error
+                monitor_list.swap_remove(idx);
+                status.push((key, result_of(MonitorStatus::Error, e)));
+            }
+            Ok(PollOutcome::Failed(reason)) => {
+                // If the poll failed set its status to failing
+                status.push((key, result_of(MonitorStatus::Failing, reason)));
+            }
+            Ok(PollOutcome::Passed) => {
+                // Otherwise set it as successful
+                status.push((key, passed()));
+            }
         }
     }
     status
 }
 
+fn passed() -> MonitorResult {
+    MonitorResult {
+        status: MonitorStatus::Successful,
+        reason: None,
+    }
+}
+
+fn result_of<R: std::fmt::Display>(
+    status: MonitorStatus,
+    reason: R,
+) -> MonitorResult {
+    MonitorResult {
+        status,
+        // Include the causes of errors, eg. why a command failed to run.
+        reason: Some(format!("{:#}", reason)),
+    }
+}
+
 async fn poll_monitors(
     monitors: &[(String, Monitor)],
-) -> Vec<(String, Result<bool>)> {
+) -> Vec<(String, Result<PollOutcome>)> {
     let mut results = vec![];
     for (key, monitor) in monitors {
-        match &monitor.task {
-            MonitorTask::Executable(exe_monitor) => {
-                debug!("Polling exe monitor: {}", key);
-                let result = poll_exe_monitor(exe_monitor).await;
-                debug!("Exe monitor result: {:?}", result);
-                results.push((key.to_string(), result));
+        // Each poll runs in a task of its own, so that if it panics the
+        // monitor errors instead of the panic taking down the runtime.
+        let task = monitor.task.clone();
+        let result = match tokio::spawn(poll_monitor(key.clone(), task)).await {
+            Ok(result) => result,
+            Err(e) if e.is_panic() => {
+                let payload = e.into_panic();
+                error!("Monitor {} panicked", key);
+                Err(anyhow!("panicked: {}", panic_message(payload.as_ref())))
             }
-            MonitorTask::LogLine(log_line_monitor) => {
-                debug!("Polling log line monitor: {}", key);
-                let result = poll_log_line_monitor(log_line_monitor).await;
-                debug!("Log line monitor result: {:?}", result);
-                results.push((key.to_string(), result));
-            }
-            MonitorTask::Net(net_monitor) => {
-                debug!("Polling net monitor: {}", key);
-                let result = poll_net_monitor(net_monitor).await;
-                debug!("Net monitor result: {:?}", result);
-                results.push((key.to_string(), result));
-            }
-        }
+            Err(e) => Err(e.into()),
+        };
+        results.push((key.to_string(), result));
     }
     results
 }
 
-async fn poll_exe_monitor(exe_monitor: &ExecMonitor) -> Result<bool> {
+async fn poll_monitor(key: String, task: MonitorTask) -> Result<PollOutcome> {
+    match &task {
+        MonitorTask::Executable(exe_monitor) => {
+            debug!("Polling exe monitor: {}", key);
+            let result = poll_exe_monitor(exe_monitor).await;
+            debug!("Exe monitor result: {:?}", result);
+            result
+        }
+        MonitorTask::LogLine(log_line_monitor) => {
+            debug!("Polling log line monitor: {}", key);
+            let result = poll_log_line_monitor(log_line_monitor).await;
+            debug!("Log line monitor result: {:?}", result);
+            result
+        }
+        MonitorTask::Net(net_monitor) => {
+            debug!("Polling net monitor: {}", key);
+            let result = poll_net_monitor(net_monitor).await;
+            debug!("Net monitor result: {:?}", result);
+            result
+        }
+    }
+}
+
+async fn poll_exe_monitor(exe_monitor: &ExecMonitor) -> Result<PollOutcome> {
     let (head, tail) = exe_monitor
         .command
         .split_first()
@@ -255,11 +326,23 @@ async fn poll_exe_monitor(exe_monitor: &ExecMonitor) -> Result<bool> {
         .args(tail)
         .stdout(Stdio::null())
         .stderr(Stdio::null())
-        .spawn()?;
+        .spawn()
+        .with_context(|| format!("failed to run {}", head))?;
 
     let child_wait_fut = child.wait();
     match timeout(Duration::from_millis(2000), child_wait_fut).await {
-        Ok(Ok(exit_status)) => Ok(exit_status.success()),
+        Ok(Ok(exit_status)) if exit_status.success() => Ok(PollOutcome::Passed),
+        Ok(Ok(exit_status)) => {
+            let reason = match (exit_status.code(), exit_status.signal()) {
+                (Some(code), _) => format!("exited with code {}", code),
+                (None, Some(signal)) => match Signal::try_from(signal) {
+                    Ok(signal) => format!("killed by {}", signal.as_str()),
+                    Err(_) => format!("killed by signal {}", signal),
+                },
+                (None, None) => String::from("exited"),
+            };
+            Ok(PollOutcome::Failed(reason))
+        }
         Ok(Err(e)) => Err(e.into()),
         Err(_) => Err(DaemonError::ExecProbeTimeout.into()),
     }
@@ -267,10 +350,12 @@ async fn poll_exe_monitor(exe_monitor: &ExecMonitor) -> Result<bool> {
 
 async fn poll_log_line_monitor(
     log_line_monitor: &LogLineMonitor,
-) -> Result<bool> {
+) -> Result<PollOutcome> {
     // TODO: Share the Searcher / RegexMatcher if expensive
-    let matcher =
-        RegexMatcher::new(&log_line_monitor.line_regex).expect("Invalid regex");
+    let matcher = RegexMatcher::new(&log_line_monitor.line_regex)
+        .with_context(|| {
+            format!("invalid regex /{}/", log_line_monitor.line_regex)
+        })?;
     let mut found = false;
 
     Searcher::new().search_path(
@@ -287,19 +372,33 @@ async fn poll_log_line_monitor(
         }),
     )?;
 
-    Ok(found)
+    if found {
+        Ok(PollOutcome::Passed)
+    } else {
+        Ok(PollOutcome::Failed(format!(
+            "no line matching /{}/ in the log",
+            log_line_monitor.line_regex
+        )))
+    }
 }
 
-async fn poll_net_monitor(net_monitor: &NetMonitor) -> Result<bool> {
-    let conn_fut = TcpStream::connect(format!(
-        "{}:{}",
-        net_monitor.hostname, net_monitor.port
-    ));
+async fn poll_net_monitor(net_monitor: &NetMonitor) -> Result<PollOutcome> {
+    let address = format!("{}:{}", net_monitor.hostname, net_monitor.port);
+    let conn_fut = TcpStream::connect(&address);
 
-    let result = match timeout(Duration::from_millis(100), conn_fut).await {
-        Ok(future) => future.is_ok(),
-        Err(_) => false,
+    let outcome = match timeout(Duration::from_millis(100), conn_fut).await {
+        Ok(Ok(_)) => PollOutcome::Passed,
+        Ok(Err(e)) if e.kind() == ErrorKind::ConnectionRefused => {
+            PollOutcome::Failed(format!("connection refused to {}", address))
+        }
+        Ok(Err(e)) => PollOutcome::Failed(format!(
+            "could not connect to {}: {}",
+            address, e
+        )),
+        Err(_) => {
+            PollOutcome::Failed(format!("timed out connecting to {}", address))
+        }
     };
 
-    Ok(result)
+    Ok(outcome)
 }
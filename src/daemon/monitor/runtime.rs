@@ -2,12 +2,15 @@ use crate::daemon::monitor::commands::*;
 use crate::daemon::monitor::poll::{
     channel_rx, cleanup_tickr, liveness_poll_tickr, readiness_poll_tickr,
 };
-use crate::daemon::monitor::state::{MonitorState, MonitorStatus};
+use crate::daemon::monitor::state::{
+    MonitorResult, MonitorState, MonitorStatus,
+};
 use anyhow::Result;
 use log::info;
 use std::collections::HashMap;
 use std::sync::Arc;
 use std::thread;
+use std::time::Duration;
 use tokio::runtime::{self, Handle, Runtime};
 use tokio::sync::mpsc;
 
@@ -40,6 +43,7 @@ impl MonitorHandle {
         monitor_type: MonitorType,
     ) {
         let tx = self.producer.clone();
+        self.monitor_state.command_queued();
         self.runtime_handle.spawn(async move {
             let cmd = MonitorCommand::NewMonitor {
                 key: name,
@@ -54,6 +58,7 @@ impl MonitorHandle {
 
     pub fn remove_monitor(&self, key: String, monitor_type: MonitorType) {
         let tx = self.producer.clone();
+        self.monitor_state.command_queued();
         self.runtime_handle.spawn(async move {
             let cmd = MonitorCommand::RemoveMonitor { key, monitor_type };
             tx.send(cmd)
@@ -66,8 +71,33 @@ impl MonitorHandle {
         self.monitor_state.monitor_status(monitor_name)
     }
 
-    pub fn monitor_statuses(&self) -> HashMap<String, MonitorStatus> {
-        self.monitor_state.monitor_statuses()
+    pub fn monitor_result(&self, monitor_name: &str) -> Option<MonitorResult> {
+        self.monitor_state.monitor_result(monitor_name)
+    }
+
+    pub fn monitor_results(&self) -> HashMap<String, MonitorResult> {
+        self.monitor_state.monitor_results()
+    }
+
+    /// The number of commands (eg. to poll the monitors) waiting to be
+    /// handled by the monitor runtime.
+    pub fn queued_commands(&self) -> usize {
+        self.monitor_state.queued_commands()
+    }
+
+    /// The number of readiness and liveness monitors being polled.
+    pub fn active_monitors(&self) -> usize {
+        self.monitor_state.active_monitors()
+    }
+
+    /// Whether the monitor runtime runs a task spawned on it within
+    /// `timeout`.
+    pub fn is_runtime_alive(&self, timeout: Duration) -> bool {
+        let (tx, rx) = std::sync::mpsc::channel();
+        self.runtime_handle.spawn(async move {
+            let _ = tx.send(());
+        });
+        rx.recv_timeout(timeout).is_ok()
     }
 }
 
@@ -100,6 +130,17 @@ pub fn monitor_key(name: &str, monitor_type: &MonitorType) -> String {
     }
 }
 
+/// Returns the name of the module a monitor key (see [monitor_key]) was
+/// created for.
+pub fn monitor_module_name(monitor_key: &str) -> Option<&str> {
+    let without_type = monitor_key
+        .strip_suffix("-liveness")
+        .or_else(|| monitor_key.strip_suffix("-readiness"))?;
+    // Strip the hyphenated UUID and the hyphen before it.
+    let uuid_len = uuid::adapter::Hyphenated::LENGTH + 1;
+    without_type.get(..without_type.len().checked_sub(uuid_len)?)
+}
+
 pub fn spawn_runtime(monitor_state: Arc<MonitorState>) -> MonitorHandle {
     let (tx, rx) = mpsc::channel::<MonitorCommand>(32);
     let tx_readiness = tx.clone();
@@ -107,6 +148,9 @@ pub fn spawn_runtime(monitor_state: Arc<MonitorState>) -> MonitorHandle {
     let tx_cleanup = tx.clone();
     let (handle_tx, handle_rx) = std::sync::mpsc::channel();
     let mst = Arc::clone(&monitor_state);
+    let mst_readiness = Arc::clone(&monitor_state);
+    let mst_liveness = Arc::clone(&monitor_state);
+    let mst_cleanup = Arc::clone(&monitor_state);
 
     thread::spawn(move || {
         let runtime = setup_runtime().expect("Unable to create the runtime");
@@ -118,11 +162,16 @@ pub fn spawn_runtime(monitor_state: Arc<MonitorState>) -> MonitorHandle {
             .expect("Unable to give runtime handle to main thread");
 
         // Spawn the ticking task for scanning of readiness monitors
-        runtime.spawn(async move { readiness_poll_tickr(tx_readiness).await });
+        runtime.spawn(async move {
+            readiness_poll_tickr(tx_readiness, mst_readiness).await
+        });
         // Spawn the ticking task for scanning of liveness monitors
-        runtime.spawn(async move { liveness_poll_tickr(tx_liveness).await });
+        runtime.spawn(async move {
+            liveness_poll_tickr(tx_liveness, mst_liveness).await
+        });
         // Spawn the ticking task for cleanup of idle monitors
-        runtime.spawn(async move { cleanup_tickr(tx_cleanup).await });
+        runtime
+            .spawn(async move { cleanup_tickr(tx_cleanup, mst_cleanup).await });
 
         // Continue running until notified to shutdown
         runtime.block_on(async { channel_rx(rx, mst).await });
@@ -1,5 +1,6 @@
 use parking_lot::Mutex;
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 #[derive(Copy, Clone, Debug)]
 pub enum MonitorStatus {
@@ -10,8 +11,22 @@ pub enum MonitorStatus {
     Failing = 0x5,
 }
 
+/// The status of a monitor, along with why its last check did not pass.
+#[derive(Clone, Debug)]
+pub struct MonitorResult {
+    pub status: MonitorStatus,
+    /// A short human readable reason (eg. the exit code of the probe
+    /// command), if the last check failed or errored.
+    pub reason: Option<String>,
+}
+
 pub struct MonitorState {
-    monitor_map: Mutex<HashMap<String, MonitorStatus>>,
+    monitor_map: Mutex<HashMap<String, MonitorResult>>,
+    /// The number of commands sent to the monitor runtime which it has not
+    /// received yet.
+    queued_commands: AtomicUsize,
+    /// The number of readiness and liveness monitors being polled.
+    active_monitors: AtomicUsize,
 }
 
 impl MonitorState {
@@ -20,21 +35,29 @@ impl MonitorState {
         monitor_name: &str,
     ) -> Option<MonitorStatus> {
         let map = self.monitor_map.lock();
-        map.get(monitor_name).copied()
+        map.get(monitor_name).map(|result| result.status)
     }
 
-    pub(super) fn monitor_statuses(&self) -> HashMap<String, MonitorStatus> {
+    pub(super) fn monitor_result(
+        &self,
+        monitor_name: &str,
+    ) -> Option<MonitorResult> {
+        let map = self.monitor_map.lock();
+        map.get(monitor_name).cloned()
+    }
+
+    pub(super) fn monitor_results(&self) -> HashMap<String, MonitorResult> {
         let map = self.monitor_map.lock();
         map.clone()
     }
 
     pub(super) fn update_states(
         &self,
-        new_states: Vec<(String, MonitorStatus)>,
+        new_states: Vec<(String, MonitorResult)>,
     ) {
         let mut map = self.monitor_map.lock();
-        new_states.into_iter().for_each(|(monitor, is_done)| {
-            map.insert(monitor, is_done);
+        new_states.into_iter().for_each(|(monitor, result)| {
+            map.insert(monitor, result);
         });
     }
 
@@ -45,9 +68,31 @@ impl MonitorState {
         });
     }
 
+    pub(super) fn command_queued(&self) {
+        self.queued_commands.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(super) fn command_received(&self) {
+        self.queued_commands.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub(super) fn queued_commands(&self) -> usize {
+        self.queued_commands.load(Ordering::Relaxed)
+    }
+
+    pub(super) fn set_active_monitors(&self, count: usize) {
+        self.active_monitors.store(count, Ordering::Relaxed);
+    }
+
+    pub(super) fn active_monitors(&self) -> usize {
+        self.active_monitors.load(Ordering::Relaxed)
+    }
+
     pub fn new() -> MonitorState {
         MonitorState {
             monitor_map: Mutex::new(HashMap::new()),
+            queued_commands: AtomicUsize::new(0),
+            active_monitors: AtomicUsize::new(0),
         }
     }
 }
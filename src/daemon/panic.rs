@@ -0,0 +1,13 @@
+use std::any::Any;
+
+/// Returns the message a thread or task panicked with, given the payload of
+/// the panic.
+pub fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown cause"
+    }
+}
@@ -4,23 +4,41 @@ use crate::daemon::error::DaemonError;
 use crate::daemon::executor::{
     task_executor, Executor, ExecutorConfig, ModuleStatus, RunStatus,
 };
-use crate::daemon::logs::log_file_path;
-use crate::daemon::module::{ModuleDefinition, ModuleKind};
+use crate::daemon::forward::Forward;
+use crate::daemon::handover::{self, HandedOverModule};
+use crate::daemon::logs::{
+    log_file_end, log_file_path, read_log_chunk, search_log_file,
+    tail_log_file, task_run_log_paths, LogChunk, LogQuery, LogSearchResult,
+};
+use crate::daemon::module::{
+    ModuleDefinition, ModuleKind, OncePer, TaskOptions,
+};
 use crate::daemon::monitor::{monitor_key, MonitorType};
-pub use crate::daemon::monitor::{Monitor, MonitorHandle, MonitorStatus};
-use anyhow::Result;
+pub use crate::daemon::monitor::{
+    Monitor, MonitorHandle, MonitorResult, MonitorStatus,
+};
+use crate::daemon::run_condition::FileHashes;
+use crate::daemon::task_runs::{TaskHistory, TaskLocks, TaskOutcome};
+use anyhow::{bail, Result};
+use log::info;
 use parking_lot::{Mutex, MutexGuard};
 use std::collections::{HashMap, HashSet};
 use std::ffi::OsString;
+use std::io::Write;
 use std::iter::FromIterator;
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
 pub struct Planner {
-    // This effectively serializes deployments, status reads etc. Since we are
-    // at most expecting one client interacting with the daemon this lock is not
-    // expected to be contended. The complexity and overhead of other lock-free
-    // implementations doesn't feel worth it here.
+    // Holds the state of the modules. It is only held briefly, and not while
+    // waiting for a module to stop, so that status reads and deploys of other
+    // modules are not blocked by a service which is slow to shut down.
     executor: Mutex<Executor>,
+    // Serializes the operations on each module (eg. a deploy and a stop), as
+    // the executor is not held for their whole duration.
+    module_locks: ModuleLocks,
     // A cloneable handle with which we can interact with the tokio runtime and
     // spawn new monitors.
     monitor_handle: MonitorHandle,
@@ -29,6 +47,30 @@ pub struct Planner {
     env_holder: Arc<CurrentEnvHolder>,
     // A shared reference to the executors config.
     executor_config: Arc<ExecutorConfig>,
+    // The services whose definition changed in the last deploy, which are
+    // rolled back by `rollback --last-deploy`.
+    last_deploy: Mutex<Option<LastDeploy>>,
+    // The latest executions of tasks.
+    task_history: TaskHistory,
+    // The hashes of the files watched by tasks, as of their last success.
+    file_hashes: FileHashes,
+    task_locks: TaskLocks,
+}
+
+struct LastDeploy {
+    id: String,
+    modules: Vec<String>,
+}
+
+#[derive(Default)]
+struct ModuleLocks {
+    locks: Mutex<HashMap<String, Arc<Mutex<()>>>>,
+}
+
+impl ModuleLocks {
+    fn get(&self, mod_name: &str) -> Arc<Mutex<()>> {
+        Arc::clone(self.locks.lock().entry(mod_name.to_string()).or_default())
+    }
 }
 
 pub struct PsStatus {
@@ -36,8 +78,21 @@ pub struct PsStatus {
     pub pid: u32,
     pub status: RunStatus,
     pub liveness_status: Option<MonitorStatus>,
+    /// Why the liveness probe last failed, if it did.
+    pub liveness_reason: Option<String>,
+    /// The status of the readiness probe of the last deploy, while it is
+    /// known.
+    pub readiness_status: Option<MonitorStatus>,
     pub exit_code: Option<i32>,
+    pub exit_signal: Option<i32>,
+    pub restarts: u32,
     pub time_since_status: u64,
+    /// When the last run of the module was started.
+    pub started_at: Option<u64>,
+    /// When the last run of the module exited (or was stopped).
+    pub exited_at: Option<u64>,
+    /// The status of the tasks run alongside the module.
+    pub sidecars: Vec<PsStatus>,
 }
 
 pub enum PlannedAction {
@@ -48,6 +103,15 @@ pub enum PlannedAction {
 
 pub struct Plan {
     pub plan: HashMap<String, PlannedAction>,
+    /// The content hashes (see [ModuleDefinition::content_hash]) of each
+    /// service's new definition and of the definition it is deployed with.
+    pub hashes: HashMap<String, DefinitionHashes>,
+}
+
+pub struct DefinitionHashes {
+    pub requested: String,
+    /// None if the service was never deployed.
+    pub deployed: Option<String>,
 }
 
 impl Planner {
@@ -65,9 +129,14 @@ impl Planner {
                 Arc::clone(&env_holder),
                 Arc::clone(&executor_config),
             )),
+            module_locks: ModuleLocks::default(),
             monitor_handle,
             env_holder,
             executor_config,
+            last_deploy: Mutex::new(None),
+            task_history: TaskHistory::default(),
+            file_hashes: FileHashes::default(),
+            task_locks: TaskLocks::default(),
         }
     }
 
@@ -80,36 +149,77 @@ impl Planner {
     /// # Arguments
     /// * `module_def` - The module definition of the module.
     /// * `force` - Force the module to deploy.
+    /// * `deployment_id` - Identifies the deploy the module is part of, which
+    ///   is recorded to be rolled back if the module's definition changed.
     pub fn deploy(
         &self,
         module_def: ModuleDefinition,
         force: bool,
+        deployment_id: Option<&str>,
     ) -> Result<bool> {
-        let mut executor = self.executor();
-        let existing = executor.module_status_by_name(&module_def.name);
+        let lock = self.module_locks.get(&module_def.name);
+        let _guard = lock.lock();
+        let redeploy = {
+            let executor = self.executor();
+            executor.module_status_by_name(&module_def.name).map(
+                |module_status| {
+                    // Collect the liveness status to decide whether to restart
+                    let liveness_status = match module_status.monitor_key {
+                        Some(ref key) => self.monitor_status(key),
+                        None => None,
+                    };
+                    let changed =
+                        module_status.module_definition.content_hash()
+                            != module_def.content_hash();
+                    let redeploy = Self::should_redeploy(
+                        &module_def,
+                        module_status,
+                        liveness_status,
+                    ) || force;
+                    (redeploy, changed)
+                },
+            )
+        };
 
-        match existing {
-            Some(module_status) => {
-                // Collect the liveness status to decide whether to restart
-                let liveness_status = match module_status.monitor_key {
-                    Some(ref key) => self.monitor_status(key),
-                    None => None,
-                };
+        match redeploy {
+            Some((true, changed)) => {
+                let name = module_def.name.clone();
+                self.redeploy_module(Arc::new(module_def))?;
+                if let (true, Some(id)) = (changed, deployment_id) {
+                    self.record_deploy(id, name);
+                }
+                Ok(true)
+            }
+            Some((false, _)) => Ok(false),
+            None => self
+                .executor()
+                .run_module(Arc::new(module_def))
+                .map(|_| true),
+        }
+    }
 
-                if Self::should_redeploy(
-                    &module_def,
-                    module_status,
-                    liveness_status,
-                ) || force
-                {
-                    executor.redeploy_module(Arc::new(module_def))?;
-                    Ok(true)
-                } else {
-                    Ok(false)
+    /// Rolls back the given modules to the definitions they were deployed
+    /// with before their current ones.
+    ///
+    /// If no modules are given, the services whose definition changed in the
+    /// last deploy are rolled back. Returns the names of the modules rolled
+    /// back.
+    pub fn rollback(&self, mod_names: &[String]) -> Result<Vec<String>> {
+        let mod_names = if mod_names.is_empty() {
+            match &*self.last_deploy.lock() {
+                Some(last_deploy) => last_deploy.modules.clone(),
+                None => {
+                    bail!("No deploy changed any service, nothing to roll back")
                 }
             }
-            None => executor.run_module(Arc::new(module_def)).map(|_| true),
+        } else {
+            mod_names.to_vec()
+        };
+
+        for mod_name in &mod_names {
+            self.rollback_module(mod_name)?;
         }
+        Ok(mod_names)
     }
 
     /// Deploys one or more modules (modules already in the correct state do not
@@ -128,7 +238,7 @@ impl Planner {
         Self::deployment_set(module_defs, selection)?
             .map(|module_def| {
                 let name = module_def.name.clone();
-                let result = self.deploy(module_def, false)?;
+                let result = self.deploy(module_def, false, None)?;
                 Ok((name, result))
             })
             .collect()
@@ -137,42 +247,280 @@ impl Planner {
     pub fn deploy_task(
         &self,
         task_definition: &ModuleDefinition,
-    ) -> Result<i32> {
+        options: &TaskOptions,
+        deployment_id: Option<&str>,
+    ) -> Result<TaskOutcome> {
+        // Deploys running the task at the same time wait for each other, so
+        // that the task runs only once.
+        let lock = match options.once_per {
+            OncePer::Always => None,
+            _ => Some(self.task_locks.get(task_definition)),
+        };
+        let _guard = lock.as_ref().map(|lock| lock.lock());
+        if let Some(reason) = self.task_history.ran_already(
+            task_definition,
+            options.once_per,
+            deployment_id,
+        ) {
+            info!("Skipping task {} ({})", task_definition.name, reason);
+            return Ok(TaskOutcome::Skipped(reason));
+        }
         task_executor::execute_task(
             task_definition,
+            options,
+            deployment_id,
+            &self.task_history,
+            &self.file_hashes,
             &self.executor_config,
             Arc::clone(&self.env_holder),
         )
-        .map(|exit_status| exit_status.code().unwrap_or(-1))
+    }
+
+    /// Returns the history of task executions.
+    pub fn task_history(&self) -> &TaskHistory {
+        &self.task_history
     }
 
     /// Restarts an existing module.
     ///
     /// The module could either be running, stopped or exited and the module
-    /// definition of the last attempted deploy will be used.
+    /// definition of the last attempted deploy will be used. The readiness
+    /// probe of the last deploy is checked again, and the sidecars are left
+    /// to be started once it passes (see [start_sidecars_when_ready]).
+    ///
+    /// [start_sidecars_when_ready]: crate::daemon::sidecars::start_sidecars_when_ready
     pub fn restart_module(&self, mod_name: &str) -> Result<()> {
-        self.executor().restart_module(mod_name)
+        info!("Restarting module: {}", mod_name);
+        let lock = self.module_locks.get(mod_name);
+        let _guard = lock.lock();
+        let (existing, readiness_probe) = self
+            .executor()
+            .module_status_by_name(mod_name)
+            .map(|m| {
+                (Arc::clone(&m.module_definition), m.readiness_probe.clone())
+            })
+            .ok_or_else(|| DaemonError::NotFound(mod_name.to_string()))?;
+        self.stop(mod_name)?;
+        self.executor().run_module(existing)?;
+        if let Some(probe) = readiness_probe {
+            self.create_monitor(mod_name, probe, MonitorType::Readiness);
+        }
+        Ok(())
+    }
+
+    /// Returns whether a module has sidecars.
+    pub fn has_sidecars(&self, mod_name: &str) -> bool {
+        self.executor()
+            .module_status_by_name(mod_name)
+            .map_or(false, |m| !m.module_definition.sidecars.is_empty())
+    }
+
+    /// Returns the key of the readiness monitor of the last deploy of a
+    /// service and how long it may take to pass, if it has a readiness probe.
+    pub fn readiness(&self, mod_name: &str) -> Option<(String, Duration)> {
+        let executor = self.executor();
+        let module = executor.module_status_by_name(mod_name)?;
+        let key = module.readiness_key.clone()?;
+        let probe = module.readiness_probe.as_ref()?;
+        Some((key, probe.readiness_timeout()))
+    }
+
+    /// Starts the sidecars of a running service.
+    pub fn start_sidecars(&self, mod_name: &str) -> Result<()> {
+        let lock = self.module_locks.get(mod_name);
+        let _guard = lock.lock();
+        self.executor().start_sidecars(mod_name)
+    }
+
+    /// Forwards a port for a running service, until the service stops.
+    ///
+    /// Returns the name of the process forwarding the port.
+    pub fn start_forward(
+        &self,
+        mod_name: &str,
+        forward: &Forward,
+    ) -> Result<String> {
+        let lock = self.module_locks.get(mod_name);
+        let _guard = lock.lock();
+        let mut executor = self.executor();
+        let service = executor
+            .module_status_by_name(mod_name)
+            .map(|m| Arc::clone(&m.module_definition))
+            .ok_or_else(|| DaemonError::NotFound(mod_name.to_string()))?;
+        let definition = Arc::new(forward.definition(&service));
+        executor.start_forward(mod_name, definition)?;
+        Ok(forward.name())
+    }
+
+    /// Returns the exit code and last lines of output of a sidecar of a
+    /// module, if the sidecar exited.
+    pub fn sidecar_exit(
+        &self,
+        mod_name: &str,
+        sidecar_name: &str,
+        lines: usize,
+    ) -> Option<(i32, Vec<String>)> {
+        let executor = self.executor();
+        let sidecar = executor
+            .module_status_by_name(mod_name)?
+            .sidecars
+            .iter()
+            .find(|s| s.module_definition.name == sidecar_name)
+            .filter(|s| s.status == RunStatus::EXITED)?;
+        let code = sidecar.exit_status.and_then(|s| s.code()).unwrap_or(-1);
+        let log_tail = tail_log_file(Path::new(&sidecar.log_file_path), lines)
+            .unwrap_or_default();
+        Some((code, log_tail))
+    }
+
+    /// Returns the environment a running module was spawned with.
+    pub fn module_environment(
+        &self,
+        mod_name: &str,
+    ) -> Result<(HashMap<String, String>, Arc<ModuleDefinition>)> {
+        self.executor().environment(mod_name)
+    }
+
+    /// Writes to the input of a running interactive service.
+    pub fn write_stdin(&self, mod_name: &str, input: &[u8]) -> Result<()> {
+        // The executor is not held while writing, which blocks until the
+        // service reads its input if the terminal is full.
+        let mut stdin = self.executor().stdin(mod_name)?;
+        stdin.write_all(input)?;
+        Ok(())
     }
 
     /// Stops a running module.
     pub fn stop_module(&self, mod_name: &str) -> Result<()> {
-        self.executor().stop_module(mod_name)
+        let lock = self.module_locks.get(mod_name);
+        let _guard = lock.lock();
+        self.stop(mod_name)
+    }
+
+    /// Stops a list of running modules in the order given.
+    ///
+    /// Modules are stopped one after the other, each being waited on before
+    /// stopping the next one. This lets the caller control teardown order (eg.
+    /// stopping dependents before their dependencies).
+    pub fn stop_many(&self, mod_names: &[String]) -> Result<()> {
+        for mod_name in mod_names {
+            self.stop_module(mod_name)?;
+        }
+        Ok(())
     }
 
     /// Returns the log path of a module.
+    ///
+    /// The project is only used for tasks, as the log path of a service is
+    /// known from its last deployment.
     pub fn log_path(
         &self,
         module_name: &str,
         module_kind: &ModuleKind,
+        project: Option<&str>,
     ) -> Result<OsString> {
         match module_kind {
             ModuleKind::Service => self.log_path_running(module_name),
             ModuleKind::Task => {
-                Ok(log_file_path(module_name, module_kind)?.into_os_string())
+                Ok(log_file_path(module_name, module_kind, project)?
+                    .into_os_string())
             }
         }
     }
 
+    /// Returns the log path of a previous run of a task.
+    ///
+    /// Runs are counted backwards, `1` being the last run of the task.
+    pub fn task_run_log_path(
+        &self,
+        task_name: &str,
+        project: Option<&str>,
+        run: usize,
+    ) -> Result<OsString> {
+        let mut runs = task_run_log_paths(task_name, project)?;
+        let index = runs.len().checked_sub(run).filter(|_| run > 0);
+        match index {
+            Some(index) => Ok(runs.swap_remove(index).into_os_string()),
+            None => bail!(
+                "Task {} has {} logged run(s), cannot show run {}",
+                task_name,
+                runs.len(),
+                run
+            ),
+        }
+    }
+
+    /// Searches the log file of a module for lines matching the query.
+    ///
+    /// A custom `log_file_path` takes precedence over the path the daemon
+    /// would otherwise use for the module.
+    pub fn search_logs(
+        &self,
+        module_name: &str,
+        module_kind: &ModuleKind,
+        project: Option<&str>,
+        log_file_path: Option<&str>,
+        query: &LogQuery,
+    ) -> Result<LogSearchResult> {
+        let path = match log_file_path {
+            Some(path) => OsString::from(path),
+            None => self.log_path(module_name, module_kind, project)?,
+        };
+        search_log_file(Path::new(&path), query)
+    }
+
+    /// Reads a chunk of the log file of a module, starting at `offset`.
+    ///
+    /// With `from_end` nothing is read, and the offset of the end of the file
+    /// is returned instead.
+    #[allow(clippy::too_many_arguments)]
+    pub fn read_logs(
+        &self,
+        module_name: &str,
+        module_kind: &ModuleKind,
+        project: Option<&str>,
+        log_file_path: Option<&str>,
+        offset: u64,
+        modified_after: Option<u64>,
+        from_end: bool,
+    ) -> Result<LogChunk> {
+        let path = match log_file_path {
+            Some(path) => OsString::from(path),
+            None => self.log_path(module_name, module_kind, project)?,
+        };
+        if from_end {
+            return Ok(LogChunk {
+                data: String::new(),
+                offset: log_file_end(Path::new(&path))?,
+            });
+        }
+        read_log_chunk(Path::new(&path), offset, modified_after)
+    }
+
+    /// Returns the last `lines` lines of the log file of a deployed module.
+    pub fn log_tail(&self, module_name: &str, lines: usize) -> Vec<String> {
+        self.log_path_running(module_name)
+            .and_then(|path| tail_log_file(Path::new(&path), lines))
+            .unwrap_or_default()
+    }
+
+    /// Returns the exit code of a module if it exited on its own.
+    ///
+    /// Modules stopped by the user, or still running, yield `None`.
+    pub fn exit_code(&self, module_name: &str) -> Option<i32> {
+        self.executor()
+            .module_status_by_name(module_name)
+            .filter(|m| m.status == RunStatus::EXITED)
+            .map(|m| m.exit_status.and_then(|s| s.code()).unwrap_or(-1))
+    }
+
+    /// Returns whether a module is running.
+    pub fn is_running(&self, module_name: &str) -> bool {
+        self.executor()
+            .module_status_by_name(module_name)
+            .map_or(false, |m| m.status == RunStatus::RUNNING)
+    }
+
     /// Returns the log path of a running module.
     pub fn log_path_running(&self, module_name: &str) -> Result<OsString> {
         let executor = self.executor();
@@ -184,26 +532,35 @@ impl Planner {
             .map(|m| m.log_file_path.clone())
     }
 
+    /// Returns the log files of all running modules.
+    pub fn log_files_in_use(&self) -> HashSet<PathBuf> {
+        self.executor()
+            .modules()
+            .flat_map(|m| std::iter::once(m).chain(m.sidecars.iter()))
+            .filter(|m| m.status == RunStatus::RUNNING)
+            .map(|m| PathBuf::from(&m.log_file_path))
+            .collect()
+    }
+
     /// Returns a summarized version of each modules status.
     pub fn module_status(&self) -> Vec<PsStatus> {
-        let mut statuses = self.monitor_handle.monitor_statuses();
+        let mut results = self.monitor_handle.monitor_results();
         self.executor()
             .modules()
-            .map(|m| PsStatus {
-                name: m.module_definition.name.clone(),
-                pid: m.pid,
-                status: m.status.clone(),
-                liveness_status: match &m.monitor_key {
-                    Some(key) => statuses.remove(key),
+            .map(|m| {
+                let liveness = match &m.monitor_key {
+                    Some(key) => results.remove(key),
                     None => None,
-                },
-                exit_code: m.exit_status.and_then(|e| e.code()),
-                time_since_status: match m.status {
-                    RunStatus::RUNNING => m.uptime,
-                    RunStatus::STOPPED => m.exit_time,
-                    RunStatus::EXITED => m.exit_time,
-                    RunStatus::WAITING => 0,
-                },
+                };
+                let readiness = m
+                    .readiness_key
+                    .as_ref()
+                    .and_then(|key| results.get(key))
+                    .map(|r| r.status);
+                PsStatus {
+                    readiness_status: readiness,
+                    ..ps_status(m, liveness)
+                }
             })
             .collect()
     }
@@ -264,14 +621,41 @@ impl Planner {
 
     /// Performs cleanup (by killing all running children).
     pub fn cleanup(&self) -> Result<()> {
-        self.executor().cleanup()
+        let mod_names = self.executor().running_module_names();
+        self.stop_many(&mod_names)
     }
 
     /// Stops all running services.
     pub fn stop_all(&self) -> Result<()> {
         // Currently uses cleanup, but having this as a separate function since
         // it may change in the future.
-        self.executor().cleanup()
+        self.cleanup()
+    }
+
+    /// Checks whether the modules can be handed over to a daemon replacing
+    /// this one (see [handover](crate::daemon::handover)).
+    pub fn check_handover(&self) -> Result<()> {
+        self.executor().check_handover()
+    }
+
+    /// Replaces the daemon with the given executable, handing the state of
+    /// the modules over to it.
+    ///
+    /// Only returns (with the reason why) if that failed.
+    pub fn exec_handover(&self, executable: &Path) -> anyhow::Error {
+        // Held until the process is replaced, so that nothing changes after
+        // the state was handed over.
+        let executor = self.executor();
+        match executor.handover() {
+            Ok(modules) => handover::exec(executable, &modules),
+            Err(e) => e,
+        }
+    }
+
+    /// Takes over the modules handed over by the daemon this one replaced.
+    pub fn adopt(&self, modules: Vec<HandedOverModule>) {
+        self.executor().adopt(modules);
+        self.collect_dead();
     }
 
     /// Creates a monitor and returns it.
@@ -288,6 +672,13 @@ impl Planner {
         monitor_type: MonitorType,
     ) -> String {
         let monitor_key = monitor_key(name, &monitor_type);
+        if matches!(monitor_type, MonitorType::Readiness) {
+            self.executor().set_readiness(
+                name,
+                monitor_key.clone(),
+                monitor.clone(),
+            );
+        }
         self.monitor_handle.new_monitor(
             monitor_key.clone(),
             monitor,
@@ -309,6 +700,25 @@ impl Planner {
         self.monitor_handle.monitor_status(monitor_name)
     }
 
+    /// Get the status of the monitor with the provided name, along with why
+    /// its last check failed (if it did).
+    pub fn monitor_result(&self, monitor_name: &str) -> Option<MonitorResult> {
+        self.monitor_handle.monitor_result(monitor_name)
+    }
+
+    /// Returns the monitor runtime, eg. to inspect its health.
+    pub fn monitor_handle(&self) -> &MonitorHandle {
+        &self.monitor_handle
+    }
+
+    /// Returns the number of modules tracked by the executor, or None if it
+    /// stayed busy for longer than `timeout`.
+    pub fn tracked_module_count(&self, timeout: Duration) -> Option<usize> {
+        self.executor
+            .try_lock_for(timeout)
+            .map(|executor| executor.modules().count())
+    }
+
     /// Returns the daemons plan (whether it will deploy the given services or
     /// not).
     ///
@@ -318,8 +728,34 @@ impl Planner {
     pub fn get_plan(&self, modules_to_deploy: &[ModuleDefinition]) -> Plan {
         Plan {
             plan: self.get_deployment_actions(modules_to_deploy),
+            hashes: self.definition_hashes(modules_to_deploy),
         }
     }
+
+    /// Returns the content hash of the given definition of each module, along
+    /// with that of the definition it is currently deployed with.
+    pub fn definition_hashes(
+        &self,
+        modules: &[ModuleDefinition],
+    ) -> HashMap<String, DefinitionHashes> {
+        let executor = self.executor();
+        let module_names: Vec<_> =
+            modules.iter().map(|m| m.name.as_str()).collect();
+        let module_statuses = executor.module_statuses_by_names(&module_names);
+
+        modules
+            .iter()
+            .zip(module_statuses)
+            .map(|(module, status)| {
+                let hashes = DefinitionHashes {
+                    requested: module.content_hash(),
+                    deployed: status
+                        .map(|s| s.module_definition.content_hash()),
+                };
+                (module.name.clone(), hashes)
+            })
+            .collect()
+    }
 }
 
 impl Planner {
@@ -327,6 +763,70 @@ impl Planner {
         self.executor.lock()
     }
 
+    /// Stops a module, waiting for it to stop without holding the executor.
+    ///
+    /// The lock of the module is expected to be held.
+    fn stop(&self, mod_name: &str) -> Result<()> {
+        let stopping = self.executor().stop_module(mod_name)?;
+        stopping.wait()
+    }
+
+    /// Redeploys a module with a newer module definition.
+    ///
+    /// The lock of the module is expected to be held.
+    fn redeploy_module(&self, module: Arc<ModuleDefinition>) -> Result<()> {
+        info!("Redeploying module: {}", module.name);
+        self.stop(&module.name)?;
+        self.executor().run_module(module)
+    }
+
+    /// Redeploys a module with the definition it was deployed with before its
+    /// current one.
+    ///
+    /// The current definition becomes the previous one, so rolling back twice
+    /// returns to it. Like [Planner::restart_module], the sidecars are left to
+    /// be started once the readiness probe passes.
+    fn rollback_module(&self, mod_name: &str) -> Result<()> {
+        info!("Rolling back module: {}", mod_name);
+        let lock = self.module_locks.get(mod_name);
+        let _guard = lock.lock();
+        let (previous, readiness_probe) = {
+            let executor = self.executor();
+            let module = executor
+                .module_status_by_name(mod_name)
+                .ok_or_else(|| DaemonError::NotFound(mod_name.to_string()))?;
+            let previous =
+                module.previous_definition.clone().ok_or_else(|| {
+                    DaemonError::NoPreviousDefinition(mod_name.to_string())
+                })?;
+            (previous, module.readiness_probe.clone())
+        };
+        self.redeploy_module(previous)?;
+        if let Some(probe) = readiness_probe {
+            self.create_monitor(mod_name, probe, MonitorType::Readiness);
+        }
+        Ok(())
+    }
+
+    /// Records a service whose definition changed as part of a deploy.
+    ///
+    /// Deploys which change no definitions are not recorded, so that they
+    /// don't replace the last deploy which can be rolled back.
+    fn record_deploy(&self, deployment_id: &str, mod_name: String) {
+        let mut last_deploy = self.last_deploy.lock();
+        match &mut *last_deploy {
+            Some(last) if last.id == deployment_id => {
+                last.modules.push(mod_name)
+            }
+            _ => {
+                *last_deploy = Some(LastDeploy {
+                    id: deployment_id.to_string(),
+                    modules: vec![mod_name],
+                })
+            }
+        }
+    }
+
     fn should_redeploy(
         module_def: &ModuleDefinition,
         module_status: &ModuleStatus,
@@ -341,10 +841,7 @@ impl Planner {
         }
 
         let current = module_status.module_definition.as_ref();
-        current.command != module_def.command
-            || current.environment != module_def.environment
-            || current.log_file_path != module_def.log_file_path
-            || current.working_dir != module_def.working_dir
+        current.content_hash() != module_def.content_hash()
     }
 
     fn deployment_set(
@@ -369,3 +866,32 @@ impl Planner {
             .filter(move |m| selection_set.contains(&m.name)))
     }
 }
+
+fn ps_status(m: &ModuleStatus, liveness: Option<MonitorResult>) -> PsStatus {
+    PsStatus {
+        name: m.module_definition.name.clone(),
+        pid: m.pid,
+        status: m.status.clone(),
+        liveness_status: liveness.as_ref().map(|l| l.status),
+        liveness_reason: liveness.and_then(|l| l.reason),
+        readiness_status: None,
+        exit_code: m.exit_status.and_then(|e| e.code()),
+        exit_signal: m.exit_status.and_then(|e| e.signal()),
+        restarts: m.restarts,
+        time_since_status: match m.status {
+            RunStatus::RUNNING => m.uptime,
+            RunStatus::STOPPED => m.exit_time,
+            RunStatus::EXITED => m.exit_time,
+            RunStatus::WAITING => 0,
+        },
+        started_at: match m.status {
+            RunStatus::WAITING => None,
+            _ => Some(m.uptime),
+        },
+        exited_at: match m.status {
+            RunStatus::STOPPED | RunStatus::EXITED => Some(m.exit_time),
+            _ => None,
+        },
+        sidecars: m.sidecars.iter().map(|s| ps_status(s, None)).collect(),
+    }
+}
@@ -0,0 +1,109 @@
+use anyhow::Result;
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+/// Returns the TCP ports each of the given process groups is listening on.
+///
+/// Ports are looked up on demand, as the processes of a group may open and
+/// close sockets at any time.
+#[cfg(target_os = "linux")]
+pub fn listening_ports(
+    pgids: &HashSet<u32>,
+) -> Result<HashMap<u32, BTreeSet<u16>>> {
+    use std::fs;
+
+    // The inodes of listening sockets, and the port each is bound to.
+    let mut sockets = HashMap::new();
+    for table in &["/proc/net/tcp", "/proc/net/tcp6"] {
+        let table = match fs::read_to_string(table) {
+            Ok(table) => table,
+            Err(_) => continue,
+        };
+        for line in table.lines().skip(1) {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            // local_address (ip:port in hex), st (0A is LISTEN) and inode.
+            if fields.len() < 10 || fields[3] != "0A" {
+                continue;
+            }
+            let port = fields[1]
+                .rsplit(':')
+                .next()
+                .and_then(|port| u16::from_str_radix(port, 16).ok());
+            if let (Some(port), Ok(inode)) = (port, fields[9].parse::<u64>()) {
+                sockets.insert(inode, port);
+            }
+        }
+    }
+
+    let mut ports: HashMap<u32, BTreeSet<u16>> = HashMap::new();
+    for entry in fs::read_dir("/proc")?.flatten() {
+        // Processes may exit while being read, which is fine to skip.
+        let stat = match fs::read_to_string(entry.path().join("stat")) {
+            Ok(stat) => stat,
+            Err(_) => continue,
+        };
+        let pgid = stat
+            .rfind(')')
+            .and_then(|idx| stat[idx + 1..].split_whitespace().nth(2))
+            .and_then(|pgid| pgid.parse::<u32>().ok());
+        let pgid = match pgid {
+            Some(pgid) if pgids.contains(&pgid) => pgid,
+            _ => continue,
+        };
+        let fds = match fs::read_dir(entry.path().join("fd")) {
+            Ok(fds) => fds,
+            Err(_) => continue,
+        };
+        for fd in fds.flatten() {
+            let inode = fs::read_link(fd.path()).ok().and_then(|link| {
+                link.to_str()?
+                    .strip_prefix("socket:[")?
+                    .strip_suffix(']')?
+                    .parse::<u64>()
+                    .ok()
+            });
+            if let Some(port) = inode.and_then(|inode| sockets.get(&inode)) {
+                ports.entry(pgid).or_default().insert(*port);
+            }
+        }
+    }
+    Ok(ports)
+}
+
+/// Returns the TCP ports each of the given process groups is listening on.
+///
+/// Ports are looked up on demand using `lsof`, as the processes of a group
+/// may open and close sockets at any time.
+#[cfg(not(target_os = "linux"))]
+pub fn listening_ports(
+    pgids: &HashSet<u32>,
+) -> Result<HashMap<u32, BTreeSet<u16>>> {
+    use std::process::Command;
+
+    // With -F lsof prints a line per field, prefixed with the field name:
+    // `p` (pid), `g` (pgid, listed due to -g) and `n` (the address, eg.
+    // *:8080).
+    let output = Command::new("lsof")
+        .args(["-nP", "-g", "-iTCP", "-sTCP:LISTEN", "-Fpgn"])
+        .output()?;
+    let mut ports: HashMap<u32, BTreeSet<u16>> = HashMap::new();
+    let mut pgid = None;
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        match line.split_at(line.len().min(1)) {
+            ("p", _) => pgid = None,
+            ("g", value) => pgid = value.parse::<u32>().ok(),
+            ("n", address) => {
+                let port = address
+                    .rsplit(':')
+                    .next()
+                    .and_then(|port| port.parse::<u16>().ok());
+                if let (Some(pgid), Some(port)) = (pgid, port) {
+                    if pgids.contains(&pgid) {
+                        ports.entry(pgid).or_default().insert(port);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(ports)
+}
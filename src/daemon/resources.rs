@@ -0,0 +1,177 @@
+use crate::daemon::executor::RunStatus;
+use crate::daemon::Core;
+use anyhow::Result;
+use log::{debug, info};
+use parking_lot::RwLock;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How often the resource usage of running modules is sampled.
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The resources used by the process group of a module.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ResourceUsage {
+    /// The resident set size (in bytes) of all processes in the group.
+    pub rss_bytes: u64,
+    /// The CPU used by all processes in the group since the previous sample,
+    /// as a percentage of one core.
+    pub cpu_percent: f32,
+}
+
+/// Holds the latest resource usage of each process group, keyed by pgid.
+#[derive(Default)]
+pub struct ResourceUsageHolder {
+    usage: RwLock<HashMap<u32, ResourceUsage>>,
+}
+
+impl ResourceUsageHolder {
+    pub fn get(&self, pgid: u32) -> Option<ResourceUsage> {
+        self.usage.read().get(&pgid).copied()
+    }
+
+    fn replace(&self, usage: HashMap<u32, ResourceUsage>) {
+        *self.usage.write() = usage;
+    }
+}
+
+/// Launches a thread which periodically samples the memory and CPU used by
+/// the process group of each running module.
+///
+/// Modules run in their own process group (with a pgid equal to their pid),
+/// so the usage of any processes they spawn is included.
+pub fn resource_sampler_thread(core: Arc<Core>) {
+    info!("Starting resource sampler thread");
+    std::thread::spawn(move || {
+        let mut sampler = Sampler::default();
+        loop {
+            let pgids = core
+                .planner()
+                .module_status()
+                .into_iter()
+                .flat_map(|mut m| {
+                    let sidecars = std::mem::take(&mut m.sidecars);
+                    std::iter::once(m).chain(sidecars)
+                })
+                .filter(|m| m.status == RunStatus::RUNNING)
+                .map(|m| m.pid)
+                .collect::<HashSet<_>>();
+            if !pgids.is_empty() {
+                match sampler.sample(&pgids) {
+                    Ok(usage) => core.resources.replace(usage),
+                    Err(e) => {
+                        debug!("Failed to sample resource usage: {:?}", e)
+                    }
+                }
+            }
+            std::thread::sleep(SAMPLE_INTERVAL);
+        }
+    });
+}
+
+#[derive(Default)]
+struct Sampler {
+    /// The total CPU time (in seconds) of each process group at the previous
+    /// sample.
+    #[cfg(target_os = "linux")]
+    previous: Option<(std::time::Instant, HashMap<u32, f64>)>,
+}
+
+#[cfg(target_os = "linux")]
+impl Sampler {
+    /// Reads the usage of each process in `/proc`, and sums it up per group.
+    fn sample(
+        &mut self,
+        pgids: &HashSet<u32>,
+    ) -> Result<HashMap<u32, ResourceUsage>> {
+        use nix::unistd::{sysconf, SysconfVar};
+
+        let ticks_per_sec = sysconf(SysconfVar::CLK_TCK)?.unwrap_or(100) as f64;
+        let page_size = sysconf(SysconfVar::PAGE_SIZE)?.unwrap_or(4096) as u64;
+        let now = std::time::Instant::now();
+
+        let mut rss = HashMap::new();
+        let mut cpu_time = HashMap::new();
+        for entry in std::fs::read_dir("/proc")?.flatten() {
+            // Processes may exit while being read, which is fine to skip.
+            let stat = match std::fs::read_to_string(entry.path().join("stat"))
+            {
+                Ok(stat) => stat,
+                Err(_) => continue,
+            };
+            // The command name may contain spaces, so fields are counted
+            // from the closing parenthesis after it.
+            let fields: Vec<&str> = match stat.rfind(')') {
+                Some(idx) => stat[idx + 1..].split_whitespace().collect(),
+                None => continue,
+            };
+            let field = |idx: usize| -> u64 {
+                fields.get(idx).and_then(|f| f.parse().ok()).unwrap_or(0)
+            };
+            // pgrp, utime, stime and rss (fields 5, 14, 15 and 24).
+            let pgid = field(2) as u32;
+            if !pgids.contains(&pgid) {
+                continue;
+            }
+            *rss.entry(pgid).or_insert(0) += field(21) * page_size;
+            *cpu_time.entry(pgid).or_insert(0.0) +=
+                (field(11) + field(12)) as f64 / ticks_per_sec;
+        }
+
+        let usage = rss
+            .into_iter()
+            .map(|(pgid, rss_bytes)| {
+                let cpu_percent = match &self.previous {
+                    Some((at, previous)) => {
+                        let elapsed = now.duration_since(*at).as_secs_f64();
+                        let used = cpu_time[&pgid]
+                            - previous.get(&pgid).copied().unwrap_or(0.0);
+                        // Processes exiting lower the total of the group.
+                        (used.max(0.0) / elapsed * 100.0) as f32
+                    }
+                    None => 0.0,
+                };
+                let usage = ResourceUsage {
+                    rss_bytes,
+                    cpu_percent,
+                };
+                (pgid, usage)
+            })
+            .collect();
+        self.previous = Some((now, cpu_time));
+        Ok(usage)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+impl Sampler {
+    /// Reads the usage of each process using `ps`, and sums it up per group.
+    ///
+    /// The CPU usage reported by `ps` is a decaying average over the last
+    /// minute rather than since the previous sample.
+    fn sample(
+        &mut self,
+        pgids: &HashSet<u32>,
+    ) -> Result<HashMap<u32, ResourceUsage>> {
+        let output = std::process::Command::new("ps")
+            .args(["-A", "-o", "pgid=,rss=,%cpu="])
+            .output()?;
+        let mut usage: HashMap<u32, ResourceUsage> = HashMap::new();
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (pgid, rss_kb, cpu) = match fields[..] {
+                [pgid, rss_kb, cpu] => (pgid, rss_kb, cpu),
+                _ => continue,
+            };
+            let pgid = match pgid.parse() {
+                Ok(pgid) if pgids.contains(&pgid) => pgid,
+                _ => continue,
+            };
+            let group = usage.entry(pgid).or_default();
+            group.rss_bytes += rss_kb.parse::<u64>().unwrap_or(0) * 1024;
+            group.cpu_percent += cpu.parse::<f32>().unwrap_or(0.0);
+        }
+        Ok(usage)
+    }
+}
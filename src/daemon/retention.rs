@@ -0,0 +1,392 @@
+use crate::config::DaemonConfig;
+use crate::daemon::daemon_log::daemon_log_path;
+use crate::daemon::events::Event;
+use crate::daemon::logs::{
+    log_directory, rotated_log_path, COMPRESSED_EXTENSION,
+};
+use crate::daemon::Core;
+use anyhow::{anyhow, Context, Result};
+use chrono::Local;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use log::{error, info};
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+const SECONDS_IN_DAY: u64 = 24 * 60 * 60;
+
+/// How often the retention policy is applied.
+const CHECK_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Determines which log files are rotated and cleaned up.
+pub struct RetentionPolicy {
+    /// Log files not modified for longer than this are cleaned up.
+    pub max_age: Option<Duration>,
+    /// The maximum total size (in bytes) of all log files.
+    pub max_total_size: Option<u64>,
+    /// Log files larger than this (in bytes) are rotated.
+    pub rotate_size: Option<u64>,
+    /// Whether rotated log files are compressed.
+    pub compress_rotated: bool,
+}
+
+/// A summary of the actions taken when applying a retention policy.
+#[derive(Default)]
+pub struct RetentionReport {
+    pub rotated: u32,
+    pub deleted: u32,
+    pub truncated: u32,
+    pub bytes_freed: u64,
+    /// Each action taken, along with the log file it was taken on.
+    pub actions: Vec<(RetentionAction, PathBuf)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RetentionAction {
+    Rotated,
+    Deleted,
+    Truncated,
+}
+
+impl RetentionAction {
+    /// The kind of the event recorded for the action.
+    fn event_kind(self) -> &'static str {
+        match self {
+            RetentionAction::Rotated => "log-rotated",
+            RetentionAction::Deleted => "log-deleted",
+            RetentionAction::Truncated => "log-truncated",
+        }
+    }
+}
+
+struct LogFile {
+    path: PathBuf,
+    size: u64,
+    modified: SystemTime,
+    /// Whether the file was deleted while applying the retention policy.
+    deleted: bool,
+}
+
+impl RetentionPolicy {
+    /// Builds the retention policy from the daemon configuration.
+    ///
+    /// Returns `None` if none of `log_retention_days`, `log_max_total_size`
+    /// and `log_rotate_size` are configured.
+    pub fn from_config(cfg: &DaemonConfig) -> Result<Option<RetentionPolicy>> {
+        let max_age = cfg
+            .log_retention_days
+            .map(|days| Duration::from_secs(days * SECONDS_IN_DAY));
+        let max_total_size = match &cfg.log_max_total_size {
+            Some(size) => Some(parse_size(size).with_context(|| {
+                format!("Invalid daemon.log_max_total_size '{}'", size)
+            })?),
+            None => None,
+        };
+
+        let rotate_size = match &cfg.log_rotate_size {
+            Some(size) => Some(parse_size(size).with_context(|| {
+                format!("Invalid daemon.log_rotate_size '{}'", size)
+            })?),
+            None => None,
+        };
+
+        if max_age.is_none()
+            && max_total_size.is_none()
+            && rotate_size.is_none()
+        {
+            return Ok(None);
+        }
+        Ok(Some(RetentionPolicy {
+            max_age,
+            max_total_size,
+            rotate_size,
+            compress_rotated: cfg.log_compress_rotated.unwrap_or(false),
+        }))
+    }
+}
+
+/// Parses a size such as `500M`, `2G`, `10K` or a plain number of bytes.
+fn parse_size(size: &str) -> Result<u64> {
+    let size = size.trim().to_uppercase();
+    let size = size.strip_suffix('B').unwrap_or(&size);
+    let (number, multiplier) = match size.chars().last() {
+        Some('K') => (&size[..size.len() - 1], 1 << 10),
+        Some('M') => (&size[..size.len() - 1], 1 << 20),
+        Some('G') => (&size[..size.len() - 1], 1 << 30),
+        _ => (size, 1),
+    };
+    let number = number
+        .trim()
+        .parse::<u64>()
+        .map_err(|_| anyhow!("Expected a size such as 500M or 2G"))?;
+    Ok(number * multiplier)
+}
+
+/// Launches a thread which periodically applies the retention policy to the
+/// log directory.
+///
+/// Log files of running services are truncated instead of being deleted.
+pub fn log_retention_thread(core: Arc<Core>, policy: RetentionPolicy) {
+    info!("Starting log retention thread");
+    std::thread::spawn(move || loop {
+        match cleanup_logs(&core, &policy) {
+            Ok(report) => {
+                if report.rotated > 0 {
+                    info!(
+                        "Log retention: rotated {} log file(s)",
+                        report.rotated
+                    );
+                }
+                if report.deleted > 0 || report.truncated > 0 {
+                    info!(
+                        "Log retention: deleted {} and truncated {} log \
+                        file(s), freeing {} bytes",
+                        report.deleted, report.truncated, report.bytes_freed
+                    );
+                }
+                // So that users can find out why a log file disappeared.
+                for (action, path) in report.actions {
+                    core.events.record(Event::daemon(
+                        action.event_kind(),
+                        &log_module_name(&path),
+                        vec![path.to_string_lossy().into_owned()],
+                    ));
+                }
+            }
+            Err(e) => {
+                error!("Log retention failed: {:#}", e);
+                core.events.record(Event::daemon(
+                    "log-retention-failed",
+                    "-",
+                    vec![format!("{:#}", e)],
+                ));
+            }
+        }
+        std::thread::sleep(CHECK_INTERVAL);
+    });
+}
+
+fn cleanup_logs(
+    core: &Core,
+    policy: &RetentionPolicy,
+) -> Result<RetentionReport> {
+    let log_dir = log_directory()?;
+    let mut in_use = core.planner().log_files_in_use();
+    // The daemon keeps writing to its own log file.
+    in_use.insert(daemon_log_path());
+    apply_retention(&log_dir, policy, &in_use)
+}
+
+/// Applies the retention policy to all log files within `log_dir`.
+///
+/// Log files exceeding the rotation size are rotated first. Files in `in_use`
+/// are truncated instead of being deleted, as they are still being written to.
+pub fn apply_retention(
+    log_dir: &Path,
+    policy: &RetentionPolicy,
+    in_use: &HashSet<PathBuf>,
+) -> Result<RetentionReport> {
+    let mut report = RetentionReport::default();
+
+    if let Some(rotate_size) = policy.rotate_size {
+        for file in list_log_files(log_dir)? {
+            let is_log =
+                file.path.extension().map_or(false, |ext| ext == "log");
+            if is_log && file.size > rotate_size {
+                rotate_log_file(&file.path, policy.compress_rotated)?;
+                report.rotated += 1;
+                report.actions.push((RetentionAction::Rotated, file.path));
+            }
+        }
+    }
+
+    let mut files = list_log_files(log_dir)?;
+    // Oldest first
+    files.sort_by_key(|f| f.modified);
+
+    if let Some(max_age) = policy.max_age {
+        let now = SystemTime::now();
+        for file in files.iter_mut() {
+            let age = now.duration_since(file.modified).unwrap_or_default();
+            if age > max_age {
+                remove_log_file(file, in_use, &mut report)?;
+            }
+        }
+        files.retain(|file| !file.deleted);
+    }
+
+    if let Some(max_total_size) = policy.max_total_size {
+        let mut total: u64 = files.iter().map(|f| f.size).sum();
+        for file in files.iter_mut() {
+            if total <= max_total_size {
+                break;
+            }
+            total -= file.size;
+            remove_log_file(file, in_use, &mut report)?;
+        }
+    }
+
+    Ok(report)
+}
+
+fn remove_log_file(
+    file: &mut LogFile,
+    in_use: &HashSet<PathBuf>,
+    report: &mut RetentionReport,
+) -> Result<()> {
+    if file.size == 0 && in_use.contains(&file.path) {
+        return Ok(());
+    }
+
+    if in_use.contains(&file.path) {
+        OpenOptions::new()
+            .write(true)
+            .open(&file.path)
+            .and_then(|f| f.set_len(0))
+            .with_context(|| format!("Failed to truncate {:?}", file.path))?;
+        report.truncated += 1;
+        report
+            .actions
+            .push((RetentionAction::Truncated, file.path.clone()));
+    } else {
+        fs::remove_file(&file.path)
+            .with_context(|| format!("Failed to delete {:?}", file.path))?;
+        report.deleted += 1;
+        report
+            .actions
+            .push((RetentionAction::Deleted, file.path.clone()));
+        file.deleted = true;
+    }
+    report.bytes_freed += file.size;
+    file.size = 0;
+    Ok(())
+}
+
+/// Returns the name of the module a log file belongs to, eg. `backend` for
+/// `backend.service.log` or `migrate` for `migrate-20210828100000000.task.log`.
+fn log_module_name(path: &Path) -> String {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let name = file_name.split('.').next().unwrap_or_default();
+    match name.rsplit_once('-') {
+        Some((task, run))
+            if !run.is_empty() && run.chars().all(|c| c.is_ascii_digit()) =>
+        {
+            task.to_string()
+        }
+        _ => name.to_string(),
+    }
+}
+
+/// Moves the contents of a log file into a new rotated segment.
+///
+/// The file is copied and then truncated (rather than renamed) since running
+/// services keep writing to it. Output written in between is lost.
+fn rotate_log_file(path: &Path, compress: bool) -> Result<()> {
+    let rotated_path = rotated_log_path(path, Local::now());
+    if compress {
+        let mut rotated_path = rotated_path.into_os_string();
+        rotated_path.push(format!(".{}", COMPRESSED_EXTENSION));
+        let mut encoder = GzEncoder::new(
+            File::create(&rotated_path)?,
+            Compression::default(),
+        );
+        io::copy(&mut File::open(path)?, &mut encoder)?;
+        encoder.finish()?;
+    } else {
+        fs::copy(path, &rotated_path)?;
+    }
+
+    OpenOptions::new()
+        .write(true)
+        .open(path)
+        .and_then(|f| f.set_len(0))
+        .with_context(|| format!("Failed to truncate {:?}", path))?;
+    Ok(())
+}
+
+/// Lists all files within the log directory (including rotated log files and
+/// those in nested directories).
+fn list_log_files(log_dir: &Path) -> Result<Vec<LogFile>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![log_dir.to_path_buf()];
+
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if metadata.is_dir() {
+                dirs.push(entry.path());
+            } else if metadata.is_file() {
+                files.push(LogFile {
+                    path: entry.path(),
+                    size: metadata.len(),
+                    modified: metadata.modified()?,
+                    deleted: false,
+                });
+            }
+        }
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const DAY: Duration = Duration::from_secs(SECONDS_IN_DAY);
+
+    fn log_file(dir: &Path, name: &str, size: usize, age: Duration) -> PathBuf {
+        let path = dir.join(name);
+        fs::write(&path, vec![b'x'; size]).unwrap();
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(SystemTime::now() - age)
+            .unwrap();
+        path
+    }
+
+    #[test]
+    fn test_apply_retention_with_age_and_size_limits() {
+        let dir = std::env::temp_dir()
+            .join(format!("cartel-retention-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let expired = log_file(&dir, "old.service.log", 100, DAY * 10);
+        let running = log_file(&dir, "running.service.log", 100, DAY * 9);
+        let older = log_file(&dir, "older.service.log", 100, DAY * 2);
+        let newer = log_file(&dir, "newer.service.log", 100, DAY);
+        let policy = RetentionPolicy {
+            max_age: Some(DAY * 5),
+            max_total_size: Some(150),
+            rotate_size: None,
+            compress_rotated: false,
+        };
+        let in_use: HashSet<_> = vec![running.clone()].into_iter().collect();
+
+        let report = apply_retention(&dir, &policy, &in_use).unwrap();
+
+        assert_eq!(report.deleted, 2);
+        assert_eq!(report.truncated, 1);
+        assert_eq!(report.bytes_freed, 300);
+        assert_eq!(
+            report.actions,
+            vec![
+                (RetentionAction::Deleted, expired.clone()),
+                (RetentionAction::Truncated, running.clone()),
+                (RetentionAction::Deleted, older.clone()),
+            ]
+        );
+        assert!(!expired.exists() && !older.exists());
+        assert_eq!(fs::metadata(&running).unwrap().len(), 0);
+        assert_eq!(fs::metadata(&newer).unwrap().len(), 100);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,164 @@
+use crate::command_builder::CommandBuilder;
+use crate::daemon::module::{ModuleDefinition, RunCondition};
+use crate::daemon::task_runs::{task_key, TaskKey};
+use crate::glob;
+use anyhow::{Context, Result};
+use parking_lot::RwLock;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+/// The hashes of the files watched by tasks (see [RunCondition]) as of their
+/// last successful run, keyed by project and task name.
+///
+/// Like the task history the hashes are kept in memory, so tasks are run
+/// again once the daemon is restarted.
+#[derive(Default)]
+pub struct FileHashes {
+    hashes: RwLock<HashMap<TaskKey, u64>>,
+}
+
+impl FileHashes {
+    fn get(&self, task_definition: &ModuleDefinition) -> Option<u64> {
+        self.hashes.read().get(&task_key(task_definition)).copied()
+    }
+
+    pub fn record(&self, task_definition: &ModuleDefinition, hash: u64) {
+        self.hashes.write().insert(task_key(task_definition), hash);
+    }
+}
+
+/// Whether a task should run, as decided by its [RunCondition].
+pub enum Guard {
+    /// The task should run. Once it succeeds the hash of its files (if it
+    /// watches any) is to be recorded.
+    Run(Option<u64>),
+    /// The task should be skipped, for the given reason.
+    Skip(String),
+}
+
+/// Checks the run condition of a task.
+///
+/// The task is skipped if the command of the condition exits with zero, or if
+/// none of the files it watches changed since it last succeeded.
+pub fn check(
+    task_definition: &ModuleDefinition,
+    condition: &RunCondition,
+    file_hashes: &FileHashes,
+    environment: &HashMap<String, String>,
+) -> Result<Guard> {
+    if let Some(command) = &condition.command {
+        let mut cmd = CommandBuilder::new(command);
+        cmd.env(environment)
+            .stdout_null()
+            .stderr_null()
+            .work_dir(task_definition.working_dir.as_deref());
+        let status = cmd.build().status().with_context(|| {
+            format!(
+                "Failed to run the run_if command of task {}",
+                task_definition.name
+            )
+        })?;
+        if status.success() {
+            return Ok(Guard::Skip(String::from("run_if command succeeded")));
+        }
+    }
+
+    if condition.files_changed.is_empty() {
+        return Ok(Guard::Run(None));
+    }
+    let dir = task_definition
+        .working_dir
+        .clone()
+        .unwrap_or_else(|| PathBuf::from("."));
+    let hash = hash_files(&dir, &condition.files_changed);
+    if file_hashes.get(task_definition) == Some(hash) {
+        Ok(Guard::Skip(String::from("no files changed")))
+    } else {
+        Ok(Guard::Run(Some(hash)))
+    }
+}
+
+/// Hashes the paths and contents of the files matching any of the patterns.
+fn hash_files(dir: &Path, patterns: &[String]) -> u64 {
+    let mut files = BTreeSet::new();
+    for pattern in patterns {
+        let components: Vec<&str> = pattern
+            .split('/')
+            .filter(|c| !c.is_empty() && *c != ".")
+            .collect();
+        let base = if pattern.starts_with('/') {
+            Path::new("/")
+        } else {
+            dir
+        };
+        expand(base, &components, &mut files);
+    }
+
+    let mut hasher = DefaultHasher::new();
+    patterns.hash(&mut hasher);
+    for file in files {
+        // Files may be removed while being read, which counts as a change.
+        if let Ok(content) = fs::read(&file) {
+            file.hash(&mut hasher);
+            content.hash(&mut hasher);
+        }
+    }
+    hasher.finish()
+}
+
+/// Collects the files matching the remaining components of a pattern.
+///
+/// Components may contain the wildcards supported by [glob::matches], while
+/// `**` matches any number of directories. Directories matched by the last
+/// component have all files under them collected.
+fn expand(path: &Path, components: &[&str], files: &mut BTreeSet<PathBuf>) {
+    match components.split_first() {
+        None => collect_files(path, files),
+        Some((&"**", rest)) => {
+            expand(path, rest, files);
+            for entry in read_dir(path) {
+                if entry.file_type().map_or(false, |t| t.is_dir()) {
+                    expand(&entry.path(), components, files);
+                }
+            }
+        }
+        Some((component, rest)) if glob::is_pattern(component) => {
+            for entry in read_dir(path) {
+                let name = entry.file_name();
+                if glob::matches(component, &name.to_string_lossy()) {
+                    expand(&entry.path(), rest, files);
+                }
+            }
+        }
+        Some((component, rest)) => {
+            let path = path.join(component);
+            if path.exists() {
+                expand(&path, rest, files);
+            }
+        }
+    }
+}
+
+fn collect_files(path: &Path, files: &mut BTreeSet<PathBuf>) {
+    if path.is_file() {
+        files.insert(path.to_path_buf());
+        return;
+    }
+    for entry in read_dir(path) {
+        // Symlinked directories are not followed, as they may form loops.
+        match entry.file_type() {
+            Ok(t) if t.is_dir() => collect_files(&entry.path(), files),
+            Ok(_) if entry.path().is_file() => {
+                files.insert(entry.path());
+            }
+            _ => {}
+        }
+    }
+}
+
+fn read_dir(path: &Path) -> impl Iterator<Item = fs::DirEntry> {
+    fs::read_dir(path).into_iter().flatten().flatten()
+}
@@ -0,0 +1,56 @@
+use crate::daemon::planner::MonitorStatus;
+use crate::daemon::Core;
+use log::{error, info};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How often the readiness of a service is checked before starting its
+/// sidecars.
+const READINESS_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Starts the sidecars of a service (see [ModuleDefinition::sidecars]) once
+/// it becomes healthy, on a thread of its own.
+///
+/// Services without a readiness probe are considered healthy straight away.
+/// If the readiness probe of the service fails, or does not pass within the
+/// time its retries take, the sidecars are not started.
+///
+/// [ModuleDefinition::sidecars]: crate::daemon::ModuleDefinition::sidecars
+pub fn start_sidecars_when_ready(core: &Arc<Core>, service_name: String) {
+    let planner = core.planner();
+    if !planner.has_sidecars(&service_name) {
+        return;
+    }
+    let readiness = planner.readiness(&service_name);
+    let core = Arc::clone(core);
+    std::thread::spawn(move || {
+        let planner = core.planner();
+        if let Some((key, timeout)) = readiness {
+            let deadline = Instant::now() + timeout;
+            loop {
+                match planner.monitor_status(&key) {
+                    Some(MonitorStatus::Successful) => break,
+                    // The monitor may not be registered yet.
+                    None
+                    | Some(MonitorStatus::Pending)
+                    | Some(MonitorStatus::Failing)
+                        if planner.is_running(&service_name)
+                            && Instant::now() < deadline =>
+                    {
+                        std::thread::sleep(READINESS_POLL_INTERVAL)
+                    }
+                    _ => {
+                        info!(
+                            "Not starting sidecars of {} as it is not healthy",
+                            service_name
+                        );
+                        return;
+                    }
+                }
+            }
+        }
+        if let Err(e) = planner.start_sidecars(&service_name) {
+            error!("Failed to start sidecars of {}: {:#}", service_name, e);
+        }
+    });
+}
@@ -1,4 +1,4 @@
-use crate::daemon::Core;
+use crate::daemon::{handover, Core};
 use log::info;
 use signal_hook::iterator::Signals;
 use signal_hook::{SIGCHLD, SIGINT, SIGTERM};
@@ -15,7 +15,10 @@ pub fn setup_signal_handlers(core: Arc<Core>) -> Result<(), Box<dyn Error>> {
                 core.planner().collect_dead();
             } else if sig == SIGTERM || sig == SIGINT {
                 info!("Cleaning up and exiting...");
-                core.planner().cleanup().ok();
+                if core.planner().cleanup().is_ok() {
+                    // Nothing is left running to take over.
+                    handover::remove_state();
+                }
                 std::process::exit(0);
             }
         }
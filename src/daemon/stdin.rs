@@ -0,0 +1,33 @@
+use anyhow::{Context, Result};
+use nix::fcntl::{fcntl, FcntlArg, FdFlag};
+use nix::pty::openpty;
+use nix::sys::termios::{tcgetattr, tcsetattr, LocalFlags, SetArg};
+use std::fs::File;
+use std::os::unix::io::FromRawFd;
+
+/// Opens the terminal an interactive service reads its input from (see
+/// [ModuleDefinition::interactive]), returning its master and slave ends.
+///
+/// The slave end is given to the service as its stdin, while input is
+/// written to the master end. Echo is turned off, as the output of the
+/// terminal is not read.
+///
+/// [ModuleDefinition::interactive]: crate::daemon::ModuleDefinition::interactive
+pub fn open_stdin_pty() -> Result<(File, File)> {
+    let pty = openpty(None, None).context("Failed to open a terminal")?;
+    for fd in [pty.master, pty.slave] {
+        fcntl(fd, FcntlArg::F_SETFD(FdFlag::FD_CLOEXEC))
+            .context("Failed to open a terminal")?;
+    }
+    // Safety: Both descriptors were just created and are exclusively owned.
+    let (master, slave) = unsafe {
+        (File::from_raw_fd(pty.master), File::from_raw_fd(pty.slave))
+    };
+
+    let mut termios =
+        tcgetattr(pty.slave).context("Failed to open a terminal")?;
+    termios.local_flags.remove(LocalFlags::ECHO);
+    tcsetattr(pty.slave, SetArg::TCSANOW, &termios)
+        .context("Failed to open a terminal")?;
+    Ok((master, slave))
+}
@@ -0,0 +1,134 @@
+use crate::constants::SUPERVISED_ENV;
+use log::{error, info};
+use nix::sys::signal::{kill, Signal};
+use nix::unistd::Pid;
+use signal_hook::iterator::Signals;
+use signal_hook::{SIGINT, SIGTERM};
+use std::convert::TryFrom;
+use std::ffi::OsString;
+use std::process::{self, Command};
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::{env, thread};
+
+/// How long to wait before restarting the daemon after it first crashed.
+const INITIAL_RESTART_DELAY: Duration = Duration::from_secs(1);
+
+/// The longest to wait before restarting the daemon, however often it
+/// crashed.
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(60);
+
+/// How long the daemon has to run for without crashing for the delay before
+/// restarting it to start over.
+const STABLE_RUN: Duration = Duration::from_secs(60);
+
+/// Whether this daemon is run by a supervisor.
+static SUPERVISED: AtomicBool = AtomicBool::new(false);
+
+/// Returns whether this daemon is run by a supervisor, which marks it in its
+/// environment.
+///
+/// The mark is removed from the environment, for the modules not to inherit
+/// it, so this is called once on startup.
+pub fn take_supervised_mark() -> bool {
+    let supervised = env::var_os(SUPERVISED_ENV).is_some();
+    env::remove_var(SUPERVISED_ENV);
+    SUPERVISED.store(supervised, Ordering::SeqCst);
+    supervised
+}
+
+/// Returns whether this daemon is run by a supervisor.
+pub fn is_supervised() -> bool {
+    SUPERVISED.load(Ordering::SeqCst)
+}
+
+/// The daemon run by the supervisor, and whether it is being stopped.
+#[derive(Default)]
+struct Supervised {
+    pid: AtomicI32,
+    stopping: AtomicBool,
+}
+
+/// Runs the daemon as a child process, restarting it whenever it crashes,
+/// and exits once it is stopped.
+///
+/// The restarted daemon takes over the services left running by the one
+/// which crashed (see [recover](crate::daemon::handover::recover)). Signals
+/// to stop the supervisor are forwarded to the daemon, which stops its
+/// services as usual.
+pub fn supervise() -> ! {
+    let supervised = Arc::new(Supervised::default());
+    if let Err(e) = forward_signals(Arc::clone(&supervised)) {
+        error!("Failed to set up the signal handlers: {}", e);
+        process::exit(1);
+    }
+    let exe = match env::current_exe() {
+        Ok(exe) => exe,
+        Err(e) => {
+            error!("Failed to locate the daemon executable: {}", e);
+            process::exit(1);
+        }
+    };
+    let args = daemon_args(env::args_os().skip(1));
+
+    let mut delay = INITIAL_RESTART_DELAY;
+    loop {
+        info!("Starting the supervised daemon");
+        let started = Instant::now();
+        let status = Command::new(&exe)
+            .args(&args)
+            .env(SUPERVISED_ENV, "1")
+            .spawn()
+            .and_then(|mut child| {
+                let pid = i32::try_from(child.id()).unwrap_or_default();
+                supervised.pid.store(pid, Ordering::SeqCst);
+                child.wait()
+            });
+        supervised.pid.store(0, Ordering::SeqCst);
+
+        match status {
+            Ok(status)
+                if status.success()
+                    || supervised.stopping.load(Ordering::SeqCst) =>
+            {
+                info!("The supervised daemon stopped ({})", status);
+                process::exit(0);
+            }
+            Ok(status) => error!("The supervised daemon crashed ({})", status),
+            Err(e) => error!("Failed to start the supervised daemon: {}", e),
+        }
+
+        if started.elapsed() >= STABLE_RUN {
+            delay = INITIAL_RESTART_DELAY;
+        }
+        info!("Restarting the supervised daemon in {:?}", delay);
+        thread::sleep(delay);
+        delay = (delay * 2).min(MAX_RESTART_DELAY);
+    }
+}
+
+/// Forwards the signals stopping the supervisor to the daemon.
+fn forward_signals(supervised: Arc<Supervised>) -> std::io::Result<()> {
+    let signals = Signals::new([SIGTERM, SIGINT])?;
+    thread::spawn(move || {
+        for sig in signals.forever() {
+            supervised.stopping.store(true, Ordering::SeqCst);
+            let pid = supervised.pid.load(Ordering::SeqCst);
+            if pid == 0 {
+                // Waiting to restart the daemon.
+                process::exit(0);
+            }
+            if let Ok(sig) = Signal::try_from(sig) {
+                kill(Pid::from_raw(pid), sig).ok();
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Returns the arguments the supervisor was started with, for the daemon.
+fn daemon_args(args: impl Iterator<Item = OsString>) -> Vec<OsString> {
+    args.filter(|arg| arg != "--supervised" && arg != "-d" && arg != "--detach")
+        .collect()
+}
@@ -0,0 +1,155 @@
+use crate::daemon::module::LogSink;
+use anyhow::{anyhow, Context, Result};
+use chrono::Local;
+use log::warn;
+use nix::fcntl::{fcntl, FcntlArg, FdFlag};
+use nix::unistd::pipe;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::io::FromRawFd;
+use std::os::unix::net::UnixDatagram;
+use std::path::Path;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Paths of the local syslog socket on the supported platforms.
+const SYSLOG_SOCKETS: [&str; 3] =
+    ["/dev/log", "/var/run/syslog", "/var/run/log"];
+
+/// The priority of forwarded lines (facility `user`, severity `info`).
+const PRIORITY: u8 = 14;
+
+/// How often to check whether a forwarder finished.
+const JOIN_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Where the output of a module is written to.
+pub struct LogOutput {
+    pub stdout: File,
+    pub stderr: File,
+    /// The thread forwarding the output to syslog (if any). It finishes once
+    /// the module and all of its children have exited.
+    pub forwarder: Option<JoinHandle<()>>,
+}
+
+impl LogOutput {
+    /// Creates the output for a module given its log file and sink.
+    ///
+    /// For the `syslog` and `both` sinks the module writes into a pipe, which
+    /// is read by a thread that sends each line to syslog (and in the case of
+    /// `both` also to the log file).
+    pub fn new(
+        module_name: &str,
+        log_file: File,
+        log_sink: &LogSink,
+    ) -> Result<LogOutput> {
+        let log_file = match log_sink {
+            LogSink::File => {
+                return Ok(LogOutput {
+                    stderr: log_file
+                        .try_clone()
+                        .with_context(|| "Failed to create log file")?,
+                    stdout: log_file,
+                    forwarder: None,
+                })
+            }
+            LogSink::Syslog => None,
+            LogSink::Both => Some(log_file),
+        };
+
+        let socket = connect_syslog()?;
+        let (reader, writer) = cloexec_pipe()?;
+        let tag = format!("cartel-{}", module_name);
+        let forwarder = thread::spawn(move || {
+            forward_lines(reader, socket, &tag, log_file)
+        });
+
+        Ok(LogOutput {
+            stderr: writer
+                .try_clone()
+                .with_context(|| "Failed to create log pipe")?,
+            stdout: writer,
+            forwarder: Some(forwarder),
+        })
+    }
+}
+
+/// Waits for a forwarder to finish, for up to `timeout`.
+///
+/// It only finishes once every process holding the write end of the pipe has
+/// exited, which background processes started by a module may never do. The
+/// forwarder then keeps running on its own. Returns whether it finished.
+pub fn join_forwarder(forwarder: JoinHandle<()>, timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+    while !forwarder.is_finished() {
+        if Instant::now() >= deadline {
+            return false;
+        }
+        thread::sleep(JOIN_INTERVAL);
+    }
+    let _ = forwarder.join();
+    true
+}
+
+fn connect_syslog() -> Result<UnixDatagram> {
+    let socket =
+        UnixDatagram::unbound().with_context(|| "Failed to create socket")?;
+    SYSLOG_SOCKETS
+        .iter()
+        .map(Path::new)
+        .filter(|path| path.exists())
+        .find_map(|path| socket.connect(path).ok())
+        .ok_or_else(|| anyhow!("Failed to connect to the syslog socket"))?;
+    Ok(socket)
+}
+
+/// Creates a pipe whose ends are not inherited by child processes.
+///
+/// The write end is still passed on as the stdout / stderr of the module,
+/// since duplicating it onto those descriptors clears the flag.
+fn cloexec_pipe() -> Result<(File, File)> {
+    let (read_fd, write_fd) =
+        pipe().with_context(|| "Failed to create log pipe")?;
+    for fd in [read_fd, write_fd] {
+        fcntl(fd, FcntlArg::F_SETFD(FdFlag::FD_CLOEXEC))
+            .with_context(|| "Failed to create log pipe")?;
+    }
+    // Safety: Both descriptors were just created and are exclusively owned.
+    unsafe { Ok((File::from_raw_fd(read_fd), File::from_raw_fd(write_fd))) }
+}
+
+fn forward_lines(
+    reader: File,
+    socket: UnixDatagram,
+    tag: &str,
+    mut log_file: Option<File>,
+) {
+    let mut reader = BufReader::new(reader);
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        match reader.read_until(b'\n', &mut line) {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(e) => {
+                warn!("Failed to read output of {}: {}", tag, e);
+                break;
+            }
+        }
+
+        if let Some(file) = log_file.as_mut() {
+            if let Err(e) = file.write_all(&line) {
+                warn!("Failed to write to log file of {}: {}", tag, e);
+            }
+        }
+
+        let message = format!(
+            "<{}>{} {}: {}",
+            PRIORITY,
+            Local::now().format("%b %e %H:%M:%S"),
+            tag,
+            String::from_utf8_lossy(&line).trim_end()
+        );
+        // Syslog is best effort, a line is lost if the socket is unavailable.
+        let _ = socket.send(message.as_bytes());
+    }
+}
@@ -0,0 +1,258 @@
+use crate::daemon::error::DaemonError;
+use crate::daemon::logs::tail_log_file;
+use crate::daemon::module::{ModuleDefinition, OncePer, TaskOptions};
+use crate::daemon::Core;
+use chrono::{Local, TimeZone};
+use log::info;
+use parking_lot::{Mutex, RwLock};
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How long a finished task run is kept around for clients to poll.
+const FINISHED_RUN_TTL: Duration = Duration::from_secs(600);
+
+/// How many executions of tasks are kept in the history.
+const HISTORY_SIZE: usize = 500;
+
+/// How many of the last lines of output of a task are returned to clients.
+pub const TASK_OUTPUT_LINES: usize = 30;
+
+#[derive(Debug, Clone)]
+pub enum TaskRunStatus {
+    Running,
+    Succeeded,
+    /// The task was skipped, with the reason why.
+    Skipped(String),
+    /// The task failed, with the reason why.
+    Failed(String),
+}
+
+/// A task running (or which ran) in the background.
+#[derive(Debug, Clone)]
+pub struct TaskRun {
+    pub task_name: String,
+    pub status: TaskRunStatus,
+    /// The last lines of output of the task, once finished.
+    pub output: Vec<String>,
+    finished_at: Option<Instant>,
+}
+
+/// Holds the task runs started in the background, keyed by their id.
+#[derive(Default)]
+pub struct TaskRuns {
+    runs: RwLock<HashMap<String, TaskRun>>,
+}
+
+impl TaskRuns {
+    pub fn get(&self, id: &str) -> Option<TaskRun> {
+        self.runs.read().get(id).cloned()
+    }
+
+    /// Returns the names of the tasks running in the background.
+    pub fn running(&self) -> Vec<String> {
+        self.runs
+            .read()
+            .values()
+            .filter(|run| matches!(run.status, TaskRunStatus::Running))
+            .map(|run| run.task_name.clone())
+            .collect()
+    }
+
+    /// Registers a new run of a task, returning its id.
+    fn start(&self, task_name: &str) -> String {
+        let id = uuid::Uuid::new_v4().to_string();
+        let run = TaskRun {
+            task_name: task_name.to_string(),
+            status: TaskRunStatus::Running,
+            output: vec![],
+            finished_at: None,
+        };
+        let mut runs = self.runs.write();
+        runs.retain(|_, run| {
+            run.finished_at
+                .map_or(true, |at| at.elapsed() < FINISHED_RUN_TTL)
+        });
+        runs.insert(id.clone(), run);
+        id
+    }
+
+    fn finish(&self, id: &str, status: TaskRunStatus, output: Vec<String>) {
+        if let Some(run) = self.runs.write().get_mut(id) {
+            run.status = status;
+            run.output = output;
+            run.finished_at = Some(Instant::now());
+        }
+    }
+}
+
+/// An execution of a task, as recorded in the [TaskHistory].
+#[derive(Debug, Clone)]
+pub struct TaskExecution {
+    pub task_name: String,
+    pub project: Option<String>,
+    /// When the task started and finished, in seconds since the epoch.
+    pub started_at: u64,
+    pub finished_at: u64,
+    /// The exit code of the task, unless it was terminated by a signal.
+    pub exit_code: Option<i32>,
+    pub log_file: PathBuf,
+    /// The attempt this was, counting from 1 (see [RetryPolicy]).
+    pub attempt: u32,
+    /// The deploy which ran the task, if any.
+    pub deployment_id: Option<String>,
+}
+
+/// The outcome of deploying a task.
+pub enum TaskOutcome {
+    /// The task ran, successfully.
+    Ran(TaskExecution),
+    /// The task was skipped by its run condition, for the given reason.
+    Skipped(String),
+}
+
+/// Holds the latest executions of all tasks, oldest first.
+///
+/// The history is kept in memory, so it starts out empty when the daemon is
+/// restarted.
+#[derive(Default)]
+pub struct TaskHistory {
+    executions: RwLock<VecDeque<TaskExecution>>,
+}
+
+impl TaskHistory {
+    pub fn record(&self, execution: TaskExecution) {
+        let mut executions = self.executions.write();
+        if executions.len() == HISTORY_SIZE {
+            executions.pop_front();
+        }
+        executions.push_back(execution);
+    }
+
+    /// Returns the recorded executions, newest first, optionally only those
+    /// of a task (of a project).
+    pub fn executions(
+        &self,
+        task_name: Option<&str>,
+        project: Option<&str>,
+    ) -> Vec<TaskExecution> {
+        self.executions
+            .read()
+            .iter()
+            .rev()
+            .filter(|e| task_name.map_or(true, |name| e.task_name == name))
+            .filter(|e| {
+                project.map_or(true, |p| e.project.as_deref() == Some(p))
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Returns why a task need not run again, if it already succeeded within
+    /// the period given by `once_per`.
+    pub fn ran_already(
+        &self,
+        task_definition: &ModuleDefinition,
+        once_per: OncePer,
+        deployment_id: Option<&str>,
+    ) -> Option<String> {
+        let reason = match once_per {
+            OncePer::Always => return None,
+            OncePer::Deploy if deployment_id.is_none() => return None,
+            OncePer::Deploy => "already ran in this deploy",
+            OncePer::Day => "already ran today",
+        };
+        let today = Local::now().naive_local().date();
+        let ran_within = |e: &TaskExecution| match once_per {
+            OncePer::Deploy => e.deployment_id.as_deref() == deployment_id,
+            _ => Local
+                .timestamp_opt(e.started_at as i64, 0)
+                .single()
+                .map_or(false, |started| started.naive_local().date() == today),
+        };
+        let ran = self.executions.read().iter().any(|e| {
+            e.task_name == task_definition.name
+                && e.project == task_definition.project
+                && e.exit_code == Some(0)
+                && ran_within(e)
+        });
+        ran.then(|| String::from(reason))
+    }
+}
+
+/// Serializes the runs of each task (by project and name), so that a task
+/// which runs only once (see [OncePer]) is not run by concurrent deploys at
+/// the same time.
+#[derive(Default)]
+pub struct TaskLocks {
+    locks: Mutex<HashMap<TaskKey, Arc<Mutex<()>>>>,
+}
+
+impl TaskLocks {
+    pub fn get(&self, task_definition: &ModuleDefinition) -> Arc<Mutex<()>> {
+        let key = task_key(task_definition);
+        Arc::clone(self.locks.lock().entry(key).or_default())
+    }
+}
+
+/// Identifies a task by its project and name.
+pub type TaskKey = (Option<String>, String);
+
+pub fn task_key(task_definition: &ModuleDefinition) -> TaskKey {
+    (
+        task_definition.project.clone(),
+        task_definition.name.clone(),
+    )
+}
+
+/// Returns the last lines of output of a task, from its log file.
+pub fn task_output(log_file: &Path) -> Vec<String> {
+    tail_log_file(log_file, TASK_OUTPUT_LINES).unwrap_or_default()
+}
+
+/// Runs a task on a thread of its own, returning the id of the run.
+///
+/// Unlike deploying a task, this returns straight away, leaving clients to
+/// poll the status of the run (see [TaskRuns::get]).
+pub fn run_task_in_background(
+    core: &Arc<Core>,
+    task_definition: ModuleDefinition,
+    options: TaskOptions,
+    deployment_id: Option<String>,
+) -> String {
+    let id = core.task_runs.start(&task_definition.name);
+    info!(
+        "Running task {} in the background ({})",
+        task_definition.name, id
+    );
+    let core = Arc::clone(core);
+    let run_id = id.clone();
+    std::thread::spawn(move || {
+        let (status, output) = match core.planner().deploy_task(
+            &task_definition,
+            &options,
+            deployment_id.as_deref(),
+        ) {
+            Ok(TaskOutcome::Ran(execution)) => {
+                let output = task_output(&execution.log_file);
+                (TaskRunStatus::Succeeded, output)
+            }
+            Ok(TaskOutcome::Skipped(reason)) => {
+                (TaskRunStatus::Skipped(reason), vec![])
+            }
+            Err(e) => {
+                let output = match e.downcast_ref::<DaemonError>() {
+                    Some(DaemonError::TaskFailed { log_file, .. })
+                    | Some(DaemonError::TaskTimedOut { log_file, .. }) => {
+                        task_output(log_file.as_ref())
+                    }
+                    _ => vec![],
+                };
+                (TaskRunStatus::Failed(format!("{:#}", e)), output)
+            }
+        };
+        core.task_runs.finish(&run_id, status, output);
+    });
+    id
+}
@@ -0,0 +1,71 @@
+use crate::constants::TMP_DIR;
+use crate::daemon::module::ModuleDefinition;
+use crate::path;
+use anyhow::{anyhow, Context, Result};
+use log::{info, warn};
+use std::fs;
+use std::path::PathBuf;
+
+/// Returns the directory the temp directories of services are created in,
+/// within the state directory (see [path::state_dir]).
+fn tmp_root() -> Result<PathBuf> {
+    path::state_dir()
+        .map(|dir| dir.join(TMP_DIR))
+        .ok_or_else(|| anyhow!("Failed to locate users state dir"))
+}
+
+fn tmpdir_path(module: &ModuleDefinition) -> Result<PathBuf> {
+    let mut dir = tmp_root()?;
+    if let Some(project) = &module.project {
+        dir.push(project);
+    }
+    dir.push(&module.name);
+    Ok(dir)
+}
+
+/// Creates an empty temp directory for a service (see
+/// [ModuleDefinition::tmpdir]), removing what a previous run left in it.
+pub fn create_tmpdir(module: &ModuleDefinition) -> Result<PathBuf> {
+    let dir = tmpdir_path(module)?;
+    if dir.exists() {
+        fs::remove_dir_all(&dir).with_context(|| {
+            format!("Failed to clean temp dir {}", dir.display())
+        })?;
+    }
+    fs::create_dir_all(&dir).with_context(|| {
+        format!("Failed to create temp dir {}", dir.display())
+    })?;
+    Ok(dir)
+}
+
+/// Removes the temp directory of a service, if it has one.
+pub fn remove_tmpdir(module: &ModuleDefinition) {
+    if !module.tmpdir {
+        return;
+    }
+    let dir = match tmpdir_path(module) {
+        Ok(dir) => dir,
+        Err(e) => return warn!("{:#}", e),
+    };
+    if dir.exists() {
+        info!("Removing temp dir {}", dir.display());
+        if let Err(e) = fs::remove_dir_all(&dir) {
+            warn!("Failed to remove temp dir {}: {}", dir.display(), e);
+        }
+    }
+}
+
+/// Removes the temp directories left behind by services of a previous run of
+/// the daemon.
+pub fn prune_tmpdirs() {
+    let root = match tmp_root() {
+        Ok(root) => root,
+        Err(e) => return warn!("{:#}", e),
+    };
+    if root.exists() {
+        info!("Pruning temp dirs in {}", root.display());
+        if let Err(e) = fs::remove_dir_all(&root) {
+            warn!("Failed to prune temp dirs in {}: {}", root.display(), e);
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use crate::collections::{FromIndexContainer, FromOwnedIndexContainer, VecExt};
 use anyhow::{bail, Result};
+use std::cmp::Reverse;
 use std::collections::{HashMap, HashSet};
 use std::hash::{Hash, Hasher};
 
@@ -59,6 +60,9 @@ pub struct DependencyEdge<M: PartialOrd> {
 pub trait WithDependencies<M: PartialOrd>: WithKey {
     fn dependencies(&self) -> Vec<DependencyEdge<M>>;
     fn is_group(&self) -> bool;
+    /// The priority of the node amongst other nodes of the same level. Nodes
+    /// with a higher priority are ordered first within their group.
+    fn priority(&self) -> i32;
 }
 
 pub trait WithKey {
@@ -154,6 +158,10 @@ where
             .map(|idx| StackEntry::new(*idx))
             .collect();
 
+        // Nodes pointed to by `From` edges of groups (eg. post tasks of a
+        // group), along with the group they belong to.
+        let mut group_hooks: Vec<(usize, usize)> = Vec::new();
+
         while !node_stack.is_empty() {
             let node_stack_entry = node_stack.pop().unwrap();
             let node_idx = node_stack_entry.node_idx;
@@ -161,6 +169,7 @@ where
 
             let node = arena.get_by_idx(node_idx);
             let dependencies = node.value.dependencies();
+            let node_is_group = node.value.is_group();
 
             dependencies.iter().for_each(|edge| {
                 let edge_dst = &edge.edge_dst;
@@ -197,6 +206,9 @@ where
                     EdgeDirection::To => (edge_src.as_str(), pointed_to_idx),
                     EdgeDirection::From => {
                         arena.push_node_idx(pointed_to_idx);
+                        if node_is_group {
+                            group_hooks.push((pointed_to_idx, node_idx));
+                        }
                         (edge_dst.as_str(), node_idx)
                     }
                 };
@@ -206,6 +218,8 @@ where
             })
         }
 
+        arena.inherit_group_origins(&group_hooks);
+
         let (edge_map, node_list) = arena.dispose();
         DependencyGraph {
             edge_map,
@@ -306,12 +320,27 @@ where
         Ok(sorted)
     }
 
+    /// Return a sorted list of dependencies in teardown order.
+    ///
+    /// This is the reverse of [DependencyGraph::dependency_sort], so that
+    /// modules are ordered before the modules they depend on (eg. an API
+    /// service is stopped before the database it depends on).
+    pub fn teardown_sort(&self) -> Result<Vec<&DependencyNode<&T, M>>> {
+        let mut sorted = self.dependency_sort()?;
+        sorted.reverse();
+        Ok(sorted)
+    }
+
     /// Sort dependencies and return in them in groups.
     ///
     /// Sorts dependencies into groups so that dependent modules are deployed
     /// before the modules that depend on them. Each group represents a set of
     /// dependencies that have no ordering between them. The topological sort is
     /// performed using modified DFS.
+    ///
+    /// Within each group, nodes are ordered by descending priority (see
+    /// [WithDependencies::priority]). Nodes of equal priority retain their
+    /// relative order.
     pub fn group_sort(&self) -> Result<SortedDeps<DependencyNode<&T, M>>> {
         let mut sorted = Vec::new();
         let mut stack: Vec<(bool, &DependencyNode<&T, M>, u8)> = Vec::new();
@@ -371,7 +400,14 @@ where
         }
 
         // Sort into groups based on their level.
-        Ok(Self::split_by_level(marked, sorted))
+        let mut sorted_deps = Self::split_by_level(marked, sorted);
+
+        // Order each group by priority, higher priority first.
+        for group in sorted_deps.groups.iter_mut() {
+            group.sort_by_key(|node| Reverse(node.value.priority()));
+        }
+
+        Ok(sorted_deps)
     }
 }
 
@@ -471,6 +507,46 @@ where
         }
     }
 
+    /// Replace group origins of group hooks with the members of the group.
+    ///
+    /// A node attached to a group through a `From` edge (eg. a post task of
+    /// the group) should be considered as originating from each member of the
+    /// group, rather than from the group itself. Members that are groups are
+    /// expanded recursively.
+    pub fn inherit_group_origins(&mut self, group_hooks: &[(usize, usize)]) {
+        for &(node_idx, group_idx) in group_hooks {
+            let mut members = self.group_members(group_idx);
+            let group_key = self.get_by_idx(group_idx).key.clone();
+            let node = self.get_mut_ref(node_idx);
+            members.remove(&node.key);
+            node.origin_nodes.remove(&group_key);
+            node.origin_nodes.extend(members);
+        }
+    }
+
+    /// Get the keys of all (non-group) members of a group, recursively.
+    fn group_members(&self, group_idx: usize) -> HashSet<String> {
+        let mut members = HashSet::new();
+        let mut visited = HashSet::new();
+        let mut stack = vec![group_idx];
+
+        while let Some(idx) = stack.pop() {
+            if !visited.insert(idx) {
+                continue;
+            }
+            let group = self.get_by_idx(idx);
+            for &member_idx in &self.edge_map[&group.key] {
+                let member = self.get_by_idx(member_idx);
+                if member.value.is_group() {
+                    stack.push(member_idx);
+                } else {
+                    members.insert(member.key.clone());
+                }
+            }
+        }
+        members
+    }
+
     /// Create the arena and populate it.
     ///
     /// Creates the arena and populates it with all nodes from `all_nodes` that
@@ -593,7 +669,7 @@ mod test {
                 HashMap::new(),
                 HashMap::new(),
                 None,
-                dependencies.iter().map(|s| s.to_string()).collect(),
+                dependencies.iter().map(|s| s.to_string().into()).collect(),
                 ordered_dependencies.iter().map(|s| s.to_string()).collect(),
                 after.iter().map(|s| s.to_string()).collect(),
                 inverse.iter().map(|s| s.to_string()).collect(),
@@ -606,10 +682,42 @@ mod test {
                 None,
                 None,
                 false,
+                0,
             )),
         }
     }
 
+    fn with_priority(
+        mut module: ModuleDefinition,
+        priority: i32,
+    ) -> ModuleDefinition {
+        if let InnerDefinition::Service(ref mut svc) = module.inner {
+            svc.priority = priority;
+        }
+        module
+    }
+
+    fn make_group(
+        name: &str,
+        dependencies: Vec<&str>,
+        post: Vec<&str>,
+    ) -> ModuleDefinition {
+        ModuleDefinition {
+            name: name.to_string(),
+            kind: ModuleKind::Group,
+            inner: InnerDefinition::Group(GroupDefinition {
+                name: name.to_string(),
+                dependencies: dependencies
+                    .iter()
+                    .map(|s| s.to_string())
+                    .collect(),
+                post_up: vec![],
+                post: post.iter().map(|s| s.to_string()).collect(),
+                checks: vec![],
+            }),
+        }
+    }
+
     fn is_before(m1: &str, m2: &str, elems: &Vec<&str>) -> bool {
         let mut index_a: i64 = -1;
         let mut index_b: i64 = -1;
@@ -737,4 +845,109 @@ mod test {
         assert!(is_before("m10", "m8", &result));
         assert!(is_before("m11", "m10", &result));
     }
+
+    #[test]
+    fn test_group_sort_orders_by_priority() {
+        let m1 =
+            make_module("m1", vec!["m2", "m3", "m4"], vec![], vec![], vec![]);
+        let m2 = make_module("m2", vec![], vec![], vec![], vec![]);
+        let m3 = with_priority(
+            make_module("m3", vec![], vec![], vec![], vec![]),
+            10,
+        );
+        let m4 = with_priority(
+            make_module("m4", vec![], vec![], vec![], vec![]),
+            -5,
+        );
+        let modules = vec![m1, m2, m3, m4];
+        let selected = vec!["m1"];
+
+        let graph = DependencyGraph::from(&modules, &selected);
+        let result: Vec<Vec<&str>> = graph
+            .group_sort()
+            .unwrap()
+            .groups
+            .iter()
+            .map(|g| g.iter().map(|v| &v.value.name[..]).collect::<Vec<_>>())
+            .collect();
+
+        assert!(result == vec![vec!["m3", "m2", "m4"], vec!["m1"]]);
+    }
+
+    #[test]
+    fn test_teardown_sort() {
+        let api =
+            make_module("api", vec!["db", "cache"], vec![], vec![], vec![]);
+        let worker = make_module("worker", vec!["api"], vec![], vec![], vec![]);
+        let db = make_module("db", vec![], vec![], vec![], vec![]);
+        let cache = make_module("cache", vec![], vec![], vec![], vec![]);
+        let modules = vec![api, worker, db, cache];
+        let selected = vec!["worker", "db"];
+
+        let graph = DependencyGraph::from(&modules, &selected);
+        let result: Vec<&str> = graph
+            .teardown_sort()
+            .unwrap()
+            .iter()
+            .map(|v| &v.value.name[..])
+            .collect();
+
+        assert!(eq_lists(&result, &["worker", "api", "db", "cache"]));
+        assert!(is_before("worker", "api", &result));
+        assert!(is_before("api", "db", &result));
+        assert!(is_before("api", "cache", &result));
+    }
+
+    #[test]
+    fn test_no_wait_edge_marker() {
+        let mut api = make_module("api", vec!["db"], vec![], vec![], vec![]);
+        if let InnerDefinition::Service(ref mut svc) = api.inner {
+            svc.dependencies[0].wait_healthy = false;
+        }
+        let worker = make_module("worker", vec!["db"], vec![], vec![], vec![]);
+        let db = make_module("db", vec![], vec![], vec![], vec![]);
+        let modules = vec![api, worker, db];
+
+        let marker_of_db = |selected: &[&str]| {
+            let graph = DependencyGraph::from(&modules, selected);
+            graph
+                .dependency_sort()
+                .unwrap()
+                .iter()
+                .find(|n| n.key == "db")
+                .unwrap()
+                .marker
+        };
+
+        assert_eq!(marker_of_db(&["api"]), Some(ModuleMarker::NoWait));
+        assert_eq!(
+            marker_of_db(&["api", "worker"]),
+            Some(ModuleMarker::WaitProbe)
+        );
+    }
+
+    #[test]
+    fn test_group_hook_inherits_member_origins() {
+        let api = make_module("api", vec![], vec![], vec![], vec![]);
+        let db = make_module("db", vec![], vec![], vec![], vec![]);
+        let cache = make_module("cache", vec![], vec![], vec![], vec![]);
+        let seed = make_module("seed", vec![], vec![], vec![], vec![]);
+        let infra = make_group("infra", vec!["db", "cache"], vec![]);
+        let all = make_group("all", vec!["api", "infra"], vec!["seed"]);
+        let modules = vec![api, db, cache, seed, infra, all];
+        let selected = vec!["all"];
+
+        let graph = DependencyGraph::from(&modules, &selected);
+        let sorted = graph.dependency_sort().unwrap();
+        let seed_node = sorted.iter().find(|n| n.key == "seed").unwrap();
+        let mut origins: Vec<_> = seed_node.origin_nodes.iter().collect();
+        origins.sort();
+
+        assert_eq!(origins, vec!["api", "cache", "db"]);
+
+        let result: Vec<&str> =
+            sorted.iter().map(|v| &v.value.name[..]).collect();
+        assert!(is_before("api", "seed", &result));
+        assert!(is_before("db", "seed", &result));
+    }
 }
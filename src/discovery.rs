@@ -0,0 +1,95 @@
+use crate::{config, path};
+use anyhow::{anyhow, Context, Result};
+use nix::sys::signal::kill;
+use nix::unistd::Pid;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::{fs, process};
+
+/// The prefix of the discovery files within the state directory. Each
+/// daemon writes its own file, named after the port it listens on.
+const DISCOVERY_FILE_PREFIX: &str = "daemon-";
+
+/// The endpoint of a running daemon.
+///
+/// The daemon writes its endpoint on startup so that clients can locate it
+/// even if it was started on a port other than the configured one.
+#[derive(Serialize, Deserialize)]
+pub struct DaemonEndpoint {
+    pub pid: u32,
+    pub port: u16,
+}
+
+fn state_dir() -> Result<PathBuf> {
+    path::state_dir().ok_or_else(|| anyhow!("Failed to locate users state dir"))
+}
+
+fn discovery_file_path(port: u16) -> Result<PathBuf> {
+    Ok(state_dir()?.join(format!("{}{}.json", DISCOVERY_FILE_PREFIX, port)))
+}
+
+/// Writes the endpoint of the current process to its discovery file.
+pub fn write_endpoint(port: u16) -> Result<()> {
+    let path = discovery_file_path(port)?;
+    fs::create_dir_all(path.parent().unwrap())?;
+    let endpoint = DaemonEndpoint {
+        pid: process::id(),
+        port,
+    };
+    fs::write(&path, serde_json::to_string(&endpoint)?).with_context(|| {
+        format!("Failed to write discovery file {}", path.display())
+    })
+}
+
+/// Reads the endpoints of all running daemons, most recently started first.
+///
+/// Discovery files left behind by daemons that are no longer running are
+/// removed.
+pub fn read_endpoints() -> Vec<DaemonEndpoint> {
+    let entries = match state_dir().and_then(|dir| Ok(fs::read_dir(dir)?)) {
+        Ok(entries) => entries,
+        Err(_) => return vec![],
+    };
+
+    let mut endpoints = vec![];
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let file_name = entry.file_name();
+        let file_name = file_name.to_string_lossy();
+        if !file_name.starts_with(DISCOVERY_FILE_PREFIX)
+            || !file_name.ends_with(".json")
+        {
+            continue;
+        }
+        let endpoint =
+            match fs::read_to_string(entry.path()).ok().and_then(|content| {
+                serde_json::from_str::<DaemonEndpoint>(&content).ok()
+            }) {
+                Some(endpoint) => endpoint,
+                None => continue,
+            };
+        if kill(Pid::from_raw(endpoint.pid as i32), None).is_err() {
+            let _ = fs::remove_file(entry.path());
+            continue;
+        }
+        let modified = entry.metadata().and_then(|m| m.modified()).ok();
+        endpoints.push((modified, endpoint));
+    }
+    endpoints.sort_by(|a, b| b.0.cmp(&a.0));
+    endpoints
+        .into_iter()
+        .map(|(_, endpoint)| endpoint)
+        .collect()
+}
+
+/// Returns the port of the local daemon to connect to.
+///
+/// A daemon on the default port is preferred, otherwise the most recently
+/// started one is used. Returns `None` if no daemon is running.
+pub fn discover_port() -> Option<u16> {
+    let endpoints = read_endpoints();
+    endpoints
+        .iter()
+        .find(|endpoint| endpoint.port == config::DEFAULT_DAEMON_PORT)
+        .or_else(|| endpoints.first())
+        .map(|endpoint| endpoint.port)
+}
@@ -0,0 +1,70 @@
+/// Returns true if the given string contains any wildcard characters.
+///
+/// Supported wildcards are `*` (matching any number of characters) and `?`
+/// (matching exactly one character).
+pub fn is_pattern(s: &str) -> bool {
+    s.contains('*') || s.contains('?')
+}
+
+/// Matches a string against a glob-like pattern.
+///
+/// The pattern may contain `*` to match any (possibly empty) sequence of
+/// characters and `?` to match any single character. All other characters
+/// are matched literally.
+///
+/// # Examples
+/// ```
+/// use cartel::glob::matches;
+/// assert!(matches("infra-*", "infra-postgres"));
+/// assert!(!matches("infra-?", "infra-db"));
+/// ```
+pub fn matches(pattern: &str, s: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let s: Vec<char> = s.chars().collect();
+
+    let (mut p_idx, mut s_idx) = (0, 0);
+    // Position of the last `*` seen in the pattern and the position in the
+    // string it was matched against, used to backtrack on a mismatch.
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while s_idx < s.len() {
+        if p_idx < pattern.len()
+            && (pattern[p_idx] == '?' || pattern[p_idx] == s[s_idx])
+        {
+            p_idx += 1;
+            s_idx += 1;
+        } else if p_idx < pattern.len() && pattern[p_idx] == '*' {
+            backtrack = Some((p_idx, s_idx));
+            p_idx += 1;
+        } else if let Some((star_idx, matched_idx)) = backtrack {
+            // Let the last `*` consume one more character and retry.
+            p_idx = star_idx + 1;
+            s_idx = matched_idx + 1;
+            backtrack = Some((star_idx, s_idx));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p_idx..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_matches() {
+        assert!(matches("infra-*", "infra-postgres"));
+        assert!(matches("infra-*", "infra-"));
+        assert!(matches("*-db", "orders-db"));
+        assert!(matches("*", "anything"));
+        assert!(matches("svc-?", "svc-1"));
+        assert!(matches("a*b*c", "axxbyyc"));
+        assert!(!matches("infra-*", "backend"));
+        assert!(!matches("svc-?", "svc-10"));
+        assert!(!matches("a*b*c", "axxbyy"));
+        assert!(matches("exact", "exact"));
+        assert!(!matches("exact", "exactly"));
+    }
+}
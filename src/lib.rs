@@ -14,6 +14,8 @@ pub mod constants;
 pub mod daemon;
 pub mod dependency;
 pub mod detach;
+pub mod discovery;
+pub mod glob;
 pub mod path;
 pub mod process;
 pub mod shell;
@@ -1,6 +1,31 @@
+use crate::constants::APP_DIR;
 use anyhow::{bail, Result};
+use std::env;
 use std::path::{Path, PathBuf};
 
+/// Returns the directory the configuration is stored in.
+///
+/// This is `$XDG_CONFIG_HOME/cartel` (`~/.config/cartel` if unset) on Linux
+/// and `~/Library/Application Support/cartel` on macOS.
+pub fn config_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join(APP_DIR))
+}
+
+/// Returns the directory state such as log files is stored in.
+///
+/// This is `$XDG_STATE_HOME/cartel` (`~/.local/state/cartel` if unset) on
+/// Linux and `~/Library/Application Support/cartel` on macOS.
+pub fn state_dir() -> Option<PathBuf> {
+    if cfg!(target_os = "macos") {
+        return dirs::data_local_dir().map(|dir| dir.join(APP_DIR));
+    }
+    env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| dirs::home_dir().map(|home| home.join(".local/state")))
+        .map(|dir| dir.join(APP_DIR))
+}
+
 /// Expands the `~` symbol in paths.
 ///
 /// Replaces the tilde in a path with the path to the users home directory.
@@ -1,4 +1,4 @@
-use std::io::Result;
+use std::io::{Error, Result};
 use std::process::{Child, ExitStatus};
 
 #[derive(Debug)]
@@ -100,6 +100,14 @@ impl Process {
     }
 }
 
+/// Returns whether waiting for an adopted process group failed because it
+/// exited, but its exit status can not be collected as it is not a child of
+/// this process (see [GroupChild::adopt]).
+#[cfg(target_family = "unix")]
+pub fn is_orphan_exit(error: &Error) -> bool {
+    error.raw_os_error() == Some(nix::libc::ECHILD)
+}
+
 pub trait CommandExt {
     /// Executes the command as a child process in a new group, returning a
     /// handle to it.
@@ -164,6 +172,21 @@ impl GroupChild {
         self.imp.kill()
     }
 
+    /// Takes over a process group started by another process.
+    ///
+    /// This is either the previous image of this process, which was replaced
+    /// using `exec` (see [handover](crate::daemon::handover)) and so is still
+    /// its parent, or a process which exited (eg. a daemon which crashed).
+    /// In the latter case the group is an orphan, whose exit status can not
+    /// be collected: waiting for it fails once it exited (see
+    /// [is_orphan_exit]).
+    #[cfg(target_family = "unix")]
+    pub fn adopt(pgid: u32) -> GroupChild {
+        GroupChild {
+            imp: imp::GroupChildImp::adopt(pgid),
+        }
+    }
+
     /// Return group process identifier.
     ///
     /// On Unix this will be the `pgid` of the process group. On Windows the
@@ -200,14 +223,28 @@ mod imp {
     use std::io::{Error, Result};
     use std::os::unix::process::{CommandExt as UnixCommandExt, ExitStatusExt};
     use std::process::{Child, Command, ExitStatus};
+    use std::thread;
+    use std::time::Duration;
+
+    /// How often an orphaned process group is checked for having exited
+    /// while waiting for it.
+    const ORPHAN_POLL_INTERVAL: Duration = Duration::from_millis(50);
 
     #[derive(Debug)]
     pub struct GroupChildImp {
         pgid: libc::pid_t,
-        inner: Child,
+        /// None if the process group was adopted.
+        inner: Option<Child>,
     }
 
     impl GroupChildImp {
+        pub(crate) fn adopt(pgid: u32) -> GroupChildImp {
+            let pgid = pgid
+                .try_into()
+                .expect("u32 -> i32 failed in GroupChildImp::adopt");
+            GroupChildImp { pgid, inner: None }
+        }
+
         /// Sends SIGINT to the pgid of this process.
         pub(crate) fn interrupt(&mut self) -> Result<()> {
             signal_process_group(self.pgid, Signal::SIGINT)
@@ -223,14 +260,14 @@ mod imp {
             signal_process_group(self.pgid, Signal::SIGKILL)
         }
 
-        /// Return the pid of the child process.
+        /// Return the pid of the child process (the leader of its group).
         #[inline]
         pub fn id(&self) -> u32 {
-            self.inner.id()
+            self.pgid as u32
         }
 
-        pub(crate) fn inner(&mut self) -> &mut Child {
-            &mut self.inner
+        pub(crate) fn inner(&mut self) -> Option<&mut Child> {
+            self.inner.as_mut()
         }
 
         pub(crate) fn wait(&mut self) -> Result<ExitStatus> {
@@ -244,6 +281,12 @@ mod imp {
                         return Ok(ExitStatus::from_raw(status))
                     }
                     Ok(_) => {} // continue
+                    Err(e) if self.is_orphan(&e) => {
+                        while self.is_group_alive() {
+                            thread::sleep(ORPHAN_POLL_INTERVAL);
+                        }
+                        return Err(e);
+                    }
                     Err(e) => return Err(e),
                 }
             }
@@ -259,9 +302,22 @@ mod imp {
                     Ok(Some(ExitStatus::from_raw(status)))
                 }
                 Ok(_) => Ok(None),
+                Err(e) if self.is_orphan(&e) && self.is_group_alive() => {
+                    Ok(None)
+                }
                 Err(e) => Err(e),
             }
         }
+
+        /// Whether waiting failed as the adopted group is not a child of
+        /// this process.
+        fn is_orphan(&self, error: &Error) -> bool {
+            self.inner.is_none() && error.raw_os_error() == Some(libc::ECHILD)
+        }
+
+        fn is_group_alive(&self) -> bool {
+            killpg(Pid::from_raw(self.pgid), None).is_ok()
+        }
     }
 
     /// Wrapper for `waitpid` libc syscall that additionally returns the raw
@@ -342,7 +398,10 @@ mod imp {
                 .try_into()
                 .expect("u32 -> i32 failed in CommandExt::group_spawn");
 
-            let imp = GroupChildImp { pgid, inner: child };
+            let imp = GroupChildImp {
+                pgid,
+                inner: Some(child),
+            };
             Ok(GroupChild { imp })
         }
     }